@@ -0,0 +1,62 @@
+// Camera rays through the cover scene's BVH, one at a time and as packets:
+// `cargo bench --bench traversal`, with `--features simd` or `f32` to compare
+// those builds.
+use rand::Rng;
+use std::hint::black_box;
+use std::time::Instant;
+
+use raytracer::bvh::Bvh;
+use raytracer::float::Float;
+use raytracer::packet::RayPacket;
+use raytracer::packet::PACKET_SIZE;
+use raytracer::ray::Ray;
+use raytracer::scenes;
+
+const WIDTH: usize = 400;
+const HEIGHT: usize = 300;
+const ROUNDS: usize = 5;
+
+fn main() {
+    let scene = scenes::cover(1);
+    let bvh = Bvh::new(scene.objects);
+    let mut rng = rand::thread_rng();
+    // Each pixel's samples, jittered within it.
+    let packets: Vec<[Ray; PACKET_SIZE]> = (0..WIDTH * HEIGHT)
+        .map(|i| {
+            let (x, y) = ((i % WIDTH) as Float, (i / WIDTH) as Float);
+            std::array::from_fn(|_| {
+                let u = (x + rng.gen::<Float>()) / (WIDTH - 1) as Float;
+                let v = (HEIGHT as Float - y - rng.gen::<Float>()) / (HEIGHT - 1) as Float;
+                scene.camera.get_ray(u, v)
+            })
+        })
+        .collect();
+    let rays = (packets.len() * PACKET_SIZE * ROUNDS) as f64;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for packet in &packets {
+            for ray in packet {
+                black_box(bvh.hit_with_id(ray, 0.001, Float::MAX).map(|(id, _)| id));
+            }
+        }
+    }
+    let single = rays / start.elapsed().as_secs_f64() / 1e6;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for packet in &packets {
+            let packet = RayPacket::new(*packet);
+            let hits = bvh.hit_packet(&packet, 0.001, &[Float::MAX; PACKET_SIZE]);
+            black_box(hits.map(|hit| hit.map(|(id, _)| id)));
+        }
+    }
+    let packed = rays / start.elapsed().as_secs_f64() / 1e6;
+
+    println!("one at a time: {:.2} Mrays/s", single);
+    println!(
+        "in packets:    {:.2} Mrays/s ({:.2}x)",
+        packed,
+        packed / single
+    );
+}
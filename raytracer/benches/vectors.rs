@@ -0,0 +1,52 @@
+// The vector arithmetic of the inner loops on their own: run as
+// `cargo bench --bench vectors`, and again with `--features simd` to compare.
+use std::hint::black_box;
+use std::time::Instant;
+
+use raytracer::float::Float;
+use raytracer::point3d::Point3D;
+
+const POINTS: usize = 4096;
+const ROUNDS: usize = 500;
+// The quickest of several tries counts, as the others were slowed down by
+// something else.
+const TRIES: usize = 10;
+
+fn main() {
+    let points: Vec<Point3D> = (0..POINTS)
+        .map(|i| {
+            let t = i as Float;
+            Point3D::new(t.sin(), t.cos(), 0.5 + t.sin() * t.cos())
+        })
+        .collect();
+
+    // What shading does most, turned into one number so none of it can be
+    // left out.
+    let mut best = f64::INFINITY;
+    for _ in 0..TRIES {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let mut sum = Point3D::new(0.0, 0.0, 0.0);
+            let mut flat = 0;
+            for pair in black_box(&points).windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                let n = a.cross(&b).unit_vector();
+                let d = (b - a) * 0.5 + n * a.dot(&b);
+                sum += d.reflect(&n);
+                flat += (b - a).near_zero() as usize;
+            }
+            black_box((sum, flat));
+        }
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    let operations = (POINTS - 1) * ROUNDS;
+    println!(
+        "{:.1} ns per round of vector operations ({})",
+        best * 1e9 / operations as f64,
+        if cfg!(feature = "simd") {
+            "simd"
+        } else {
+            "scalar"
+        }
+    );
+}
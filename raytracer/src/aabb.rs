@@ -0,0 +1,108 @@
+use crate::float::Float;
+use crate::point3d::Point3D;
+use crate::ray::Ray;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3D,
+    pub max: Point3D,
+}
+
+impl Aabb {
+    pub fn new(min: Point3D, max: Point3D) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn surrounding_box(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb::new(
+            Point3D::new(
+                a.min.x().min(b.min.x()),
+                a.min.y().min(b.min.y()),
+                a.min.z().min(b.min.z()),
+            ),
+            Point3D::new(
+                a.max.x().max(b.max.x()),
+                a.max.y().max(b.max.y()),
+                a.max.z().max(b.max.z()),
+            ),
+        )
+    }
+
+    pub fn centroid(&self) -> Point3D {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    // Index (0 = x, 1 = y, 2 = z) of the axis along which the box is widest.
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn axis_min(&self, a: usize) -> Float {
+        self.min[a]
+    }
+
+    pub fn axis_max(&self, a: usize) -> Float {
+        self.max[a]
+    }
+
+    // Slab test: intersect the ray with the three pairs of axis-aligned planes
+    // and check that the resulting parameter ranges overlap.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.span(ray, t_min, t_max).is_some()
+    }
+
+    // Where along the ray the box is entered and left, limited to
+    // [t_min, t_max].
+    pub fn span(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for a in 0..3 {
+            let inv_d = 1.0 / ray.direction[a];
+            let mut t0 = (self.axis_min(a) - ray.origin[a]) * inv_d;
+            let mut t1 = (self.axis_max(a) - ray.origin[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[test]
+fn test_aabb_hit() {
+    let aabb = Aabb::new(Point3D::new(-1.0, -1.0, -1.0), Point3D::new(1.0, 1.0, 1.0));
+    let towards = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    let away = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, -1.0));
+    let beside = Ray::new(Point3D::new(2.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    assert!(aabb.hit(&towards, 0.0, Float::INFINITY));
+    assert!(!aabb.hit(&towards, 0.0, 3.0));
+    assert!(!aabb.hit(&away, 0.0, Float::INFINITY));
+    assert!(!aabb.hit(&beside, 0.0, Float::INFINITY));
+}
+
+#[test]
+fn test_surrounding_box() {
+    let a = Aabb::new(Point3D::new(-1.0, 0.0, 0.0), Point3D::new(0.0, 1.0, 1.0));
+    let b = Aabb::new(Point3D::new(0.0, -2.0, 0.0), Point3D::new(3.0, 0.0, 1.0));
+    let s = Aabb::surrounding_box(&a, &b);
+    assert_eq!(s.min, Point3D::new(-1.0, -2.0, 0.0));
+    assert_eq!(s.max, Point3D::new(3.0, 1.0, 1.0));
+    assert_eq!(s.longest_axis(), 0);
+}
@@ -0,0 +1,222 @@
+use palette::Srgb;
+use std::io::Error;
+use std::path::Path;
+
+use crate::film::Film;
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::output;
+use crate::output::Encoding;
+use crate::output::Format;
+use crate::output::Transfer;
+use crate::point3d::Point3D;
+use crate::tone_map::ToneMap;
+
+#[cfg(test)]
+use std::fs;
+
+// What the camera sees first through each pixel, besides the lit image:
+// the inputs denoisers and compositing work from. All three are averaged
+// over the pixel like the image itself, so edges line up.
+pub struct Aovs {
+    // The colour of the surface hit (the sky's colour where nothing is).
+    pub albedo: Film,
+    // The world space normal of the surface hit, facing the camera, or 0.
+    pub normal: Film,
+    // The distance from the camera to the surface hit, in all three
+    // channels, or 0.
+    pub depth: Film,
+}
+
+// One first hit, as added to Aovs.
+pub struct AovSample {
+    pub albedo: Srgb,
+    pub normal: Point3D,
+    pub depth: Float,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Aovs {
+        Aovs {
+            albedo: Film::new(width, height),
+            normal: Film::new(width, height),
+            depth: Film::new(width, height),
+        }
+    }
+
+    // Adds a sample to pixel `i`, counting row by row.
+    pub fn add(&mut self, i: usize, sample: &AovSample) {
+        let normal = &sample.normal;
+        let depth = to_f32(sample.depth);
+        self.albedo.pixels_mut()[i].add(sample.albedo);
+        self.normal.pixels_mut()[i].add(Srgb::new(
+            to_f32(normal.x()),
+            to_f32(normal.y()),
+            to_f32(normal.z()),
+        ));
+        self.depth.pixels_mut()[i].add(Srgb::new(depth, depth, depth));
+    }
+
+    // Writes the passes next to the image `filename`, e.g. out.albedo.png,
+    // out.normal.png and out.depth.png for out.png. OpenEXR gets the values
+    // as they are; other formats, which can only hold 0 to 1, get normals
+    // moved into that range and depth divided by the greatest one.
+    pub fn write(&self, filename: &str, encoding: &Encoding) -> Result<(), Error> {
+        let albedo = Encoding {
+            tone_map: ToneMap::Clamp,
+            ..*encoding
+        };
+        output::write_film(&pass_filename(filename, "albedo"), &self.albedo, &albedo)?;
+        if Format::from_filename(filename) == Some(Format::Exr) {
+            output::write_film(&pass_filename(filename, "normal"), &self.normal, encoding)?;
+            return output::write_film(&pass_filename(filename, "depth"), &self.depth, encoding);
+        }
+
+        let linear = Encoding {
+            tone_map: ToneMap::Clamp,
+            transfer: Transfer::Linear,
+            ..*encoding
+        };
+        let normal = remap(&self.normal, |c| 0.5 * (c + 1.0));
+        output::write_film(&pass_filename(filename, "normal"), &normal, &linear)?;
+        let far = self
+            .depth
+            .pixels()
+            .iter()
+            .map(|e| e.mean().red)
+            .fold(0.0, f32::max);
+        let depth = remap(&self.depth, |c| if far > 0.0 { c / far } else { 0.0 });
+        output::write_film(&pass_filename(filename, "depth"), &depth, &linear)
+    }
+}
+
+// A film holding `f` of every channel of `film`'s means.
+fn remap(film: &Film, f: impl Fn(f32) -> f32) -> Film {
+    let mut remapped = Film::new(film.width, film.height);
+    for (to, from) in remapped.pixels_mut().iter_mut().zip(film.pixels()) {
+        let mean = from.mean();
+        to.add(Srgb::new(f(mean.red), f(mean.green), f(mean.blue)));
+    }
+    remapped
+}
+
+// Which object and which material each pixel's centre sees, for picking
+// them out when compositing. Objects are numbered from 1 in the order the
+// scene file lists them, and materials from 1 in the order they first
+// appear; 0 is nothing.
+pub struct IdPasses {
+    pub width: usize,
+    pub height: usize,
+    pub objects: Vec<u32>,
+    pub materials: Vec<u32>,
+}
+
+impl IdPasses {
+    // Writes the ids next to the image `filename`: as the whole number
+    // channels "object" and "material" of out.id.exr for out.exr, or in other
+    // formats as out.object_id.png and out.material_id.png, where each id has
+    // a colour of its own.
+    pub fn write(&self, filename: &str, encoding: &Encoding) -> Result<(), Error> {
+        let bounds = (self.width, self.height);
+        if Format::from_filename(filename) == Some(Format::Exr) {
+            return output::write_ids_exr(
+                &pass_filename(filename, "id"),
+                bounds,
+                &[("object", &self.objects), ("material", &self.materials)],
+            );
+        }
+        for (pass, ids) in [
+            ("object_id", &self.objects),
+            ("material_id", &self.materials),
+        ] {
+            let pixels: Vec<u8> = ids.iter().flat_map(|id| id_color(*id)).collect();
+            output::write_rgb8(&pass_filename(filename, pass), &pixels, bounds, encoding)?;
+        }
+        Ok(())
+    }
+}
+
+// A colour for `id` that neighbouring ids are unlikely to share; black for 0.
+pub fn id_color(id: u32) -> [u8; 3] {
+    if id == 0 {
+        return [0; 3];
+    }
+    // Murmur3's finaliser scatters the bits.
+    let mut h = id;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    // Keep away from black, so nothing looks like the background.
+    let [r, g, b, _] = h.to_le_bytes();
+    [r | 0x40, g | 0x40, b | 0x40]
+}
+
+// `filename` with `pass` put in front of its extension.
+pub fn pass_filename(filename: &str, pass: &str) -> String {
+    let path = Path::new(filename);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => path
+            .with_extension(format!("{}.{}", pass, extension))
+            .to_string_lossy()
+            .into_owned(),
+        None => format!("{}.{}", filename, pass),
+    }
+}
+
+#[test]
+fn test_pass_filename() {
+    assert_eq!(pass_filename("out.png", "albedo"), "out.albedo.png");
+    assert_eq!(
+        pass_filename("a.b/render.exr", "depth"),
+        "a.b/render.depth.exr"
+    );
+    assert_eq!(pass_filename("out", "normal"), "out.normal");
+}
+
+#[test]
+fn test_write_aovs() {
+    let mut aovs = Aovs::new(2, 1);
+    let sample = |depth, normal| AovSample {
+        albedo: Srgb::new(0.5, 0.5, 0.5),
+        normal,
+        depth,
+    };
+    aovs.add(0, &sample(2.0, Point3D::new(0.0, 0.0, 1.0)));
+    aovs.add(1, &sample(4.0, Point3D::new(-1.0, 0.0, 0.0)));
+    aovs.write("/tmp/test_write_aovs.ppm", &Encoding::default())
+        .unwrap();
+
+    // The normals and depths, brought into 0 to 1, after the header.
+    let normal = fs::read("/tmp/test_write_aovs.normal.ppm").unwrap();
+    assert_eq!(normal[normal.len() - 6..], [128, 128, 255, 0, 128, 128]);
+    let depth = fs::read("/tmp/test_write_aovs.depth.ppm").unwrap();
+    assert_eq!(depth[depth.len() - 6..], [128, 128, 128, 255, 255, 255]);
+    assert!(fs::metadata("/tmp/test_write_aovs.albedo.ppm").is_ok());
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_write_id_passes() {
+    let ids = IdPasses {
+        width: 3,
+        height: 1,
+        objects: vec![0, 1, 2],
+        materials: vec![0, 1, 1],
+    };
+    ids.write("/tmp/test_write_id_passes.ppm", &Encoding::default())
+        .unwrap();
+    let objects = fs::read("/tmp/test_write_id_passes.object_id.ppm").unwrap();
+    let pixels = &objects[objects.len() - 9..];
+    assert_eq!(pixels[..3], [0, 0, 0]);
+    assert_eq!(pixels[3..6], id_color(1));
+    assert_ne!(pixels[3..6], pixels[6..]);
+    let materials = fs::read("/tmp/test_write_id_passes.material_id.ppm").unwrap();
+    let pixels = &materials[materials.len() - 9..];
+    assert_eq!(pixels[3..6], pixels[6..]);
+
+    ids.write("/tmp/test_write_id_passes.exr", &Encoding::default())
+        .unwrap();
+    assert!(fs::metadata("/tmp/test_write_id_passes.id.exr").is_ok());
+}
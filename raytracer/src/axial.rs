@@ -0,0 +1,156 @@
+use crate::float::consts::PI;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// A ray as seen by a shape built around an axis, such as a cylinder or a
+// cone: split into its parts along the axis and across it, from a point
+// `origin` on the axis.
+pub struct AxialRay {
+    pub axis: Point3D,
+    pub oc_along: Float,
+    pub d_along: Float,
+    pub oc_across: Point3D,
+    pub d_across: Point3D,
+    tangent: Point3D,
+    bitangent: Point3D,
+}
+
+impl AxialRay {
+    pub fn new(ray: &Ray, origin: Point3D, axis: Point3D) -> AxialRay {
+        // Scene files may not give a unit axis.
+        let axis = axis.unit_vector();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let oc = ray.origin - origin;
+        let oc_along = oc.dot(&axis);
+        let d_along = ray.direction.dot(&axis);
+        AxialRay {
+            axis,
+            oc_along,
+            d_along,
+            oc_across: oc - axis * oc_along,
+            d_across: ray.direction - axis * d_along,
+            tangent,
+            bitangent,
+        }
+    }
+
+    // How far along the axis, and where across it, the ray is at `t`.
+    pub fn at(&self, t: Float) -> (Float, Point3D) {
+        (
+            self.oc_along + self.d_along * t,
+            self.oc_across + self.d_across * t,
+        )
+    }
+
+    // The texture's u on a side, going once around the axis, for a point
+    // in the direction `radial` from it.
+    pub fn around(&self, radial: Point3D) -> Float {
+        let angle = radial.dot(&self.bitangent).atan2(radial.dot(&self.tangent));
+        0.5 + angle / (2.0 * PI)
+    }
+
+    // Where the ray crosses a flat cap of `radius` across the axis, `along`
+    // it: t and the texture coordinates, with the texture laid flat over the
+    // cap.
+    pub fn cap(&self, along: Float, radius: Float) -> Option<(Float, Float, Float)> {
+        if self.d_along == 0.0 {
+            return None;
+        }
+        let t = (along - self.oc_along) / self.d_along;
+        let (_, across) = self.at(t);
+        if across.length_squared() > radius * radius {
+            return None;
+        }
+        let u = 0.5 + across.dot(&self.tangent) / (2.0 * radius);
+        let v = 0.5 + across.dot(&self.bitangent) / (2.0 * radius);
+        Some((t, u, v))
+    }
+}
+
+// The nearest of the hits found on the parts of a shape, each given by t,
+// the outward normal and the texture coordinates.
+pub struct Closest {
+    t_min: Float,
+    t_max: Float,
+    hit: Option<(Float, Point3D, Float, Float)>,
+}
+
+impl Closest {
+    pub fn new(t_min: Float, t_max: Float) -> Closest {
+        Closest {
+            t_min,
+            t_max,
+            hit: None,
+        }
+    }
+
+    // Keeps the hit if it is in range and nearer than the one so far.
+    pub fn consider(&mut self, t: Float, outward: Point3D, u: Float, v: Float) {
+        if t > self.t_min && t < self.hit.map_or(self.t_max, |hit| hit.0) {
+            self.hit = Some((t, outward, u, v));
+        }
+    }
+
+    pub fn record<'material>(
+        self,
+        ray: &Ray,
+        material: &'material Material,
+    ) -> Option<HitRecord<'material>> {
+        let (t, outward, u, v) = self.hit?;
+        let front_face = ray.direction.dot(&outward) < 0.0;
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal: if front_face { outward } else { -outward },
+            front_face,
+            material,
+            u,
+            v,
+            tangent: None,
+        })
+    }
+}
+
+#[test]
+fn test_axial_ray() {
+    // A ray coming down beside the axis, one across from it.
+    let axis = Point3D::new(0.0, 2.0, 0.0);
+    let ray = Ray::new(Point3D::new(1.0, 4.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let axial = AxialRay::new(&ray, Point3D::new(0.0, 1.0, 0.0), axis);
+    let (along, across) = axial.at(1.0);
+    assert_approx_eq!(along, 2.0);
+    assert_eq!(across, Point3D::new(1.0, 0.0, 0.0));
+    assert!(axial.cap(0.0, 0.5).is_none());
+    let (t, u, v) = axial.cap(0.0, 2.0).unwrap();
+    assert_approx_eq!(t, 3.0);
+    assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+    // Half way around is half way along u.
+    let u = axial.around(Point3D::new(1.0, 0.0, 0.0));
+    let opposite = axial.around(Point3D::new(-1.0, 0.0, 0.0));
+    assert_approx_eq!((u - opposite).abs(), 0.5);
+}
+
+#[test]
+fn test_closest() {
+    let material = Material::grey();
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, 1.0));
+    let mut closest = Closest::new(0.001, 10.0);
+    let towards = Point3D::new(0.0, 0.0, -1.0);
+    closest.consider(5.0, towards, 0.1, 0.1);
+    closest.consider(0.0, towards, 0.2, 0.2);
+    closest.consider(2.0, -towards, 0.3, 0.3);
+    closest.consider(20.0, towards, 0.4, 0.4);
+    let hit = closest.record(&ray, &material).unwrap();
+    assert_eq!(hit.t, 2.0);
+    assert_eq!(hit.u, 0.3);
+    // Leaving through the back, so the normal is turned to face the ray.
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, towards);
+    assert!(Closest::new(0.001, 10.0).record(&ray, &material).is_none());
+}
@@ -0,0 +1,178 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::sync::OnceLock;
+
+use crate::float::Float;
+
+// Width and height of the mask, which tiles the image.
+pub const SIZE: usize = 64;
+
+// Spread of the Gaussian used to measure how clustered points are.
+const SIGMA: Float = 1.5;
+// Beyond this many pixels the Gaussian is negligible.
+const RADIUS: isize = 6;
+
+// A value in [0, 1) for each pixel of a SIZE x SIZE tile, every value
+// appearing once, arranged so that similar values are never close together:
+// blue noise. Noise dithered with it has no low-frequency clumps, which looks
+// finer grained and is easier for denoisers to remove.
+pub fn mask_value(x: usize, y: usize) -> Float {
+    let mask = MASK.get_or_init(void_and_cluster);
+    let rank = mask[(y % SIZE) * SIZE + x % SIZE];
+    (rank as Float + 0.5) / (SIZE * SIZE) as Float
+}
+
+static MASK: OnceLock<Vec<usize>> = OnceLock::new();
+
+// How crowded each pixel's neighbourhood is with the pattern's points.
+struct Energy {
+    values: Vec<Float>,
+    kernel: Vec<Float>,
+}
+
+impl Energy {
+    fn new() -> Energy {
+        let width = 2 * RADIUS + 1;
+        let mut kernel = Vec::with_capacity((width * width) as usize);
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let d2 = (dx * dx + dy * dy) as Float;
+                kernel.push((-d2 / (2.0 * SIGMA * SIGMA)).exp());
+            }
+        }
+        Energy {
+            values: vec![0.0; SIZE * SIZE],
+            kernel,
+        }
+    }
+
+    // Adds (or with a negative sign removes) a point, wrapping at the edges
+    // so the mask tiles seamlessly.
+    fn splat(&mut self, index: usize, sign: Float) {
+        let (x, y) = ((index % SIZE) as isize, (index / SIZE) as isize);
+        let size = SIZE as isize;
+        let mut k = 0;
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let nx = (x + dx).rem_euclid(size) as usize;
+                let ny = (y + dy).rem_euclid(size) as usize;
+                self.values[ny * SIZE + nx] += sign * self.kernel[k];
+                k += 1;
+            }
+        }
+    }
+
+    // The point of the pattern in the most crowded spot.
+    fn tightest_cluster(&self, pattern: &[bool]) -> usize {
+        self.extreme(pattern, true, |a, b| a > b)
+    }
+
+    // The empty pixel furthest from any point.
+    fn largest_void(&self, pattern: &[bool]) -> usize {
+        self.extreme(pattern, false, |a, b| a < b)
+    }
+
+    fn extreme(&self, pattern: &[bool], set: bool, better: impl Fn(Float, Float) -> bool) -> usize {
+        let mut best = None;
+        for (i, e) in self.values.iter().enumerate() {
+            if pattern[i] == set && best.is_none_or(|b: usize| better(*e, self.values[b])) {
+                best = Some(i);
+            }
+        }
+        best.unwrap()
+    }
+}
+
+// Ulichney's void-and-cluster method: start from a few random points, even
+// them out, then rank every pixel by the order in which it is removed from
+// the clusters or added to the voids.
+fn void_and_cluster() -> Vec<usize> {
+    let n = SIZE * SIZE;
+    let initial = n / 10;
+    let mut pixels: Vec<usize> = (0..n).collect();
+    pixels.shuffle(&mut StdRng::seed_from_u64(0));
+
+    let mut pattern = vec![false; n];
+    let mut energy = Energy::new();
+    for &i in &pixels[..initial] {
+        pattern[i] = true;
+        energy.splat(i, 1.0);
+    }
+    // Move points from the tightest cluster to the largest void until that
+    // would put a point straight back.
+    loop {
+        let cluster = energy.tightest_cluster(&pattern);
+        pattern[cluster] = false;
+        energy.splat(cluster, -1.0);
+        let void = energy.largest_void(&pattern);
+        pattern[void] = true;
+        energy.splat(void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; n];
+    // The initial points are ranked by taking them away again...
+    let prototype = pattern.clone();
+    let prototype_energy = energy.values.clone();
+    for rank in (0..initial).rev() {
+        let cluster = energy.tightest_cluster(&pattern);
+        pattern[cluster] = false;
+        energy.splat(cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+    // ...and the rest by filling in the voids left between them.
+    pattern = prototype;
+    energy.values = prototype_energy;
+    for rank in initial..n {
+        let void = energy.largest_void(&pattern);
+        pattern[void] = true;
+        energy.splat(void, 1.0);
+        ranks[void] = rank;
+    }
+    ranks
+}
+
+#[test]
+fn test_blue_noise_mask() {
+    // Every value appears exactly once.
+    let mut seen = vec![false; SIZE * SIZE];
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let v = mask_value(x, y);
+            assert!((0.0..1.0).contains(&v));
+            seen[(v * (SIZE * SIZE) as Float) as usize] = true;
+        }
+    }
+    assert!(seen.iter().all(|s| *s));
+    assert_eq!(mask_value(3, 5), mask_value(3 + SIZE, 5 + 2 * SIZE));
+
+    // The darkest tenth of the pixels are spread out, none of them touching
+    // another; with white noise about 160 pairs would.
+    assert_eq!(touching_pairs(mask_value, SIZE, 0.1), 0);
+}
+
+// For pixels of a `size` x `size` area whose `value` is below `threshold`,
+// the number of pairs that are next to each other.
+#[cfg(test)]
+pub(crate) fn touching_pairs(
+    value: impl Fn(usize, usize) -> Float,
+    size: usize,
+    threshold: Float,
+) -> usize {
+    let mut pairs = 0;
+    for y in 0..size - 1 {
+        for x in 1..size - 1 {
+            if value(x, y) < threshold {
+                for (nx, ny) in [(x + 1, y), (x - 1, y + 1), (x, y + 1), (x + 1, y + 1)] {
+                    if value(nx, ny) < threshold {
+                        pairs += 1;
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
@@ -0,0 +1,659 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::debug;
+
+use crate::aabb::Aabb;
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::packet::RayPacket;
+use crate::packet::PACKET_SIZE;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::stats;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use crate::moving_sphere::MovingSphere;
+#[cfg(test)]
+use crate::point3d::Point3D;
+#[cfg(test)]
+use crate::sphere::Sphere;
+#[cfg(test)]
+use crate::triangle::Triangle;
+
+const MAX_LEAF_SIZE: usize = 2;
+// Nodes this deep are leaves, however many objects they hold, so that the
+// nodes still to visit fit on a stack of a fixed size.
+const MAX_DEPTH: usize = 64;
+// The surface area heuristic may stop splitting earlier than this, when
+// testing a few objects costs less than another level.
+const MAX_SAH_LEAF_SIZE: usize = 4;
+// How many buckets the surface area heuristic sorts centroids into along
+// each axis, and its relative costs of visiting a node and testing an
+// object.
+const SAH_BINS: usize = 12;
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECTION_COST: Float = 1.5;
+
+// How a hierarchy chooses where to split.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BvhQuality {
+    // Halfway through the objects along the longest axis: the quickest to
+    // build.
+    Median,
+    // Where the surface area heuristic expects the fewest tests per ray,
+    // scored over a few buckets along each axis. Slower to build, quicker to
+    // trace, particularly through large meshes.
+    #[default]
+    Sah,
+}
+
+impl BvhQuality {
+    pub fn is_sah(&self) -> bool {
+        *self == BvhQuality::Sah
+    }
+}
+
+impl FromStr for BvhQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BvhQuality, String> {
+        match s.to_lowercase().as_str() {
+            "median" => Ok(BvhQuality::Median),
+            "sah" => Ok(BvhQuality::Sah),
+            _ => Err(format!("unknown BVH quality: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    // For a leaf this is the index of its first object and `count` is the
+    // number of objects. For an interior node the left child immediately
+    // follows this node, `first` is the index of the right child and `count`
+    // is zero.
+    first: usize,
+    count: usize,
+    axis: usize,
+}
+
+// The nodes still to visit on the way down: at most one per level, plus the
+// one being visited, without allocating for each ray.
+struct Stack {
+    entries: [usize; MAX_DEPTH + 1],
+    len: usize,
+}
+
+impl Stack {
+    fn new(root: usize) -> Stack {
+        let mut stack = Stack {
+            entries: [0; MAX_DEPTH + 1],
+            len: 0,
+        };
+        stack.push(root);
+        stack
+    }
+
+    fn push(&mut self, index: usize) {
+        self.entries[self.len] = index;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.entries[self.len])
+    }
+}
+
+// A bounding volume hierarchy over any list of hittables. Objects without a
+// bounding box (e.g. infinite planes) are kept aside and tested on every ray.
+#[derive(Debug, Clone)]
+pub struct Bvh<T> {
+    nodes: Vec<BvhNode>,
+    objects: Vec<T>,
+    unbounded: Vec<T>,
+    // Where each object was in the list the hierarchy was built from.
+    ids: Vec<usize>,
+    unbounded_ids: Vec<usize>,
+}
+
+impl<T: Hittable> Bvh<T> {
+    // A hierarchy for rays sent at times in [0, 1), the default shutter.
+    pub fn new(objects: Vec<T>) -> Bvh<T> {
+        Bvh::for_shutter(objects, 0.0, 1.0)
+    }
+
+    // A hierarchy for rays sent at times in [t0, t1]: moving objects are
+    // bounded only where they go in that time.
+    pub fn for_shutter(objects: Vec<T>, t0: Float, t1: Float) -> Bvh<T> {
+        Bvh::with_quality(objects, t0, t1, BvhQuality::default())
+    }
+
+    // As for_shutter, split the given way.
+    pub fn with_quality(objects: Vec<T>, t0: Float, t1: Float, quality: BvhQuality) -> Bvh<T> {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (id, object) in objects.into_iter().enumerate() {
+            match object.bounding_box(t0, t1) {
+                Some(bounds) => bounded.push(((object, id), bounds)),
+                None => unbounded.push((object, id)),
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if !bounded.is_empty() {
+            build(&mut nodes, &mut bounded, 0, quality, 0);
+        }
+        debug!(
+            "{:?} BVH over {} objects ({} unbounded): {} nodes, {} leaves",
+            quality,
+            bounded.len(),
+            unbounded.len(),
+            nodes.len(),
+            nodes.iter().filter(|node| node.count > 0).count()
+        );
+        let (objects, ids) = bounded.into_iter().map(|(object, _)| object).unzip();
+        let (unbounded, unbounded_ids) = unbounded.into_iter().unzip();
+        Bvh {
+            nodes,
+            objects,
+            unbounded,
+            ids,
+            unbounded_ids,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The closest hit, along with the position of the object hit in the list
+    // the hierarchy was built from.
+    pub fn hit_with_id(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+        for (object, id) in self.unbounded.iter().zip(&self.unbounded_ids) {
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_record = Some((*id, hit));
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return hit_record;
+        }
+        let mut stack = Stack::new(0);
+        let mut visited = 0;
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            visited += 1;
+            if !node.bounds.hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+            if node.count > 0 {
+                let range = node.first..node.first + node.count;
+                for (object, id) in self.objects[range.clone()].iter().zip(&self.ids[range]) {
+                    if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                        closest_so_far = hit.t;
+                        hit_record = Some((*id, hit));
+                    }
+                }
+            } else if ray.direction[node.axis] < 0.0 {
+                // Visit the child nearer to the ray origin first so that
+                // closest_so_far shrinks as early as possible.
+                stack.push(index + 1);
+                stack.push(node.first);
+            } else {
+                stack.push(node.first);
+                stack.push(index + 1);
+            }
+        }
+        stats::count(|c| c.nodes_visited += visited);
+        hit_record
+    }
+
+    // As hit_with_id for each ray of the packet, up to its own t_max, going
+    // down the hierarchy once for all of them: a node is visited while any
+    // ray still reaches it, and its objects are tested against just those
+    // rays.
+    pub fn hit_packet(
+        &self,
+        packet: &RayPacket,
+        t_min: Float,
+        t_max: &[Float; PACKET_SIZE],
+    ) -> [Option<(usize, HitRecord<'_>)>; PACKET_SIZE] {
+        let mut closest_so_far = *t_max;
+        let mut hit_records: [Option<(usize, HitRecord<'_>)>; PACKET_SIZE] = Default::default();
+        for (lane, ray) in packet.rays.iter().enumerate() {
+            for (object, id) in self.unbounded.iter().zip(&self.unbounded_ids) {
+                if let Some(hit) = object.hit(ray, t_min, closest_so_far[lane]) {
+                    closest_so_far[lane] = hit.t;
+                    hit_records[lane] = Some((*id, hit));
+                }
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return hit_records;
+        }
+        let mut stack = Stack::new(0);
+        let mut visited = 0;
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            visited += 1;
+            let active = packet.hit(&node.bounds, t_min, &closest_so_far);
+            let first_active = match active.iter().position(|a| *a) {
+                Some(lane) => lane,
+                None => continue,
+            };
+            if node.count > 0 {
+                let range = node.first..node.first + node.count;
+                for lane in (0..PACKET_SIZE).filter(|lane| active[*lane]) {
+                    let ray = &packet.rays[lane];
+                    for (object, id) in self.objects[range.clone()]
+                        .iter()
+                        .zip(&self.ids[range.clone()])
+                    {
+                        if let Some(hit) = object.hit(ray, t_min, closest_so_far[lane]) {
+                            closest_so_far[lane] = hit.t;
+                            hit_records[lane] = Some((*id, hit));
+                        }
+                    }
+                }
+            } else if packet.rays[first_active].direction[node.axis] < 0.0 {
+                // The near child first, as the first ray still going sees it.
+                stack.push(index + 1);
+                stack.push(node.first);
+            } else {
+                stack.push(node.first);
+                stack.push(index + 1);
+            }
+        }
+        stats::count(|c| c.nodes_visited += visited);
+        hit_records
+    }
+
+    // The bounded objects, in the order the leaves refer to them.
+    pub(crate) fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    // The nodes as flat records for code that can't use the Rust types (a
+    // shader): min x, y, z, first, max x, y, z, count. Interior nodes, which
+    // have no objects, store their split axis as -(axis + 1) in place of the
+    // count.
+    pub(crate) fn packed_nodes(&self) -> Vec<[f32; 8]> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                let count = if node.count > 0 {
+                    node.count as f32
+                } else {
+                    -(node.axis as f32 + 1.0)
+                };
+                [
+                    to_f32(node.bounds.min.x()),
+                    to_f32(node.bounds.min.y()),
+                    to_f32(node.bounds.min.z()),
+                    node.first as f32,
+                    to_f32(node.bounds.max.x()),
+                    to_f32(node.bounds.max.y()),
+                    to_f32(node.bounds.max.z()),
+                    count,
+                ]
+            })
+            .collect()
+    }
+}
+
+// Recursively splits `objects`, `depth` levels down, as `quality` says,
+// appending nodes in depth-first order.
+fn build<T>(
+    nodes: &mut Vec<BvhNode>,
+    objects: &mut [(T, Aabb)],
+    offset: usize,
+    quality: BvhQuality,
+    depth: usize,
+) -> usize {
+    let bounds = objects
+        .iter()
+        .skip(1)
+        .fold(objects[0].1, |b, (_, o)| Aabb::surrounding_box(&b, o));
+    let index = nodes.len();
+    nodes.push(BvhNode {
+        bounds,
+        first: offset,
+        count: objects.len(),
+        axis: 0,
+    });
+    if objects.len() <= MAX_LEAF_SIZE || depth == MAX_DEPTH {
+        return index;
+    }
+
+    let centroids = objects.iter().skip(1).fold(
+        Aabb::new(objects[0].1.centroid(), objects[0].1.centroid()),
+        |b, (_, o)| Aabb::surrounding_box(&b, &Aabb::new(o.centroid(), o.centroid())),
+    );
+    let sah = match quality {
+        BvhQuality::Sah => sah_split(objects, &bounds, &centroids),
+        BvhQuality::Median => None,
+    };
+    let (axis, mid) = match sah {
+        Some(Split::Leaf) => return index,
+        Some(Split::At(axis, mid)) => (axis, mid),
+        // At the median centroid along the longest axis of their centroid
+        // bounds.
+        None => {
+            let axis = centroids.longest_axis();
+            let mid = objects.len() / 2;
+            objects.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+                let ca = a.axis_min(axis) + a.axis_max(axis);
+                let cb = b.axis_min(axis) + b.axis_max(axis);
+                ca.total_cmp(&cb)
+            });
+            (axis, mid)
+        }
+    };
+
+    let (left, right) = objects.split_at_mut(mid);
+    build(nodes, left, offset, quality, depth + 1);
+    let right_index = build(nodes, right, offset + mid, quality, depth + 1);
+    nodes[index].first = right_index;
+    nodes[index].count = 0;
+    nodes[index].axis = axis;
+    index
+}
+
+enum Split {
+    Leaf,
+    // Along the axis, with the objects reordered so that the first `mid` go
+    // on the left.
+    At(usize, usize),
+}
+
+// The split the surface area heuristic scores best: the expected cost of a
+// ray through the node is one traversal plus the intersections of each side,
+// weighted by the chance of the ray passing through its box. None if the
+// centroids all coincide, leaving nothing to choose between.
+fn sah_split<T>(objects: &mut [(T, Aabb)], bounds: &Aabb, centroids: &Aabb) -> Option<Split> {
+    let bin = |b: &Aabb, axis: usize| {
+        let low = centroids.axis_min(axis);
+        let extent = centroids.axis_max(axis) - low;
+        let c = (b.axis_min(axis) + b.axis_max(axis)) / 2.0;
+        (((c - low) / extent * SAH_BINS as Float) as usize).min(SAH_BINS - 1)
+    };
+    let mut best: Option<(Float, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroids.axis_max(axis) - centroids.axis_min(axis) <= 0.0 {
+            continue;
+        }
+        let mut counts = [0; SAH_BINS];
+        let mut boxes: [Option<Aabb>; SAH_BINS] = [None; SAH_BINS];
+        for (_, b) in objects.iter() {
+            let i = bin(b, axis);
+            counts[i] += 1;
+            boxes[i] = Some(boxes[i].map_or(*b, |a| Aabb::surrounding_box(&a, b)));
+        }
+        // The area and count right of each boundary, then swept from the
+        // left to score each.
+        let mut right = [(0.0, 0); SAH_BINS];
+        let mut bounds_right: Option<Aabb> = None;
+        let mut on_right = 0;
+        for i in (1..SAH_BINS).rev() {
+            bounds_right = merge(bounds_right, boxes[i]);
+            on_right += counts[i];
+            right[i] = (bounds_right.map_or(0.0, |b| b.surface_area()), on_right);
+        }
+        let mut bounds_left: Option<Aabb> = None;
+        let mut on_left = 0;
+        for i in 1..SAH_BINS {
+            bounds_left = merge(bounds_left, boxes[i - 1]);
+            on_left += counts[i - 1];
+            let (area_right, on_right) = right[i];
+            if on_left == 0 || on_right == 0 {
+                continue;
+            }
+            let area_left = bounds_left.map_or(0.0, |b| b.surface_area());
+            let cost = area_left * on_left as Float + area_right * on_right as Float;
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, i));
+            }
+        }
+    }
+
+    let (cost, axis, boundary) = best?;
+    let cost = TRAVERSAL_COST + INTERSECTION_COST * cost / bounds.surface_area().max(1e-12);
+    if objects.len() <= MAX_SAH_LEAF_SIZE && INTERSECTION_COST * objects.len() as Float <= cost {
+        return Some(Split::Leaf);
+    }
+    let mut mid = 0;
+    for i in 0..objects.len() {
+        if bin(&objects[i].1, axis) < boundary {
+            objects.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some(Split::At(axis, mid))
+}
+
+fn merge(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Aabb::surrounding_box(&a, &b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl<T: Hittable> Hittable for Bvh<T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hit_with_id(ray, t_min, t_max).map(|(_, hit)| hit)
+    }
+
+    // The box of the shutter the hierarchy was built for.
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        if !self.unbounded.is_empty() || self.nodes.is_empty() {
+            return None;
+        }
+        Some(self.nodes[0].bounds)
+    }
+}
+
+#[test]
+fn test_bvh_matches_linear_scan() {
+    let mut spheres = Vec::new();
+    for i in 0..10 {
+        for j in 0..10 {
+            spheres.push(Sphere::new(
+                Point3D::new(i as Float - 5.0, j as Float - 5.0, -(i + j) as Float),
+                0.4,
+                Material::Glass(Glass::new(1.5)),
+            ));
+        }
+    }
+    let bvh = Bvh::new(spheres.clone());
+    assert_eq!(bvh.len(), 100);
+
+    for _ in 0..200 {
+        let ray = Ray::new(
+            Point3D::new(0.0, 0.0, 10.0),
+            Point3D::random(-1.0, 1.0) - Point3D::new(0.0, 0.0, 1.0),
+        );
+        let expected = spheres
+            .iter()
+            .filter_map(|s| s.hit(&ray, 0.001, Float::MAX))
+            .map(|h| h.t)
+            .fold(Float::INFINITY, Float::min);
+        let actual = bvh
+            .hit(&ray, 0.001, Float::MAX)
+            .map_or(Float::INFINITY, |h| h.t);
+        assert_eq!(expected, actual);
+        // The id is the hit sphere's place in the original list.
+        if let Some((id, hit)) = bvh.hit_with_id(&ray, 0.001, Float::MAX) {
+            assert_eq!(spheres[id].hit(&ray, 0.001, Float::MAX).unwrap().t, hit.t);
+        }
+    }
+
+    // Packets of rays fanning out from one point, as camera rays do.
+    for _ in 0..50 {
+        let rays = [(); PACKET_SIZE].map(|_| {
+            Ray::new(
+                Point3D::new(0.0, 0.0, 10.0),
+                Point3D::random(-0.5, 0.5) - Point3D::new(0.0, 0.0, 1.0),
+            )
+        });
+        let t_max = [Float::MAX, 9.0, 10.0, 11.0];
+        let hits = bvh.hit_packet(&RayPacket::new(rays), 0.001, &t_max);
+        for ((ray, hit), t_max) in rays.iter().zip(hits).zip(t_max) {
+            let expected = bvh.hit_with_id(ray, 0.001, t_max);
+            assert_eq!(
+                expected.map(|(id, h)| (id, h.t)),
+                hit.map(|(id, h)| (id, h.t))
+            );
+        }
+    }
+}
+
+#[test]
+fn test_sah_bvh() {
+    // A mesh with most of its triangles bunched up at one end of a long
+    // strip, as a detailed model standing on a big floor is. Splitting at
+    // the median cuts through the bunch and leaves boxes stretched along
+    // the strip, which SAH avoids, so rays visit fewer nodes.
+    let mut triangles = Vec::new();
+    for i in 0..400 {
+        let x = if i < 360 {
+            i as Float * 0.01
+        } else {
+            (i - 360) as Float * 5.0
+        };
+        let v0 = Point3D::new(x, 0.0, 0.0);
+        triangles.push(Triangle::new(
+            v0,
+            v0 + Point3D::new(0.008, 0.0, 0.0),
+            v0 + Point3D::new(0.0, 0.008, 0.0),
+            Material::Glass(Glass::new(1.5)),
+        ));
+    }
+    let visited = |quality| {
+        let bvh = Bvh::with_quality(triangles.clone(), 0.0, 1.0, quality);
+        stats::take();
+        let mut hits = Vec::new();
+        for i in 0..1000 {
+            let x = i as Float * 0.2 + 0.001;
+            let ray = Ray::new(Point3D::new(x, 0.001, 1.0), Point3D::new(0.0, 0.0, -1.0));
+            hits.push(bvh.hit(&ray, 0.001, Float::MAX).map(|h| h.t));
+        }
+        (stats::take().nodes_visited, hits)
+    };
+    let (sah, sah_hits) = visited(BvhQuality::Sah);
+    let (median, median_hits) = visited(BvhQuality::Median);
+    assert_eq!(sah_hits, median_hits);
+    assert!(sah < median, "SAH visited {} nodes, median {}", sah, median);
+}
+
+#[test]
+fn test_empty_bvh() {
+    let bvh: Bvh<Sphere> = Bvh::new(Vec::new());
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(bvh.is_empty());
+    assert!(bvh.hit(&ray, 0.001, Float::MAX).is_none());
+    assert!(bvh.bounding_box(0.0, 1.0).is_none());
+}
+
+#[test]
+fn test_bvh_for_shutter() {
+    // A ball that shoots off along x over the second, and one that stays put.
+    let ball = |from: Point3D, to: Point3D| {
+        MovingSphere::new(from, to, 0.0, 1.0, 0.5, Material::Glass(Glass::new(1.5)))
+    };
+    let still = Point3D::new(0.0, 3.0, 0.0);
+    let balls = || {
+        vec![
+            ball(Point3D::new(0.0, 0.0, 0.0), Point3D::new(10.0, 0.0, 0.0)),
+            ball(still, still),
+        ]
+    };
+    let whole = Bvh::new(balls());
+    assert_eq!(whole.bounding_box(0.0, 1.0).unwrap().max.x(), 10.5);
+    // With the shutter open for a tenth of the second, the ball is only
+    // bounded as far as it gets by then, and still found in that time.
+    let brief = Bvh::for_shutter(balls(), 0.0, 0.1);
+    let bounds = brief.bounding_box(0.0, 0.1).unwrap();
+    assert_eq!(bounds.max.x(), 1.5);
+    assert_eq!(bounds.max.y(), 3.5);
+    let down = Point3D::new(0.0, 0.0, -1.0);
+    let during = Ray::with_time(Point3D::new(0.5, 0.0, 5.0), down, 0.05);
+    assert_eq!(brief.hit(&during, 0.001, Float::MAX).unwrap().t, 4.5);
+    // Long after the shutter closed it would be somewhere the hierarchy
+    // doesn't look.
+    let after = Ray::with_time(Point3D::new(9.0, 0.0, 5.0), down, 0.9);
+    assert!(whole.hit(&after, 0.001, Float::MAX).is_some());
+    assert!(brief.hit(&after, 0.001, Float::MAX).is_none());
+}
+
+#[cfg(test)]
+fn depth<T>(bvh: &Bvh<T>, index: usize) -> usize {
+    let node = &bvh.nodes[index];
+    if node.count > 0 {
+        0
+    } else {
+        1 + depth(bvh, index + 1).max(depth(bvh, node.first))
+    }
+}
+
+#[test]
+fn test_deep_bvh() {
+    // Spread ever further apart, every split peels off only the furthest
+    // few, so that a hierarchy over them would be 32 deep. Started a few
+    // levels short of the deepest, splitting stops there.
+    let spheres: Vec<Sphere> = (0..140)
+        .map(|i| {
+            let x = Float::powi(1.8, i);
+            Sphere::new(
+                Point3D::new(x, 0.0, 0.0),
+                0.1,
+                Material::Glass(Glass::new(1.5)),
+            )
+        })
+        .collect();
+    let mut bounded: Vec<_> = spheres
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(id, sphere)| {
+            let bounds = sphere.bounding_box(0.0, 1.0).unwrap();
+            ((sphere, id), bounds)
+        })
+        .collect();
+    let mut nodes = Vec::new();
+    build(&mut nodes, &mut bounded, 0, BvhQuality::Sah, MAX_DEPTH - 10);
+    let (objects, ids) = bounded.into_iter().map(|(object, _)| object).unzip();
+    let bvh = Bvh {
+        nodes,
+        objects,
+        unbounded: Vec::new(),
+        ids,
+        unbounded_ids: Vec::new(),
+    };
+    assert_eq!(depth(&bvh, 0), 10);
+    assert_eq!(depth(&Bvh::new(spheres.clone()), 0), 32);
+    for (i, sphere) in spheres.iter().enumerate() {
+        let above = sphere.center + Point3D::new(0.0, 1.0, 0.0);
+        let ray = Ray::new(above, Point3D::new(0.0, -1.0, 0.0));
+        let (id, _) = bvh.hit_with_id(&ray, 0.001, Float::MAX).unwrap();
+        assert_eq!(id, i);
+    }
+}
@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use crate::float::consts::PI;
+use crate::float::Float;
 use crate::point3d::Point3D;
 use crate::ray::Ray;
+use crate::sampler;
 
+#[cfg(test)]
+use crate::float::TOLERANCE;
 #[cfg(test)]
 use assert_approx_eq::assert_approx_eq;
 
@@ -14,16 +19,36 @@ pub struct Camera {
     #[serde(skip_serializing)]
     pub lower_left_corner: Point3D,
     #[serde(skip_serializing)]
-    pub focal_length: f64,
+    pub focal_length: Float,
     #[serde(skip_serializing)]
     pub horizontal: Point3D,
     #[serde(skip_serializing)]
     pub vertical: Point3D,
+    #[serde(skip_serializing)]
+    u: Point3D,
+    #[serde(skip_serializing)]
+    v: Point3D,
+    #[serde(skip_serializing)]
+    lens_radius: Float,
     look_from: Point3D,
     look_at: Point3D,
     vup: Point3D,
-    vfov: f64, // vertical field-of-view in degrees
-    aspect: f64,
+    vfov: Float, // vertical field-of-view in degrees
+    aspect: Float,
+    aperture: Float,
+    focus_dist: Float, // distance from look_from to the plane in perfect focus
+    shutter_open: Float,
+    shutter_close: Float,
+    #[serde(skip_serializing_if = "Projection::is_perspective")]
+    projection: Projection,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stereo: Option<Stereo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    polarizer: Option<Float>,
+    // Which of the eyes of a stereo pair rendered to two files this is: -1
+    // for the left, 1 for the right.
+    #[serde(skip_serializing)]
+    eye: Float,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -31,13 +56,132 @@ pub struct CameraParams {
     pub look_from: Point3D,
     pub look_at: Point3D,
     pub vup: Point3D,
-    pub vfov: f64, // vertical field-of-view in degrees
-    pub aspect: f64,
+    pub vfov: Float, // vertical field-of-view in degrees
+    pub aspect: Float,
+    // A zero aperture is a pinhole camera with everything in focus.
+    #[serde(default)]
+    pub aperture: Float,
+    // Defaults to the distance between look_from and look_at.
+    #[serde(default)]
+    pub focus_dist: Option<Float>,
+    // Rays are sent at uniformly random times in [shutter_open, shutter_close).
+    #[serde(default)]
+    pub shutter_open: Float,
+    #[serde(default = "default_shutter_close")]
+    pub shutter_close: Float,
+    #[serde(default)]
+    pub projection: Projection,
+    #[serde(default)]
+    pub stereo: Option<Stereo>,
+    // A linear polarizing filter over the lens, turned this many degrees
+    // from letting through light polarized across the image. Only seen by
+    // the polarized integrator.
+    #[serde(default)]
+    pub polarizer: Option<Float>,
+}
+
+// How directions out of the camera are laid out over the image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    // A pinhole (or thin lens) camera: straight lines stay straight, and
+    // further away is smaller.
+    #[default]
+    Perspective,
+    // Parallel rays from a view `height` units tall (and `aspect` times as
+    // wide), so sizes don't depend on distance. vfov and the lens are unused.
+    Orthographic {
+        height: Float,
+    },
+    // A fisheye lens seeing `fov` degrees, which may be 180 or more, from
+    // the top of the image to the bottom; the corners see further still.
+    // vfov and the lens are unused.
+    Fisheye {
+        fov: Float,
+        #[serde(default)]
+        mapping: FisheyeMapping,
+    },
+    // Every direction around the camera, longitude across the image and
+    // latitude up it, as in an equirectangular environment map: the
+    // centre looks at look_at and the left and right edges behind. The
+    // image should be twice as wide as it is tall. vfov and the lens are
+    // unused.
+    Equirectangular,
+}
+
+// How the angle away from the centre of a fisheye's view grows with the
+// distance from the centre of the image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum FisheyeMapping {
+    // In proportion: equal angles take equal room.
+    #[default]
+    Equidistant,
+    // Equal solid angles take equal areas, squeezing the edges more.
+    Equisolid,
+}
+
+impl FisheyeMapping {
+    // The angle seen `r` from the centre of the image, where 1 is the top
+    // edge and sees half of `fov` (all in radians), up to straight behind.
+    fn angle(&self, r: Float, fov: Float) -> Float {
+        let angle = match self {
+            FisheyeMapping::Equidistant => r * fov / 2.0,
+            FisheyeMapping::Equisolid => 2.0 * (r * (fov / 4.0).sin()).min(1.0).asin(),
+        };
+        angle.min(PI)
+    }
+}
+
+// A pair of eyes `interocular` apart, either side of the camera, for 3D
+// viewing. Things `convergence` away (by default, the focus distance) are
+// seen in the same place by both.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stereo {
+    pub interocular: Float,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convergence: Option<Float>,
+    #[serde(default)]
+    pub layout: StereoLayout,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum StereoLayout {
+    // The left eye's view in the left half of the image and the right's in
+    // the right half. Each gets half the image's width, and `aspect` is
+    // that of one eye.
+    #[default]
+    SideBySide,
+    // Each eye's view in an image of its own.
+    Files,
+}
+
+impl Projection {
+    fn is_perspective(&self) -> bool {
+        *self == Projection::Perspective
+    }
+}
+
+fn default_shutter_close() -> Float {
+    1.0
 }
 
 impl From<CameraParams> for Camera {
     fn from(p: CameraParams) -> Self {
-        Camera::new(p.look_from, p.look_at, p.vup, p.vfov, p.aspect)
+        let focus_dist = p
+            .focus_dist
+            .unwrap_or_else(|| (p.look_from - p.look_at).length());
+        Camera::new(
+            p.look_from,
+            p.look_at,
+            p.vup,
+            p.vfov,
+            p.aspect,
+            p.aperture,
+            focus_dist,
+        )
+        .with_shutter(p.shutter_open, p.shutter_close)
+        .with_projection(p.projection)
+        .with_stereo(p.stereo)
+        .with_polarizer(p.polarizer)
     }
 }
 
@@ -46,8 +190,10 @@ impl Camera {
         look_from: Point3D,
         look_at: Point3D,
         vup: Point3D,
-        vfov: f64, // vertical field-of-view in degrees
-        aspect: f64,
+        vfov: Float, // vertical field-of-view in degrees
+        aspect: Float,
+        aperture: Float,
+        focus_dist: Float,
     ) -> Camera {
         let theta = vfov.to_radians();
         let half_height = (theta / 2.0).tan();
@@ -57,10 +203,12 @@ impl Camera {
         let u = vup.cross(&w).unit_vector();
         let v = w.cross(&u);
 
+        // The image plane sits at the focus distance, so rays leaving any
+        // point of the lens converge there.
         let origin = look_from;
-        let lower_left_corner = origin - (u * half_width) - (v * half_height) - w;
-        let horizontal = u * 2.0 * half_width;
-        let vertical = v * 2.0 * half_height;
+        let horizontal = u * 2.0 * half_width * focus_dist;
+        let vertical = v * 2.0 * half_height * focus_dist;
+        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - w * focus_dist;
 
         Camera {
             origin,
@@ -68,19 +216,238 @@ impl Camera {
             focal_length: (look_from - look_at).length(),
             horizontal,
             vertical,
+            u,
+            v,
+            lens_radius: aperture / 2.0,
             look_from,
             look_at,
             vup,
             vfov,
             aspect,
+            aperture,
+            focus_dist,
+            shutter_open: 0.0,
+            shutter_close: default_shutter_close(),
+            projection: Projection::Perspective,
+            stereo: None,
+            polarizer: None,
+            eye: 0.0,
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        Ray::new(
-            self.origin,
-            self.lower_left_corner + (self.horizontal * u) + (self.vertical * v) - self.origin,
+    // Sets the interval during which the shutter is open. The default is
+    // [0, 1), the time span moving objects are usually described over.
+    pub fn with_shutter(mut self, open: Float, close: Float) -> Camera {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    // When the shutter opens and closes.
+    pub fn shutter(&self) -> (Float, Float) {
+        (self.shutter_open, self.shutter_close)
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Camera {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_stereo(mut self, stereo: Option<Stereo>) -> Camera {
+        self.stereo = stereo;
+        self
+    }
+
+    pub fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
+
+    pub fn with_polarizer(mut self, polarizer: Option<Float>) -> Camera {
+        self.polarizer = polarizer;
+        self
+    }
+
+    pub fn polarizer(&self) -> Option<Float> {
+        self.polarizer
+    }
+
+    // The way across the image, left to right.
+    pub fn right(&self) -> Point3D {
+        self.u
+    }
+
+    // The way up the image.
+    pub fn up(&self) -> Point3D {
+        self.v
+    }
+
+    pub fn lens_radius(&self) -> Float {
+        self.lens_radius
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    // The left and right eyes of a stereo camera laid out as
+    // StereoLayout::Files, each rendering its own image.
+    pub fn eyes(&self) -> Option<[Camera; 2]> {
+        let stereo = Stereo {
+            layout: StereoLayout::Files,
+            ..self.stereo?
+        };
+        Some([-1.0, 1.0].map(|eye| Camera {
+            stereo: Some(stereo),
+            eye,
+            ..*self
+        }))
+    }
+
+    // The camera moved `forward` along the way it looks and `right` and `up`
+    // across it, still looking the same way.
+    pub fn travel(&self, forward: Float, right: Float, up: Float) -> Camera {
+        let w = (self.look_from - self.look_at).unit_vector();
+        let offset = -w * forward + self.u * right + self.v * up;
+        self.looking(self.look_from + offset, self.look_at + offset)
+    }
+
+    // The camera swung around the point it looks at, `yaw` degrees around vup
+    // and `pitch` degrees up. It stops short of looking straight down or up.
+    pub fn orbit(&self, yaw: Float, pitch: Float) -> Camera {
+        let up = self.vup.unit_vector();
+        let offset = rotate(self.look_from - self.look_at, up, yaw.to_radians());
+        let pitched = rotate(offset, offset.cross(&up).unit_vector(), pitch.to_radians());
+        let offset = if pitched.unit_vector().dot(&up).abs() < 0.99 {
+            pitched
+        } else {
+            offset
+        };
+        self.looking(self.look_at + offset, self.look_at)
+    }
+
+    // The same camera, moved to `look_from` and looking at `look_at`.
+    fn looking(&self, look_from: Point3D, look_at: Point3D) -> Camera {
+        Camera::new(
+            look_from,
+            look_at,
+            self.vup,
+            self.vfov,
+            self.aspect,
+            self.aperture,
+            self.focus_dist,
         )
+        .with_shutter(self.shutter_open, self.shutter_close)
+        .with_projection(self.projection)
+        .with_stereo(self.stereo)
+        .with_polarizer(self.polarizer)
+    }
+
+    // Each ray is sent at a random time while the shutter is open.
+    pub fn get_ray(&self, s: Float, t: Float) -> Ray {
+        let stereo = match self.stereo {
+            Some(stereo) => stereo,
+            None => return self.eye_ray(s, t, Point3D::new(0.0, 0.0, 0.0), 1.0),
+        };
+        let (s, eye) = match stereo.layout {
+            StereoLayout::SideBySide if s < 0.5 => (2.0 * s, -1.0),
+            StereoLayout::SideBySide => (2.0 * s - 1.0, 1.0),
+            StereoLayout::Files => (s, self.eye),
+        };
+        let convergence = stereo.convergence.unwrap_or(self.focus_dist);
+        let offset = self.u * (eye * stereo.interocular / 2.0);
+        self.eye_ray(s, t, offset, self.focus_dist / convergence)
+    }
+
+    // A ray from an eye `eye` away from the camera's centre. The eye's view
+    // is shifted across rather than turned, so that the two eyes' images
+    // coincide at the distance `focus_dist / shift`, as they would on a
+    // screen there.
+    fn eye_ray(&self, s: Float, t: Float, eye: Point3D, shift: Float) -> Ray {
+        let origin = self.origin + eye;
+        match self.projection {
+            Projection::Perspective => {
+                let rd = Point3D::disk_from_square(sampler::next_2d()) * self.lens_radius;
+                let offset = self.u * rd.x() + self.v * rd.y();
+                Ray::with_time(
+                    origin + offset,
+                    self.lower_left_corner + (self.horizontal * s) + (self.vertical * t)
+                        - eye * shift
+                        - self.origin
+                        - offset,
+                    self.time(),
+                )
+            }
+            Projection::Orthographic { height } => {
+                let across =
+                    self.u * ((s - 0.5) * height * self.aspect) + self.v * ((t - 0.5) * height);
+                Ray::with_time(origin + across, -self.u.cross(&self.v), self.time())
+            }
+            Projection::Fisheye { fov, mapping } => {
+                let x = (2.0 * s - 1.0) * self.aspect;
+                let y = 2.0 * t - 1.0;
+                let theta = mapping.angle((x * x + y * y).sqrt(), fov.to_radians());
+                let phi = y.atan2(x);
+                let w = self.u.cross(&self.v);
+                let direction =
+                    (self.u * phi.cos() + self.v * phi.sin()) * theta.sin() - w * theta.cos();
+                Ray::with_time(origin, direction, self.time())
+            }
+            Projection::Equirectangular => {
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let polar = (1.0 - t) * PI;
+                let forward = -self.u.cross(&self.v);
+                let around = self.u * longitude.sin() + forward * longitude.cos();
+                let direction = around * polar.sin() + self.v * polar.cos();
+                Ray::with_time(origin, direction, self.time())
+            }
+        }
+    }
+
+    // Roughly how wide one of `height` rows of pixels is as it leaves the
+    // camera, and how much wider it gets a unit further away.
+    pub fn pixel_cone(&self, height: usize) -> (Float, Float) {
+        let rows = height.max(1) as Float;
+        match self.projection {
+            Projection::Perspective => (0.0, self.vertical.length() / self.focus_dist / rows),
+            Projection::Orthographic { height } => (height / rows, 0.0),
+            Projection::Fisheye { fov, .. } => (0.0, fov.to_radians() / rows),
+            Projection::Equirectangular => (0.0, PI / rows),
+        }
+    }
+
+    fn time(&self) -> Float {
+        self.shutter_open + sampler::next_1d() * (self.shutter_close - self.shutter_open)
+    }
+}
+
+// A move of the camera asked for while exploring the scene, e.g. by a key
+// pressed in the preview.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Navigation {
+    // Forward, right and up, in steps.
+    Travel(Float, Float, Float),
+    // Degrees around the point looked at, sideways and up.
+    Orbit(Float, Float),
+    Finish,
+    // Ctrl-C: stop without rendering, as the terminal no longer interrupts.
+    Cancel,
+}
+
+// How far one key press turns the camera, in degrees.
+const ORBIT_STEP: Float = 5.0;
+
+impl Navigation {
+    // The camera after this move. Each step is a tenth of the way to the
+    // point it looks at, so the speed suits the scene.
+    pub fn apply(&self, camera: &Camera) -> Camera {
+        let step = camera.focal_length / 10.0;
+        match *self {
+            Navigation::Travel(forward, right, up) => {
+                camera.travel(forward * step, right * step, up * step)
+            }
+            Navigation::Orbit(yaw, pitch) => camera.orbit(yaw * ORBIT_STEP, pitch * ORBIT_STEP),
+            Navigation::Finish | Navigation::Cancel => *camera,
+        }
     }
 }
 
@@ -91,7 +458,9 @@ fn test_camera() {
         Point3D::new(0.0, 0.0, -1.0),
         Point3D::new(0.0, 1.0, 0.0),
         90.0,
-        (800.0 / 600.0) as f64,
+        800.0 / 600.0,
+        0.0,
+        1.0,
     );
     assert_eq!(camera.origin.x(), 0.0);
     assert_eq!(camera.origin.y(), 0.0);
@@ -102,6 +471,40 @@ fn test_camera() {
     assert_approx_eq!(camera.lower_left_corner.z(), -1.0);
 }
 
+// `v` turned `angle` radians around the unit vector `axis` (Rodrigues).
+fn rotate(v: Point3D, axis: Point3D, angle: Float) -> Point3D {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis.cross(&v) * sin + axis * (axis.dot(&v) * (1.0 - cos))
+}
+
+#[test]
+fn test_camera_navigation() {
+    let camera = Camera::new(
+        Point3D::new(0.0, 0.0, 4.0),
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        90.0,
+        1.0,
+        0.0,
+        1.0,
+    );
+    let moved = camera.travel(1.0, 2.0, 0.5);
+    assert_approx_eq!(moved.origin.x(), 2.0);
+    assert_approx_eq!(moved.origin.y(), 0.5);
+    assert_approx_eq!(moved.origin.z(), 3.0);
+    assert_approx_eq!(moved.look_at.z(), -1.0);
+
+    // A quarter turn around the y axis keeps the distance to look_at.
+    let orbited = camera.orbit(90.0, 0.0);
+    assert_approx_eq!(orbited.origin.x(), 4.0);
+    assert_approx_eq!(orbited.origin.z(), 0.0);
+    let raised = camera.orbit(0.0, 30.0);
+    assert_approx_eq!(raised.origin.y(), 2.0);
+    assert_approx_eq!(raised.origin.length(), 4.0);
+    // Going over the top is not allowed.
+    assert_approx_eq!(camera.orbit(0.0, 90.0).origin.z(), 4.0);
+}
+
 #[test]
 fn test_camera_get_ray() {
     let camera = Camera::new(
@@ -109,7 +512,9 @@ fn test_camera_get_ray() {
         Point3D::new(0.0, 0.0, -1.0),
         Point3D::new(0.0, 1.0, 0.0),
         160.0,
-        (800 / 600) as f64,
+        (800 / 600) as Float,
+        0.0,
+        1.0,
     );
     let ray = camera.get_ray(0.5, 0.5);
     assert_eq!(ray.origin.x(), -4.0);
@@ -128,10 +533,12 @@ fn test_to_json() {
         Point3D::new(0.0, 0.0, -1.0),
         Point3D::new(0.0, 1.0, 0.0),
         160.0,
-        (800 / 600) as f64,
+        (800 / 600) as Float,
+        0.0,
+        1.0,
     );
     let serialized = serde_json::to_string(&camera).unwrap();
-    assert_eq!("{\"look_from\":{\"x\":-4.0,\"y\":4.0,\"z\":1.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":160.0,\"aspect\":1.0}", serialized);
+    assert_eq!("{\"look_from\":{\"x\":-4.0,\"y\":4.0,\"z\":1.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":160.0,\"aspect\":1.0,\"aperture\":0.0,\"focus_dist\":1.0,\"shutter_open\":0.0,\"shutter_close\":1.0}", serialized);
     let c = serde_json::from_str::<Camera>(&serialized).unwrap();
     assert_eq!(camera.origin, c.origin);
     assert_eq!(camera.lower_left_corner, c.lower_left_corner);
@@ -139,3 +546,214 @@ fn test_to_json() {
     assert_eq!(camera.horizontal, c.horizontal);
     assert_eq!(camera.vertical, c.vertical);
 }
+
+#[test]
+fn test_defocus_blur() {
+    let look_from = Point3D::new(0.0, 0.0, 0.0);
+    let look_at = Point3D::new(0.0, 0.0, -1.0);
+    let vup = Point3D::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(look_from, look_at, vup, 90.0, 1.0, 2.0, 4.0);
+    for _ in 0..100 {
+        let ray = camera.get_ray(0.5, 0.5);
+        // Origins spread over the lens, but every ray through the centre of
+        // the image converges on the focus plane.
+        assert!(ray.origin.distance(&look_from) <= 1.0);
+        assert_approx_eq!(ray.origin.z(), 0.0);
+        let focus = ray.at(1.0);
+        assert_approx_eq!(focus.x(), 0.0);
+        assert_approx_eq!(focus.y(), 0.0);
+        assert_approx_eq!(focus.z(), -4.0);
+    }
+
+    let json = r#"{"look_from":{"x":0.0,"y":0.0,"z":0.0},"look_at":{"x":0.0,"y":0.0,"z":-3.0},"vup":{"x":0.0,"y":1.0,"z":0.0},"vfov":90.0,"aspect":1.0}"#;
+    let c = serde_json::from_str::<Camera>(json).unwrap();
+    assert_eq!(c.aperture, 0.0);
+    assert_eq!(c.focus_dist, 3.0);
+    assert_eq!((c.shutter_open, c.shutter_close), (0.0, 1.0));
+}
+
+#[test]
+fn test_shutter() {
+    let camera = Camera::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        90.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+    .with_shutter(0.25, 0.5);
+    for _ in 0..100 {
+        let time = camera.get_ray(0.5, 0.5).time;
+        assert!((0.25..0.5).contains(&time));
+    }
+
+    // A closed interval freezes every ray at one instant.
+    let still = camera.with_shutter(2.0, 2.0);
+    assert_eq!(still.get_ray(0.1, 0.9).time, 2.0);
+}
+
+#[test]
+fn test_orthographic_camera() {
+    let json = r#"{"look_from":{"x":0.0,"y":0.0,"z":5.0},"look_at":{"x":0.0,"y":0.0,"z":0.0},"vup":{"x":0.0,"y":1.0,"z":0.0},"vfov":90.0,"aspect":2.0,"projection":{"Orthographic":{"height":4.0}}}"#;
+    let camera = serde_json::from_str::<Camera>(json).unwrap();
+    // The rays are parallel, and spread over 8 by 4 units.
+    for (s, t, x, y) in [
+        (0.5, 0.5, 0.0, 0.0),
+        (0.0, 0.0, -4.0, -2.0),
+        (1.0, 0.75, 4.0, 1.0),
+    ] {
+        let ray = camera.get_ray(s, t);
+        assert_approx_eq!(ray.origin.x(), x);
+        assert_approx_eq!(ray.origin.y(), y);
+        assert_approx_eq!(ray.origin.z(), 5.0);
+        assert_approx_eq!(ray.direction.unit_vector().z(), -1.0);
+    }
+    // Moving keeps the projection, which is saved with the camera.
+    let moved = camera.travel(1.0, 0.0, 0.0);
+    assert_approx_eq!(moved.get_ray(0.0, 0.0).origin.x(), -4.0);
+    assert!(serde_json::to_string(&moved)
+        .unwrap()
+        .contains("Orthographic"));
+}
+
+#[test]
+fn test_fisheye_camera() {
+    let fisheye = |mapping| {
+        Camera::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, -1.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            1.0,
+        )
+        .with_projection(Projection::Fisheye {
+            fov: 180.0,
+            mapping,
+        })
+    };
+    for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+        let camera = fisheye(mapping);
+        let centre = camera.get_ray(0.5, 0.5).direction;
+        assert_approx_eq!(centre.z(), -1.0);
+        // The top and bottom edges look 90 degrees away, straight up and
+        // down, and the sides further round, behind the camera.
+        let top = camera.get_ray(0.5, 1.0).direction;
+        assert_approx_eq!(top.y(), 1.0);
+        assert_approx_eq!(top.z(), 0.0);
+        assert!(camera.get_ray(0.0, 0.5).direction.z() > 0.0);
+    }
+    // Halfway up, equisolid has seen less far round, leaving more room for
+    // the edges of the view.
+    let equidistant = fisheye(FisheyeMapping::Equidistant).get_ray(0.5, 0.75);
+    let equisolid = fisheye(FisheyeMapping::Equisolid).get_ray(0.5, 0.75);
+    assert_approx_eq!(equidistant.direction.y().asin().to_degrees(), 45.0);
+    assert!(equisolid.direction.y() < equidistant.direction.y());
+
+    let json = r#"{"Fisheye":{"fov":200.0}}"#;
+    assert_eq!(
+        serde_json::from_str::<Projection>(json).unwrap(),
+        Projection::Fisheye {
+            fov: 200.0,
+            mapping: FisheyeMapping::Equidistant
+        }
+    );
+}
+
+#[test]
+fn test_equirectangular_camera() {
+    let camera = Camera::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        90.0,
+        2.0,
+        0.0,
+        1.0,
+    )
+    .with_projection(Projection::Equirectangular);
+    let direction = |s, t| camera.get_ray(s, t).direction;
+    assert_approx_eq!(direction(0.5, 0.5).z(), -1.0);
+    assert_approx_eq!(direction(0.75, 0.5).x(), 1.0);
+    assert_approx_eq!(direction(0.0, 0.5).z(), 1.0);
+    assert_approx_eq!(direction(0.3, 1.0).y(), 1.0);
+    assert_approx_eq!(direction(0.3, 0.0).y(), -1.0);
+    // Each pixel sees what an environment map made from the image holds
+    // there.
+    for (s, t) in [(0.1, 0.2), (0.6, 0.9), (0.95, 0.45)] {
+        let d = direction(s, t);
+        assert_approx_eq!(0.5 + d.x().atan2(-d.z()) / (2.0 * PI), s);
+        assert_approx_eq!(d.y().acos() / PI, 1.0 - t);
+    }
+}
+
+#[test]
+fn test_stereo_camera() {
+    let stereo = Stereo {
+        interocular: 0.5,
+        convergence: Some(4.0),
+        layout: StereoLayout::SideBySide,
+    };
+    let camera = Camera::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 0.0, -1.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        90.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+    .with_stereo(Some(stereo));
+    // The middle of each half of the image looks from its eye at the point
+    // the eyes converge on.
+    for (s, x) in [(0.25, -0.25), (0.75, 0.25)] {
+        let ray = camera.get_ray(s, 0.5);
+        assert_approx_eq!(ray.origin.x(), x);
+        let d = ray.direction;
+        let converged = ray.at(4.0 / -d.z());
+        assert_approx_eq!(converged.x(), 0.0);
+        assert_approx_eq!(converged.z(), -4.0);
+    }
+    // The frames match there too: the left edge of each eye's view.
+    let left = camera.get_ray(0.0, 0.5);
+    let right = camera.get_ray(0.5, 0.5);
+    assert_approx_eq!(left.at(4.0 / -left.direction.z()).x(), -4.0);
+    assert_approx_eq!(right.at(4.0 / -right.direction.z()).x(), -4.0);
+
+    // As two files, each eye has the whole image.
+    let [left, right] = camera.eyes().unwrap();
+    assert_approx_eq!(left.get_ray(0.5, 0.5).origin.x(), -0.25);
+    assert_approx_eq!(right.get_ray(0.0, 0.5).origin.x(), 0.25);
+    assert!(left.with_stereo(None).eyes().is_none());
+}
+
+#[test]
+fn test_camera_aims_at_look_at() {
+    let look_from = Point3D::new(3.0, 2.0, 5.0);
+    let look_at = Point3D::new(-1.0, 0.5, 0.0);
+    let camera = Camera::new(
+        look_from,
+        look_at,
+        Point3D::new(0.0, 1.0, 0.0),
+        40.0,
+        2.0,
+        0.0,
+        (look_from - look_at).length(),
+    );
+    // The centre of the image looks straight at look_at...
+    let centre = camera.get_ray(0.5, 0.5);
+    let hit = centre.at(1.0);
+    assert_approx_eq!(hit.x(), look_at.x());
+    assert_approx_eq!(hit.y(), look_at.y());
+    assert_approx_eq!(hit.z(), look_at.z());
+
+    // ...and the top edge of the frame is above the bottom edge in world space.
+    let top = camera.get_ray(0.5, 1.0).direction.unit_vector();
+    let bottom = camera.get_ray(0.5, 0.0).direction.unit_vector();
+    assert!(top.y() > bottom.y());
+    // The vertical field of view spans the requested angle.
+    assert_approx_eq!(top.dot(&bottom).acos().to_degrees(), 40.0, TOLERANCE);
+}
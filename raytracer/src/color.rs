@@ -0,0 +1,108 @@
+use palette::Srgb;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul};
+
+// Light, or the share of it a surface passes on, as linear red, green and
+// blue: what a path adds up and multiplies as it's traced. Scenes, materials
+// and textures give their colours as palette's Srgb, whose channels the
+// renderer reads as linear all the same (only the image written out is
+// encoded, see output::Encoding), so converting either way keeps the
+// numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
+
+    pub const fn new(red: f32, green: f32, blue: f32) -> Color {
+        Color { red, green, blue }
+    }
+
+    pub fn largest(&self) -> f32 {
+        self.red.max(self.green).max(self.blue)
+    }
+}
+
+impl From<Srgb> for Color {
+    fn from(c: Srgb) -> Color {
+        Color::new(c.red, c.green, c.blue)
+    }
+}
+
+impl From<Color> for Srgb {
+    fn from(c: Color) -> Srgb {
+        Srgb::new(c.red, c.green, c.blue)
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, other: Color) -> Color {
+        Color::new(
+            self.red + other.red,
+            self.green + other.green,
+            self.blue + other.blue,
+        )
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, other: Color) {
+        *self = *self + other;
+    }
+}
+
+// Light passing through a filter, or off a surface: each channel as much as
+// the other lets through.
+impl Mul for Color {
+    type Output = Color;
+
+    fn mul(self, other: Color) -> Color {
+        Color::new(
+            self.red * other.red,
+            self.green * other.green,
+            self.blue * other.blue,
+        )
+    }
+}
+
+impl Mul<f32> for Color {
+    type Output = Color;
+
+    fn mul(self, s: f32) -> Color {
+        Color::new(self.red * s, self.green * s, self.blue * s)
+    }
+}
+
+impl Div<f32> for Color {
+    type Output = Color;
+
+    fn div(self, d: f32) -> Color {
+        Color::new(self.red / d, self.green / d, self.blue / d)
+    }
+}
+
+impl Sum for Color {
+    fn sum<I: Iterator<Item = Color>>(iter: I) -> Color {
+        iter.fold(Color::BLACK, Add::add)
+    }
+}
+
+#[test]
+fn test_color() {
+    let red = Color::new(0.8, 0.1, 0.1);
+    let light = Color::new(2.0, 2.0, 2.0);
+    assert_eq!(red * light, Color::new(1.6, 0.2, 0.2));
+    assert_eq!((red + red) / 2.0, red);
+    assert_eq!([red, light].into_iter().sum::<Color>(), red + light);
+    assert_eq!((red * 10.0).largest(), 8.0);
+    let srgb: Srgb = red.into();
+    assert_eq!(srgb, Srgb::new(0.8, 0.1, 0.1));
+    assert_eq!(Color::from(srgb), red);
+}
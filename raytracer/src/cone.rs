@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::axial::AxialRay;
+use crate::axial::Closest;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Rays closer to parallel with the cone's side than this only cross it once.
+const PARALLEL_EPSILON: Float = 1e-12;
+
+// A cone with its tip at `apex`, widening along `axis` to a base `radius`
+// across, `height` away. The base is closed by a flat cap unless `cap` is
+// false.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "ConeParams")]
+pub struct Cone {
+    pub apex: Point3D,
+    pub axis: Point3D,
+    pub radius: Float,
+    pub height: Float,
+    #[serde(default = "default_cap")]
+    pub cap: bool,
+    pub material: Material,
+}
+
+// A cone as scenes give it: by the radius of its base, or the `angle` in
+// degrees between its side and its axis.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConeParams {
+    pub apex: Point3D,
+    pub axis: Point3D,
+    #[serde(default)]
+    pub radius: Option<Float>,
+    #[serde(default)]
+    pub angle: Option<Float>,
+    pub height: Float,
+    #[serde(default = "default_cap")]
+    pub cap: bool,
+    pub material: Material,
+}
+
+fn default_cap() -> bool {
+    true
+}
+
+impl TryFrom<ConeParams> for Cone {
+    type Error = String;
+
+    fn try_from(p: ConeParams) -> Result<Cone, String> {
+        let cone = match (p.radius, p.angle) {
+            (Some(radius), None) => Cone::new(p.apex, p.axis, radius, p.height, p.material),
+            (None, Some(angle)) => Cone::with_angle(p.apex, p.axis, angle, p.height, p.material),
+            _ => return Err("a cone needs either a radius or an angle".to_string()),
+        };
+        Ok(cone.with_cap(p.cap))
+    }
+}
+
+impl Cone {
+    pub fn new(
+        apex: Point3D,
+        axis: Point3D,
+        radius: Float,
+        height: Float,
+        material: Material,
+    ) -> Cone {
+        Cone {
+            apex,
+            axis: axis.unit_vector(),
+            radius,
+            height,
+            cap: true,
+            material,
+        }
+    }
+
+    // A cone whose side is `angle` degrees from its axis.
+    pub fn with_angle(
+        apex: Point3D,
+        axis: Point3D,
+        angle: Float,
+        height: Float,
+        material: Material,
+    ) -> Cone {
+        Cone::new(
+            apex,
+            axis,
+            height * angle.to_radians().tan(),
+            height,
+            material,
+        )
+    }
+
+    pub fn with_cap(mut self, cap: bool) -> Cone {
+        self.cap = cap;
+        self
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let axial = AxialRay::new(ray, self.apex, self.axis);
+        let (oc_along, d_along) = (axial.oc_along, axial.d_along);
+        let (oc_across, d_across) = (axial.oc_across, axial.d_across);
+        // How much wider the cone gets per unit along its axis.
+        let slope = self.radius / self.height;
+        let k2 = slope * slope;
+        let mut closest = Closest::new(t_min, t_max);
+
+        // The side, where the distance from the axis is `slope` times the
+        // distance along it, on the near side of the apex and short of the
+        // base. u goes around the axis and v from the apex to the base.
+        let a = d_across.length_squared() - k2 * d_along * d_along;
+        let half_b = oc_across.dot(&d_across) - k2 * oc_along * d_along;
+        let c = oc_across.length_squared() - k2 * oc_along * oc_along;
+        let mut roots = [Float::NAN; 2];
+        if a.abs() < PARALLEL_EPSILON * ray.direction.length_squared() {
+            if half_b != 0.0 {
+                roots[0] = -c / (2.0 * half_b);
+            }
+        } else {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                roots = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a];
+            }
+        }
+        // (NaN, for a root that isn't there, is never in range.)
+        for t in roots {
+            let (h, across) = axial.at(t);
+            if (0.0..=self.height).contains(&h) && across.length_squared() > 0.0 {
+                let radial = across.unit_vector();
+                let outward = (radial - axial.axis * slope).unit_vector();
+                closest.consider(t, outward, axial.around(radial), h / self.height);
+            }
+        }
+
+        // The cap, a disc across the axis at the base.
+        if self.cap {
+            if let Some((t, u, v)) = axial.cap(self.height, self.radius) {
+                closest.consider(t, axial.axis, u, v);
+            }
+        }
+
+        closest.record(ray, &self.material)
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // The base is a circle, reaching out along each world axis by the
+        // radius times the sine of that axis's angle with the cone's.
+        let axis = self.axis.unit_vector();
+        let reach = |a: Float| self.radius * (1.0 - a * a).max(0.0).sqrt();
+        let reach = Point3D::new(reach(axis.x()), reach(axis.y()), reach(axis.z()));
+        let base = self.apex + axis * self.height;
+        let (low, high) = (base - reach, base + reach);
+        let apex = self.apex;
+        Some(Aabb::new(
+            Point3D::new(
+                apex.x().min(low.x()),
+                apex.y().min(low.y()),
+                apex.z().min(low.z()),
+            ),
+            Point3D::new(
+                apex.x().max(high.x()),
+                apex.y().max(high.y()),
+                apex.z().max(high.z()),
+            ),
+        ))
+    }
+}
+
+#[test]
+fn test_cone_hit() {
+    let cone =    // Upside down, like a funnel: tip at the origin, 1 wide at y = 1.
+Cone::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        1.0,
+        1.0,
+        Material::grey(),
+    );
+    // Halfway up the side is half as wide, and the side is at 45 degrees.
+    let side = Ray::new(Point3D::new(-5.0, 0.5, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    let hit = cone.hit(&side, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 4.5);
+    assert!(hit.front_face);
+    assert_approx_eq!(hit.normal.x(), -(Float::sqrt(0.5)));
+    assert_approx_eq!(hit.normal.y(), -(Float::sqrt(0.5)));
+    assert_approx_eq!(hit.v, 0.5);
+    // From above onto the cap, or straight through it with no cap.
+    let down = Ray::new(Point3D::new(0.25, 3.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let hit = cone.hit(&down, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 2.0);
+    assert_eq!(hit.normal, Point3D::new(0.0, 1.0, 0.0));
+    let open = cone.clone().with_cap(false);
+    let hit = open.hit(&down, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 2.75);
+    assert!(!hit.front_face);
+    // The other nappe of the cone, below the apex, isn't there.
+    let below = Ray::new(Point3D::new(-5.0, -0.5, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    assert!(cone.hit(&below, 0.001, Float::MAX).is_none());
+    // A ray parallel to the side crosses it once.
+    let parallel = Ray::new(Point3D::new(-0.5, 0.0, 0.0), Point3D::new(1.0, 1.0, 0.0));
+    let hit = open.hit(&parallel, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), -0.25);
+}
+
+#[test]
+fn test_cone_with_angle() {
+    let material = Material::grey();
+    let apex = Point3D::new(1.0, 2.0, 3.0);
+    let cone = Cone::with_angle(apex, Point3D::new(0.0, 0.0, -2.0), 30.0, 3.0, material);
+    assert_approx_eq!(cone.radius, 3.0 * Float::to_radians(30.0).tan());
+    let bounds = cone.bounding_box(0.0, 1.0).unwrap();
+    assert_approx_eq!(bounds.axis_max(2), 3.0);
+    assert_approx_eq!(bounds.axis_min(2), 0.0);
+    assert_approx_eq!(bounds.axis_max(0), 1.0 + cone.radius);
+    let json = serde_json::to_string(&cone).unwrap();
+    assert!(serde_json::from_str::<Cone>(&json).unwrap().cap);
+
+    let json = r#"{"apex": {"x": 1.0, "y": 2.0, "z": 3.0}, "axis": {"x": 0.0, "y": 0.0, "z": -2.0},
+        "angle": 30.0, "height": 3.0, "cap": false, "material": {"Light": {}}}"#;
+    let loaded = serde_json::from_str::<Cone>(json).unwrap();
+    assert_approx_eq!(loaded.radius, cone.radius);
+    assert_approx_eq!(loaded.axis.z(), -1.0);
+    assert!(!loaded.cap);
+    let both = json.replace(r#""angle""#, r#""radius": 1.0, "angle""#);
+    assert!(serde_json::from_str::<Cone>(&both).is_err());
+}
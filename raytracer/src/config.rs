@@ -1,21 +1,33 @@
-use jpeg_decoder::Decoder;
-use palette::Srgb;
-use rand::Rng;
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
-use std::fs::File;
-use std::io::BufReader;
+use std::fs;
+use std::str::FromStr;
 
+use crate::bvh::Bvh;
+use crate::bvh::BvhQuality;
 use crate::camera::Camera;
-use crate::materials::Glass;
+use crate::environment::EnvironmentMap;
+use crate::gltf;
+use crate::grid::Grid;
+use crate::kdtree::KdTree;
+use crate::light::PointLight;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::registry::Registry;
+use crate::sampler::Adaptive;
+use crate::sampler::FireflyFilter;
+use crate::sampler::Sampler;
+use crate::texture;
+
+#[cfg(test)]
 use crate::materials::Lambertian;
+#[cfg(test)]
 use crate::materials::Material;
-use crate::materials::Metal;
-use crate::point3d::Point3D;
+#[cfg(test)]
+use crate::scenes::cover;
+#[cfg(test)]
 use crate::sphere::Sphere;
-
 #[cfg(test)]
-use std::fs;
+use palette::Srgb;
 
 #[serde_with::serde_as]
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,25 +37,24 @@ pub struct Sky {
     // a light blue colored sky will be used.
     #[serde_as(as = "TextureOptionPixelsAsPath")]
     pub texture: Option<(Vec<u8>, usize, usize, String)>,
+    // An equirectangular .hdr or .exr image looked up by ray direction.
+    // Takes precedence over `texture`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentMap>,
 }
 
 impl Sky {
     pub fn new_default_sky() -> Sky {
-        Sky { texture: None }
+        Sky {
+            texture: None,
+            environment: None,
+        }
     }
 }
 
-fn load_texture_image(path: &str) -> (Vec<u8>, usize, usize, String) {
-    let file = File::open(path).expect(path);
-    let mut decoder = Decoder::new(BufReader::new(file));
-    let pixels = decoder.decode().expect("failed to decode image");
-    let metadata = decoder.info().unwrap();
-    (
-        pixels,
-        metadata.width as usize,
-        metadata.height as usize,
-        path.to_string(),
-    )
+fn load_texture_image(path: &str) -> Result<(Vec<u8>, usize, usize, String), String> {
+    let (pixels, width, height) = texture::decode(path)?;
+    Ok((pixels, width, height, path.to_string()))
 }
 
 serde_with::serde_conv!(
@@ -55,10 +66,10 @@ serde_with::serde_conv!(
             None => "".to_string(),
         }
     },
-    |value: &str| -> Result<_, std::convert::Infallible> {
+    |value: &str| -> Result<_, String> {
         match value {
             "" => Ok(None),
-            _ => Ok(Some(load_texture_image(value))),
+            _ => load_texture_image(value).map(Some),
         }
     }
 );
@@ -71,9 +82,166 @@ pub struct Config {
     pub max_depth: usize,
     pub sky: Option<Sky>,
     pub camera: Camera,
-    pub objects: Vec<Sphere>,
+    pub objects: Vec<Object>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub point_lights: Vec<PointLight>,
+    #[serde(default, skip_serializing_if = "Sampler::is_random")]
+    pub sampler: Sampler,
+    // If set, samples_per_pixel is ignored in favour of adaptive sampling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adaptive: Option<Adaptive>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firefly: Option<FireflyFilter>,
+    #[serde(default, skip_serializing_if = "Accelerator::is_bvh")]
+    pub accelerator: Accelerator,
+    #[serde(default, skip_serializing_if = "BvhQuality::is_sah")]
+    pub bvh_quality: BvhQuality,
+    #[serde(default, skip_serializing_if = "Integrator::is_rgb")]
+    pub integrator: Integrator,
+    // The objects, once rendering starts, in whichever structure the
+    // accelerator says.
     #[serde(skip)]
-    pub bvh: Option<bvh::bvh::Bvh<f64,3>>,
+    pub built: Option<Built>,
+}
+
+// The scene's objects, built into one of the accelerators.
+#[derive(Debug)]
+pub enum Built {
+    Bvh(Bvh<Object>),
+    KdTree(KdTree<Object>),
+    Grid(Grid<Object>),
+}
+
+// How the renderer finds what each ray hits first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Accelerator {
+    // A bounding volume hierarchy: quick to build, and good all round.
+    #[default]
+    Bvh,
+    // A kd-tree, slower to build but sometimes quicker to trace, e.g. in
+    // rooms of axis-aligned walls and boxes.
+    KdTree,
+    // A uniform grid, built at once and often the quickest through many
+    // evenly spread objects of similar sizes, like the cover scene's.
+    Grid,
+}
+
+impl Accelerator {
+    pub fn is_bvh(&self) -> bool {
+        *self == Accelerator::Bvh
+    }
+}
+
+impl FromStr for Accelerator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Accelerator, String> {
+        match s.to_lowercase().as_str() {
+            "bvh" => Ok(Accelerator::Bvh),
+            "kd-tree" | "kdtree" => Ok(Accelerator::KdTree),
+            "grid" => Ok(Accelerator::Grid),
+            _ => Err(format!("unknown accelerator: {}", s)),
+        }
+    }
+}
+
+// What the light along each path is carried as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Integrator {
+    // Red, green and blue, as every color in the scene is given.
+    #[default]
+    Rgb,
+    // The spectrum at a few wavelengths picked at random for each path
+    // (hero wavelength sampling), with the scene's colors turned into smooth
+    // spectra and the result back into RGB through the CIE matching
+    // functions. Noisier in color, but where light depends on wavelength,
+    // as through a prism, it is right.
+    Spectral,
+    // Red, green and blue, with how the light is polarized followed back
+    // from the camera through reflections and refractions off glass and
+    // mirrors, so that a polarizing filter on the camera can cut the glare
+    // off water and windows.
+    Polarized,
+}
+
+impl Integrator {
+    pub fn is_rgb(&self) -> bool {
+        *self == Integrator::Rgb
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Integrator, String> {
+        match s.to_lowercase().as_str() {
+            "rgb" => Ok(Integrator::Rgb),
+            "spectral" => Ok(Integrator::Spectral),
+            "polarized" => Ok(Integrator::Polarized),
+            _ => Err(format!("unknown integrator: {}", s)),
+        }
+    }
+}
+
+// A small, empty scene under the default sky, for filling in the rest of.
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            width: 400,
+            height: 300,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            sky: Some(Sky::new_default_sky()),
+            camera: Camera::new(
+                Point3D::new(0.0, 0.0, 1.0),
+                Point3D::new(0.0, 0.0, 0.0),
+                Point3D::new(0.0, 1.0, 0.0),
+                90.0,
+                4.0 / 3.0,
+                0.0,
+                1.0,
+            ),
+            objects: Vec::new(),
+            point_lights: Vec::new(),
+            sampler: Sampler::default(),
+            adaptive: None,
+            firefly: None,
+            accelerator: Accelerator::default(),
+            bvh_quality: BvhQuality::default(),
+            integrator: Integrator::default(),
+            built: None,
+        }
+    }
+}
+
+impl Config {
+    // Reads a scene from a JSON file. Textures referenced by the scene are
+    // loaded as part of parsing, so a missing image is reported here too.
+    // A .gltf or .glb file is imported instead.
+    pub fn load(path: &str) -> Result<Config, std::io::Error> {
+        Config::load_with(path, &Registry::new())
+    }
+
+    // As load, with the custom materials and distance fields in `registry`
+    // usable.
+    pub fn load_with(path: &str, registry: &Registry) -> Result<Config, std::io::Error> {
+        if path.ends_with(".gltf") || path.ends_with(".glb") {
+            return gltf::load(path);
+        }
+        let json = fs::read(path)?;
+        let config = registry.scope(|| serde_json::from_slice::<Config>(&json))?;
+        Ok(config)
+    }
+
+    // When each pixel has had enough samples. Without adaptive sampling,
+    // that is after samples_per_pixel.
+    pub fn sampling(&self) -> Adaptive {
+        self.adaptive.unwrap_or(Adaptive {
+            min_samples: self.samples_per_pixel,
+            max_samples: self.samples_per_pixel,
+            threshold: 0.0,
+        })
+    }
 }
 
 #[test]
@@ -90,19 +258,22 @@ fn test_to_json() {
             Point3D::new(0.0, 1.0, 0.0),
             90.0,
             1.0,
+            0.0,
+            1.0,
         ),
         objects: vec![Sphere::new(
             Point3D::new(0.0, 0.0, -1.0),
             0.5,
-            Material::Lambertian(Lambertian::new(Srgb::new(
-                0.8 as f32, 0.3 as f32, 0.3 as f32,
-            ))),
-        )],
+            Material::Lambertian(Lambertian::new(Srgb::new(0.8, 0.3, 0.3))),
+        )
+        .into()],
+        ..Default::default()
     };
     let serialized = serde_json::to_string(&config).unwrap();
-    assert_eq!("{\"width\":100,\"height\":100,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":{\"texture\":\"\"},\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0},\"objects\":[{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"radius\":0.5,\"material\":{\"Lambertian\":{\"albedo\":[0.8,0.3,0.3]}}}]}", serialized);
+    assert_eq!("{\"width\":100,\"height\":100,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":{\"texture\":\"\"},\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0,\"aperture\":0.0,\"focus_dist\":1.0,\"shutter_open\":0.0,\"shutter_close\":1.0},\"objects\":[{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"radius\":0.5,\"material\":{\"Lambertian\":{\"albedo\":[0.8,0.3,0.3]}}}]}", serialized);
 }
 
+#[cfg(feature = "images")]
 #[test]
 fn test_sky_perms_to_from_json() {
     let config = Config {
@@ -117,22 +288,24 @@ fn test_sky_perms_to_from_json() {
             Point3D::new(0.0, 1.0, 0.0),
             90.0,
             1.0,
+            0.0,
+            1.0,
         ),
         objects: vec![Sphere::new(
             Point3D::new(0.0, 0.0, -1.0),
             0.5,
-            Material::Lambertian(Lambertian::new(Srgb::new(
-                0.8 as f32, 0.3 as f32, 0.3 as f32,
-            ))),
-        )],
+            Material::Lambertian(Lambertian::new(Srgb::new(0.8, 0.3, 0.3))),
+        )
+        .into()],
+        ..Default::default()
     };
     let serialized = serde_json::to_string(&config).unwrap();
-    assert_eq!("{\"width\":100,\"height\":100,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":null,\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0},\"objects\":[{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"radius\":0.5,\"material\":{\"Lambertian\":{\"albedo\":[0.8,0.3,0.3]}}}]}", serialized);
+    assert_eq!("{\"width\":100,\"height\":100,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":null,\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0,\"aperture\":0.0,\"focus_dist\":1.0,\"shutter_open\":0.0,\"shutter_close\":1.0},\"objects\":[{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"radius\":0.5,\"material\":{\"Lambertian\":{\"albedo\":[0.8,0.3,0.3]}}}]}", serialized);
     let _ = serde_json::from_str::<Config>(&serialized).expect("Unable to parse json");
 
     // This scene contains a sky texture at data/earth,jpg
-    let scene_json = "{\"width\":100,\"height\":100,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":{\"texture\":\"data/earth.jpg\"},\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0},\"objects\":[{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"radius\":0.5,\"material\":{\"Lambertian\":{\"albedo\":[0.8,0.3,0.3]}}}]}";
-    let scene = serde_json::from_str::<Config>(&scene_json).expect("Unable to parse json");
+    let scene_json = "{\"width\":100,\"height\":100,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":{\"texture\":\"data/earth.jpg\"},\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0,\"aperture\":0.0,\"focus_dist\":1.0,\"shutter_open\":0.0,\"shutter_close\":1.0},\"objects\":[{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"radius\":0.5,\"material\":{\"Lambertian\":{\"albedo\":[0.8,0.3,0.3]}}}]}";
+    let scene = serde_json::from_str::<Config>(scene_json).expect("Unable to parse json");
 
     assert_eq!(
         match scene.sky {
@@ -148,106 +321,28 @@ fn test_sky_perms_to_from_json() {
     )
 }
 
-fn _make_cover_world() -> Vec<Sphere> {
-    let mut world = Vec::new();
-
-    world.push(Sphere::new(
-        Point3D::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Material::Lambertian(Lambertian::new(Srgb::new(0.5, 0.5, 0.5))),
-    ));
-
-    let mut rng = rand::thread_rng();
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen::<f64>();
-            let center = Point3D::new(
-                a as f64 + 0.9 * rng.gen::<f64>(),
-                0.2,
-                b as f64 + 0.9 * rng.gen::<f64>(),
-            );
-
-            if ((center - Point3D::new(4.0, 0.2, 0.0)).length()) < 0.9 {
-                continue;
-            }
-
-            if choose_mat < 0.8 {
-                // diffuse
-                world.push(Sphere::new(
-                    center,
-                    0.2,
-                    Material::Lambertian(Lambertian::new(Srgb::new(
-                        rng.gen::<f32>() * rng.gen::<f32>(),
-                        rng.gen::<f32>() * rng.gen::<f32>(),
-                        rng.gen::<f32>() * rng.gen::<f32>(),
-                    ))),
-                ));
-            } else if choose_mat < 0.95 {
-                // metal
-                world.push(Sphere::new(
-                    center,
-                    0.2,
-                    Material::Metal(Metal::new(
-                        Srgb::new(
-                            0.5 * (1.0 + rng.gen::<f32>()),
-                            0.5 * (1.0 + rng.gen::<f32>()),
-                            0.5 * (1.0 + rng.gen::<f32>()),
-                        ),
-                        0.5 * rng.gen::<f64>(),
-                    )),
-                ));
-            } else {
-                // glass
-                world.push(Sphere::new(center, 0.2, Material::Glass(Glass::new(1.5))));
-            }
-        }
-    }
-
-    world.push(Sphere::new(
-        Point3D::new(0.0, 1.0, 0.0),
-        1.0,
-        Material::Glass(Glass::new(1.5)),
-    ));
-    world.push(Sphere::new(
-        Point3D::new(-4.0, 1.0, 0.0),
-        1.0,
-        Material::Lambertian(Lambertian::new(Srgb::new(
-            0.4 as f32, 0.2 as f32, 0.1 as f32,
-        ))),
-    ));
-    world.push(Sphere::new(
-        Point3D::new(4.0, 1.0, 0.0),
-        1.0,
-        Material::Metal(Metal::new(
-            Srgb::new(0.7 as f32, 0.6 as f32, 0.5 as f32),
-            0.0,
-        )),
-    ));
-    world
-}
-
 #[test]
 fn test_cover_scene_to_json() {
-    let config = Config {
-        width: 800,
-        height: 600,
-        samples_per_pixel: 64,
-        max_depth: 50,
-        sky: Some(Sky::new_default_sky()),
-        camera: Camera::new(
-            Point3D::new(13.0, 2.0, 3.0),
-            Point3D::new(0.0, 0.0, 0.0),
-            Point3D::new(0.0, 1.0, 0.0),
-            20.0,
-            (800.0 / 600.0) as f64,
-        ),
-        objects: _make_cover_world(),
-    };
+    let config = cover(0);
     let serialized = serde_json::to_string_pretty(&config).unwrap();
     fs::write("/tmp/cover_scene.json", serialized).unwrap();
 }
 
+#[test]
+fn test_load() {
+    let scene = Config::load("data/cover_scene.json").expect("Unable to load scene");
+    assert_eq!(scene.objects.len(), 484);
+
+    let missing = Config::load("data/no_such_scene.json").unwrap_err();
+    assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+
+    let bad_texture = "{\"width\":1,\"height\":1,\"samples_per_pixel\":1,\"max_depth\":1,\"sky\":{\"texture\":\"data/missing.jpg\"},\"camera\":{\"look_from\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"look_at\":{\"x\":0.0,\"y\":0.0,\"z\":-1.0},\"vup\":{\"x\":0.0,\"y\":1.0,\"z\":0.0},\"vfov\":90.0,\"aspect\":1.0,\"aperture\":0.0,\"focus_dist\":1.0,\"shutter_open\":0.0,\"shutter_close\":1.0},\"objects\":[]}";
+    fs::write("/tmp/bad_texture_scene.json", bad_texture).unwrap();
+    let err = Config::load("/tmp/bad_texture_scene.json").unwrap_err();
+    assert!(err.to_string().contains("data/missing.jpg"));
+}
+
+#[cfg(feature = "images")]
 #[test]
 fn test_from_file() {
     let json = fs::read("data/test_scene.json").expect("Unable to read file");
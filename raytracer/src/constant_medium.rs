@@ -0,0 +1,120 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Isotropic;
+use crate::materials::Material;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::texture::Texture;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::sphere::Sphere;
+#[cfg(test)]
+use palette::Srgb;
+
+// Fog or smoke filling a closed `boundary` object. Rays passing through are
+// scattered at a random point with a probability that grows with `density`
+// and the distance travelled inside, so thin parts are see-through and
+// thick parts opaque. The material should normally be Isotropic.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConstantMedium {
+    pub boundary: Box<Object>,
+    pub density: Float,
+    pub material: Material,
+}
+
+// Where along the ray a closed, convex `boundary` is entered and left, if
+// it is, limited to [t_min, t_max].
+pub(crate) fn span_inside(
+    boundary: &Object,
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float)> {
+    let enter = boundary.hit(ray, Float::NEG_INFINITY, Float::INFINITY)?;
+    let exit = boundary.hit(ray, enter.t + 0.0001, Float::INFINITY)?;
+    let t0 = enter.t.max(t_min).max(0.0);
+    let t1 = exit.t.min(t_max);
+    if t0 >= t1 {
+        return None;
+    }
+    Some((t0, t1))
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Object, density: Float, albedo: Texture) -> ConstantMedium {
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            density,
+            material: Material::Isotropic(Isotropic::new(albedo)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t0, t1) = span_inside(&self.boundary, ray, t_min, t_max)?;
+        let length = ray.direction.length();
+        let distance_inside = (t1 - t0) * length;
+        let hit_distance = -(1.0 - sampler::rng().gen::<Float>()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t0 + hit_distance / length;
+        // Normal and facing are meaningless inside a volume.
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal: Point3D::new(1.0, 0.0, 0.0),
+            front_face: true,
+            material: &self.material,
+            u: 0.0,
+            v: 0.0,
+            tangent: None,
+        })
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[test]
+fn test_constant_medium() {
+    let boundary = Sphere::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        1.0,
+        Material::Glass(Glass::new(1.5)),
+    );
+    let white = Texture::Solid(Srgb::new(1.0, 1.0, 1.0));
+    let ray = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+
+    // Dense fog is hit just inside the boundary...
+    let fog = ConstantMedium::new(boundary.clone().into(), 1000.0, white.clone());
+    let hit = fog.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert!(hit.t >= 4.0 && hit.t < 4.1);
+    assert!(matches!(hit.material, Material::Isotropic(_)));
+
+    // ...while thin haze lets most rays through.
+    let haze = ConstantMedium::new(boundary.into(), 0.01, white);
+    let hits = (0..1000)
+        .filter(|_| haze.hit(&ray, 0.001, Float::MAX).is_some())
+        .count();
+    // 1 - exp(-0.02) ~ 2%.
+    assert!(hits < 60);
+
+    let miss = Ray::new(Point3D::new(0.0, 2.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    assert!(fog.hit(&miss, 0.001, Float::MAX).is_none());
+    // Rays starting inside the fog scatter too.
+    let inside = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, 1.0));
+    assert!(fog.hit(&inside, 0.001, Float::MAX).unwrap().t < 0.1);
+}
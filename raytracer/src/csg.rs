@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::cylinder::Cylinder;
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use crate::sphere::Sphere;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// How far past one crossing of a shape the search for the next starts.
+const STEP: Float = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum CsgOperation {
+    // Inside either.
+    Union,
+    // Inside both.
+    Intersection,
+    // Inside the left but not the right.
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// Two closed shapes combined into one solid, e.g. a lens as the
+// intersection of two spheres or a bead as a sphere minus a cylinder. The
+// ray's way in and out of each is followed and the first crossing where it
+// goes in or out of the combination is the hit.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<Object>,
+    pub right: Box<Object>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Object, right: Object) -> Csg {
+        Csg {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn union(left: Object, right: Object) -> Csg {
+        Csg::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection(left: Object, right: Object) -> Csg {
+        Csg::new(CsgOperation::Intersection, left, right)
+    }
+
+    pub fn difference(left: Object, right: Object) -> Csg {
+        Csg::new(CsgOperation::Difference, left, right)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // The next crossing of each side. Whether the ray starts inside a
+        // side is told by whether its first crossing is on the way out.
+        let mut next_left = self.left.hit(ray, t_min, Float::INFINITY);
+        let mut next_right = self.right.hit(ray, t_min, Float::INFINITY);
+        let mut in_left = next_left.as_ref().is_some_and(|h| !h.front_face);
+        let mut in_right = next_right.as_ref().is_some_and(|h| !h.front_face);
+        let inside = self.operation.contains(in_left, in_right);
+
+        loop {
+            let left_first = match (&next_left, &next_right) {
+                (None, None) => return None,
+                (Some(l), Some(r)) => l.t <= r.t,
+                (l, _) => l.is_some(),
+            };
+            let (next, side) = if left_first {
+                (&mut next_left, &self.left)
+            } else {
+                (&mut next_right, &self.right)
+            };
+            let hit = next.take()?;
+            if hit.t >= t_max {
+                return None;
+            }
+            // A side is entered through its front.
+            if left_first {
+                in_left = hit.front_face;
+            } else {
+                in_right = hit.front_face;
+            }
+            if self.operation.contains(in_left, in_right) != inside {
+                let mut hit = hit;
+                // What's inside the right is outside the difference, so its
+                // surface faces the other way.
+                if !left_first && self.operation == CsgOperation::Difference {
+                    hit.front_face = !hit.front_face;
+                }
+                return Some(hit);
+            }
+            *next = side.hit(ray, hit.t + STEP, Float::INFINITY);
+        }
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        let left = self.left.bounding_box(t0, t1);
+        let right = self.right.bounding_box(t0, t1);
+        match self.operation {
+            CsgOperation::Union => Some(Aabb::surrounding_box(&left?, &right?)),
+            CsgOperation::Intersection => match (left, right) {
+                (Some(l), Some(r)) => Some(Aabb::new(
+                    Point3D::new(
+                        l.min.x().max(r.min.x()),
+                        l.min.y().max(r.min.y()),
+                        l.min.z().max(r.min.z()),
+                    ),
+                    Point3D::new(
+                        l.max.x().min(r.max.x()),
+                        l.max.y().min(r.max.y()),
+                        l.max.z().min(r.max.z()),
+                    ),
+                )),
+                (l, r) => l.or(r),
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_ball(x: Float) -> Object {
+    Object::from(Sphere::new(
+        Point3D::new(x, 0.0, 0.0),
+        1.0,
+        Material::grey(),
+    ))
+}
+
+#[test]
+fn test_csg_union_and_intersection() {
+    let along = Ray::new(Point3D::new(-5.0, 0.0, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    // Two overlapping balls make one with no walls inside.
+    let union = Csg::union(test_ball(-0.5), test_ball(0.5));
+    let hit = union.hit(&along, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), -1.5);
+    let hit = union.hit(&along, hit.t + 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), 1.5);
+    assert!(!hit.front_face);
+
+    // The lens where they overlap.
+    let lens = Csg::intersection(test_ball(-0.5), test_ball(0.5));
+    let hit = lens.hit(&along, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), -0.5);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(-1.0, 0.0, 0.0));
+    let up = Ray::new(Point3D::new(0.0, -5.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = lens.hit(&up, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.y(), -(Float::sqrt(0.75)));
+    let bounds = lens.bounding_box(0.0, 1.0).unwrap();
+    assert_approx_eq!(bounds.axis_min(0), -0.5);
+    assert_approx_eq!(bounds.axis_max(0), 0.5);
+    // Nothing ends up past them.
+    let past = Ray::new(Point3D::new(-5.0, 0.0, 2.0), Point3D::new(1.0, 0.0, 0.0));
+    assert!(union.hit(&past, 0.001, Float::MAX).is_none());
+}
+
+#[test]
+fn test_csg_difference() {
+    // A ball with a hole down through it.
+    let hole = Object::from(Cylinder::new(
+        Point3D::new(0.0, -2.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        0.5,
+        4.0,
+        Material::grey(),
+    ));
+    let bead = Csg::difference(test_ball(0.0), hole);
+    let down_the_hole = Ray::new(Point3D::new(0.0, -5.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    assert!(bead.hit(&down_the_hole, 0.001, Float::MAX).is_none());
+    let beside = Ray::new(Point3D::new(0.75, -5.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = bead.hit(&beside, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.y(), -(Float::sqrt(0.4375)));
+
+    // Across the middle: in through the ball, out into the hole, whose wall
+    // faces into the hole.
+    let across = Ray::new(Point3D::new(-5.0, 0.0, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    let hit = bead.hit(&across, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), -1.0);
+    let hit = bead.hit(&across, hit.t + 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), -0.5);
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(-1.0, 0.0, 0.0));
+    let hit = bead.hit(&across, hit.t + 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), 0.5);
+    assert!(hit.front_face);
+    assert_eq!(bead.bounding_box(0.0, 1.0).unwrap().axis_max(1), 1.0);
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+fn components(p: &Point3D) -> [Float; 3] {
+    [p.x(), p.y(), p.z()]
+}
+
+// An axis-aligned box spanning `min` to `max`, e.g. the walls and blocks of
+// a Cornell box.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cuboid {
+    pub min: Point3D,
+    pub max: Point3D,
+    pub material: Material,
+}
+
+impl Cuboid {
+    // The corners may be given in any order.
+    pub fn new(a: Point3D, b: Point3D, material: Material) -> Cuboid {
+        Cuboid {
+            min: Point3D::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point3D::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+            material,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    // Slab test like Aabb::hit, also remembering which face the ray enters
+    // and leaves through so the right normal can be reported. A ray starting
+    // inside the box hits the face it leaves through.
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let origin = components(&ray.origin);
+        let direction = components(&ray.direction);
+        let min = components(&self.min);
+        let max = components(&self.max);
+
+        let mut t_enter = Float::NEG_INFINITY;
+        let mut t_exit = Float::INFINITY;
+        let mut enter_axis = 0;
+        let mut exit_axis = 0;
+        for a in 0..3 {
+            let inv_d = 1.0 / direction[a];
+            let mut t0 = (min[a] - origin[a]) * inv_d;
+            let mut t1 = (max[a] - origin[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_enter {
+                t_enter = t0;
+                enter_axis = a;
+            }
+            if t1 < t_exit {
+                t_exit = t1;
+                exit_axis = a;
+            }
+        }
+        if t_exit < t_enter {
+            return None;
+        }
+
+        let (t, a, front_face) = if t_enter > t_min && t_enter < t_max {
+            (t_enter, enter_axis, true)
+        } else if t_exit > t_min && t_exit < t_max {
+            (t_exit, exit_axis, false)
+        } else {
+            return None;
+        };
+
+        // Outward normal of the face: facing against the ray on entry and
+        // along it on exit.
+        let sign = if (direction[a] < 0.0) == front_face {
+            1.0
+        } else {
+            -1.0
+        };
+        let mut outward = [0.0; 3];
+        outward[a] = sign;
+        let outward = Point3D::new(outward[0], outward[1], outward[2]);
+
+        // Texture coordinates run across the face along the other two axes.
+        let point = ray.at(t);
+        let p = components(&point);
+        let (i, j) = ((a + 1) % 3, (a + 2) % 3);
+        let u = (p[i] - min[i]) / (max[i] - min[i]);
+        let v = (p[j] - min[j]) / (max[j] - min[j]);
+        Some(HitRecord {
+            t,
+            point,
+            normal: if front_face { outward } else { -outward },
+            front_face,
+            material: &self.material,
+            u,
+            v,
+            tangent: None,
+        })
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+#[test]
+fn test_cuboid_normals() {
+    let cuboid = Cuboid::new(
+        Point3D::new(1.0, 1.0, 1.0),
+        Point3D::new(-1.0, -1.0, -1.0),
+        Material::grey(),
+    );
+    let faces = [
+        Point3D::new(1.0, 0.0, 0.0),
+        Point3D::new(-1.0, 0.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        Point3D::new(0.0, -1.0, 0.0),
+        Point3D::new(0.0, 0.0, 1.0),
+        Point3D::new(0.0, 0.0, -1.0),
+    ];
+    for face in faces {
+        // Fire at each face from outside, slightly off-centre.
+        let offset = Point3D::new(0.1, 0.2, 0.3);
+        let origin = face * 5.0 + offset - face * face.dot(&offset);
+        let hit = cuboid
+            .hit(&Ray::new(origin, -face), 0.001, Float::MAX)
+            .unwrap();
+        assert_approx_eq!(hit.t, 4.0);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, face);
+        assert!((0.0..=1.0).contains(&hit.u));
+        assert!((0.0..=1.0).contains(&hit.v));
+    }
+}
+
+#[test]
+fn test_cuboid_from_inside() {
+    let cuboid = Cuboid::new(
+        Point3D::new(1.0, 1.0, 1.0),
+        Point3D::new(-1.0, -1.0, -1.0),
+        Material::grey(),
+    );
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, -2.0));
+    let hit = cuboid.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 0.5);
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 0.0, 1.0));
+
+    let miss = Ray::new(Point3D::new(0.0, 3.0, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    assert!(cuboid.hit(&miss, 0.001, Float::MAX).is_none());
+}
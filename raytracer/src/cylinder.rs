@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::axial::AxialRay;
+use crate::axial::Closest;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::float::TOLERANCE;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// A cylinder of `radius` around `axis`, running `height` along it from the
+// centre of its `base`. Both ends are closed by flat caps, unless `caps` is
+// false, which leaves an open tube.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cylinder {
+    pub base: Point3D,
+    pub axis: Point3D,
+    pub radius: Float,
+    pub height: Float,
+    #[serde(default = "default_caps")]
+    pub caps: bool,
+    pub material: Material,
+}
+
+fn default_caps() -> bool {
+    true
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point3D,
+        axis: Point3D,
+        radius: Float,
+        height: Float,
+        material: Material,
+    ) -> Cylinder {
+        Cylinder {
+            base,
+            axis: axis.unit_vector(),
+            radius,
+            height,
+            caps: true,
+            material,
+        }
+    }
+
+    pub fn with_caps(mut self, caps: bool) -> Cylinder {
+        self.caps = caps;
+        self
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let axial = AxialRay::new(ray, self.base, self.axis);
+        let r = self.radius;
+        let mut closest = Closest::new(t_min, t_max);
+
+        // The side, where the ray is `radius` from the axis between the two
+        // ends. u goes around the axis and v along it.
+        let a = axial.d_across.length_squared();
+        let half_b = axial.oc_across.dot(&axial.d_across);
+        let c = axial.oc_across.length_squared() - r * r;
+        let discriminant = half_b * half_b - a * c;
+        if a > 0.0 && discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                let (h, across) = axial.at(t);
+                if (0.0..=self.height).contains(&h) {
+                    let outward = across / r;
+                    closest.consider(t, outward, axial.around(outward), h / self.height);
+                }
+            }
+        }
+
+        // The caps, discs across the axis at either end.
+        if self.caps {
+            for (h, outward) in [(0.0, -axial.axis), (self.height, axial.axis)] {
+                if let Some((t, u, v)) = axial.cap(h, r) {
+                    closest.consider(t, outward, u, v);
+                }
+            }
+        }
+
+        closest.record(ray, &self.material)
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // Each end is a circle, reaching out along each world axis by the
+        // radius times the sine of that axis's angle with its own.
+        let axis = self.axis.unit_vector();
+        let reach = |a: Float| self.radius * (1.0 - a * a).max(0.0).sqrt();
+        let reach = Point3D::new(reach(axis.x()), reach(axis.y()), reach(axis.z()));
+        let top = self.base + axis * self.height;
+        let min = Point3D::new(
+            self.base.x().min(top.x()),
+            self.base.y().min(top.y()),
+            self.base.z().min(top.z()),
+        );
+        let max = Point3D::new(
+            self.base.x().max(top.x()),
+            self.base.y().max(top.y()),
+            self.base.z().max(top.z()),
+        );
+        Some(Aabb::new(min - reach, max + reach))
+    }
+}
+
+#[test]
+fn test_cylinder_hit() {
+    let cylinder = Cylinder::new(
+        Point3D::new(0.0, -1.0, 0.0),
+        Point3D::new(0.0, 2.0, 0.0),
+        0.5,
+        2.0,
+        Material::grey(),
+    );
+    // Into the side...
+    let side = Ray::new(Point3D::new(0.0, 0.5, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    let hit = cylinder.hit(&side, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 4.5);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 0.0, -1.0));
+    assert_approx_eq!(hit.v, 0.75);
+    // ...and down onto the top cap...
+    let top = Ray::new(Point3D::new(0.2, 5.0, 0.1), Point3D::new(0.0, -1.0, 0.0));
+    let hit = cylinder.hit(&top, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 4.0);
+    assert_eq!(hit.normal, Point3D::new(0.0, 1.0, 0.0));
+    assert!((0.0..=1.0).contains(&hit.u) && (0.0..=1.0).contains(&hit.v));
+    // ...which an open tube doesn't have, so the ray carries on to the inside
+    // of the side, and from the inside out through the bottom.
+    let tube = cylinder.clone().with_caps(false);
+    let down = Ray::new(Point3D::new(0.0, 1.5, 0.0), Point3D::new(0.25, -1.0, 0.0));
+    let hit = tube.hit(&down, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_approx_eq!(hit.point.x(), 0.5);
+    let inside = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    assert!(tube.hit(&inside, 0.001, Float::MAX).is_none());
+    let hit = cylinder.hit(&inside, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn test_tilted_cylinder() {
+    let base = Point3D::new(1.0, 2.0, 3.0);
+    let axis = Point3D::new(1.0, 1.0, -0.5).unit_vector();
+    let tilted = Cylinder::new(base, axis, 0.7, 1.5, Material::grey());
+    let bounds = tilted.bounding_box(0.0, 1.0).unwrap();
+    // Rays square to the axis, aimed past its middle from all around: those
+    // passing closer than the radius hit the side, as far out along the ray
+    // as the circle allows, and nothing lands outside the box.
+    let middle = base + axis * 0.75;
+    let (tangent, bitangent) = axis.orthonormal_basis();
+    for i in 0..36 {
+        let angle = (i * 10) as Float;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let towards = tangent * cos + bitangent * sin;
+        let aside = axis.cross(&towards);
+        for miss in [0.0, 0.3, 0.69, 0.71, 1.0] {
+            let ray = Ray::new(middle - towards * 5.0 + aside * miss, towards);
+            match tilted.hit(&ray, 0.001, Float::MAX) {
+                Some(hit) => {
+                    assert!(miss < 0.7);
+                    assert_approx_eq!(hit.t, 5.0 - (0.49 - miss * miss).sqrt(), TOLERANCE);
+                    for a in 0..3 {
+                        let p = hit.point[a];
+                        assert!(p >= bounds.axis_min(a) && p <= bounds.axis_max(a));
+                    }
+                }
+                None => assert!(miss > 0.7),
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::aov::Aovs;
+use crate::film::Film;
+
+#[cfg(feature = "oidn")]
+use palette::Srgb;
+#[cfg(feature = "oidn")]
+use std::ffi::{c_char, c_void, CStr};
+
+#[cfg(all(test, feature = "oidn"))]
+use rand::rngs::StdRng;
+#[cfg(all(test, feature = "oidn"))]
+use rand::Rng;
+#[cfg(all(test, feature = "oidn"))]
+use rand::SeedableRng;
+
+// The parts of Intel Open Image Denoise's C API that are used, as in
+// oidn.h (version 2). Experimental: written against the header, and only
+// tried where the library is installed (see test_denoise); CI without it
+// only type checks this.
+#[cfg(feature = "oidn")]
+#[link(name = "OpenImageDenoise")]
+extern "C" {
+    fn oidnNewDevice(device_type: i32) -> *mut c_void;
+    fn oidnCommitDevice(device: *mut c_void);
+    fn oidnGetDeviceError(device: *mut c_void, message: *mut *const c_char) -> i32;
+    fn oidnReleaseDevice(device: *mut c_void);
+    fn oidnNewFilter(device: *mut c_void, filter_type: *const c_char) -> *mut c_void;
+    fn oidnSetSharedFilterImage(
+        filter: *mut c_void,
+        name: *const c_char,
+        pointer: *mut c_void,
+        format: i32,
+        width: usize,
+        height: usize,
+        byte_offset: usize,
+        byte_pixel_stride: usize,
+        byte_row_stride: usize,
+    );
+    fn oidnSetFilterBool(filter: *mut c_void, name: *const c_char, value: bool);
+    fn oidnCommitFilter(filter: *mut c_void);
+    fn oidnExecuteFilter(filter: *mut c_void);
+    fn oidnReleaseFilter(filter: *mut c_void);
+}
+
+#[cfg(feature = "oidn")]
+const OIDN_DEVICE_TYPE_DEFAULT: i32 = 0;
+#[cfg(feature = "oidn")]
+const OIDN_FORMAT_FLOAT3: i32 = 3;
+
+#[cfg(not(feature = "oidn"))]
+pub fn denoise(_film: &Film, _aovs: &Aovs) -> Result<Film, String> {
+    Err("built without denoising (the oidn feature)".to_string())
+}
+
+// The film's image with the noise taken out by Open Image Denoise, which
+// uses the albedo and normal passes to tell noise from detail. The result
+// holds one sample per pixel, so it is for writing out rather than for
+// rendering more into.
+#[cfg(feature = "oidn")]
+pub fn denoise(film: &Film, aovs: &Aovs) -> Result<Film, String> {
+    let (width, height) = (film.width, film.height);
+    let mut color = means(film);
+    let mut albedo = means(&aovs.albedo);
+    let mut normal = means(&aovs.normal);
+    let mut output = vec![0.0; width * height * 3];
+
+    // Safety: the buffers outlive the filter, and are the size it is told.
+    unsafe {
+        let device = oidnNewDevice(OIDN_DEVICE_TYPE_DEFAULT);
+        oidnCommitDevice(device);
+        let filter = oidnNewFilter(device, c"RT".as_ptr());
+        let images: [(&CStr, &mut Vec<f32>); 4] = [
+            (c"color", &mut color),
+            (c"albedo", &mut albedo),
+            (c"normal", &mut normal),
+            (c"output", &mut output),
+        ];
+        for (name, buffer) in images {
+            oidnSetSharedFilterImage(
+                filter,
+                name.as_ptr(),
+                buffer.as_mut_ptr() as *mut c_void,
+                OIDN_FORMAT_FLOAT3,
+                width,
+                height,
+                0,
+                0,
+                0,
+            );
+        }
+        // The image is linear and may be brighter than white.
+        oidnSetFilterBool(filter, c"hdr".as_ptr(), true);
+        oidnCommitFilter(filter);
+        oidnExecuteFilter(filter);
+
+        let mut message = std::ptr::null();
+        let error = oidnGetDeviceError(device, &mut message);
+        let result = if error == 0 {
+            Ok(())
+        } else if message.is_null() {
+            Err(format!("Open Image Denoise failed with error {}", error))
+        } else {
+            Err(CStr::from_ptr(message).to_string_lossy().into_owned())
+        };
+        oidnReleaseFilter(filter);
+        oidnReleaseDevice(device);
+        result?;
+    }
+
+    let mut denoised = Film::new(width, height);
+    for (estimate, c) in denoised.pixels_mut().iter_mut().zip(output.chunks(3)) {
+        estimate.add(Srgb::new(c[0], c[1], c[2]));
+    }
+    Ok(denoised)
+}
+
+// A film's means as packed RGB floats, row by row.
+#[cfg(feature = "oidn")]
+fn means(film: &Film) -> Vec<f32> {
+    film.pixels()
+        .iter()
+        .flat_map(|estimate| {
+            let mean = estimate.mean();
+            [mean.red, mean.green, mean.blue]
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "oidn"))]
+#[test]
+fn test_denoise_unavailable() {
+    let film = Film::new(2, 2);
+    assert!(denoise(&film, &Aovs::new(2, 2)).is_err());
+}
+
+// The oidn feature only links with the library there, so this runs wherever
+// it is installed and the tests are built with the feature.
+#[cfg(feature = "oidn")]
+#[test]
+fn test_denoise() {
+    // A flat grey wall facing the camera, seen through heavy noise.
+    let (width, height) = (32, 32);
+    let mut film = Film::new(width, height);
+    let mut aovs = Aovs::new(width, height);
+    let mut rng = StdRng::seed_from_u64(1);
+    for i in 0..width * height {
+        let v = 0.5 + rng.gen_range(-0.3..0.3);
+        film.pixels_mut()[i].add(Srgb::new(v, v, v));
+        aovs.albedo.pixels_mut()[i].add(Srgb::new(0.5, 0.5, 0.5));
+        aovs.normal.pixels_mut()[i].add(Srgb::new(0.0, 0.0, 1.0));
+    }
+    let error = |film: &Film| -> f32 {
+        let pixels = film.pixels();
+        pixels
+            .iter()
+            .map(|e| (e.mean().red - 0.5).abs())
+            .sum::<f32>()
+            / pixels.len() as f32
+    };
+    let denoised = denoise(&film, &aovs).unwrap();
+    assert_eq!((denoised.width, denoised.height), (width, height));
+    assert!(error(&denoised) < error(&film) / 4.0);
+}
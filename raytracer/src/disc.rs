@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Rays closer to parallel with the disc than this are treated as misses.
+const PARALLEL_EPSILON: Float = 1e-9;
+
+// A flat disc of `radius` around `center`, facing `normal`, e.g. a light
+// panel or a table top. With an `inner_radius` it is a ring, with a hole
+// that size in the middle.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Disc {
+    pub center: Point3D,
+    pub normal: Point3D,
+    pub radius: Float,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner_radius: Option<Float>,
+    pub material: Material,
+}
+
+impl Disc {
+    pub fn new(center: Point3D, normal: Point3D, radius: Float, material: Material) -> Disc {
+        Disc {
+            center,
+            normal: normal.unit_vector(),
+            radius,
+            inner_radius: None,
+            material,
+        }
+    }
+
+    pub fn with_hole(mut self, inner_radius: Float) -> Disc {
+        self.inner_radius = Some(inner_radius);
+        self
+    }
+}
+
+impl Hittable for Disc {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Scene files may not give a unit normal.
+        let normal = self.normal.unit_vector();
+        let denom = ray.direction.dot(&normal);
+        if denom.abs() < PARALLEL_EPSILON * ray.direction.length() {
+            return None;
+        }
+        let t = (self.center - ray.origin).dot(&normal) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let point = ray.at(t);
+        let offset = point - self.center;
+        let distance_squared = offset.length_squared();
+        let inner = self.inner_radius.unwrap_or(0.0);
+        if distance_squared > self.radius * self.radius || distance_squared < inner * inner {
+            return None;
+        }
+
+        // A square texture is laid flat over the disc, touching its edge.
+        let front_face = denom < 0.0;
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Some(HitRecord {
+            t,
+            point,
+            normal: if front_face { normal } else { -normal },
+            front_face,
+            material: &self.material,
+            u: 0.5 + offset.dot(&tangent) / (2.0 * self.radius),
+            v: 0.5 + offset.dot(&bitangent) / (2.0 * self.radius),
+            tangent: None,
+        })
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // Along each world axis the edge reaches out by the radius times the
+        // sine of that axis's angle with the normal. Some padding keeps the
+        // box from being flat.
+        let normal = self.normal.unit_vector();
+        let reach = |n: Float| (self.radius * (1.0 - n * n).max(0.0).sqrt()).max(1e-4);
+        let reach = Point3D::new(reach(normal.x()), reach(normal.y()), reach(normal.z()));
+        Some(Aabb::new(self.center - reach, self.center + reach))
+    }
+}
+
+#[test]
+fn test_disc_hit() {
+    let disc = Disc::new(
+        Point3D::new(0.0, 1.0, 0.0),
+        Point3D::new(0.0, -2.0, 0.0),
+        2.0,
+        Material::grey(),
+    );
+    // From below, the side it faces, and from above.
+    let up = Ray::new(Point3D::new(1.0, -1.0, 1.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = disc.hit(&up, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 2.0);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, -1.0, 0.0));
+    assert!((0.0..=1.0).contains(&hit.u) && (0.0..=1.0).contains(&hit.v));
+    let down = Ray::new(Point3D::new(1.0, 3.0, 1.0), Point3D::new(0.0, -1.0, 0.0));
+    let hit = disc.hit(&down, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 1.0, 0.0));
+
+    // Past the edge, and through the hole of a ring.
+    let outside = Ray::new(Point3D::new(1.5, -1.0, 1.5), Point3D::new(0.0, 1.0, 0.0));
+    assert!(disc.hit(&outside, 0.001, Float::MAX).is_none());
+    let ring = disc.clone().with_hole(1.0);
+    let centre = Ray::new(Point3D::new(0.5, -1.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    assert!(ring.hit(&centre, 0.001, Float::MAX).is_none());
+    assert!(ring.hit(&up, 0.001, Float::MAX).is_some());
+}
+
+#[test]
+fn test_disc_edges() {
+    // A ring tilted halfway between facing up and facing along x, from 1 to
+    // 2 around its centre, seen from straight above.
+    let center = Point3D::new(1.0, 2.0, 3.0);
+    let normal = Point3D::new(1.0, 1.0, 0.0).unit_vector();
+    let ring = Disc::new(center, normal, 2.0, Material::grey()).with_hole(1.0);
+    let (across, _) = normal.orthonormal_basis();
+    // Just inside either edge it's hit and just outside it isn't, all the
+    // way around.
+    for i in 0..36 {
+        let angle = (i * 10) as Float;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let outward = across * cos + normal.cross(&across) * sin;
+        for (r, hit) in [(0.99, false), (1.01, true), (1.99, true), (2.01, false)] {
+            let on_disc = center + outward * r;
+            let ray = Ray::new(
+                on_disc + Point3D::new(0.0, 5.0, 0.0),
+                Point3D::new(0.0, -1.0, 0.0),
+            );
+            assert_eq!(ring.hit(&ray, 0.001, Float::MAX).is_some(), hit);
+        }
+    }
+}
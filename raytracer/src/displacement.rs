@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::mesh::shade;
+use crate::mesh::Shading;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::triangle::Triangle;
+
+#[cfg(test)]
+use crate::mesh::Mesh;
+#[cfg(test)]
+use crate::object::Object;
+#[cfg(test)]
+use crate::texture::Noise;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use palette::Srgb;
+
+// Each level splits every triangle into four, so more than this would be
+// millions of triangles from even a small mesh.
+const MAX_SUBDIVISIONS: u32 = 8;
+
+fn default_subdivisions() -> u32 {
+    4
+}
+
+fn default_segments() -> usize {
+    64
+}
+
+// A corner being displaced: where it is, the way it's pushed, and where it
+// looks up the height.
+#[derive(Clone, Copy)]
+struct Corner {
+    point: Point3D,
+    normal: Point3D,
+    uv: (Float, Float),
+}
+
+impl Corner {
+    fn midpoint(&self, other: &Corner) -> Corner {
+        Corner {
+            point: (self.point + other.point) / 2.0,
+            normal: (self.normal + other.normal).unit_vector(),
+            uv: (
+                (self.uv.0 + other.uv.0) / 2.0,
+                (self.uv.1 + other.uv.1) / 2.0,
+            ),
+        }
+    }
+}
+
+// Real bumps: each triangle is split `subdivisions` times over and the
+// corners are pushed out along their normals by `scale` times the height
+// `map` gives there, black for none and white for all of it, so the outline
+// changes too. Done once, as the scene is built.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Displacement {
+    pub map: Texture,
+    pub scale: Float,
+    #[serde(default = "default_subdivisions")]
+    pub subdivisions: u32,
+}
+
+impl Displacement {
+    pub fn new(map: Texture, scale: Float, subdivisions: u32) -> Displacement {
+        Displacement {
+            map,
+            scale,
+            subdivisions,
+        }
+    }
+
+    // The displaced triangles, with smooth normals for their new shape.
+    // Corners shared before are shared after, as long as they had the same
+    // normal and texture coordinates, so no cracks open between
+    // triangles.
+    pub fn apply(&self, triangles: &[Triangle]) -> Vec<Triangle> {
+        // Corners without a normal of their own are pushed along the
+        // average of the faces around them, the same way from every face.
+        let mut triangles = triangles.to_vec();
+        shade(&mut triangles, Shading::Smooth);
+
+        let mut displaced = Vec::new();
+        for triangle in &triangles {
+            let normals = triangle.normals.unwrap();
+            let uvs = triangle.uvs.unwrap_or([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+            let points = [triangle.v0, triangle.v1, triangle.v2];
+            let corner = |i: usize| Corner {
+                point: points[i],
+                normal: normals[i],
+                uv: uvs[i],
+            };
+            let mut pieces = vec![[corner(0), corner(1), corner(2)]];
+            for _ in 0..self.subdivisions.min(MAX_SUBDIVISIONS) {
+                pieces = pieces
+                    .iter()
+                    .flat_map(|[a, b, c]| {
+                        let (ab, bc, ca) = (a.midpoint(b), b.midpoint(c), c.midpoint(a));
+                        [[*a, ab, ca], [ab, *b, bc], [ca, bc, *c], [ab, bc, ca]]
+                    })
+                    .collect();
+            }
+            for piece in pieces {
+                let [a, b, c] = piece.map(|corner| self.push(&corner));
+                let mut out = Triangle::new(a, b, c, triangle.material.clone());
+                if triangle.uvs.is_some() {
+                    out.uvs = Some(piece.map(|corner| corner.uv));
+                }
+                displaced.push(out);
+            }
+        }
+        shade(&mut displaced, Shading::Smooth);
+        displaced
+    }
+
+    fn push(&self, corner: &Corner) -> Point3D {
+        let (u, v) = corner.uv;
+        let height = self.map.height(u, v, &corner.point);
+        corner.point + corner.normal * (self.scale * height)
+    }
+}
+
+// A sphere with a displaced surface: tessellated into `segments` around
+// and half as many from pole to pole, then displaced, as the scene is
+// loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "DisplacedSphereParams")]
+pub struct DisplacedSphere {
+    pub center: Point3D,
+    pub radius: Float,
+    pub material: Material,
+    pub segments: usize,
+    pub displacement: Displacement,
+    #[serde(skip_serializing)]
+    triangles: Bvh<Triangle>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplacedSphereParams {
+    pub center: Point3D,
+    pub radius: Float,
+    pub material: Material,
+    #[serde(default = "default_segments")]
+    pub segments: usize,
+    pub displacement: Displacement,
+}
+
+impl From<DisplacedSphereParams> for DisplacedSphere {
+    fn from(p: DisplacedSphereParams) -> Self {
+        let sphere = Sphere::new(p.center, p.radius, p.material.clone());
+        let triangles = p.displacement.apply(&sphere.tessellate(p.segments));
+        DisplacedSphere {
+            center: p.center,
+            radius: p.radius,
+            material: p.material,
+            segments: p.segments,
+            displacement: p.displacement,
+            triangles: Bvh::new(triangles),
+        }
+    }
+}
+
+impl DisplacedSphere {
+    pub fn new(
+        center: Point3D,
+        radius: Float,
+        material: Material,
+        displacement: Displacement,
+    ) -> DisplacedSphere {
+        DisplacedSphere::from(DisplacedSphereParams {
+            center,
+            radius,
+            material,
+            segments: default_segments(),
+            displacement,
+        })
+    }
+}
+
+impl Hittable for DisplacedSphere {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.triangles.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.triangles.bounding_box(t0, t1)
+    }
+}
+
+#[test]
+fn test_displacement() {
+    let material = Material::grey();
+    // A square floor, raised all over by half of white.
+    let floor = [
+        Triangle::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, 1.0),
+            Point3D::new(1.0, 0.0, 0.0),
+            material.clone(),
+        ),
+        Triangle::new(
+            Point3D::new(1.0, 0.0, 0.0),
+            Point3D::new(0.0, 0.0, 1.0),
+            Point3D::new(1.0, 0.0, 1.0),
+            material.clone(),
+        ),
+    ];
+    let white = Texture::Solid(Srgb::new(1.0, 1.0, 1.0));
+    let raised = Displacement::new(white, 0.5, 2).apply(&floor);
+    assert_eq!(raised.len(), 2 * 16);
+    let mesh = Mesh::new(raised, material.clone());
+    let down = Ray::new(Point3D::new(0.3, 5.0, 0.6), Point3D::new(0.0, -1.0, 0.0));
+    let hit = mesh.hit(&down, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.y(), 0.5);
+    assert_approx_eq!(hit.normal.y(), 1.0);
+
+    // A lumpy ball stays closed: every edge is shared by two triangles.
+    let ball = Sphere::new(Point3D::new(0.0, 0.0, 0.0), 1.0, material);
+    let triangles = ball.tessellate(16);
+    // Wound to face out, as the sphere does.
+    assert!(triangles
+        .iter()
+        .all(|t| t.normal().dot(&(t.v0 + t.v1 + t.v2)) > 0.0));
+    let lumpy = Displacement::new(Texture::Noise(Noise::new(3.0, 7)), 0.2, 1).apply(&triangles);
+    assert_eq!(lumpy.len(), 4 * triangles.len());
+    let key = |p: &Point3D| [p.x(), p.y(), p.z()].map(Float::to_bits);
+    let mut edges = std::collections::HashMap::new();
+    for t in &lumpy {
+        for (a, b) in [(t.v0, t.v1), (t.v1, t.v2), (t.v2, t.v0)] {
+            let edge = if key(&a) < key(&b) {
+                (key(&a), key(&b))
+            } else {
+                (key(&b), key(&a))
+            };
+            *edges.entry(edge).or_insert(0) += 1;
+        }
+    }
+    assert!(edges.values().all(|n| *n == 2));
+    // And the lumps push it outwards, by up to the scale.
+    for t in &lumpy {
+        let r = t.v0.length();
+        assert!((1.0..=1.2 + 1e-9).contains(&r));
+    }
+}
+
+#[test]
+fn test_displaced_sphere() {
+    let json = r#"{"DisplacedSphere": {
+        "center": {"x": 0.0, "y": 0.0, "z": 0.0},
+        "radius": 1.0,
+        "material": {"Lambertian": {"albedo": [0.5, 0.5, 0.5]}},
+        "segments": 32,
+        "displacement": {"map": {"Noise": {"scale": 3.0, "seed": 7}}, "scale": 0.2, "subdivisions": 1}
+    }}"#;
+    let object: Object = serde_json::from_str(json).unwrap();
+    // Rays from all round land between the sphere and the tops of the
+    // lumps.
+    for direction in [
+        Point3D::new(1.0, 0.0, 0.0),
+        Point3D::new(0.0, -1.0, 0.0),
+        Point3D::new(0.3, 0.4, -0.8),
+    ] {
+        let ray = Ray::new(direction * -5.0, direction);
+        let hit = object.hit(&ray, 0.001, Float::MAX).unwrap();
+        let r = hit.point.length();
+        assert!((0.99..=1.2 + 1e-6).contains(&r));
+    }
+}
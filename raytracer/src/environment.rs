@@ -0,0 +1,181 @@
+#[cfg(feature = "images")]
+use image::hdr::HDRDecoder;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "images")]
+use std::fs::File;
+#[cfg(feature = "images")]
+use std::io::BufReader;
+
+use crate::float::consts::PI;
+use crate::float::Float;
+use crate::point3d::Point3D;
+
+#[cfg(all(test, feature = "images"))]
+use std::fs;
+
+// An equirectangular HDR image surrounding the scene, looked up by the
+// direction of rays that escape it. Pixel values are linear radiance and
+// are not limited to [0, 1], so bright regions (like the sun) light the
+// scene and show up in reflections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EnvironmentMap {
+    path: String,
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl TryFrom<String> for EnvironmentMap {
+    type Error = String;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        EnvironmentMap::load(&path)
+    }
+}
+
+impl From<EnvironmentMap> for String {
+    fn from(map: EnvironmentMap) -> Self {
+        map.path
+    }
+}
+
+#[cfg(not(feature = "images"))]
+fn load_hdr(path: &str) -> Result<(usize, usize, Vec<[f32; 3]>), String> {
+    Err(format!("{}: built without image support", path))
+}
+
+#[cfg(not(feature = "images"))]
+fn load_exr(path: &str) -> Result<(usize, usize, Vec<[f32; 3]>), String> {
+    Err(format!("{}: built without image support", path))
+}
+
+#[cfg(feature = "images")]
+fn load_hdr(path: &str) -> Result<(usize, usize, Vec<[f32; 3]>), String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let decoder = HDRDecoder::new(BufReader::new(file))
+        .map_err(|e| format!("{}: failed to decode image: {}", path, e))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()
+        .map_err(|e| format!("{}: failed to decode image: {}", path, e))?;
+    Ok((
+        metadata.width as usize,
+        metadata.height as usize,
+        pixels.into_iter().map(|p| p.data).collect(),
+    ))
+}
+
+#[cfg(feature = "images")]
+fn load_exr(path: &str) -> Result<(usize, usize, Vec<[f32; 3]>), String> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![[0.0; 3]; resolution.width() * resolution.height()],
+            )
+        },
+        |(width, pixels): &mut (usize, Vec<[f32; 3]>),
+         position,
+         (r, g, b, _): (f32, f32, f32, f32)| {
+            pixels[position.y() * *width + position.x()] = [r, g, b];
+        },
+    )
+    .map_err(|e| format!("{}: failed to decode image: {}", path, e))?;
+    let (width, pixels) = image.layer_data.channel_data.pixels;
+    Ok((width, pixels.len() / width, pixels))
+}
+
+impl EnvironmentMap {
+    // Loads a Radiance .hdr or OpenEXR .exr file, chosen by extension.
+    pub fn load(path: &str) -> Result<EnvironmentMap, String> {
+        let lower = path.to_lowercase();
+        let (width, height, pixels) = if lower.ends_with(".hdr") {
+            load_hdr(path)?
+        } else if lower.ends_with(".exr") {
+            load_exr(path)?
+        } else {
+            return Err(format!(
+                "{}: environment maps must be .hdr or .exr files",
+                path
+            ));
+        };
+        if width == 0 || height == 0 {
+            return Err(format!("{}: image is empty", path));
+        }
+        Ok(EnvironmentMap {
+            path: path.to_string(),
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    #[cfg(all(test, feature = "images"))]
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> Srgb {
+        let [r, g, b] = self.pixels[y * self.width + x];
+        Srgb::new(r, g, b)
+    }
+
+    // The centre of the image is straight down -z, the top row is +y.
+    pub fn lookup(&self, direction: &Point3D) -> Srgb {
+        let d = direction.unit_vector();
+        let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI);
+        let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * self.width as Float) as usize).min(self.width - 1);
+        let y = ((v * self.height as Float) as usize).min(self.height - 1);
+        let [r, g, b] = self.pixels[y * self.width + x];
+        Srgb::new(r, g, b)
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_load_hdr() {
+    // A 4x2 Radiance file, uncompressed: red on top, blue at the bottom.
+    // RGBE (128, 0, 0, 129) is 0.5 * 2^1 = 1.0.
+    let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 4\n".to_vec();
+    for _ in 0..4 {
+        data.extend_from_slice(&[128, 0, 0, 129]);
+    }
+    for _ in 0..4 {
+        data.extend_from_slice(&[0, 0, 128, 130]);
+    }
+    fs::write("/tmp/test_environment.hdr", data).unwrap();
+
+    let map = EnvironmentMap::load("/tmp/test_environment.hdr").unwrap();
+    let up = map.lookup(&Point3D::new(0.0, 1.0, 0.0));
+    let down = map.lookup(&Point3D::new(0.0, -1.0, 0.0));
+    assert_eq!(up, Srgb::new(1.0, 0.0, 0.0));
+    assert_eq!(down, Srgb::new(0.0, 0.0, 2.0));
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_load_exr() {
+    // Left half dark, right half bright.
+    exr::prelude::write_rgb_file("/tmp/test_environment.exr", 4, 2, |x, _| {
+        if x < 2 {
+            (0.0_f32, 0.0_f32, 0.0_f32)
+        } else {
+            (5.0_f32, 5.0_f32, 5.0_f32)
+        }
+    })
+    .unwrap();
+
+    let map = EnvironmentMap::load("/tmp/test_environment.exr").unwrap();
+    assert_eq!(
+        map.lookup(&Point3D::new(1.0, 0.0, 0.0)),
+        Srgb::new(5.0, 5.0, 5.0)
+    );
+    assert_eq!(
+        map.lookup(&Point3D::new(-1.0, 0.0, 0.0)),
+        Srgb::new(0.0, 0.0, 0.0)
+    );
+
+    let serialized = serde_json::to_string(&map).unwrap();
+    assert_eq!("\"/tmp/test_environment.exr\"", serialized);
+    assert!(EnvironmentMap::load("data/earth.jpg").is_err());
+}
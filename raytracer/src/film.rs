@@ -0,0 +1,247 @@
+use palette::Pixel;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use crate::output::Encoding;
+use crate::sampler::Estimate;
+
+#[cfg(test)]
+use crate::output::Transfer;
+#[cfg(test)]
+use crate::tone_map::ToneMap;
+
+// The image being rendered, as the running estimate of every pixel, so that
+// more samples can be added to it and it can be written out at any point.
+#[derive(Deserialize, Serialize)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Estimate>,
+}
+
+// A rectangle of the image, which is rendered as one piece of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![Estimate::default(); width * height],
+        }
+    }
+
+    // Row by row, top to bottom.
+    pub fn pixels(&self) -> &[Estimate] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Estimate] {
+        &mut self.pixels
+    }
+
+    // The image cut into `size` x `size` tiles, row by row; those along the
+    // right and bottom edges are cut short.
+    pub fn tiles(&self, size: usize) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y in (0..self.height).step_by(size) {
+            for x in (0..self.width).step_by(size) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: size.min(self.width - x),
+                    height: size.min(self.height - y),
+                });
+            }
+        }
+        tiles
+    }
+
+    // A copy of the estimates inside `tile`, row by row.
+    pub fn tile(&self, tile: &Tile) -> Vec<Estimate> {
+        let mut pixels = Vec::with_capacity(tile.width * tile.height);
+        for y in tile.y..tile.y + tile.height {
+            let start = y * self.width + tile.x;
+            pixels.extend_from_slice(&self.pixels[start..start + tile.width]);
+        }
+        pixels
+    }
+
+    // Puts back estimates taken out with `tile`.
+    pub fn set_tile(&mut self, tile: &Tile, pixels: &[Estimate]) {
+        for (row, y) in pixels.chunks(tile.width).zip(tile.y..) {
+            let start = y * self.width + tile.x;
+            self.pixels[start..start + tile.width].copy_from_slice(row);
+        }
+    }
+
+    // Saves everything rendered so far, so the render can be picked up again
+    // with `load`. The file is replaced in one step, so a crash while saving
+    // leaves the previous checkpoint intact.
+    pub fn save(&self, path: &str) -> Result<(), std::io::Error> {
+        let partial = format!("{}.partial", path);
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(&partial, path)
+    }
+
+    // Loads a checkpoint saved with `save`. One whose pixels don't fill its
+    // width and height, say cut short or edited by hand, is refused rather
+    // than left to go wrong when its tiles are taken out.
+    pub fn load(path: &str) -> Result<Film, std::io::Error> {
+        let json = fs::read(path)?;
+        let film = serde_json::from_slice::<Film>(&json)?;
+        if film.width.checked_mul(film.height) != Some(film.pixels.len()) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} has {} pixels for a {}x{} image",
+                    path,
+                    film.pixels.len(),
+                    film.width,
+                    film.height
+                ),
+            ));
+        }
+        Ok(film)
+    }
+
+    // The current estimate as opaque 8-bit RGBA, the layout of a browser's
+    // canvas.
+    pub fn to_rgba8(&self, encoding: &Encoding) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for estimate in &self.pixels {
+            bytes.extend_from_slice(&to_rgb8(estimate, encoding));
+            bytes.push(255);
+        }
+        bytes
+    }
+
+    // The current estimate as 8-bit RGB.
+    pub fn to_rgb8(&self, encoding: &Encoding) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+        for estimate in &self.pixels {
+            bytes.extend_from_slice(&to_rgb8(estimate, encoding));
+        }
+        bytes
+    }
+
+    // The current estimate as 16-bit RGB, which doesn't band in smooth
+    // gradients the way 8 bits can.
+    pub fn to_rgb16(&self, encoding: &Encoding) -> Vec<u16> {
+        let mut values = Vec::with_capacity(self.pixels.len() * 3);
+        for estimate in &self.pixels {
+            let color = encoding.display(estimate.mean());
+            let pixel: [u16; 3] = color.into_format().into_raw();
+            values.extend_from_slice(&pixel);
+        }
+        values
+    }
+}
+
+// One pixel's estimate as 8-bit RGB.
+pub fn to_rgb8(estimate: &Estimate, encoding: &Encoding) -> [u8; 3] {
+    let color: Srgb = encoding.display(estimate.mean());
+    color.into_format().into_raw()
+}
+
+#[test]
+fn test_film_to_rgb8() {
+    let mut film = Film::new(2, 1);
+    film.pixels_mut()[1].add(Srgb::new(0.25, 1.0, 0.0));
+    film.pixels_mut()[1].add(Srgb::new(0.25, 1.0, 0.0));
+    // A quarter of white is half way with the default gamma 2, and 137 in
+    // sRGB.
+    assert_eq!(
+        film.to_rgb8(&Encoding::default()),
+        vec![0, 0, 0, 128, 255, 0]
+    );
+    let srgb = Encoding {
+        transfer: Transfer::Srgb,
+        ..Default::default()
+    };
+    assert_eq!(film.to_rgb8(&srgb), vec![0, 0, 0, 137, 255, 0]);
+}
+
+#[test]
+fn test_film_checkpoint() {
+    let mut film = Film::new(3, 2);
+    film.pixels_mut()[4].add(Srgb::new(0.5, 0.25, 1.0));
+    film.pixels_mut()[4].add(Srgb::new(0.3, 0.25, 1.0));
+    film.save("/tmp/test_film_checkpoint.json").unwrap();
+    let loaded = Film::load("/tmp/test_film_checkpoint.json").unwrap();
+    assert_eq!((loaded.width, loaded.height), (3, 2));
+    assert_eq!(loaded.pixels()[4].count(), 2);
+    assert_eq!(
+        loaded.to_rgb8(&Encoding::default()),
+        film.to_rgb8(&Encoding::default())
+    );
+
+    // A checkpoint cut short is refused.
+    let mut json: serde_json::Value = serde_json::to_value(&film).unwrap();
+    json["pixels"].as_array_mut().unwrap().pop();
+    fs::write("/tmp/test_film_checkpoint_short.json", json.to_string()).unwrap();
+    let error = Film::load("/tmp/test_film_checkpoint_short.json")
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_film_tiles() {
+    let mut film = Film::new(5, 3);
+    let tiles = film.tiles(2);
+    assert_eq!(tiles.len(), 6);
+    assert_eq!(
+        tiles[5],
+        Tile {
+            x: 4,
+            y: 2,
+            width: 1,
+            height: 1
+        }
+    );
+    assert_eq!(tiles.iter().map(|t| t.width * t.height).sum::<usize>(), 15);
+
+    let mut pixels = film.tile(&tiles[1]);
+    assert_eq!(pixels.len(), 4);
+    pixels[3].add(Srgb::new(1.0, 1.0, 1.0));
+    film.set_tile(&tiles[1], &pixels);
+    // The tile's bottom right pixel is at (3, 1).
+    let counts: Vec<u32> = film.pixels().iter().map(|e| e.count()).collect();
+    assert_eq!(counts, vec![0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_film_to_rgb16() {
+    let mut film = Film::new(2, 1);
+    film.pixels_mut()[1].add(Srgb::new(0.25, 1.0, 0.0));
+    let gamma_2 = Encoding {
+        transfer: Transfer::Gamma(2.0),
+        ..Default::default()
+    };
+    assert_eq!(film.to_rgb16(&gamma_2), vec![0, 0, 0, 32768, 65535, 0]);
+}
+
+#[test]
+fn test_film_tone_map() {
+    let mut film = Film::new(1, 1);
+    film.pixels_mut()[0].add(Srgb::new(0.0, 1.0, 3.0));
+    // Clipped by default, but kept apart by Reinhard: 1 / (1 + 1) and
+    // 3 / (1 + 3), with gamma 2.
+    assert_eq!(film.to_rgb8(&Encoding::default()), vec![0, 255, 255]);
+    let encoding = Encoding {
+        tone_map: ToneMap::Reinhard,
+        transfer: Transfer::Gamma(2.0),
+        ..Default::default()
+    };
+    assert_eq!(film.to_rgb8(&encoding), vec![0, 180, 221]);
+}
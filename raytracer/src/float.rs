@@ -0,0 +1,54 @@
+// The floating point type all the geometry is done in: f64 by default, or f32
+// with the f32 feature, which is quicker and lighter on memory at the cost of
+// more visible self-intersection acne and less range. Colours stay f32
+// either way, as palette's Srgb.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+// How far from zero rounding can leave a result of a few operations on
+// numbers around 1 that should be zero: root finding takes anything closer
+// as zero.
+#[cfg(not(feature = "f32"))]
+pub const ROUNDING: Float = 1e-12;
+#[cfg(feature = "f32")]
+pub const ROUNDING: Float = 1e-5;
+
+// How close tests can expect a result to come to the exact answer.
+#[cfg(all(test, not(feature = "f32")))]
+pub const TOLERANCE: Float = 1e-6;
+#[cfg(all(test, feature = "f32"))]
+pub const TOLERANCE: Float = 1e-4;
+
+// The constants that go with it, e.g. consts::PI.
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+// A geometric quantity as a colour channel, which is f32 either way. This is
+// the one place it's converted, as with the f32 feature there's nothing to do.
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(x: Float) -> f32 {
+    x as f32
+}
+
+// A constant given to f64's precision, as published tables of them are, as a
+// Float: with the f32 feature it's rounded once here.
+#[allow(clippy::unnecessary_cast)]
+pub fn from_f64(x: f64) -> Float {
+    x as Float
+}
+
+#[test]
+fn test_float() {
+    let bytes = if cfg!(feature = "f32") { 4 } else { 8 };
+    assert_eq!(std::mem::size_of::<Float>(), bytes);
+    assert_eq!(to_f32(from_f64(0.1)), 0.1);
+    assert_eq!(from_f64(std::f64::consts::PI), consts::PI);
+    // Root finding takes what's left of a few operations that should cancel
+    // out as zero.
+    let third = from_f64(1.0) / 3.0;
+    assert!((third * 7.0 - third * 4.0 - 1.0).abs() < ROUNDING);
+}
@@ -0,0 +1,821 @@
+use palette::Srgb;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::camera::Projection;
+use crate::config::Config;
+use crate::config::Sky;
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::materials::DiffuseLight;
+use crate::materials::Material;
+use crate::mesh::Mesh;
+use crate::normal_map::NormalMap;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::principled::Principled;
+use crate::ray::Hittable;
+use crate::texture::ImageTexture;
+use crate::texture::Texture;
+use crate::transform::Matrix4;
+use crate::triangle::Triangle;
+
+#[cfg(test)]
+use crate::ray::Ray;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// What a glTF file doesn't say about how to render it.
+const WIDTH: usize = 800;
+const SAMPLES_PER_PIXEL: u32 = 64;
+const MAX_DEPTH: usize = 50;
+
+// The parts of a glTF 2.0 document that are imported.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Document {
+    scene: Option<usize>,
+    scenes: Vec<SceneDef>,
+    nodes: Vec<Node>,
+    meshes: Vec<MeshDef>,
+    accessors: Vec<Accessor>,
+    buffer_views: Vec<BufferView>,
+    buffers: Vec<Buffer>,
+    materials: Vec<MaterialDef>,
+    cameras: Vec<CameraDef>,
+    textures: Vec<TextureDef>,
+    images: Vec<ImageDef>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SceneDef {
+    nodes: Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Node {
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    camera: Option<usize>,
+    // Column by column.
+    matrix: Option<[Float; 16]>,
+    translation: Option<[Float; 3]>,
+    // A unit quaternion, (x, y, z, w).
+    rotation: Option<[Float; 4]>,
+    scale: Option<[Float; 3]>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MeshDef {
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Primitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    mode: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    byte_offset: usize,
+    component_type: u32,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    normalized: bool,
+    sparse: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Buffer {
+    uri: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct MaterialDef {
+    pbr_metallic_roughness: Pbr,
+    normal_texture: Option<NormalTextureRef>,
+    emissive_factor: [Float; 3],
+    extensions: MaterialExtensions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Pbr {
+    base_color_factor: [Float; 4],
+    base_color_texture: Option<TextureRef>,
+    metallic_factor: Float,
+    roughness_factor: Float,
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Pbr {
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TextureRef {
+    index: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct NormalTextureRef {
+    index: usize,
+    #[serde(default = "one")]
+    scale: Float,
+}
+
+fn one() -> Float {
+    1.0
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_transmission")]
+    transmission: Option<Transmission>,
+    #[serde(rename = "KHR_materials_ior")]
+    ior: Option<Ior>,
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    emissive_strength: Option<EmissiveStrength>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Transmission {
+    transmission_factor: Float,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ior {
+    ior: Float,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmissiveStrength {
+    emissive_strength: Float,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CameraDef {
+    perspective: Option<Perspective>,
+    orthographic: Option<Orthographic>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Perspective {
+    // Radians.
+    yfov: Float,
+    aspect_ratio: Option<Float>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Orthographic {
+    // Half the width and height of the view.
+    xmag: Float,
+    ymag: Float,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TextureDef {
+    source: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ImageDef {
+    uri: Option<String>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// Reads a .gltf file, or a binary .glb, into a scene: every mesh of the
+// default scene with its node's transforms baked in, its metallic-roughness
+// materials as Principled ones (or lights, if emissive), and the first
+// camera found. Buffers and images are looked for next to the file.
+pub fn load(path: &str) -> Result<Config, Error> {
+    let bytes = fs::read(path)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    import(&bytes, dir).map_err(|e| invalid(format!("{}: {}", path, e)))
+}
+
+fn import(bytes: &[u8], dir: &Path) -> Result<Config, Error> {
+    let (json, bin) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes)?
+    } else {
+        (bytes, None)
+    };
+    let document: Document =
+        serde_json::from_slice(json).map_err(|e| invalid(format!("bad glTF: {}", e)))?;
+    let buffers = document
+        .buffers
+        .iter()
+        .map(|buffer| match &buffer.uri {
+            Some(uri) => read_uri(uri, dir),
+            None => bin
+                .map(|b| b.to_vec())
+                .ok_or_else(|| invalid("a buffer has no data".to_string())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let materials = document
+        .materials
+        .iter()
+        .map(|m| import_material(&document, m, dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut importer = Importer {
+        document: &document,
+        buffers,
+        materials,
+        objects: Vec::new(),
+        camera: None,
+    };
+    let roots = match document.scene.and_then(|i| document.scenes.get(i)) {
+        Some(scene) => scene.nodes.clone(),
+        None => match document.scenes.first() {
+            Some(scene) => scene.nodes.clone(),
+            // Without scenes, every node that isn't a child is a root.
+            None => (0..document.nodes.len())
+                .filter(|i| !document.nodes.iter().any(|n| n.children.contains(i)))
+                .collect(),
+        },
+    };
+    for root in roots {
+        importer.visit(root, Matrix4::identity(), 0)?;
+    }
+
+    let (camera, aspect) = match importer.camera {
+        Some(camera) => camera,
+        None => (default_camera(&importer.objects), 1.5),
+    };
+    Ok(Config {
+        width: WIDTH,
+        height: (WIDTH as Float / aspect).round() as usize,
+        samples_per_pixel: SAMPLES_PER_PIXEL,
+        max_depth: MAX_DEPTH,
+        sky: Some(Sky::new_default_sky()),
+        camera,
+        objects: importer.objects,
+        ..Default::default()
+    })
+}
+
+// A .glb is a header, then a JSON chunk and optionally a binary one.
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), Error> {
+    let word = |at: usize| -> Result<usize, Error> {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid("truncated .glb".to_string()))
+    };
+    if word(4)? != 2 {
+        return Err(invalid("only glTF 2.0 is supported".to_string()));
+    }
+    let end = word(8)?.min(bytes.len());
+    let (mut json, mut bin) = (None, None);
+    let mut at = 12;
+    while at + 8 <= end {
+        let length = word(at)?;
+        let chunk = bytes
+            .get(at + 8..at + 8 + length)
+            .ok_or_else(|| invalid("truncated .glb".to_string()))?;
+        match &bytes[at + 4..at + 8] {
+            b"JSON" => json = Some(chunk),
+            b"BIN\0" => bin = Some(chunk),
+            _ => {}
+        }
+        at += 8 + length;
+    }
+    let json = json.ok_or_else(|| invalid(".glb has no JSON chunk".to_string()))?;
+    Ok((json, bin))
+}
+
+fn read_uri(uri: &str, dir: &Path) -> Result<Vec<u8>, Error> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .ok_or_else(|| invalid("only base64 data URIs are supported".to_string()))?;
+        return decode_base64(encoded).ok_or_else(|| invalid("bad base64 data".to_string()));
+    }
+    fs::read(dir.join(uri))
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text
+        .bytes()
+        .filter(|c| *c != b'=' && !c.is_ascii_whitespace())
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+fn import_material(document: &Document, m: &MaterialDef, dir: &Path) -> Result<Material, Error> {
+    let extensions = &m.extensions;
+    let strength = extensions
+        .emissive_strength
+        .as_ref()
+        .map_or(1.0, |e| e.emissive_strength);
+    let [r, g, b] = m.emissive_factor.map(|c| to_f32(c * strength));
+    if r > 0.0 || g > 0.0 || b > 0.0 {
+        return Ok(Material::DiffuseLight(DiffuseLight::new(Srgb::new(
+            r, g, b,
+        ))));
+    }
+    let pbr = &m.pbr_metallic_roughness;
+    let [r, g, b, _] = pbr.base_color_factor.map(to_f32);
+    let mut principled = Principled::new(
+        Srgb::new(r, g, b),
+        pbr.metallic_factor,
+        pbr.roughness_factor,
+    );
+    // A texture replaces the factor rather than being tinted by it.
+    if let Some(texture) = &pbr.base_color_texture {
+        if let Some(image) = load_image(document, texture.index, dir)? {
+            principled.base_color = Texture::Image(image);
+        }
+    }
+    if let Some(transmission) = &extensions.transmission {
+        principled.transmission = transmission.transmission_factor;
+    }
+    if let Some(ior) = &extensions.ior {
+        principled.ior = ior.ior;
+    }
+    let material = Material::Principled(principled);
+    if let Some(normal) = &m.normal_texture {
+        if let Some(image) = load_image(document, normal.index, dir)? {
+            let map = NormalMap::new(Texture::Image(image), material).with_strength(normal.scale);
+            return Ok(Material::NormalMap(map));
+        }
+    }
+    Ok(material)
+}
+
+// Images embedded in a buffer aren't supported, and are left out.
+fn load_image(
+    document: &Document,
+    texture: usize,
+    dir: &Path,
+) -> Result<Option<ImageTexture>, Error> {
+    let uri = document
+        .textures
+        .get(texture)
+        .and_then(|t| t.source)
+        .and_then(|i| document.images.get(i))
+        .and_then(|i| i.uri.as_ref());
+    match uri {
+        Some(uri) => {
+            let path = dir.join(uri);
+            ImageTexture::load(&path.to_string_lossy())
+                .map(Some)
+                .map_err(invalid)
+        }
+        None => Ok(None),
+    }
+}
+
+// Nodes deeper than this are taken to be a cycle.
+const MAX_NODE_DEPTH: usize = 256;
+
+struct Importer<'a> {
+    document: &'a Document,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Material>,
+    objects: Vec<Object>,
+    camera: Option<(Camera, Float)>,
+}
+
+impl Importer<'_> {
+    fn visit(&mut self, index: usize, parent: Matrix4, depth: usize) -> Result<(), Error> {
+        let node = self
+            .document
+            .nodes
+            .get(index)
+            .ok_or_else(|| invalid(format!("no node {}", index)))?;
+        if depth > MAX_NODE_DEPTH {
+            return Err(invalid("the nodes form a cycle".to_string()));
+        }
+        let world = parent * local_matrix(node);
+        if let Some(mesh) = node.mesh {
+            let mesh = self
+                .document
+                .meshes
+                .get(mesh)
+                .ok_or_else(|| invalid(format!("no mesh {}", mesh)))?;
+            for primitive in &mesh.primitives {
+                // Points and lines have nothing to hit.
+                if primitive.mode.unwrap_or(4) != 4 {
+                    continue;
+                }
+                let material = match primitive.material {
+                    Some(i) => self
+                        .materials
+                        .get(i)
+                        .cloned()
+                        .ok_or_else(|| invalid(format!("no material {}", i)))?,
+                    // The spec's default: white, fully rough and metallic.
+                    None => {
+                        Material::Principled(Principled::new(Srgb::new(1.0, 1.0, 1.0), 1.0, 1.0))
+                    }
+                };
+                let triangles = self.triangles(primitive, &world, &material)?;
+                if !triangles.is_empty() {
+                    self.objects
+                        .push(Object::from(Mesh::new(triangles, material)));
+                }
+            }
+        }
+        if let (Some(camera), None) = (node.camera, &self.camera) {
+            let camera = self
+                .document
+                .cameras
+                .get(camera)
+                .ok_or_else(|| invalid(format!("no camera {}", camera)))?;
+            self.camera = Some(import_camera(camera, &world));
+        }
+        for child in &node.children {
+            self.visit(*child, world, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn triangles(
+        &self,
+        primitive: &Primitive,
+        world: &Matrix4,
+        material: &Material,
+    ) -> Result<Vec<Triangle>, Error> {
+        let attribute = |name: &str, width: usize| -> Result<Option<Vec<Float>>, Error> {
+            match primitive.attributes.get(name) {
+                Some(i) => self.read(*i, width).map(Some),
+                None => Ok(None),
+            }
+        };
+        let positions = attribute("POSITION", 3)?
+            .ok_or_else(|| invalid("a primitive has no POSITION".to_string()))?;
+        let normals = attribute("NORMAL", 3)?;
+        let uvs = attribute("TEXCOORD_0", 2)?;
+        let count = positions.len() / 3;
+        let indices: Vec<usize> = match primitive.indices {
+            Some(i) => self.read(i, 1)?.iter().map(|i| *i as usize).collect(),
+            None => (0..count).collect(),
+        };
+        if let Some(i) = indices.iter().find(|i| **i >= count) {
+            return Err(invalid(format!("vertex index {} out of range", i)));
+        }
+
+        let position = |i: usize| {
+            let p = Point3D::new(positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]);
+            world.point(&p)
+        };
+        let normal = |n: &[Float], i: usize| {
+            transform_normal(world, &Point3D::new(n[3 * i], n[3 * i + 1], n[3 * i + 2]))
+        };
+        // glTF counts v down from the top of the image.
+        let uv = |t: &[Float], i: usize| (t[2 * i], 1.0 - t[2 * i + 1]);
+        Ok(indices
+            .chunks_exact(3)
+            .map(|face| {
+                let [a, b, c] = [face[0], face[1], face[2]];
+                let mut triangle =
+                    Triangle::new(position(a), position(b), position(c), material.clone());
+                if let Some(n) = &normals {
+                    triangle.normals = Some([normal(n, a), normal(n, b), normal(n, c)]);
+                }
+                if let Some(t) = &uvs {
+                    triangle.uvs = Some([uv(t, a), uv(t, b), uv(t, c)]);
+                }
+                triangle
+            })
+            .collect())
+    }
+
+    // An accessor's elements, `width` numbers each, flattened.
+    fn read(&self, index: usize, width: usize) -> Result<Vec<Float>, Error> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| invalid(format!("no accessor {}", index)))?;
+        let expected = match width {
+            1 => "SCALAR",
+            2 => "VEC2",
+            _ => "VEC3",
+        };
+        if accessor.kind != expected {
+            return Err(invalid(format!("accessor {} isn't a {}", index, expected)));
+        }
+        if accessor.sparse.is_some() {
+            return Err(invalid("sparse accessors aren't supported".to_string()));
+        }
+        let size = match accessor.component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            t => return Err(invalid(format!("unknown component type {}", t))),
+        };
+        let view = match accessor.buffer_view {
+            Some(v) => self
+                .document
+                .buffer_views
+                .get(v)
+                .ok_or_else(|| invalid(format!("no buffer view {}", v)))?,
+            // Without a view, every element is 0.
+            None => return Ok(vec![0.0; accessor.count * width]),
+        };
+        let buffer = self
+            .buffers
+            .get(view.buffer)
+            .ok_or_else(|| invalid(format!("no buffer {}", view.buffer)))?;
+        let data = buffer
+            .get(view.byte_offset..view.byte_offset + view.byte_length)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "buffer view past the end of buffer {}",
+                    view.buffer
+                ))
+            })?;
+        let stride = view.byte_stride.unwrap_or(size * width);
+
+        let mut values = Vec::with_capacity(accessor.count * width);
+        for i in 0..accessor.count {
+            for j in 0..width {
+                let at = accessor.byte_offset + i * stride + j * size;
+                let b = data.get(at..at + size).ok_or_else(|| {
+                    invalid(format!("accessor {} past the end of its view", index))
+                })?;
+                let (value, max) = match accessor.component_type {
+                    5120 => (b[0] as i8 as Float, 127.0),
+                    5121 => (b[0] as Float, 255.0),
+                    5122 => (i16::from_le_bytes([b[0], b[1]]) as Float, 32767.0),
+                    5123 => (u16::from_le_bytes([b[0], b[1]]) as Float, 65535.0),
+                    5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as Float, 1.0),
+                    _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as Float, 1.0),
+                };
+                values.push(if accessor.normalized {
+                    (value / max).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+        Ok(values)
+    }
+}
+
+// A node's own transform: a matrix, or scaling, then turning, then moving.
+fn local_matrix(node: &Node) -> Matrix4 {
+    if let Some(columns) = node.matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, value) in columns.iter().enumerate() {
+            m[i % 4][i / 4] = *value;
+        }
+        return Matrix4 { m };
+    }
+    let [tx, ty, tz] = node.translation.unwrap_or([0.0; 3]);
+    let [sx, sy, sz] = node.scale.unwrap_or([1.0; 3]);
+    let [x, y, z, w] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+    let rotation = Matrix4 {
+        m: [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+    Matrix4::translation(Point3D::new(tx, ty, tz))
+        * rotation
+        * Matrix4::scaling(Point3D::new(sx, sy, sz))
+}
+
+// Normals go through the cofactor matrix, which is the inverse transpose
+// scaled by the determinant, so the matrix needn't be invertible-by-steps.
+fn transform_normal(m: &Matrix4, n: &Point3D) -> Point3D {
+    let column = |j: usize| Point3D::new(m.m[0][j], m.m[1][j], m.m[2][j]);
+    let (c0, c1, c2) = (column(0), column(1), column(2));
+    (c1.cross(&c2) * n.x() + c2.cross(&c0) * n.y() + c0.cross(&c1) * n.z()).unit_vector()
+}
+
+// A glTF camera looks down its node's -z with +y up. Also returns the
+// width over the height of the image it's for.
+fn import_camera(camera: &CameraDef, world: &Matrix4) -> (Camera, Float) {
+    let look_from = world.point(&Point3D::new(0.0, 0.0, 0.0));
+    let look_at = look_from + world.vector(&Point3D::new(0.0, 0.0, -1.0)).unit_vector();
+    let vup = world.vector(&Point3D::new(0.0, 1.0, 0.0));
+    if let Some(o) = &camera.orthographic {
+        let aspect = o.xmag / o.ymag;
+        let camera = Camera::new(look_from, look_at, vup, 90.0, aspect, 0.0, 1.0).with_projection(
+            Projection::Orthographic {
+                height: 2.0 * o.ymag,
+            },
+        );
+        return (camera, aspect);
+    }
+    let (vfov, aspect) = match &camera.perspective {
+        Some(p) => (p.yfov.to_degrees(), p.aspect_ratio.unwrap_or(1.5)),
+        None => (40.0, 1.5),
+    };
+    (
+        Camera::new(look_from, look_at, vup, vfov, aspect, 0.0, 1.0),
+        aspect,
+    )
+}
+
+// Without a camera in the file, look at everything from the front (+z).
+fn default_camera(objects: &[Object]) -> Camera {
+    let bounds = objects
+        .iter()
+        .filter_map(|o| o.bounding_box(0.0, 1.0))
+        .reduce(|a, b| Aabb::surrounding_box(&a, &b))
+        .unwrap_or(Aabb::new(
+            Point3D::new(-1.0, -1.0, -1.0),
+            Point3D::new(1.0, 1.0, 1.0),
+        ));
+    let center = (bounds.min + bounds.max) / 2.0;
+    let radius = (bounds.max - bounds.min).length() / 2.0;
+    let vfov: Float = 40.0;
+    let distance = radius / (vfov.to_radians() / 2.0).sin();
+    Camera::new(
+        center + Point3D::new(0.0, 0.0, bounds.max.z() - center.z() + distance),
+        center,
+        Point3D::new(0.0, 1.0, 0.0),
+        vfov,
+        1.5,
+        0.0,
+        1.0,
+    )
+}
+
+#[cfg(test)]
+fn test_document(buffer_uri: Option<&str>) -> String {
+    // One triangle at z = 0 moved back to z = -5, and a camera at the
+    // origin; the quad's indices are u16s after the f32 positions.
+    let uri = match buffer_uri {
+        Some(uri) => format!(r#""uri":"{}","#, uri),
+        None => String::new(),
+    };
+    format!(
+        r#"{{
+        "asset": {{"version": "2.0"}},
+        "scene": 0,
+        "scenes": [{{"nodes": [0, 1]}}],
+        "nodes": [
+            {{"mesh": 0, "translation": [0, 0, -5],
+              "rotation": [0, 0, 0.7071067811865476, 0.7071067811865476]}},
+            {{"camera": 0}}
+        ],
+        "cameras": [{{"type": "perspective",
+            "perspective": {{"yfov": 0.7853981633974483, "aspectRatio": 2.0,
+                            "znear": 0.1}}}}],
+        "meshes": [{{"primitives": [
+            {{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}
+        ]}}],
+        "materials": [{{"pbrMetallicRoughness": {{
+            "baseColorFactor": [0.8, 0.1, 0.1, 1.0],
+            "metallicFactor": 0.0, "roughnessFactor": 0.5}}}}],
+        "accessors": [
+            {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+            {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+        ],
+        "bufferViews": [
+            {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+            {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+        ],
+        "buffers": [{{{}"byteLength": 42}}]
+    }}"#,
+        uri
+    )
+}
+
+#[cfg(test)]
+fn test_buffer() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for c in [-1.0f32, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0] {
+        bytes.extend(c.to_le_bytes());
+    }
+    for i in [0u16, 1, 2] {
+        bytes.extend(i.to_le_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn test_import_gltf() {
+    let base64 = "AACAvwAAgL8AAAAAAACAPwAAgL8AAAAAAAAAAAAAgD8AAAAAAAABAAIA";
+    assert_eq!(decode_base64(base64).unwrap(), test_buffer());
+    let uri = format!("data:application/octet-stream;base64,{}", base64);
+    let json = test_document(Some(&uri));
+    let scene = import(json.as_bytes(), Path::new("")).unwrap();
+    assert_eq!(scene.objects.len(), 1);
+    assert_eq!((scene.width, scene.height), (800, 400));
+    // 45 degrees from the top of the view to the bottom.
+    assert_approx_eq!(
+        scene.camera.vertical.length(),
+        2.0 * Float::to_radians(22.5).tan()
+    );
+
+    // Turned a quarter around z, the triangle's tip points along -x.
+    let ray = Ray::new(Point3D::new(-0.9, 0.0, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = scene.objects[0].hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 5.0);
+    assert!(hit.front_face);
+    match hit.material {
+        Material::Principled(p) => {
+            assert_eq!(p.metallic, 0.0);
+            assert_eq!(p.roughness, 0.5);
+        }
+        m => panic!("expected a Principled material, got {:?}", m),
+    }
+    let above = Ray::new(Point3D::new(0.0, 0.9, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(scene.objects[0].hit(&above, 0.001, Float::MAX).is_none());
+}
+
+#[test]
+fn test_import_glb() {
+    let mut json = test_document(None).into_bytes();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let bin = test_buffer();
+    let mut glb = Vec::new();
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(&json);
+    glb.extend((bin.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(&bin);
+    let scene = import(&glb, Path::new("")).unwrap();
+    assert_eq!(scene.objects.len(), 1);
+
+    // Without the binary chunk, the buffer has no data.
+    let truncated = &glb[..12 + 8 + json.len()];
+    assert!(import(truncated, Path::new("")).is_err());
+}
@@ -0,0 +1,454 @@
+use std::str::FromStr;
+
+use crate::bvh::Bvh;
+use crate::camera::Projection;
+use crate::config::Config;
+use crate::config::Sky;
+use crate::float::to_f32;
+use crate::materials::Material;
+use crate::object::Object;
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+
+#[cfg(feature = "gpu")]
+use crate::film::Film;
+#[cfg(all(test, feature = "gpu"))]
+use crate::raytracer::RenderSettings;
+#[cfg(all(test, feature = "gpu"))]
+use crate::raytracer::Renderer;
+#[cfg(feature = "gpu")]
+use crate::sampler::Estimate;
+#[cfg(feature = "gpu")]
+use palette::Srgb;
+#[cfg(feature = "gpu")]
+use tracing::info;
+#[cfg(feature = "gpu")]
+use wgpu::util::DeviceExt;
+
+#[cfg(test)]
+use crate::camera::Camera;
+#[cfg(test)]
+use crate::materials::Lambertian;
+#[cfg(test)]
+use crate::materials::Metal;
+#[cfg(test)]
+use crate::point3d::Point3D;
+#[cfg(test)]
+use crate::texture::Checker;
+#[cfg(test)]
+use naga::valid::Capabilities;
+#[cfg(test)]
+use naga::valid::ValidationFlags;
+#[cfg(test)]
+use naga::valid::Validator;
+#[cfg(test)]
+use naga::TypeInner;
+#[cfg(all(test, not(feature = "gpu")))]
+use palette::Srgb;
+
+// Material kinds, as stored in the first field of a packed material.
+pub const LAMBERTIAN: f32 = 0.0;
+pub const METAL: f32 = 1.0;
+pub const GLASS: f32 = 2.0;
+pub const DIFFUSE_LIGHT: f32 = 3.0;
+
+// Where the image is rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Cpu,
+    // The compute shader, which renders only what GpuScene can hold, and
+    // only the image, with none of the passes.
+    Gpu,
+}
+
+// For the command line, e.g. `--backend gpu`. Without the gpu feature
+// there is only the CPU.
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s.to_lowercase().as_str() {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" if cfg!(feature = "gpu") => Ok(Backend::Gpu),
+            "gpu" => Err("built without the gpu feature".to_string()),
+            _ => Err(format!("unknown backend: {}", s)),
+        }
+    }
+}
+
+// The path tracer, in WGSL.
+pub const SHADER: &str = include_str!("gpu.wgsl");
+
+// A scene laid out as plain arrays of 32-byte records, ready to be copied
+// into storage buffers for the compute shader to trace. Only what the shader
+// supports can be: spheres with solid-coloured Lambertian, Metal, Glass or
+// DiffuseLight materials.
+#[derive(Debug)]
+pub struct GpuScene {
+    // Center x, y, z, radius, material index, then padding; in the order
+    // the BVH leaves refer to them.
+    pub spheres: Vec<[f32; 8]>,
+    // Kind, r, g, b (albedo, or emission for lights), fuzz or index of
+    // refraction, then padding.
+    pub materials: Vec<[f32; 8]>,
+    // As described for Bvh::packed_nodes.
+    pub nodes: Vec<[f32; 8]>,
+}
+
+impl GpuScene {
+    pub fn new(scene: &Config) -> Result<GpuScene, String> {
+        let mut spheres = Vec::new();
+        for object in &scene.objects {
+            match object {
+                Object::Sphere(sphere) => spheres.push(sphere.clone()),
+                _ => return Err("Only spheres can be rendered on the GPU".to_string()),
+            }
+        }
+        let bvh = Bvh::new(spheres);
+
+        let mut packed = Vec::new();
+        let mut materials = Vec::new();
+        for (i, sphere) in bvh.objects().iter().enumerate() {
+            materials.push(pack_material(&sphere.material)?);
+            packed.push(pack_sphere(sphere, i));
+        }
+        Ok(GpuScene {
+            spheres: packed,
+            materials,
+            nodes: bvh.packed_nodes(),
+        })
+    }
+}
+
+fn pack_sphere(sphere: &Sphere, material: usize) -> [f32; 8] {
+    [
+        to_f32(sphere.center.x()),
+        to_f32(sphere.center.y()),
+        to_f32(sphere.center.z()),
+        to_f32(sphere.radius),
+        material as f32,
+        0.0,
+        0.0,
+        0.0,
+    ]
+}
+
+fn pack_material(material: &Material) -> Result<[f32; 8], String> {
+    let (kind, color, parameter) = match material {
+        Material::Lambertian(l) => (LAMBERTIAN, &l.albedo, 0.0),
+        Material::Metal(m) => (METAL, &m.albedo, to_f32(m.fuzz)),
+        Material::Glass(g) => {
+            return Ok([
+                GLASS,
+                1.0,
+                1.0,
+                1.0,
+                to_f32(g.index_of_refraction),
+                0.0,
+                0.0,
+                0.0,
+            ])
+        }
+        Material::DiffuseLight(d) => (DIFFUSE_LIGHT, &d.emit, 0.0),
+        _ => return Err(
+            "Only Lambertian, Metal, Glass and DiffuseLight materials can be rendered on the GPU"
+                .to_string(),
+        ),
+    };
+    match color {
+        Texture::Solid(c) => Ok([kind, c.red, c.green, c.blue, parameter, 0.0, 0.0, 0.0]),
+        _ => Err("Only solid colours can be rendered on the GPU".to_string()),
+    }
+}
+
+// Records as the little-endian bytes a storage buffer is filled with.
+pub fn as_bytes(records: &[[f32; 8]]) -> Vec<u8> {
+    records
+        .iter()
+        .flatten()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+// How many 32-bit values the shader's Params hold.
+const PARAMS: usize = 32;
+
+// The shader's Params for `scene`, but for which sample a dispatch takes:
+// the camera, the sky, the image size and depth, and the seed. Only what
+// the shader can render is accepted.
+pub fn params(scene: &Config, seed: u64, nodes: usize) -> Result<[u32; PARAMS], String> {
+    let camera = &scene.camera;
+    if camera.projection() != Projection::Perspective || camera.stereo().is_some() {
+        return Err("Only perspective cameras can be rendered on the GPU".to_string());
+    }
+    if !scene.point_lights.is_empty() {
+        return Err("Point lights can't be rendered on the GPU".to_string());
+    }
+    let sky = match &scene.sky {
+        None => 0.0,
+        Some(Sky {
+            texture: None,
+            environment: None,
+        }) => 1.0,
+        Some(_) => return Err("Only the default sky can be rendered on the GPU".to_string()),
+    };
+    let vector = |p: crate::point3d::Point3D, w: f32| {
+        [to_f32(p.x()), to_f32(p.y()), to_f32(p.z()), w].map(f32::to_bits)
+    };
+    let mut params = [0; PARAMS];
+    params[0..4].copy_from_slice(&vector(camera.origin, to_f32(camera.lens_radius())));
+    params[4..8].copy_from_slice(&vector(camera.lower_left_corner, sky));
+    params[8..12].copy_from_slice(&vector(camera.horizontal, 0.0));
+    params[12..16].copy_from_slice(&vector(camera.vertical, 0.0));
+    params[16..20].copy_from_slice(&vector(camera.right(), 0.0));
+    params[20..24].copy_from_slice(&vector(camera.up(), 0.0));
+    params[24..28].copy_from_slice(&[
+        scene.width as u32,
+        scene.height as u32,
+        scene.max_depth as u32,
+        0,
+    ]);
+    params[28..32].copy_from_slice(&[seed as u32, (seed >> 32) as u32, nodes as u32, 0]);
+    Ok(params)
+}
+
+// Renders `scene` on the first GPU wgpu finds, one sample per pixel per
+// dispatch so that no dispatch runs long enough for the driver to give up
+// on it.
+#[cfg(feature = "gpu")]
+pub fn render(scene: &Config, seed: u64) -> Result<Film, String> {
+    let gpu = GpuScene::new(scene)?;
+    let params = params(scene, seed, gpu.nodes.len())?;
+    pollster::block_on(run(scene, &gpu, params))
+}
+
+#[cfg(feature = "gpu")]
+async fn run(scene: &Config, gpu: &GpuScene, mut params: [u32; PARAMS]) -> Result<Film, String> {
+    let instance =
+        wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .map_err(|e| format!("No GPU found: {}", e))?;
+    let info = adapter.get_info();
+    info!("Rendering on {} ({:?})", info.name, info.backend);
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor::default())
+        .await
+        .map_err(|e| format!("Unable to open the GPU: {}", e))?;
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("path tracer"),
+        source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("path tracer"),
+        layout: None,
+        module: &module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+
+    let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("params"),
+        size: 4 * PARAMS as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    // Storage buffers can't be empty.
+    let storage = |label, records: &[[f32; 8]]| {
+        let mut contents = as_bytes(records);
+        contents.resize(contents.len().max(32), 0);
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: &contents,
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    };
+    let spheres = storage("spheres", &gpu.spheres);
+    let materials = storage("materials", &gpu.materials);
+    let nodes = storage("nodes", &gpu.nodes);
+    let size = (scene.width * scene.height * 16) as u64;
+    let pixels = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("pixels"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("scene"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[&uniform, &spheres, &materials, &nodes, &pixels]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+    });
+
+    let groups = |n: usize| (n as u32).div_ceil(8);
+    for sample in 0..scene.samples_per_pixel {
+        params[27] = sample;
+        let bytes: Vec<u8> = params.iter().flat_map(|p| p.to_le_bytes()).collect();
+        queue.write_buffer(&uniform, 0, &bytes);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups(scene.width), groups(scene.height), 1);
+        }
+        if sample + 1 == scene.samples_per_pixel {
+            encoder.copy_buffer_to_buffer(&pixels, 0, &readback, 0, size);
+        }
+        queue.submit([encoder.finish()]);
+    }
+
+    readback.map_async(wgpu::MapMode::Read, .., |_| {});
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(|e| format!("Lost the GPU: {}", e))?;
+    let view = readback
+        .get_mapped_range(..)
+        .map_err(|e| format!("Unable to read the image back: {}", e))?;
+    let mut film = Film::new(scene.width, scene.height);
+    for (estimate, sum) in film.pixels_mut().iter_mut().zip(view.chunks(16)) {
+        let [r, g, b, n] =
+            [0, 4, 8, 12].map(|i| f32::from_le_bytes(sum[i..i + 4].try_into().unwrap()));
+        let n = n.max(1.0);
+        *estimate = Estimate::from_mean(Srgb::new(r / n, g / n, b / n), n as u32);
+    }
+    Ok(film)
+}
+
+#[test]
+fn test_gpu_scene() {
+    let mut scene = Config {
+        width: 8,
+        height: 6,
+        samples_per_pixel: 1,
+        max_depth: 2,
+        sky: Some(Sky::new_default_sky()),
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, 3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            40.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects: vec![
+            Sphere::new(
+                Point3D::new(1.0, 0.0, 0.0),
+                0.5,
+                Material::Lambertian(Lambertian::new(Srgb::new(0.5, 0.25, 1.0))),
+            )
+            .into(),
+            Sphere::new(
+                Point3D::new(-1.0, 0.0, 0.0),
+                0.25,
+                Material::Metal(Metal::new(Srgb::new(0.8, 0.8, 0.8), 0.5)),
+            )
+            .into(),
+        ],
+        ..Default::default()
+    };
+    let gpu = GpuScene::new(&scene).unwrap();
+    assert_eq!(gpu.spheres.len(), 2);
+    assert_eq!(gpu.nodes.len(), 1);
+    // A single leaf, from the first sphere on, holding both and reaching to
+    // x = 1.5.
+    assert_eq!(gpu.nodes[0][3..5], [0.0, 1.5]);
+    assert_eq!(gpu.nodes[0][7], 2.0);
+    for sphere in &gpu.spheres {
+        let material = gpu.materials[sphere[4] as usize];
+        if sphere[0] > 0.0 {
+            assert_eq!(material[..4], [LAMBERTIAN, 0.5, 0.25, 1.0]);
+        } else {
+            assert_eq!(material[..5], [METAL, 0.8, 0.8, 0.8, 0.5]);
+        }
+    }
+    assert_eq!(as_bytes(&gpu.spheres).len(), 64);
+    let params = params(&scene, 7, gpu.nodes.len()).unwrap();
+    assert_eq!(params[24..28], [8, 6, 2, 0]);
+    // The default sky.
+    assert_eq!(f32::from_bits(params[7]), 1.0);
+    assert_eq!("gpu".parse::<Backend>().is_ok(), cfg!(feature = "gpu"));
+
+    scene.objects.push(
+        Sphere::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            1.0,
+            Material::Lambertian(Lambertian {
+                albedo: Texture::Checker(Checker::new(
+                    Srgb::new(1.0, 1.0, 1.0),
+                    Srgb::new(0.0, 0.0, 0.0),
+                    1.0,
+                )),
+            }),
+        )
+        .into(),
+    );
+    assert!(GpuScene::new(&scene).is_err());
+}
+
+#[test]
+fn test_shader() {
+    let module = naga::front::wgsl::parse_str(SHADER).unwrap();
+    Validator::new(ValidationFlags::all(), Capabilities::default())
+        .validate(&module)
+        .unwrap();
+    // The records and parameters are laid out as they are packed here.
+    let span = |name: &str| {
+        module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match ty.inner {
+                TypeInner::Struct { span, .. } if ty.name.as_deref() == Some(name) => Some(span),
+                _ => None,
+            })
+            .unwrap()
+    };
+    assert_eq!(span("Record"), 32);
+    assert_eq!(span("Params") as usize, 4 * PARAMS);
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn test_gpu_render() {
+    let json = std::fs::read("data/cover_scene.json").unwrap();
+    let mut scene = serde_json::from_slice::<Config>(&json).unwrap();
+    scene.width = 40;
+    scene.height = 30;
+    scene.samples_per_pixel = 16;
+    let film = match render(&scene, 1) {
+        Ok(film) => film,
+        // Nothing to test on without a GPU, or a software one.
+        Err(e) if e.starts_with("No GPU found") => return,
+        Err(e) => panic!("{}", e),
+    };
+    assert!(film.pixels().iter().all(|e| e.count() == 16));
+    // The same scene on the CPU looks the same on the whole.
+    let cpu = Renderer::new(scene, RenderSettings::default())
+        .render()
+        .unwrap();
+    let brightness = |film: &Film| -> f32 {
+        film.pixels()
+            .iter()
+            .map(|e| e.mean().red + e.mean().green + e.mean().blue)
+            .sum::<f32>()
+            / film.pixels().len() as f32
+    };
+    assert!((brightness(&film) - brightness(&cpu)).abs() < 0.05);
+}
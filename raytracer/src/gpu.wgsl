@@ -0,0 +1,274 @@
+// The path tracer gpu.rs runs: one invocation per pixel, taking one sample
+// per dispatch and adding it to the pixel's sum. The scene is as GpuScene
+// lays it out, and the materials scatter as the CPU's do.
+
+struct Record {
+    a: vec4<f32>,
+    b: vec4<f32>,
+}
+
+struct Params {
+    // w: the lens radius.
+    origin: vec4<f32>,
+    // w: 1 for the default sky, 0 for none.
+    lower_left: vec4<f32>,
+    horizontal: vec4<f32>,
+    vertical: vec4<f32>,
+    u: vec4<f32>,
+    v: vec4<f32>,
+    // Width, height, maximum depth and which sample this is.
+    size: vec4<u32>,
+    // The render's seed, low and high, and how many nodes there are.
+    seed: vec4<u32>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> spheres: array<Record>;
+@group(0) @binding(2) var<storage, read> materials: array<Record>;
+@group(0) @binding(3) var<storage, read> nodes: array<Record>;
+@group(0) @binding(4) var<storage, read_write> pixels: array<vec4<f32>>;
+
+const LAMBERTIAN: u32 = 0u;
+const METAL: u32 = 1u;
+const GLASS: u32 = 2u;
+const DIFFUSE_LIGHT: u32 = 3u;
+
+// One more than the deepest a Bvh goes.
+const STACK_SIZE: u32 = 65u;
+const T_MIN: f32 = 0.001;
+const T_MAX: f32 = 1e30;
+const PI: f32 = 3.14159265;
+
+var<private> rng: u32;
+
+// PCG, as hashed for GPUs by Jarzynski and Olano.
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random() -> f32 {
+    rng = pcg(rng);
+    return f32(rng >> 8u) / 16777216.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = 2.0 * random() - 1.0;
+    let a = 2.0 * PI * random();
+    let r = sqrt(max(0.0, 1.0 - z * z));
+    return vec3<f32>(r * cos(a), r * sin(a), z);
+}
+
+fn random_in_unit_sphere() -> vec3<f32> {
+    return random_unit_vector() * pow(random(), 1.0 / 3.0);
+}
+
+fn random_in_unit_disk() -> vec2<f32> {
+    let r = sqrt(random());
+    let a = 2.0 * PI * random();
+    return vec2<f32>(r * cos(a), r * sin(a));
+}
+
+struct Hit {
+    t: f32,
+    normal: vec3<f32>,
+    front_face: bool,
+    material: u32,
+}
+
+fn hit_sphere(
+    index: u32,
+    origin: vec3<f32>,
+    direction: vec3<f32>,
+    t_max: f32,
+    hit: ptr<function, Hit>,
+) -> bool {
+    let sphere = spheres[index];
+    let center = sphere.a.xyz;
+    let radius = sphere.a.w;
+    let oc = origin - center;
+    let a = dot(direction, direction);
+    let half_b = dot(oc, direction);
+    let c = dot(oc, oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return false;
+    }
+    let root = sqrt(discriminant);
+    var t = (-half_b - root) / a;
+    if t < T_MIN || t > t_max {
+        t = (-half_b + root) / a;
+        if t < T_MIN || t > t_max {
+            return false;
+        }
+    }
+    // A negative radius turns the normal inwards, for hollow glass.
+    let outward = (origin + t * direction - center) / radius;
+    let front_face = dot(direction, outward) < 0.0;
+    (*hit).t = t;
+    (*hit).normal = select(-outward, outward, front_face);
+    (*hit).front_face = front_face;
+    (*hit).material = u32(sphere.b.x);
+    return true;
+}
+
+fn hit_box(node: Record, origin: vec3<f32>, inverse: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.a.xyz - origin) * inverse;
+    let t1 = (node.b.xyz - origin) * inverse;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(max(near.x, near.y), max(near.z, T_MIN));
+    let exit = min(min(far.x, far.y), min(far.z, t_max));
+    return enter <= exit;
+}
+
+// The nearest hit, going down the hierarchy as Bvh::hit does: a node's
+// count is negative for interior nodes, -(split axis + 1).
+fn hit_world(origin: vec3<f32>, direction: vec3<f32>, hit: ptr<function, Hit>) -> bool {
+    if params.seed.z == 0u {
+        return false;
+    }
+    // Kept finite, as infinities needn't be.
+    let safe = select(direction, vec3<f32>(1e-30), abs(direction) < vec3<f32>(1e-30));
+    let inverse = 1.0 / safe;
+    var closest = T_MAX;
+    var found = false;
+    var stack: array<u32, STACK_SIZE>;
+    stack[0] = 0u;
+    var top = 1u;
+    while top > 0u {
+        top -= 1u;
+        let index = stack[top];
+        let node = nodes[index];
+        if !hit_box(node, origin, inverse, closest) {
+            continue;
+        }
+        let first = u32(node.a.w);
+        if node.b.w > 0.0 {
+            for (var i = first; i < first + u32(node.b.w); i++) {
+                if hit_sphere(i, origin, direction, closest, hit) {
+                    closest = (*hit).t;
+                    found = true;
+                }
+            }
+        } else {
+            // The nearer child is visited first.
+            let axis = u32(-node.b.w) - 1u;
+            if direction[axis] < 0.0 {
+                stack[top] = index + 1u;
+                stack[top + 1u] = first;
+            } else {
+                stack[top] = first;
+                stack[top + 1u] = index + 1u;
+            }
+            top += 2u;
+        }
+    }
+    return found;
+}
+
+fn sky(direction: vec3<f32>) -> vec3<f32> {
+    if params.lower_left.w == 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let t = clamp(0.5 * (normalize(direction).y + 1.0), 0.0, 1.0);
+    return (1.0 - t) * vec3<f32>(1.0) + t * vec3<f32>(0.5, 0.7, 1.0);
+}
+
+fn reflectance(cosine: f32, ratio: f32) -> f32 {
+    var r0 = (1.0 - ratio) / (1.0 + ratio);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+// Where light arriving at `hit` along `direction` goes next and how much of
+// it does, or false if it is absorbed.
+fn scatter(
+    direction: vec3<f32>,
+    hit: Hit,
+    attenuation: ptr<function, vec3<f32>>,
+    scattered: ptr<function, vec3<f32>>,
+) -> bool {
+    let material = materials[hit.material];
+    let color = material.a.yzw;
+    let parameter = material.b.x;
+    switch u32(material.a.x) {
+        case LAMBERTIAN: {
+            var d = hit.normal + random_unit_vector();
+            if all(abs(d) < vec3<f32>(1e-8)) {
+                d = hit.normal;
+            }
+            *scattered = d;
+            *attenuation = color;
+            return true;
+        }
+        case METAL: {
+            let reflected = reflect(normalize(direction), hit.normal)
+                + parameter * random_in_unit_sphere();
+            *scattered = reflected;
+            *attenuation = color;
+            return dot(reflected, hit.normal) > 0.0;
+        }
+        case GLASS: {
+            let ratio = select(parameter, 1.0 / parameter, hit.front_face);
+            let unit = normalize(direction);
+            let cosine = min(dot(-unit, hit.normal), 1.0);
+            let sine = sqrt(max(0.0, 1.0 - cosine * cosine));
+            if ratio * sine > 1.0 || reflectance(cosine, ratio) > random() {
+                *scattered = reflect(unit, hit.normal);
+            } else {
+                *scattered = refract(unit, hit.normal, ratio);
+            }
+            *attenuation = vec3<f32>(1.0);
+            return true;
+        }
+        default: {
+            return false;
+        }
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let width = params.size.x;
+    let height = params.size.y;
+    if id.x >= width || id.y >= height {
+        return;
+    }
+    let pixel = id.y * width + id.x;
+    rng = pcg(pixel ^ pcg(params.size.w ^ pcg(params.seed.x ^ pcg(params.seed.y))));
+
+    // As camera_ray and Camera::get_ray do it, for a perspective camera.
+    let s = (f32(id.x) + random()) / f32(max(width, 2u) - 1u);
+    let t = (f32(height) - (f32(id.y) + random())) / f32(max(height, 2u) - 1u);
+    let disk = random_in_unit_disk() * params.origin.w;
+    let offset = params.u.xyz * disk.x + params.v.xyz * disk.y;
+    var origin = params.origin.xyz + offset;
+    var direction = params.lower_left.xyz + s * params.horizontal.xyz + t * params.vertical.xyz
+        - params.origin.xyz - offset;
+
+    var throughput = vec3<f32>(1.0);
+    var color = vec3<f32>(0.0);
+    for (var depth = 0u; depth < params.size.z; depth++) {
+        var hit: Hit;
+        if !hit_world(origin, direction, &hit) {
+            color += throughput * sky(direction);
+            break;
+        }
+        let material = materials[hit.material];
+        if u32(material.a.x) == DIFFUSE_LIGHT {
+            color += throughput * material.a.yzw;
+            break;
+        }
+        var attenuation: vec3<f32>;
+        var scattered: vec3<f32>;
+        if !scatter(direction, hit, &attenuation, &scattered) {
+            break;
+        }
+        throughput *= attenuation;
+        origin += hit.t * direction;
+        direction = scattered;
+    }
+    pixels[pixel] += vec4<f32>(color, 1.0);
+}
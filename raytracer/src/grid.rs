@@ -0,0 +1,320 @@
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::stats;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use crate::sphere::Sphere;
+
+// About how many cells the grid has per object, and at most how many across
+// any axis.
+const CELLS_PER_OBJECT: Float = 3.0;
+const MAX_RESOLUTION: usize = 128;
+// Objects this many times bigger across than the median are kept out of the
+// cells: one ground sphere would otherwise stretch the grid so far that
+// everything else crowds into a few cells.
+const OUTSIZED: Float = 16.0;
+
+// A uniform grid over any list of hittables: the scene's box cut into
+// equal cells, each listing the objects overlapping it, and walked cell by
+// cell along the ray (3D-DDA) until a hit is found in the cell it belongs
+// to. It builds in one pass and is hard to beat when the objects are evenly
+// spread and of similar sizes, like the spheres of the cover scene. Objects
+// without a bounding box, or far bigger than the rest, are kept aside and
+// tested on every ray.
+#[derive(Debug, Clone)]
+pub struct Grid<T> {
+    bounds: Aabb,
+    // The box around everything, aside objects included, if they all have
+    // one.
+    whole: Option<Aabb>,
+    resolution: [usize; 3],
+    cell_size: [Float; 3],
+    // Cell i's objects are refs[starts[i]..starts[i + 1]], as indices into
+    // `objects`. Cells are numbered x first, then y, then z.
+    starts: Vec<usize>,
+    refs: Vec<usize>,
+    objects: Vec<T>,
+    aside: Vec<T>,
+    // Where each object was in the list the grid was built from.
+    ids: Vec<usize>,
+    aside_ids: Vec<usize>,
+}
+
+impl<T: Hittable> Grid<T> {
+    // A grid for rays sent at times in [0, 1), the default shutter.
+    pub fn new(objects: Vec<T>) -> Grid<T> {
+        Grid::for_shutter(objects, 0.0, 1.0)
+    }
+
+    // A grid for rays sent at times in [t0, t1].
+    pub fn for_shutter(objects: Vec<T>, t0: Float, t1: Float) -> Grid<T> {
+        let all: Vec<(T, Option<Aabb>)> = objects
+            .into_iter()
+            .map(|object| {
+                let bounds = object.bounding_box(t0, t1);
+                (object, bounds)
+            })
+            .collect();
+        let whole = all
+            .iter()
+            .map(|(_, bounds)| *bounds)
+            .reduce(|a, b| Some(Aabb::surrounding_box(&a?, &b?)))
+            .flatten();
+        let mut sizes: Vec<Float> = all
+            .iter()
+            .filter_map(|(_, bounds)| bounds.map(|b| (b.max - b.min).length()))
+            .collect();
+        sizes.sort_by(Float::total_cmp);
+        let limit = sizes.get(sizes.len() / 2).map_or(0.0, |s| s * OUTSIZED);
+
+        let mut bounded = Vec::new();
+        let mut boxes = Vec::new();
+        let mut ids = Vec::new();
+        let mut aside = Vec::new();
+        let mut aside_ids = Vec::new();
+        for (id, (object, bounds)) in all.into_iter().enumerate() {
+            match bounds {
+                Some(bounds) if (bounds.max - bounds.min).length() <= limit => {
+                    bounded.push(object);
+                    boxes.push(bounds);
+                    ids.push(id);
+                }
+                _ => {
+                    aside.push(object);
+                    aside_ids.push(id);
+                }
+            }
+        }
+
+        let origin = Point3D::new(0.0, 0.0, 0.0);
+        let tight = boxes
+            .iter()
+            .copied()
+            .reduce(|a, b| Aabb::surrounding_box(&a, &b))
+            .unwrap_or(Aabb::new(origin, origin));
+        // Padded so that nothing lies exactly on the outer faces, and a flat
+        // scene still has some depth.
+        let extent = tight.max - tight.min;
+        let pad = 1e-4 * extent.length().max(1.0);
+        let pad = Point3D::new(pad, pad, pad);
+        let bounds = Aabb::new(tight.min - pad, tight.max + pad);
+
+        // Roughly cubic cells, CELLS_PER_OBJECT of them for each object.
+        let size = bounds.max - bounds.min;
+        let volume = size.x() * size.y() * size.z();
+        let per_unit = (CELLS_PER_OBJECT * boxes.len() as Float / volume).cbrt();
+        let resolution =
+            [0, 1, 2].map(|a| ((size[a] * per_unit).round() as usize).clamp(1, MAX_RESOLUTION));
+        let cell_size = [0, 1, 2].map(|a| size[a] / resolution[a] as Float);
+
+        let mut grid = Grid {
+            bounds,
+            whole,
+            resolution,
+            cell_size,
+            starts: Vec::new(),
+            refs: Vec::new(),
+            objects: bounded,
+            aside,
+            ids,
+            aside_ids,
+        };
+        let cells = resolution[0] * resolution[1] * resolution[2];
+        let mut lists = vec![Vec::new(); cells];
+        for (i, b) in boxes.iter().enumerate() {
+            let low = grid.cell_of(&b.min);
+            let high = grid.cell_of(&b.max);
+            for z in low[2]..=high[2] {
+                for y in low[1]..=high[1] {
+                    for x in low[0]..=high[0] {
+                        lists[grid.index([x, y, z])].push(i);
+                    }
+                }
+            }
+        }
+        grid.starts.push(0);
+        for list in lists {
+            grid.refs.extend(list);
+            grid.starts.push(grid.refs.len());
+        }
+        grid
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.aside.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The cell containing `p`, clamped to the grid.
+    fn cell_of(&self, p: &Point3D) -> [usize; 3] {
+        [0, 1, 2].map(|a| {
+            let offset = (p[a] - self.bounds.axis_min(a)) / self.cell_size[a];
+            (offset.max(0.0) as usize).min(self.resolution[a] - 1)
+        })
+    }
+
+    fn index(&self, cell: [usize; 3]) -> usize {
+        cell[0] + self.resolution[0] * (cell[1] + self.resolution[1] * cell[2])
+    }
+
+    // The closest hit, along with the position of the object hit in the list
+    // the grid was built from.
+    pub fn hit_with_id(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+        for (object, id) in self.aside.iter().zip(&self.aside_ids) {
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_record = Some((*id, hit));
+            }
+        }
+        if self.objects.is_empty() {
+            return hit_record;
+        }
+        let (enter, exit) = match self.bounds.span(ray, t_min, closest_so_far) {
+            Some(span) => span,
+            None => return hit_record,
+        };
+
+        // Where the ray crosses into the next cell along each axis, and how
+        // far apart those crossings are.
+        let mut cell = self.cell_of(&ray.at(enter));
+        let mut next = [Float::INFINITY; 3];
+        let mut delta = [Float::INFINITY; 3];
+        let mut step = [0isize; 3];
+        for a in 0..3 {
+            let d = ray.direction[a];
+            let o = ray.origin[a];
+            let low = self.bounds.axis_min(a) + cell[a] as Float * self.cell_size[a];
+            if d > 0.0 {
+                step[a] = 1;
+                delta[a] = self.cell_size[a] / d;
+                next[a] = (low + self.cell_size[a] - o) / d;
+            } else if d < 0.0 {
+                step[a] = -1;
+                delta[a] = -self.cell_size[a] / d;
+                next[a] = (low - o) / d;
+            }
+        }
+
+        // Each cell stepped through counts as a node visited.
+        let mut visited = 0;
+        loop {
+            visited += 1;
+            let i = self.index(cell);
+            for &r in &self.refs[self.starts[i]..self.starts[i + 1]] {
+                if let Some(hit) = self.objects[r].hit(ray, t_min, closest_so_far) {
+                    closest_so_far = hit.t;
+                    hit_record = Some((self.ids[r], hit));
+                }
+            }
+            let a = if next[0] < next[1] && next[0] < next[2] {
+                0
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            // A hit before the next cell can't be beaten further along.
+            if next[a] > exit || closest_so_far <= next[a] {
+                break;
+            }
+            let moved = cell[a] as isize + step[a];
+            if moved < 0 || moved >= self.resolution[a] as isize {
+                break;
+            }
+            cell[a] = moved as usize;
+            next[a] += delta[a];
+        }
+        stats::count(|c| c.nodes_visited += visited);
+        hit_record
+    }
+}
+
+impl<T: Hittable> Hittable for Grid<T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hit_with_id(ray, t_min, t_max).map(|(_, hit)| hit)
+    }
+
+    // The box of the shutter the grid was built for.
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        self.whole
+    }
+}
+
+#[test]
+fn test_grid_matches_linear_scan() {
+    // Spheres of a few sizes in a loose field, a big one spanning many
+    // cells, and a ground so big it's kept out of the cells.
+    let mut spheres = Vec::new();
+    for i in 0..15 {
+        for j in 0..15 {
+            let radius = 0.1 + 0.1 * ((i * 7 + j * 3) % 5) as Float;
+            spheres.push(Sphere::new(
+                Point3D::new(
+                    i as Float - 7.0,
+                    0.2 * ((i + j) % 3) as Float,
+                    j as Float - 7.0,
+                ),
+                radius,
+                Material::Glass(Glass::new(1.5)),
+            ));
+        }
+    }
+    spheres.push(Sphere::new(
+        Point3D::new(0.0, 1.0, 0.0),
+        3.0,
+        Material::Glass(Glass::new(1.5)),
+    ));
+    spheres.push(Sphere::new(
+        Point3D::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::Glass(Glass::new(1.5)),
+    ));
+    let grid = Grid::new(spheres.clone());
+    assert_eq!(grid.len(), spheres.len());
+    assert_eq!(grid.aside.len(), 1);
+    assert!(grid.resolution.iter().all(|r| *r > 1));
+
+    for i in 0..500 {
+        let ray = Ray::new(
+            Point3D::random(-10.0, 10.0) + Point3D::new(0.0, 5.0, 0.0),
+            Point3D::random(-1.0, 1.0),
+        );
+        let t_max = if i % 2 == 0 { Float::MAX } else { 4.0 };
+        let expected = spheres
+            .iter()
+            .filter_map(|s| s.hit(&ray, 0.001, t_max))
+            .map(|h| h.t)
+            .fold(Float::INFINITY, Float::min);
+        let found = grid.hit_with_id(&ray, 0.001, t_max);
+        assert_eq!(
+            expected,
+            found.as_ref().map_or(Float::INFINITY, |(_, h)| h.t)
+        );
+        if let Some((id, hit)) = found {
+            assert_eq!(spheres[id].hit(&ray, 0.001, t_max).unwrap().t, hit.t);
+        }
+    }
+
+    let empty: Grid<Sphere> = Grid::new(Vec::new());
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(empty.is_empty());
+    assert!(empty.hit(&ray, 0.001, Float::MAX).is_none());
+}
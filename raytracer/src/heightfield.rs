@@ -0,0 +1,394 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Rays closer to parallel with a triangle than this miss it.
+const PARALLEL_EPSILON: Float = 1e-9;
+
+// A grid of heights, `columns` across and `rows` deep. Written in scenes
+// as the path to a greyscale image, black 0 and white 1, or as the rows
+// themselves.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "HeightsParams", into = "HeightsParams")]
+pub struct Heights {
+    path: Option<String>,
+    columns: usize,
+    rows: usize,
+    // Shared so that cloning the heightfield doesn't copy the grid.
+    values: Arc<Vec<Float>>,
+    lowest: Float,
+    highest: Float,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HeightsParams {
+    Image(String),
+    Rows(Vec<Vec<Float>>),
+}
+
+impl TryFrom<HeightsParams> for Heights {
+    type Error = String;
+
+    fn try_from(p: HeightsParams) -> Result<Self, Self::Error> {
+        match p {
+            HeightsParams::Image(path) => Heights::load(&path),
+            HeightsParams::Rows(rows) => Heights::from_rows(rows),
+        }
+    }
+}
+
+impl From<Heights> for HeightsParams {
+    fn from(heights: Heights) -> Self {
+        match heights.path {
+            Some(path) => HeightsParams::Image(path),
+            None => HeightsParams::Rows(
+                heights
+                    .values
+                    .chunks(heights.columns)
+                    .map(|row| row.to_vec())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Heights {
+    // `values` row by row, at least 2 x 2 of them.
+    pub fn new(columns: usize, rows: usize, values: Vec<Float>) -> Result<Heights, String> {
+        if columns < 2 || rows < 2 || values.len() != columns * rows {
+            return Err("a heightfield needs a grid of at least 2 x 2 heights".to_string());
+        }
+        let lowest = values.iter().copied().fold(Float::INFINITY, Float::min);
+        let highest = values.iter().copied().fold(Float::NEG_INFINITY, Float::max);
+        Ok(Heights {
+            path: None,
+            columns,
+            rows,
+            values: Arc::new(values),
+            lowest,
+            highest,
+        })
+    }
+
+    pub fn from_rows(rows: Vec<Vec<Float>>) -> Result<Heights, String> {
+        let columns = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != columns) {
+            return Err("every row of a heightfield must be as long".to_string());
+        }
+        let count = rows.len();
+        Heights::new(columns, count, rows.concat())
+    }
+
+    #[cfg(not(feature = "images"))]
+    pub fn load(path: &str) -> Result<Heights, String> {
+        Err(format!("{}: built without image support", path))
+    }
+
+    #[cfg(feature = "images")]
+    pub fn load(path: &str) -> Result<Heights, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("{}: failed to load image: {}", path, e))?
+            .to_luma();
+        let (width, height) = image.dimensions();
+        let values = image
+            .into_raw()
+            .iter()
+            .map(|v| *v as Float / 255.0)
+            .collect();
+        let mut heights = Heights::new(width as usize, height as usize, values)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        heights.path = Some(path.to_string());
+        Ok(heights)
+    }
+
+    fn at(&self, column: usize, row: usize) -> Float {
+        self.values[row * self.columns + column]
+    }
+}
+
+// Terrain: `heights` spread over `size.x` by `size.z` from the `corner`,
+// the first column at its x and the first row (the top of an image) at its
+// z. A height of 1 is `size.y` above the corner. Each square of four
+// neighbouring heights is split into two triangles, and rays walk the
+// grid square by square, so only the squares under the ray are tested.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Heightfield {
+    pub corner: Point3D,
+    pub size: Point3D,
+    pub heights: Heights,
+    pub material: Material,
+}
+
+impl Heightfield {
+    pub fn new(
+        corner: Point3D,
+        size: Point3D,
+        heights: Heights,
+        material: Material,
+    ) -> Heightfield {
+        Heightfield {
+            corner,
+            size,
+            heights,
+            material,
+        }
+    }
+
+    fn vertex(&self, column: usize, row: usize) -> Point3D {
+        let h = &self.heights;
+        self.corner
+            + Point3D::new(
+                self.size.x() * column as Float / (h.columns - 1) as Float,
+                self.size.y() * h.at(column, row),
+                self.size.z() * row as Float / (h.rows - 1) as Float,
+            )
+    }
+
+    // The nearest crossing of the two triangles of the square from
+    // (column, row), and the triangle's normal.
+    fn hit_square(
+        &self,
+        ray: &Ray,
+        column: usize,
+        row: usize,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, Point3D)> {
+        let a = self.vertex(column, row);
+        let b = self.vertex(column + 1, row);
+        let c = self.vertex(column, row + 1);
+        let d = self.vertex(column + 1, row + 1);
+        let mut closest = None;
+        for (v0, v1, v2) in [(a, c, b), (b, c, d)] {
+            let t_max = closest.map_or(t_max, |(t, _)| t);
+            if let Some(t) = crossing(ray, v0, v1, v2, t_min, t_max) {
+                closest = Some((t, (v1 - v0).cross(&(v2 - v0)).unit_vector()));
+            }
+        }
+        closest
+    }
+}
+
+// Möller–Trumbore, for t only.
+fn crossing(
+    ray: &Ray,
+    v0: Point3D,
+    v1: Point3D,
+    v2: Point3D,
+    t_min: Float,
+    t_max: Float,
+) -> Option<Float> {
+    let e1 = v1 - v0;
+    let e2 = v2 - v0;
+    let pvec = ray.direction.cross(&e2);
+    let det = e1.dot(&pvec);
+    if det.abs() < PARALLEL_EPSILON * ray.direction.length() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin - v0;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(&e1);
+    let v = ray.direction.dot(&qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&qvec) * inv_det;
+    (t > t_min && t < t_max).then_some(t)
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let bounds = self.bounding_box(ray.time, ray.time)?;
+        let (t_start, t_end) = bounds.span(ray, t_min, t_max)?;
+        let h = &self.heights;
+        let (squares_x, squares_z) = (h.columns - 1, h.rows - 1);
+        // Positions and steps in units of squares.
+        let square_x = self.size.x() / squares_x as Float;
+        let square_z = self.size.z() / squares_z as Float;
+        let start = ray.at(t_start) - self.corner;
+        let (x, z) = (start.x() / square_x, start.z() / square_z);
+        let (dx, dz) = (ray.direction.x() / square_x, ray.direction.z() / square_z);
+        let mut column = (x.floor().max(0.0) as usize).min(squares_x - 1);
+        let mut row = (z.floor().max(0.0) as usize).min(squares_z - 1);
+
+        // Where the ray next crosses a column or row line, and how far apart
+        // the crossings are (2D DDA).
+        let next = |position: Float, cell: usize, d: Float| {
+            if d > 0.0 {
+                t_start + ((cell + 1) as Float - position) / d
+            } else if d < 0.0 {
+                t_start + (cell as Float - position) / d
+            } else {
+                Float::INFINITY
+            }
+        };
+        let mut next_x = next(x, column, dx);
+        let mut next_z = next(z, row, dz);
+        let (step_x, step_z) = (1.0 / dx.abs(), 1.0 / dz.abs());
+
+        loop {
+            // Squares are walked nearest first, so the first hit is it.
+            if let Some((t, outward)) = self.hit_square(ray, column, row, t_min, t_max) {
+                let point = ray.at(t);
+                let front_face = ray.direction.dot(&outward) < 0.0;
+                let local = point - self.corner;
+                return Some(HitRecord {
+                    t,
+                    point,
+                    normal: if front_face { outward } else { -outward },
+                    front_face,
+                    material: &self.material,
+                    u: (local.x() / self.size.x()).clamp(0.0, 1.0),
+                    v: 1.0 - (local.z() / self.size.z()).clamp(0.0, 1.0),
+                    tangent: None,
+                });
+            }
+            if next_x.min(next_z) > t_end {
+                return None;
+            }
+            if next_x < next_z {
+                if dx > 0.0 && column + 1 < squares_x {
+                    column += 1;
+                } else if dx < 0.0 && column > 0 {
+                    column -= 1;
+                } else {
+                    return None;
+                }
+                next_x += step_x;
+            } else {
+                if dz > 0.0 && row + 1 < squares_z {
+                    row += 1;
+                } else if dz < 0.0 && row > 0 {
+                    row -= 1;
+                } else {
+                    return None;
+                }
+                next_z += step_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // Padded so a flat field doesn't get a flat box.
+        let low = self.size.y() * self.heights.lowest;
+        let high = self.size.y() * self.heights.highest;
+        Some(Aabb::new(
+            self.corner + Point3D::new(0.0, low.min(high) - 1e-4, 0.0),
+            self.corner + Point3D::new(self.size.x(), low.max(high) + 1e-4, self.size.z()),
+        ))
+    }
+}
+
+#[test]
+fn test_heightfield_hit() {
+    // A 4 x 4 unit grid, flat at 0 but for a peak of 2 in the middle of the
+    // row at z = 1.
+    let mut rows = vec![vec![0.0; 5]; 5];
+    rows[1][2] = 1.0;
+    let field = Heightfield::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(4.0, 2.0, 4.0),
+        Heights::from_rows(rows).unwrap(),
+        Material::grey(),
+    );
+    // Straight down onto the flat part, and onto the peak.
+    let down = Ray::new(Point3D::new(3.5, 5.0, 3.5), Point3D::new(0.0, -1.0, 0.0));
+    let hit = field.hit(&down, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 5.0);
+    assert_eq!(hit.normal, Point3D::new(0.0, 1.0, 0.0));
+    assert!(hit.front_face);
+    assert_approx_eq!(hit.u, 0.875);
+    assert_approx_eq!(hit.v, 0.125);
+    let peak = Ray::new(Point3D::new(2.0, 5.0, 1.0), Point3D::new(0.0, -1.0, 0.0));
+    assert_approx_eq!(field.hit(&peak, 0.001, Float::MAX).unwrap().t, 3.0);
+
+    // Skimming low over the grid from either side, the ray walks the
+    // squares to the peak's slopes.
+    for (from, direction) in [(-1.0, 1.0), (5.0, -1.0)] {
+        let skim = Ray::new(
+            Point3D::new(from, 1.0, 1.0),
+            Point3D::new(direction, 0.0, 0.0),
+        );
+        let hit = field.hit(&skim, 0.001, Float::MAX).unwrap();
+        assert_approx_eq!(hit.point.x(), 2.0 - direction * 0.5);
+        assert!(hit.front_face);
+        assert!(hit.normal.x() * direction < 0.0);
+    }
+    // Over the top there's nothing, and underneath it's the back.
+    let over = Ray::new(Point3D::new(-1.0, 2.5, 1.0), Point3D::new(1.0, 0.0, 0.0));
+    assert!(field.hit(&over, 0.001, Float::MAX).is_none());
+    let up = Ray::new(Point3D::new(0.5, -1.0, 0.5), Point3D::new(0.0, 1.0, 0.0));
+    assert!(!field.hit(&up, 0.001, Float::MAX).unwrap().front_face);
+}
+
+#[test]
+fn test_heightfield_silhouette() {
+    // A peak of 1 in the middle of a 4 x 4 grid, seen from the side. Its
+    // outline comes down from the top in straight lines to the ground a unit
+    // either side; rays along z just under it hit and just over it miss.
+    let mut rows = vec![vec![0.0; 5]; 5];
+    rows[2][2] = 1.0;
+    let field = Heightfield::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(4.0, 1.0, 4.0),
+        Heights::from_rows(rows).unwrap(),
+        Material::grey(),
+    );
+    for i in 0..20 {
+        let x = i as Float * 0.2 + 0.1;
+        let outline = (1.0 - (x - 2.0).abs()).max(0.0);
+        let along = |y: Float| {
+            let ray = Ray::new(Point3D::new(x, y, 10.0), Point3D::new(0.0, 0.0, -1.0));
+            field.hit(&ray, 0.001, Float::MAX)
+        };
+        assert!(along(outline + 0.05).is_none(), "hit over x = {}", x);
+        if outline > 0.05 {
+            let hit = along(outline - 0.05).unwrap();
+            assert!(hit.front_face);
+            assert!(hit.point.z() > 2.0 && hit.point.z() < 3.0);
+        }
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_heights_from_image() {
+    // 3 x 2: black, grey, white over white, black, grey.
+    let mut image = image::GrayImage::new(3, 2);
+    for (i, value) in [0, 51, 255, 255, 0, 51].into_iter().enumerate() {
+        image.put_pixel(i as u32 % 3, i as u32 / 3, image::Luma([value]));
+    }
+    image.save("/tmp/test_heights.png").unwrap();
+    let json = r#"{"corner":{"x":0.0,"y":0.0,"z":0.0},"size":{"x":2.0,"y":10.0,"z":1.0},"heights":"/tmp/test_heights.png","material":{"Lambertian":{"albedo":[0.5,0.5,0.5]}}}"#;
+    let field = serde_json::from_str::<Heightfield>(json).unwrap();
+    assert_eq!(json, serde_json::to_string(&field).unwrap());
+    assert_eq!(field.vertex(1, 0).y(), 2.0);
+    assert_eq!(field.vertex(0, 1), Point3D::new(0.0, 10.0, 1.0));
+    assert_eq!(
+        field.bounding_box(0.0, 1.0).unwrap().axis_max(1),
+        10.0 + 1e-4
+    );
+
+    let ragged = r#"[[0.0, 1.0], [0.0]]"#;
+    assert!(serde_json::from_str::<Heights>(ragged).is_err());
+    let rows = serde_json::from_str::<Heights>(r#"[[0.0, 1.0], [0.5, 0.0]]"#).unwrap();
+    assert_eq!(
+        serde_json::to_string(&rows).unwrap(),
+        "[[0.0,1.0],[0.5,0.0]]"
+    );
+}
@@ -0,0 +1,151 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::constant_medium::span_inside;
+use crate::float::Float;
+use crate::materials::Isotropic;
+use crate::materials::Material;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::texture::Texture;
+
+#[cfg(test)]
+use crate::cuboid::Cuboid;
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::texture::Checker;
+#[cfg(test)]
+use palette::Srgb;
+
+// A volume like ConstantMedium whose density varies through space, e.g.
+// clouds or wisps of smoke. The density at a point is `max_density` times
+// the brightness of `density` there, so a Noise texture gives billowing
+// shapes and a Checker gives blocks.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HeterogeneousMedium {
+    pub boundary: Box<Object>,
+    pub density: Texture,
+    pub max_density: Float,
+    pub material: Material,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        boundary: Object,
+        density: Texture,
+        max_density: Float,
+        albedo: Texture,
+    ) -> HeterogeneousMedium {
+        HeterogeneousMedium {
+            boundary: Box::new(boundary),
+            density,
+            max_density,
+            material: Material::Isotropic(Isotropic::new(albedo)),
+        }
+    }
+
+    // Density as a fraction of `max_density`, in [0, 1].
+    fn density_fraction(&self, p: &Point3D) -> Float {
+        let c = self.density.value(0.0, 0.0, p);
+        Float::from((c.red + c.green + c.blue) / 3.0).clamp(0.0, 1.0)
+    }
+
+    // Fraction of light passing along the ray between t_min and t_max,
+    // estimated by ratio tracking: step through the volume as if it had the
+    // maximum density everywhere, and at each step keep the fraction of light
+    // that the real density there would let through. Unbiased, and unlike
+    // testing `hit` for a shadow ray it never returns all-or-nothing.
+    pub fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Float {
+        let (t0, t1) = match span_inside(&self.boundary, ray, t_min, t_max) {
+            Some(span) => span,
+            None => return 1.0,
+        };
+        if self.max_density <= 0.0 {
+            return 1.0;
+        }
+        let mut rng = sampler::rng();
+        let length = ray.direction.length();
+        let mut t = t0;
+        let mut transmittance = 1.0;
+        loop {
+            t += -(1.0 - rng.gen::<Float>()).ln() / self.max_density / length;
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_fraction(&ray.at(t));
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    // Delta (Woodcock) tracking: take exponential steps sized for the maximum
+    // density, and at each step scatter with probability (real density /
+    // maximum density). The other steps are "null" collisions that leave the
+    // ray untouched, which makes the result exact for any density field.
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t0, t1) = span_inside(&self.boundary, ray, t_min, t_max)?;
+        if self.max_density <= 0.0 {
+            return None;
+        }
+        let mut rng = sampler::rng();
+        let length = ray.direction.length();
+        let mut t = t0;
+        loop {
+            t += -(1.0 - rng.gen::<Float>()).ln() / self.max_density / length;
+            if t >= t1 {
+                return None;
+            }
+            let point = ray.at(t);
+            if rng.gen::<Float>() < self.density_fraction(&point) {
+                // Normal and facing are meaningless inside a volume.
+                return Some(HitRecord {
+                    t,
+                    point,
+                    normal: Point3D::new(1.0, 0.0, 0.0),
+                    front_face: true,
+                    material: &self.material,
+                    u: 0.0,
+                    v: 0.0,
+                    tangent: None,
+                });
+            }
+        }
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[test]
+fn test_heterogeneous_medium() {
+    // A 2x1x1 box that is empty for x < 0 and dense for x > 0.
+    let boundary = Cuboid::new(
+        Point3D::new(-1.0, 0.0, 0.0),
+        Point3D::new(1.0, 1.0, 1.0),
+        Material::Glass(Glass::new(1.5)),
+    );
+    let density = Texture::Checker(Checker::new(
+        Srgb::new(0.0, 0.0, 0.0),
+        Srgb::new(1.0, 1.0, 1.0),
+        1.0,
+    ));
+    let white = Texture::Solid(Srgb::new(1.0, 1.0, 1.0));
+    let medium = HeterogeneousMedium::new(boundary.into(), density, 50.0, white);
+
+    let through_empty = Ray::new(Point3D::new(-0.5, 0.5, -1.0), Point3D::new(0.0, 0.0, 1.0));
+    let through_dense = Ray::new(Point3D::new(0.5, 0.5, -1.0), Point3D::new(0.0, 0.0, 1.0));
+    for _ in 0..100 {
+        assert!(medium.hit(&through_empty, 0.001, Float::MAX).is_none());
+        let hit = medium.hit(&through_dense, 0.001, Float::MAX).unwrap();
+        assert!(hit.point.x() > 0.0);
+    }
+    assert_eq!(medium.transmittance(&through_empty, 0.001, Float::MAX), 1.0);
+    assert!(medium.transmittance(&through_dense, 0.001, Float::MAX) < 1e-6);
+}
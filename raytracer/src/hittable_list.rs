@@ -0,0 +1,118 @@
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::cuboid::Cuboid;
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use crate::plane::Plane;
+#[cfg(test)]
+use crate::point3d::Point3D;
+#[cfg(test)]
+use crate::sphere::Sphere;
+
+// Closest hit among `objects`, checking every one of them.
+pub fn hit_closest<'a, T: Hittable>(
+    objects: &'a [T],
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<HitRecord<'a>> {
+    let mut closest_so_far = t_max;
+    let mut hit_record = None;
+    for object in objects {
+        if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+            closest_so_far = hit.t;
+            hit_record = Some(hit);
+        }
+    }
+    hit_record
+}
+
+// A plain list of hittables, tested one by one. Fine for a handful of
+// objects; use a Bvh for anything bigger. Holding `Box<dyn Hittable>` lets
+// different kinds of objects share one list.
+#[derive(Debug, Clone)]
+pub struct HittableList<T> {
+    pub objects: Vec<T>,
+}
+
+impl<T: Hittable> HittableList<T> {
+    pub fn new(objects: Vec<T>) -> HittableList<T> {
+        HittableList { objects }
+    }
+
+    pub fn push(&mut self, object: T) {
+        self.objects.push(object);
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl<T: Hittable> Hittable for HittableList<T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        hit_closest(&self.objects, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|o| o.bounding_box(t0, t1));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(Aabb::surrounding_box(&acc, &b?)))
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Box<T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        (**self).bounding_box(t0, t1)
+    }
+}
+
+#[test]
+fn test_hittable_list() {
+    let glass = Material::Glass(Glass::new(1.5));
+    let mut list: HittableList<Box<dyn Hittable>> = HittableList::new(vec![
+        Box::new(Sphere::new(
+            Point3D::new(0.0, 0.0, -5.0),
+            1.0,
+            glass.clone(),
+        )),
+        Box::new(Cuboid::new(
+            Point3D::new(-1.0, -1.0, -3.0),
+            Point3D::new(1.0, 1.0, -2.0),
+            glass.clone(),
+        )),
+    ]);
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    assert_eq!(list.hit(&ray, 0.001, Float::MAX).unwrap().t, 2.0);
+    let bounds = list.bounding_box(0.0, 1.0).unwrap();
+    assert_eq!(bounds.min, Point3D::new(-1.0, -1.0, -6.0));
+    assert_eq!(bounds.max, Point3D::new(1.0, 1.0, -2.0));
+
+    // An infinite plane makes the whole list unbounded.
+    list.push(Box::new(Plane::new(
+        Point3D::new(0.0, 0.0, -1.0),
+        Point3D::new(0.0, 0.0, 1.0),
+        glass,
+    )));
+    assert_eq!(list.hit(&ray, 0.001, Float::MAX).unwrap().t, 1.0);
+    assert!(list.bounding_box(0.0, 1.0).is_none());
+    assert!(HittableList::<Sphere>::new(Vec::new())
+        .bounding_box(0.0, 1.0)
+        .is_none());
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::normal_map::tangent_frame;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::transform::Transform;
+
+#[cfg(test)]
+use crate::cuboid::Cuboid;
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// `object` moved by `offset`, so that one object, e.g. a mesh, can be
+// placed several times without rewriting its coordinates.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Translate {
+    pub offset: Point3D,
+    pub object: Box<Object>,
+}
+
+// `object` turned `angle` degrees around `axis` through the origin,
+// anticlockwise looking down the axis. Translate it afterwards to turn it
+// around another point.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rotate {
+    pub axis: Point3D,
+    pub angle: Float,
+    pub object: Box<Object>,
+}
+
+// `object` put through a general `transform`, e.g. scaled then turned
+// then moved. The object is shared, so placing one mesh many times
+// doesn't copy it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Instance {
+    pub transform: Transform,
+    pub object: Arc<Object>,
+}
+
+impl Translate {
+    pub fn new(object: Object, offset: Point3D) -> Translate {
+        Translate {
+            offset,
+            object: Box::new(object),
+        }
+    }
+}
+
+impl Rotate {
+    pub fn new(object: Object, axis: Point3D, angle: Float) -> Rotate {
+        Rotate {
+            axis: axis.unit_vector(),
+            angle,
+            object: Box::new(object),
+        }
+    }
+
+    // Turned around the vertical, the usual case.
+    pub fn y(object: Object, angle: Float) -> Rotate {
+        Rotate::new(object, Point3D::new(0.0, 1.0, 0.0), angle)
+    }
+
+    // `v` turned by `angle` radians around the unit `axis` (Rodrigues'
+    // formula).
+    fn turn(v: Point3D, axis: Point3D, angle: Float) -> Point3D {
+        let (sin, cos) = angle.sin_cos();
+        v * cos + axis.cross(&v) * sin + axis * (axis.dot(&v) * (1.0 - cos))
+    }
+}
+
+impl Instance {
+    pub fn new(object: Arc<Object>, transform: Transform) -> Instance {
+        Instance { transform, object }
+    }
+}
+
+// The box around the corners of `bounds`, each put through `f`.
+fn around_corners(bounds: &Aabb, f: impl Fn(Point3D) -> Point3D) -> Aabb {
+    let mut min = [Float::INFINITY; 3];
+    let mut max = [Float::NEG_INFINITY; 3];
+    for corner in 0..8 {
+        let pick = |a: usize| {
+            if corner & (1 << a) == 0 {
+                bounds.axis_min(a)
+            } else {
+                bounds.axis_max(a)
+            }
+        };
+        let p = f(Point3D::new(pick(0), pick(1), pick(2)));
+        for (a, value) in [p.x(), p.y(), p.z()].into_iter().enumerate() {
+            min[a] = min[a].min(value);
+            max[a] = max[a].max(value);
+        }
+    }
+    Aabb::new(
+        Point3D::new(min[0], min[1], min[2]),
+        Point3D::new(max[0], max[1], max[2]),
+    )
+}
+
+impl Hittable for Translate {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Move the ray instead of the object, then the hit back.
+        let moved = Ray {
+            origin: ray.origin - self.offset,
+            ..*ray
+        };
+        let mut hit = self.object.hit(&moved, t_min, t_max)?;
+        hit.point += self.offset;
+        Some(hit)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        let bounds = self.object.bounding_box(t0, t1)?;
+        Some(Aabb::new(
+            bounds.min + self.offset,
+            bounds.max + self.offset,
+        ))
+    }
+}
+
+impl Hittable for Rotate {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Turn the ray the other way instead of turning the object, then the
+        // hit back. Turning keeps lengths, so t stays the same.
+        let axis = self.axis.unit_vector();
+        let angle = self.angle.to_radians();
+        let turned = Ray {
+            origin: Rotate::turn(ray.origin, axis, -angle),
+            direction: Rotate::turn(ray.direction, axis, -angle),
+            ..*ray
+        };
+        let mut hit = self.object.hit(&turned, t_min, t_max)?;
+        let (tangent, _) = tangent_frame(&hit);
+        hit.point = Rotate::turn(hit.point, axis, angle);
+        hit.normal = Rotate::turn(hit.normal, axis, angle);
+        hit.tangent = Some(Rotate::turn(tangent, axis, angle));
+        Some(hit)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        // The box around the turned corners of the object's box.
+        let bounds = self.object.bounding_box(t0, t1)?;
+        let axis = self.axis.unit_vector();
+        let angle = self.angle.to_radians();
+        Some(around_corners(&bounds, |p| Rotate::turn(p, axis, angle)))
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Into the object's own space and the hit back out. The direction
+        // isn't renormalised, so t means the same in both.
+        let inverse = self.transform.inverse();
+        let local = Ray {
+            origin: inverse.point(&ray.origin),
+            direction: inverse.vector(&ray.direction),
+            ..*ray
+        };
+        let mut hit = self.object.hit(&local, t_min, t_max)?;
+        let (tangent, _) = tangent_frame(&hit);
+        hit.point = self.transform.matrix().point(&hit.point);
+        hit.normal = self.transform.normal(&hit.normal);
+        hit.tangent = Some(self.transform.matrix().vector(&tangent).unit_vector());
+        Some(hit)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        let bounds = self.object.bounding_box(t0, t1)?;
+        let matrix = self.transform.matrix();
+        Some(around_corners(&bounds, |p| matrix.point(&p)))
+    }
+}
+
+#[cfg(test)]
+fn test_cube() -> Object {
+    Object::from(Cuboid::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(1.0, 1.0, 1.0),
+        Material::grey(),
+    ))
+}
+
+#[test]
+fn test_translate() {
+    let moved = Translate::new(test_cube(), Point3D::new(2.0, 0.0, 0.0));
+    let ray = Ray::new(Point3D::new(2.5, 0.5, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = moved.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 4.0);
+    assert_eq!(hit.point, Point3D::new(2.5, 0.5, 1.0));
+    assert_eq!(hit.normal, Point3D::new(0.0, 0.0, 1.0));
+    // Where the cube used to be there's nothing.
+    let old = Ray::new(Point3D::new(0.5, 0.5, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(moved.hit(&old, 0.001, Float::MAX).is_none());
+    let bounds = moved.bounding_box(0.0, 1.0).unwrap();
+    assert_eq!(bounds.axis_min(0), 2.0);
+    assert_eq!(bounds.axis_max(0), 3.0);
+}
+
+#[test]
+fn test_rotate() {
+    // A quarter turn around y takes the cube from x in [0, 1] to z in
+    // [-1, 0], with its +x face now facing -z.
+    let turned = Rotate::y(test_cube(), 90.0);
+    let ray = Ray::new(Point3D::new(0.5, 0.5, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    let hit = turned.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 4.0);
+    assert_approx_eq!(hit.point.z(), -1.0);
+    assert_approx_eq!(hit.normal.z(), -1.0);
+    assert!(hit.front_face);
+    let bounds = turned.bounding_box(0.0, 1.0).unwrap();
+    assert_approx_eq!(bounds.axis_min(2), -1.0);
+    assert_approx_eq!(bounds.axis_max(2), 0.0);
+    assert_approx_eq!(bounds.axis_max(0), 1.0);
+
+    // Half a turn around the diagonal of the xy plane swaps x and y.
+    let diagonal = Rotate::new(test_cube(), Point3D::new(1.0, 1.0, 0.0), 180.0);
+    let bounds = diagonal.bounding_box(0.0, 1.0).unwrap();
+    assert_approx_eq!(bounds.axis_max(0), 1.0);
+    assert_approx_eq!(bounds.axis_min(2), -1.0);
+    let json = serde_json::to_string(&Object::from(diagonal)).unwrap();
+    assert!(json.starts_with(r#"{"Rotate":{"axis":"#));
+}
+
+#[test]
+fn test_instance() {
+    // Two copies of one cube, one stretched to twice as tall and stood on
+    // its corner, the other moved.
+    let cube = Arc::new(test_cube());
+    let tall = Instance::new(
+        cube.clone(),
+        Transform::new()
+            .scale(Point3D::new(1.0, 2.0, 1.0))
+            .rotate(Point3D::new(0.0, 1.0, 0.0), 45.0),
+    );
+    let moved = Instance::new(
+        cube.clone(),
+        Transform::new().translate(Point3D::new(5.0, 0.0, 0.0)),
+    );
+    assert_eq!(Arc::strong_count(&cube), 3);
+
+    // Down onto the top of the tall one, which is 2 up and its corner at
+    // the origin; the normal is still straight up.
+    let down = Ray::new(Point3D::new(0.5, 5.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let hit = tall.hit(&down, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 3.0);
+    assert_approx_eq!(hit.normal.y(), 1.0);
+    // The turned corner of the cube reaches sqrt(2) along x.
+    let bounds = tall.bounding_box(0.0, 1.0).unwrap();
+    assert_approx_eq!(bounds.axis_max(0), Float::sqrt(2.0));
+    assert_approx_eq!(bounds.axis_max(1), 2.0);
+    let side = Ray::new(Point3D::new(5.5, 0.5, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = moved.hit(&side, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.z(), 1.0);
+
+    let json = serde_json::to_string(&Object::from(tall)).unwrap();
+    assert!(json.starts_with(r#"{"Instance":{"transform":[{"Scale":"#));
+    let loaded = serde_json::from_str::<Object>(&json).unwrap();
+    assert!(loaded.hit(&down, 0.001, Float::MAX).is_some());
+}
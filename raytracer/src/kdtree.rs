@@ -0,0 +1,384 @@
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::stats;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use crate::sphere::Sphere;
+
+// Relative costs of stepping through a node and of hitting an object, for
+// the surface area heuristic, and how much cheaper a split leaving one side
+// empty is counted, since rays through that side skip it outright.
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECTION_COST: Float = 1.5;
+const EMPTY_BONUS: Float = 0.5;
+
+#[derive(Debug, Clone, Copy)]
+struct KdNode {
+    // For an interior node the plane at `split` along `axis` divides it,
+    // the part below is the next node and the part above is node `first`.
+    // A leaf (`axis` 3) holds `count` objects, listed in `refs` from `first`.
+    split: Float,
+    axis: usize,
+    first: usize,
+    count: usize,
+}
+
+const LEAF: usize = 3;
+
+// Where an object's box starts or ends along an axis, for sweeping a plane
+// across a node.
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    at: Float,
+    starts: bool,
+}
+
+// A kd-tree over any list of hittables: space is cut by axis-aligned planes
+// where the surface area heuristic expects rays to be cheapest to trace,
+// with an object straddling a plane listed on both sides. On scenes of
+// walls, floors and other boxy, axis-aligned parts this can beat a
+// bounding volume hierarchy. As with Bvh, objects without a bounding box are
+// kept aside and tested on every ray.
+#[derive(Debug, Clone)]
+pub struct KdTree<T> {
+    nodes: Vec<KdNode>,
+    bounds: Aabb,
+    objects: Vec<T>,
+    // The objects in each leaf, as indices into `objects`.
+    refs: Vec<usize>,
+    unbounded: Vec<T>,
+    // Where each object was in the list the tree was built from.
+    ids: Vec<usize>,
+    unbounded_ids: Vec<usize>,
+}
+
+impl<T: Hittable> KdTree<T> {
+    // A tree for rays sent at times in [0, 1), the default shutter.
+    pub fn new(objects: Vec<T>) -> KdTree<T> {
+        KdTree::for_shutter(objects, 0.0, 1.0)
+    }
+
+    // A tree for rays sent at times in [t0, t1].
+    pub fn for_shutter(objects: Vec<T>, t0: Float, t1: Float) -> KdTree<T> {
+        let mut bounded = Vec::new();
+        let mut boxes = Vec::new();
+        let mut ids = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut unbounded_ids = Vec::new();
+        for (id, object) in objects.into_iter().enumerate() {
+            match object.bounding_box(t0, t1) {
+                Some(bounds) => {
+                    bounded.push(object);
+                    boxes.push(bounds);
+                    ids.push(id);
+                }
+                None => {
+                    unbounded.push(object);
+                    unbounded_ids.push(id);
+                }
+            }
+        }
+
+        let origin = Point3D::new(0.0, 0.0, 0.0);
+        let bounds = boxes
+            .iter()
+            .copied()
+            .reduce(|a, b| Aabb::surrounding_box(&a, &b))
+            .unwrap_or(Aabb::new(origin, origin));
+        let mut tree = KdTree {
+            nodes: Vec::new(),
+            bounds,
+            objects: bounded,
+            refs: Vec::new(),
+            unbounded,
+            ids,
+            unbounded_ids,
+        };
+        if !boxes.is_empty() {
+            // Past this depth splitting rarely pays for the extra nodes.
+            let depth = (8.0 + 1.3 * (boxes.len() as Float).log2()).round() as usize;
+            let all = (0..boxes.len()).collect();
+            tree.build(&boxes, all, bounds, depth);
+        }
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn leaf(&mut self, index: usize, members: &[usize]) {
+        self.nodes[index] = KdNode {
+            split: 0.0,
+            axis: LEAF,
+            first: self.refs.len(),
+            count: members.len(),
+        };
+        self.refs.extend_from_slice(members);
+    }
+
+    // Appends the node for `members` (indices into `boxes`) filling
+    // `bounds`, and the nodes under it, depth first; returns its index.
+    fn build(&mut self, boxes: &[Aabb], members: Vec<usize>, bounds: Aabb, depth: usize) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(KdNode {
+            split: 0.0,
+            axis: LEAF,
+            first: 0,
+            count: 0,
+        });
+        let n = members.len();
+        let area = bounds.surface_area();
+        if n <= 1 || depth == 0 || area <= 0.0 {
+            self.leaf(index, &members);
+            return index;
+        }
+
+        // Sweep a plane along each axis, keeping the cheapest position.
+        let mut best: Option<(Float, usize, Float)> = None;
+        let leaf_cost = INTERSECTION_COST * n as Float;
+        let mut edges = Vec::with_capacity(2 * n);
+        for a in 0..3 {
+            let (low, high) = (bounds.axis_min(a), bounds.axis_max(a));
+            if high <= low {
+                continue;
+            }
+            edges.clear();
+            for &m in &members {
+                edges.push(Edge {
+                    at: boxes[m].axis_min(a).max(low),
+                    starts: true,
+                });
+                edges.push(Edge {
+                    at: boxes[m].axis_max(a).min(high),
+                    starts: false,
+                });
+            }
+            // At the same place, objects ending count as below a plane
+            // there before any starting count as above it.
+            edges.sort_by(|e, f| e.at.total_cmp(&f.at).then(e.starts.cmp(&f.starts)));
+            let (mut below, mut above) = (0, n);
+            for edge in &edges {
+                if !edge.starts {
+                    above -= 1;
+                }
+                if edge.at > low && edge.at < high {
+                    let (mut lower, mut upper) = (bounds, bounds);
+                    lower.max[a] = edge.at;
+                    upper.min[a] = edge.at;
+                    let bonus = if below == 0 || above == 0 {
+                        EMPTY_BONUS
+                    } else {
+                        0.0
+                    };
+                    let cost = TRAVERSAL_COST
+                        + INTERSECTION_COST
+                            * (1.0 - bonus)
+                            * (lower.surface_area() * below as Float
+                                + upper.surface_area() * above as Float)
+                            / area;
+                    if best.is_none_or(|(c, _, _)| cost < c) {
+                        best = Some((cost, a, edge.at));
+                    }
+                }
+                if edge.starts {
+                    below += 1;
+                }
+            }
+        }
+
+        let (a, split) = match best {
+            Some((cost, a, split)) if cost < leaf_cost => (a, split),
+            _ => {
+                self.leaf(index, &members);
+                return index;
+            }
+        };
+        // Flat boxes lying in the plane go on both sides.
+        let lower_members: Vec<usize> = members
+            .iter()
+            .copied()
+            .filter(|&m| boxes[m].axis_min(a) < split || boxes[m].axis_max(a) <= split)
+            .collect();
+        let upper_members: Vec<usize> = members
+            .iter()
+            .copied()
+            .filter(|&m| boxes[m].axis_max(a) > split || boxes[m].axis_min(a) >= split)
+            .collect();
+        let (mut lower, mut upper) = (bounds, bounds);
+        lower.max[a] = split;
+        upper.min[a] = split;
+        self.build(boxes, lower_members, lower, depth - 1);
+        let above = self.build(boxes, upper_members, upper, depth - 1);
+        self.nodes[index] = KdNode {
+            split,
+            axis: a,
+            first: above,
+            count: 0,
+        };
+        index
+    }
+
+    // The closest hit, along with the position of the object hit in the list
+    // the tree was built from.
+    pub fn hit_with_id(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+        for (object, id) in self.unbounded.iter().zip(&self.unbounded_ids) {
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_record = Some((*id, hit));
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit_record;
+        }
+        let (mut near, mut far) = match self.bounds.span(ray, t_min, closest_so_far) {
+            Some(span) => span,
+            None => return hit_record,
+        };
+
+        // The nodes still to visit, with the part of the ray inside each,
+        // nearest on top.
+        let mut stack: Vec<(usize, Float, Float)> = Vec::new();
+        let mut index = 0;
+        let mut visited = 0;
+        loop {
+            let node = &self.nodes[index];
+            visited += 1;
+            if node.axis == LEAF {
+                let refs = &self.refs[node.first..node.first + node.count];
+                for &r in refs {
+                    if let Some(hit) = self.objects[r].hit(ray, t_min, closest_so_far) {
+                        closest_so_far = hit.t;
+                        hit_record = Some((self.ids[r], hit));
+                    }
+                }
+                // Nodes further along can't have anything closer.
+                match stack.pop() {
+                    Some((next, from, to)) if from <= closest_so_far => {
+                        (index, near, far) = (next, from, to);
+                    }
+                    _ => break,
+                }
+                continue;
+            }
+
+            let origin = ray.origin[node.axis];
+            let direction = ray.direction[node.axis];
+            let below_first = origin < node.split || (origin == node.split && direction <= 0.0);
+            let (first, second) = if below_first {
+                (index + 1, node.first)
+            } else {
+                (node.first, index + 1)
+            };
+            if direction == 0.0 {
+                index = first;
+                continue;
+            }
+            let t_split = (node.split - origin) / direction;
+            if t_split > far || t_split <= 0.0 {
+                index = first;
+            } else if t_split < near {
+                index = second;
+            } else {
+                stack.push((second, t_split, far));
+                index = first;
+                far = t_split;
+            }
+        }
+        stats::count(|c| c.nodes_visited += visited);
+        hit_record
+    }
+}
+
+impl<T: Hittable> Hittable for KdTree<T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hit_with_id(ray, t_min, t_max).map(|(_, hit)| hit)
+    }
+
+    // The box of the shutter the tree was built for.
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        if !self.unbounded.is_empty() || self.nodes.is_empty() {
+            return None;
+        }
+        Some(self.bounds)
+    }
+}
+
+#[test]
+fn test_kdtree_matches_linear_scan() {
+    // Overlapping spheres of all sizes, so many straddle the planes, a flat
+    // one, and a glass bubble, whose inside is a sphere of negative radius.
+    let mut spheres = Vec::new();
+    for i in 0..12 {
+        for j in 0..12 {
+            let radius = 0.2 + 0.15 * ((i * 7 + j * 3) % 5) as Float;
+            spheres.push(Sphere::new(
+                Point3D::new(i as Float - 6.0, j as Float - 6.0, -((i * j) % 7) as Float),
+                radius,
+                Material::Glass(Glass::new(1.5)),
+            ));
+        }
+    }
+    spheres.push(Sphere::new(
+        Point3D::new(0.0, 0.0, 0.5),
+        0.0,
+        Material::Glass(Glass::new(1.5)),
+    ));
+    for radius in [1.5, -1.4] {
+        spheres.push(Sphere::new(
+            Point3D::new(2.0, 1.0, 2.0),
+            radius,
+            Material::Glass(Glass::new(1.5)),
+        ));
+    }
+    let tree = KdTree::new(spheres.clone());
+    assert_eq!(tree.len(), spheres.len());
+    assert!(tree.nodes.len() > 1);
+
+    stats::take();
+    for _ in 0..500 {
+        let ray = Ray::new(
+            Point3D::random(-8.0, 8.0) + Point3D::new(0.0, 0.0, 12.0),
+            Point3D::random(-1.0, 1.0),
+        );
+        let expected = spheres
+            .iter()
+            .filter_map(|s| s.hit(&ray, 0.001, Float::MAX))
+            .map(|h| h.t)
+            .fold(Float::INFINITY, Float::min);
+        let found = tree.hit_with_id(&ray, 0.001, Float::MAX);
+        assert_eq!(
+            expected,
+            found.as_ref().map_or(Float::INFINITY, |(_, h)| h.t)
+        );
+        if let Some((id, hit)) = found {
+            assert_eq!(spheres[id].hit(&ray, 0.001, Float::MAX).unwrap().t, hit.t);
+        }
+    }
+    assert!(stats::take().nodes_visited > 500);
+
+    let empty: KdTree<Sphere> = KdTree::new(Vec::new());
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(empty.is_empty());
+    assert!(empty.hit(&ray, 0.001, Float::MAX).is_none());
+    assert!(empty.bounding_box(0.0, 1.0).is_none());
+}
@@ -1,7 +1,67 @@
+pub mod aabb;
+pub mod aov;
+pub mod axial;
+pub mod blue_noise;
+pub mod bvh;
 pub mod camera;
+pub mod color;
+pub mod cone;
 pub mod config;
+pub mod constant_medium;
+pub mod csg;
+pub mod cuboid;
+pub mod cylinder;
+pub mod denoise;
+pub mod disc;
+pub mod displacement;
+pub mod environment;
+pub mod film;
+pub mod float;
+pub mod gltf;
+pub mod gpu;
+pub mod grid;
+pub mod heightfield;
+pub mod heterogeneous_medium;
+pub mod hittable_list;
+pub mod instance;
+pub mod kdtree;
+pub mod light;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logger;
 pub mod materials;
+pub mod mesh;
+pub mod metaballs;
+pub mod moving_sphere;
+pub mod normal_map;
+pub mod object;
+pub mod onb;
+pub mod output;
+pub mod packet;
+pub mod perlin;
+pub mod plane;
+pub mod ply;
 pub mod point3d;
+pub mod polarization;
+pub mod poly;
+#[cfg(all(feature = "preview", not(target_arch = "wasm32")))]
+pub mod preview;
+pub mod principled;
+pub mod progress;
 pub mod ray;
 pub mod raytracer;
-pub mod sphere;
\ No newline at end of file
+pub mod rect;
+pub mod registry;
+pub mod sampler;
+pub mod scenes;
+pub mod sdf;
+pub mod spectrum;
+pub mod sphere;
+pub mod stats;
+pub mod stl;
+pub mod subsurface;
+pub mod texture;
+pub mod thin_film;
+pub mod tone_map;
+pub mod torus;
+pub mod transform;
+pub mod triangle;
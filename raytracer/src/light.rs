@@ -0,0 +1,117 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::materials::SrgbAsArray;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::rect::XyRect;
+use crate::rect::XzRect;
+use crate::rect::YzRect;
+use crate::sphere::Sphere;
+
+// An infinitely small light source. It can't be hit by rays, so it is only
+// seen through the direct lighting it adds at each surface.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct PointLight {
+    pub position: Point3D,
+    // Radiant intensity per channel; falls off with the square of distance.
+    #[serde_as(as = "SrgbAsArray")]
+    pub intensity: Srgb,
+}
+
+impl PointLight {
+    pub fn new(position: Point3D, intensity: Srgb) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+
+    // Light arriving at distance `distance` from the light, before any
+    // cosine or shadowing term.
+    pub fn irradiance_at(&self, distance: Float) -> Srgb {
+        let falloff = to_f32(1.0 / (distance * distance));
+        Srgb::new(
+            self.intensity.red * falloff,
+            self.intensity.green * falloff,
+            self.intensity.blue * falloff,
+        )
+    }
+}
+
+// A glowing surface that is sampled directly at every bounce: a sphere or a
+// rectangle with the DiffuseLight material.
+#[derive(Debug, Clone)]
+pub enum Emitter {
+    Sphere(Sphere),
+    XyRect(XyRect),
+    XzRect(XzRect),
+    YzRect(YzRect),
+}
+
+impl Emitter {
+    pub fn from_object(object: &Object) -> Option<Emitter> {
+        if !matches!(object.material(), Material::DiffuseLight(_)) {
+            return None;
+        }
+        match object {
+            Object::Sphere(s) => Some(Emitter::Sphere(s.clone())),
+            Object::XyRect(r) => Some(Emitter::XyRect(r.clone())),
+            Object::XzRect(r) => Some(Emitter::XzRect(r.clone())),
+            Object::YzRect(r) => Some(Emitter::YzRect(r.clone())),
+            _ => None,
+        }
+    }
+
+    // A direction from `from` towards the emitter and the density, by solid
+    // angle, with which it was picked. None if the emitter can't be seen
+    // from there this way.
+    pub fn sample_direction(&self, from: &Point3D) -> Option<(Point3D, Float)> {
+        match self {
+            Emitter::Sphere(s) => s.sample_direction(from),
+            Emitter::XyRect(r) => r.sample_direction(from),
+            Emitter::XzRect(r) => r.sample_direction(from),
+            Emitter::YzRect(r) => r.sample_direction(from),
+        }
+    }
+
+    // The density `sample_direction` gives to unit `direction`.
+    pub fn direction_pdf(&self, from: &Point3D, direction: &Point3D) -> Float {
+        match self {
+            Emitter::Sphere(s) => s.direction_pdf(from, direction),
+            Emitter::XyRect(r) => r.direction_pdf(from, direction),
+            Emitter::XzRect(r) => r.direction_pdf(from, direction),
+            Emitter::YzRect(r) => r.direction_pdf(from, direction),
+        }
+    }
+
+    pub fn is_on_surface(&self, point: &Point3D) -> bool {
+        match self {
+            Emitter::Sphere(s) => s.is_on_surface(point),
+            Emitter::XyRect(r) => r.is_on_surface(point),
+            Emitter::XzRect(r) => r.is_on_surface(point),
+            Emitter::YzRect(r) => r.is_on_surface(point),
+        }
+    }
+}
+
+#[test]
+fn test_irradiance_falloff() {
+    let light = PointLight::new(Point3D::new(0.0, 0.0, 0.0), Srgb::new(8.0, 4.0, 0.0));
+    assert_eq!(light.irradiance_at(1.0), Srgb::new(8.0, 4.0, 0.0));
+    assert_eq!(light.irradiance_at(2.0), Srgb::new(2.0, 1.0, 0.0));
+}
+
+#[test]
+fn test_to_json() {
+    let light = PointLight::new(Point3D::new(1.0, 2.0, 3.0), Srgb::new(1.0, 1.0, 1.0));
+    let serialized = serde_json::to_string(&light).unwrap();
+    assert_eq!(
+        r#"{"position":{"x":1.0,"y":2.0,"z":3.0},"intensity":[1.0,1.0,1.0]}"#,
+        serialized
+    );
+}
@@ -1,20 +1,500 @@
-use std::env;
-use std::fs;
+use clap::error::ErrorKind;
+use clap::CommandFactory;
+use clap::Parser;
+use clap::Subcommand;
+use std::io::IsTerminal;
+#[cfg(feature = "preview")]
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::info;
+use tracing::warn;
 
+use raytracer::bvh::BvhQuality;
+#[cfg(feature = "preview")]
+use raytracer::camera::Camera;
+#[cfg(feature = "preview")]
+use raytracer::camera::Navigation;
+use raytracer::camera::Stereo;
+use raytracer::camera::StereoLayout;
+use raytracer::config::Accelerator;
 use raytracer::config::Config;
-use raytracer::raytracer::render;
+use raytracer::config::Integrator;
+use raytracer::float::Float;
+#[cfg(feature = "gpu")]
+use raytracer::gpu;
+use raytracer::gpu::Backend;
+use raytracer::logger;
+#[cfg(feature = "gpu")]
+use raytracer::output;
+use raytracer::output::Encoding;
+use raytracer::output::Format;
+use raytracer::output::Transfer;
+#[cfg(feature = "preview")]
+use raytracer::preview::read_keys;
+#[cfg(feature = "preview")]
+use raytracer::preview::Preview;
+use raytracer::progress::Progress;
+#[cfg(feature = "preview")]
+use raytracer::raytracer::explore;
+use raytracer::raytracer::render_with_settings;
+use raytracer::raytracer::RenderSettings;
+use raytracer::raytracer::Renderer;
+use raytracer::sampler::Adaptive;
+use raytracer::sampler::Sampler;
+use raytracer::scenes;
+use raytracer::tone_map::ToneMap;
+
+/// Renders a JSON scene description to a PNG image.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Scene description file (JSON), a glTF 2.0 file (.gltf or .glb) to
+    /// import, or "cornell" for the built-in Cornell box, or "cover" for a
+    /// random book cover scene (see --seed)
+    #[arg(long, required = true)]
+    scene: Option<String>,
+
+    /// Output image file
+    #[arg(long, default_value = "out.png")]
+    output: String,
+
+    /// Image width in pixels, overriding the scene's width
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Image height in pixels, overriding the scene's height
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Samples per pixel, overriding the scene's samples_per_pixel
+    #[arg(long)]
+    samples: Option<u32>,
+
+    /// Maximum ray bounce depth, overriding the scene's max_depth
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// How samples are placed within a pixel (random, stratified, halton,
+    /// blue-noise or sobol), overriding the scene's sampler
+    #[arg(long)]
+    sampler: Option<Sampler>,
+
+    /// How rays find what they hit (bvh, kd-tree or grid), overriding the
+    /// scene's accelerator
+    #[arg(long)]
+    accelerator: Option<Accelerator>,
+
+    /// How the bounding volume hierarchy is split (sah or median),
+    /// overriding the scene's bvh_quality
+    #[arg(long)]
+    bvh_quality: Option<BvhQuality>,
+
+    /// Carry light as RGB, as a spectrum at sampled wavelengths or as RGB
+    /// with its polarization (rgb, spectral or polarized), overriding the
+    /// scene's integrator
+    #[arg(long)]
+    integrator: Option<Integrator>,
+
+    /// Put a linear polarizing filter turned this many degrees over the
+    /// lens, rendering with the polarized integrator
+    #[arg(long, allow_negative_numbers = true)]
+    polarizer: Option<Float>,
+
+    /// Sample each pixel until its estimated relative error is below this
+    /// (e.g. 0.01), instead of a fixed number of samples
+    #[arg(long)]
+    adaptive: Option<Float>,
+
+    /// Limit each sample's direct light at the first surface to this
+    /// brightness, against fireflies
+    #[arg(long)]
+    clamp_direct: Option<f32>,
+
+    /// Limit each sample's light from bounces to this brightness
+    #[arg(long)]
+    clamp_indirect: Option<f32>,
+
+    /// Scale back samples brighter than their pixel's mean by more than this
+    /// many standard deviations
+    #[arg(long)]
+    reject_outliers: Option<Float>,
+
+    /// Seed for the random numbers, so the same seed renders the same image;
+    /// also picks the spheres of the "cover" scene
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Render a stereo pair with the eyes this far apart, side by side in
+    /// one image twice the width
+    #[arg(long)]
+    stereo: Option<Float>,
+
+    /// Distance at which the stereo pair's eyes converge (by default the
+    /// camera's focus distance)
+    #[arg(long, requires = "stereo")]
+    convergence: Option<Float>,
+
+    /// Write the stereo pair's eyes to two files, e.g. out.left.png and
+    /// out.right.png, instead of side by side
+    #[arg(long, requires = "stereo")]
+    stereo_files: bool,
+
+    /// Render progressively, writing the image so far to the output file
+    /// every this many seconds
+    #[arg(long)]
+    progressive: Option<f64>,
+
+    /// Save the samples taken so far to this file whenever the image is
+    /// written, so the render can be resumed
+    #[arg(long)]
+    checkpoint: Option<String>,
+
+    /// Also save the checkpoint while rendering, every this many seconds
+    #[arg(long, default_value_t = 60.0)]
+    checkpoint_interval: f64,
+
+    /// Carry on from a checkpoint of the same scene
+    #[arg(long)]
+    resume: Option<String>,
+
+    /// Show the image in the terminal as it renders (with the preview
+    /// feature, on by default), this many characters wide
+    #[arg(long, num_args = 0..=1, default_missing_value = "80")]
+    preview: Option<usize>,
+
+    /// Before rendering, move the camera around in the preview: W/A/S/D,
+    /// R/F, the arrow keys and dragging with the mouse, then Q or Enter to
+    /// render from there
+    #[arg(long)]
+    navigate: bool,
+
+    /// Where to render: cpu, or gpu (with the gpu feature) for a quick
+    /// render of spheres with simple materials in a wgpu compute shader,
+    /// without passes, previews or checkpoints
+    #[arg(long, default_value = "cpu")]
+    backend: Backend,
+
+    /// Write .ppm output as text (P3) instead of binary (P6)
+    #[arg(long)]
+    ppm_ascii: bool,
+
+    /// Write PNG output with 16 bits per channel instead of 8
+    #[arg(long)]
+    png_16: bool,
+
+    /// JPEG quality, from 1 to 100
+    #[arg(long, default_value_t = 90)]
+    jpeg_quality: u8,
+
+    /// How colours brighter than white are brought into range: clamp,
+    /// reinhard, aces or uncharted2
+    #[arg(long, default_value = "clamp")]
+    tone_map: ToneMap,
+
+    /// How light values are encoded in the image: a gamma such as 2.2, srgb,
+    /// or linear
+    #[arg(long, default_value = "2")]
+    transfer: Transfer,
+
+    /// Also write albedo, normal and depth passes, e.g. out.albedo.png next
+    /// to out.png
+    #[arg(long)]
+    aovs: bool,
+
+    /// Also write object and material id passes: out.id.exr for out.exr, or
+    /// a colour per id in out.object_id.png and out.material_id.png
+    #[arg(long)]
+    ids: bool,
+
+    /// Remove the noise from the finished image with Open Image Denoise
+    /// (needs a build with the experimental oidn feature)
+    #[arg(long)]
+    denoise: bool,
+
+    /// Print what the render cost (rays traced, accelerator nodes visited,
+    /// texture lookups, peak memory...) and write it to out.stats.json
+    #[arg(long)]
+    stats: bool,
+
+    /// Log what the renderer is doing to stderr: -v for loading, building
+    /// and timings, -vv for each tile as well
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render the built-in scenes at a fixed seed, without writing them, and
+    /// report how long they took and how fast rays were traced
+    Bench(BenchArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// Image width in pixels; the height keeps each scene's aspect ratio
+    #[arg(long, default_value_t = 400)]
+    width: usize,
+
+    /// Samples per pixel
+    #[arg(long, default_value_t = 16)]
+    samples: u32,
+
+    /// How rays find what they hit (bvh, kd-tree or grid), overriding each
+    /// scene's accelerator
+    #[arg(long)]
+    accelerator: Option<Accelerator>,
+}
+
+// The scene file with the command line's overrides applied.
+fn load_scene(args: &Args) -> Config {
+    let name = args.scene.as_deref().expect("No scene given");
+    let start = Instant::now();
+    let mut scene = match scenes::named(name, args.seed.unwrap_or(0)) {
+        Some(scene) => scene,
+        None => Config::load(name).expect("Unable to load scene file"),
+    };
+    info!(
+        "Loaded {} ({} objects) in {}ms",
+        name,
+        scene.objects.len(),
+        start.elapsed().as_millis()
+    );
+    if let Some(width) = args.width {
+        scene.width = width;
+    }
+    if let Some(height) = args.height {
+        scene.height = height;
+    }
+    if let Some(samples) = args.samples {
+        scene.samples_per_pixel = samples;
+    }
+    if let Some(max_depth) = args.max_depth {
+        scene.max_depth = max_depth;
+    }
+    if let Some(sampler) = args.sampler {
+        scene.sampler = sampler;
+    }
+    if let Some(accelerator) = args.accelerator {
+        scene.accelerator = accelerator;
+    }
+    if let Some(angle) = args.polarizer {
+        scene.camera = scene.camera.with_polarizer(Some(angle));
+        scene.integrator = Integrator::Polarized;
+    }
+    if let Some(integrator) = args.integrator {
+        scene.integrator = integrator;
+    }
+    if scene.camera.polarizer().is_some() && scene.integrator != Integrator::Polarized {
+        warn!("The camera's polarizer only shows with the polarized integrator");
+    }
+    if let Some(quality) = args.bvh_quality {
+        scene.bvh_quality = quality;
+    }
+    if let Some(threshold) = args.adaptive {
+        let mut adaptive = scene.adaptive.unwrap_or(Adaptive::new(threshold));
+        adaptive.threshold = threshold;
+        scene.adaptive = Some(adaptive);
+    }
+    if args.clamp_direct.is_some()
+        || args.clamp_indirect.is_some()
+        || args.reject_outliers.is_some()
+    {
+        let mut firefly = scene.firefly.unwrap_or_default();
+        firefly.direct = args.clamp_direct.or(firefly.direct);
+        firefly.indirect = args.clamp_indirect.or(firefly.indirect);
+        firefly.outlier_sigmas = args.reject_outliers.or(firefly.outlier_sigmas);
+        scene.firefly = Some(firefly);
+    }
+    if let Some(interocular) = args.stereo {
+        let layout = if args.stereo_files {
+            StereoLayout::Files
+        } else {
+            // Each eye keeps the width asked for.
+            scene.width *= 2;
+            StereoLayout::SideBySide
+        };
+        scene.camera = scene.camera.with_stereo(Some(Stereo {
+            interocular,
+            convergence: args.convergence,
+            layout,
+        }));
+    }
+    scene
+}
+
+// Lets the user move the camera around a preview of `scene`, `columns`
+// characters wide, and returns where they left it.
+#[cfg(feature = "preview")]
+fn navigate(mut scene: Config, columns: usize, encoding: Encoding) -> Camera {
+    // Only as many pixels as the preview shows are rendered.
+    let columns = columns.clamp(1, scene.width);
+    scene.height = (scene.height * columns / scene.width).max(1);
+    scene.width = columns;
+    let preview = Arc::new(Preview::new(scene.width, scene.height, columns, encoding));
+    let settings = RenderSettings {
+        on_tile: Some(Arc::new(move |tile, pixels| preview.update(tile, pixels))),
+        ..Default::default()
+    };
+    let (keys, terminal) = read_keys();
+    let mut terminal = Some(terminal);
+    explore(scene, settings, || match keys.try_recv() {
+        Ok(Navigation::Cancel) => {
+            // Back out of raw mode before going.
+            drop(terminal.take());
+            std::process::exit(130);
+        }
+        Ok(navigation) => Some(navigation),
+        Err(TryRecvError::Empty) => None,
+        Err(TryRecvError::Disconnected) => Some(Navigation::Finish),
+    })
+}
+
+// Renders each built-in scene as `args` says and prints a line of timings
+// and counts for it.
+fn bench(args: &BenchArgs) {
+    const SEED: u64 = 1;
+    println!(
+        "{:<10} {:>9} {:>7} {:>9} {:>12} {:>9} {:>8}",
+        "scene", "size", "samples", "time (s)", "rays", "Mrays/s", "bounces"
+    );
+    for name in scenes::NAMES {
+        let mut scene = scenes::named(name, SEED).expect("Unknown built-in scene");
+        scene.height = (scene.height * args.width / scene.width).max(1);
+        scene.width = args.width;
+        scene.samples_per_pixel = args.samples;
+        scene.adaptive = None;
+        if let Some(accelerator) = args.accelerator {
+            scene.accelerator = accelerator;
+        }
+        let size = format!("{}x{}", scene.width, scene.height);
+        let settings = RenderSettings {
+            seed: Some(SEED),
+            ..Default::default()
+        };
+
+        // From building the accelerator to the last sample.
+        let start = Instant::now();
+        let renderer = Renderer::new(scene, settings);
+        renderer.render().expect("Unable to render");
+        let seconds = start.elapsed().as_secs_f64();
+        let counts = renderer.counts();
+        println!(
+            "{:<10} {:>9} {:>7} {:>9.3} {:>12} {:>9.2} {:>8.2}",
+            name,
+            size,
+            args.samples,
+            seconds,
+            counts.rays,
+            counts.rays as f64 / seconds / 1e6,
+            counts.average_bounces()
+        );
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        println!("Usage: {} <config_file> <output_file>", args[0]);
+    let args = Args::parse();
+    logger::init(args.verbose);
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        bench(bench_args);
+        return;
+    }
+    Format::from_filename(&args.output)
+        .expect("Unknown output format: use .png, .jpg, .exr or .ppm");
+    if args.denoise && !cfg!(feature = "oidn") {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--denoise needs a build with the oidn feature",
+            )
+            .exit();
+    }
+    if (args.preview.is_some() || args.navigate) && !cfg!(feature = "preview") {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--preview and --navigate need a build with the preview feature",
+            )
+            .exit();
+    }
+
+    let encoding = Encoding {
+        ascii_ppm: args.ppm_ascii,
+        png_16_bit: args.png_16,
+        jpeg_quality: args.jpeg_quality,
+        tone_map: args.tone_map,
+        transfer: args.transfer,
+    };
+    let scene = load_scene(&args);
+    #[cfg(feature = "preview")]
+    let scene = if args.navigate {
+        let camera = navigate(load_scene(&args), args.preview.unwrap_or(80), encoding);
+        println!(
+            "Camera: {}",
+            serde_json::to_string(&camera).expect("Unable to save camera")
+        );
+        Config { camera, ..scene }
+    } else {
+        scene
+    };
+
+    #[cfg(feature = "gpu")]
+    if args.backend == Backend::Gpu {
+        info!("Rendering {} on the GPU", args.output);
+        let start = Instant::now();
+        let seed = args.seed.unwrap_or_else(rand::random);
+        let film = gpu::render(&scene, seed).expect("Unable to render on the GPU");
+        info!("Frame time: {}ms", start.elapsed().as_millis());
+        output::write_film(&args.output, &film, &encoding).expect("error writing image");
         return;
     }
 
-    let json = fs::read(&args[1]).expect("Unable to read config file.");
-    let scene = serde_json::from_slice::<Config>(&json).expect("Unable to parse config json");
+    let mut settings = RenderSettings {
+        flush_interval: args.progressive.map(Duration::from_secs_f64),
+        checkpoint: args.checkpoint,
+        checkpoint_interval: Some(Duration::from_secs_f64(args.checkpoint_interval)),
+        resume: args.resume,
+        encoding,
+        aovs: args.aovs,
+        ids: args.ids,
+        denoise: args.denoise,
+        stats: args.stats,
+        seed: args.seed,
+        ..Default::default()
+    };
+    #[cfg(feature = "preview")]
+    let preview = args
+        .preview
+        .map(|columns| Arc::new(Preview::new(scene.width, scene.height, columns, encoding)));
+    #[cfg(feature = "preview")]
+    if let Some(preview) = &preview {
+        let preview = preview.clone();
+        settings.on_tile = Some(Arc::new(move |tile, pixels| preview.update(tile, pixels)));
+    }
+    if settings.on_tile.is_none() && std::io::stderr().is_terminal() {
+        // The preview takes the whole terminal, so it's one or the other.
+        let progress = Progress::for_scene(&scene);
+        settings.on_tile = Some(Arc::new(move |tile, pixels| progress.update(tile, pixels)));
+    }
 
-    let filename = &args[2]; //format!("{}_{:0>3}.png", args[2], i);
-    println!("\nRendering {}", filename);
-    render(&filename, scene);
+    info!("Rendering {}", args.output);
+    let reports = render_with_settings(&args.output, scene, settings).expect("Unable to render");
+    #[cfg(feature = "preview")]
+    if let Some(preview) = preview {
+        preview.draw();
+    }
+    if args.stats {
+        for report in reports {
+            println!("{}", report);
+        }
+    }
 }
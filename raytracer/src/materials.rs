@@ -1,22 +1,58 @@
-use jpeg_decoder::Decoder;
 use palette::Srgb;
-use rand::Rng;
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
-use std::fs::File;
-use std::io::BufReader;
+use std::fmt;
+use std::sync::Arc;
 
+use crate::float::consts::PI;
+use crate::float::from_f64;
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::normal_map::BumpMap;
+use crate::normal_map::NormalMap;
+use crate::onb::Onb;
 use crate::point3d::Point3D;
+use crate::principled::Principled;
 use crate::ray::HitRecord;
 use crate::ray::Ray;
+use crate::registry::Registry;
+use crate::sampler;
+use crate::spectrum;
+use crate::subsurface::Subsurface;
+use crate::texture::ImageTexture;
+use crate::texture::Texture;
+use crate::thin_film::ThinFilm;
+
+#[cfg(test)]
+use crate::texture::Checker;
 
 pub trait Scatterable {
     fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)>;
+
+    // Light given off by the surface itself, added before any scattering.
+    fn emitted(&self, _u: Float, _v: Float, _p: &Point3D) -> Srgb {
+        Srgb::new(0.0, 0.0, 0.0)
+    }
+
+    // How much of the light arriving from unit `direction` is scattered back
+    // along `ray`: the BRDF times the cosine term. Lets the renderer sample
+    // light sources directly instead of waiting for `scatter` to find them.
+    // None for surfaces that can't be evaluated for an arbitrary direction,
+    // such as mirrors and glass.
+    fn evaluate(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Point3D) -> Option<Srgb> {
+        None
+    }
+
+    // The probability density (per unit of solid angle) with which `scatter`
+    // picks unit `direction`, used to weigh scattering against sampling the
+    // lights. Zero if unknown, which leaves lighting to light sampling.
+    fn pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Point3D) -> Float {
+        0.0
+    }
 }
 
 // https://docs.rs/serde_with/1.9.4/serde_with/macro.serde_conv.html
 serde_with::serde_conv!(
-    SrgbAsArray,
+    pub(crate) SrgbAsArray,
     Srgb,
     |srgb: &Srgb| [srgb.red, srgb.green, srgb.blue],
     |value: [f32; 3]| -> Result<_, std::convert::Infallible> {
@@ -24,35 +60,307 @@ serde_with::serde_conv!(
     }
 );
 
-// TODO: replace this with the more elegant implementation in config.rs
-serde_with::serde_conv!(
-    TexturePixelsAsPath,
-    Vec<u8>,
-    |_pixels: &Vec<u8>| "/tmp/texture.jpg",
-    |value: &str| -> Result<_, std::convert::Infallible> { Ok(load_texture_image(value).0) }
-);
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum Material {
     Lambertian(Lambertian),
+    OrenNayar(OrenNayar),
     Metal(Metal),
+    RoughMetal(RoughMetal),
     Glass(Glass),
-    Texture(Texture),
+    Principled(Principled),
+    Subsurface(Subsurface),
+    Texture(Textured),
     Light(Light),
+    DiffuseLight(DiffuseLight),
+    Isotropic(Isotropic),
+    NormalMap(NormalMap),
+    BumpMap(BumpMap),
+    ThinFilm(ThinFilm),
+    Custom(Custom),
 }
 
 impl Scatterable for Material {
     fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
         match self {
             Material::Lambertian(l) => l.scatter(ray, hit_record),
+            Material::OrenNayar(o) => o.scatter(ray, hit_record),
             Material::Metal(m) => m.scatter(ray, hit_record),
+            Material::RoughMetal(m) => m.scatter(ray, hit_record),
             Material::Glass(g) => g.scatter(ray, hit_record),
+            Material::Principled(p) => p.scatter(ray, hit_record),
+            Material::Subsurface(s) => s.scatter(ray, hit_record),
             Material::Texture(t) => t.scatter(ray, hit_record),
             Material::Light(l) => l.scatter(ray, hit_record),
+            Material::DiffuseLight(d) => d.scatter(ray, hit_record),
+            Material::Isotropic(i) => i.scatter(ray, hit_record),
+            Material::NormalMap(n) => n.scatter(ray, hit_record),
+            Material::BumpMap(b) => b.scatter(ray, hit_record),
+            Material::ThinFilm(f) => f.scatter(ray, hit_record),
+            Material::Custom(c) => c.scatter(ray, hit_record),
+        }
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        match self {
+            Material::Lambertian(l) => l.emitted(u, v, p),
+            Material::OrenNayar(o) => o.emitted(u, v, p),
+            Material::Metal(m) => m.emitted(u, v, p),
+            Material::RoughMetal(m) => m.emitted(u, v, p),
+            Material::Glass(g) => g.emitted(u, v, p),
+            Material::Principled(m) => m.emitted(u, v, p),
+            Material::Subsurface(s) => s.emitted(u, v, p),
+            Material::Texture(t) => t.emitted(u, v, p),
+            Material::Light(l) => l.emitted(u, v, p),
+            Material::DiffuseLight(d) => d.emitted(u, v, p),
+            Material::Isotropic(i) => i.emitted(u, v, p),
+            Material::NormalMap(n) => n.emitted(u, v, p),
+            Material::BumpMap(b) => b.emitted(u, v, p),
+            Material::ThinFilm(f) => f.emitted(u, v, p),
+            Material::Custom(c) => c.emitted(u, v, p),
+        }
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        match self {
+            Material::Lambertian(l) => l.evaluate(ray, hit_record, direction),
+            Material::OrenNayar(o) => o.evaluate(ray, hit_record, direction),
+            Material::Metal(m) => m.evaluate(ray, hit_record, direction),
+            Material::RoughMetal(m) => m.evaluate(ray, hit_record, direction),
+            Material::Glass(g) => g.evaluate(ray, hit_record, direction),
+            Material::Principled(p) => p.evaluate(ray, hit_record, direction),
+            Material::Subsurface(s) => s.evaluate(ray, hit_record, direction),
+            Material::Texture(t) => t.evaluate(ray, hit_record, direction),
+            Material::Light(l) => l.evaluate(ray, hit_record, direction),
+            Material::DiffuseLight(d) => d.evaluate(ray, hit_record, direction),
+            Material::Isotropic(i) => i.evaluate(ray, hit_record, direction),
+            Material::NormalMap(n) => n.evaluate(ray, hit_record, direction),
+            Material::BumpMap(b) => b.evaluate(ray, hit_record, direction),
+            Material::ThinFilm(f) => f.evaluate(ray, hit_record, direction),
+            Material::Custom(c) => c.evaluate(ray, hit_record, direction),
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        match self {
+            Material::Lambertian(l) => l.pdf(ray, hit_record, direction),
+            Material::OrenNayar(o) => o.pdf(ray, hit_record, direction),
+            Material::Metal(m) => m.pdf(ray, hit_record, direction),
+            Material::RoughMetal(m) => m.pdf(ray, hit_record, direction),
+            Material::Glass(g) => g.pdf(ray, hit_record, direction),
+            Material::Principled(p) => p.pdf(ray, hit_record, direction),
+            Material::Subsurface(s) => s.pdf(ray, hit_record, direction),
+            Material::Texture(t) => t.pdf(ray, hit_record, direction),
+            Material::Light(l) => l.pdf(ray, hit_record, direction),
+            Material::DiffuseLight(d) => d.pdf(ray, hit_record, direction),
+            Material::Isotropic(i) => i.pdf(ray, hit_record, direction),
+            Material::NormalMap(n) => n.pdf(ray, hit_record, direction),
+            Material::BumpMap(b) => b.pdf(ray, hit_record, direction),
+            Material::ThinFilm(f) => f.pdf(ray, hit_record, direction),
+            Material::Custom(c) => c.pdf(ray, hit_record, direction),
+        }
+    }
+}
+
+impl Material {
+    // The same material in a plain `color`. Materials without a color of
+    // their own are left as they are.
+    pub fn with_color(&self, color: Srgb) -> Material {
+        self.with_texture(Texture::Solid(color))
+    }
+
+    // The same, with its color from `texture`, e.g. one blending a mesh's
+    // vertex colors.
+    pub fn with_texture(&self, texture: Texture) -> Material {
+        let mut material = self.clone();
+        match &mut material {
+            Material::Lambertian(Lambertian { albedo, .. })
+            | Material::OrenNayar(OrenNayar { albedo, .. })
+            | Material::Metal(Metal { albedo, .. })
+            | Material::RoughMetal(RoughMetal { albedo, .. }) => *albedo = texture,
+            Material::Principled(p) => p.base_color = texture,
+            Material::DiffuseLight(d) => d.emit = texture,
+            Material::NormalMap(n) => *n.material = n.material.with_texture(texture),
+            Material::BumpMap(b) => *b.material = b.material.with_texture(texture),
+            Material::ThinFilm(f) => *f.material = f.material.with_texture(texture),
+            _ => {}
         }
+        material
+    }
+
+    // Whether any of the material's textures is an image: those are worth
+    // filtering over the pixel they're seen in.
+    pub fn has_image(&self) -> bool {
+        let image = |texture: &Texture| matches!(texture, Texture::Image(_));
+        match self {
+            Material::Lambertian(Lambertian { albedo, .. })
+            | Material::OrenNayar(OrenNayar { albedo, .. })
+            | Material::Metal(Metal { albedo, .. })
+            | Material::RoughMetal(RoughMetal { albedo, .. })
+            | Material::Isotropic(Isotropic { albedo, .. }) => image(albedo),
+            Material::Principled(p) => image(&p.base_color),
+            Material::DiffuseLight(d) => image(&d.emit),
+            Material::NormalMap(n) => image(&n.map) || n.material.has_image(),
+            Material::BumpMap(b) => image(&b.map) || b.material.has_image(),
+            Material::ThinFilm(f) => f.material.has_image(),
+            Material::Texture(_) => true,
+            _ => false,
+        }
+    }
+
+    // Whether the way light leaves depends on its wavelength, so that a path
+    // can only go on at one.
+    pub fn disperses(&self) -> bool {
+        matches!(
+            self,
+            Material::Glass(Glass {
+                dispersion: Some(_),
+                ..
+            }) | Material::ThinFilm(_)
+        )
+    }
+
+    // A plain grey diffuse material, for tests of shapes, where what they
+    // are made of doesn't matter.
+    #[cfg(test)]
+    pub fn grey() -> Material {
+        Material::Lambertian(Lambertian::new(Srgb::new(0.5, 0.5, 0.5)))
+    }
+}
+
+pub type SharedScatterable = Arc<dyn Scatterable + Send + Sync>;
+
+// A material implemented outside this crate, so new kinds of surfaces don't
+// need a new Material variant. Scene files name it, and the Registry they
+// are loaded with makes it.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(try_from = "CustomParams", into = "CustomParams")]
+pub struct Custom {
+    name: String,
+    params: serde_json::Value,
+    scatterable: SharedScatterable,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CustomParams {
+    pub name: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+impl TryFrom<CustomParams> for Custom {
+    type Error = String;
+
+    fn try_from(p: CustomParams) -> Result<Self, Self::Error> {
+        let scatterable = Registry::material(&p.name, &p.params)?;
+        Ok(Custom {
+            name: p.name,
+            params: p.params,
+            scatterable,
+        })
+    }
+}
+
+impl From<Custom> for CustomParams {
+    fn from(c: Custom) -> Self {
+        CustomParams {
+            name: c.name,
+            params: c.params,
+        }
+    }
+}
+
+impl Custom {
+    // For materials built in code; `name` and `params` are only used when
+    // the scene is saved.
+    pub fn new(name: &str, params: serde_json::Value, scatterable: SharedScatterable) -> Custom {
+        Custom {
+            name: name.to_string(),
+            params,
+            scatterable,
+        }
+    }
+}
+
+impl fmt::Debug for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Custom")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl Scatterable for Custom {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        self.scatterable.scatter(ray, hit_record)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        self.scatterable.emitted(u, v, p)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        self.scatterable.evaluate(ray, hit_record, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        self.scatterable.pdf(ray, hit_record, direction)
+    }
+}
+
+#[cfg(test)]
+struct Absorber {
+    glow: f32,
+}
+
+#[cfg(test)]
+impl Scatterable for Absorber {
+    fn scatter(&self, _ray: &Ray, _hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        None
+    }
+
+    fn emitted(&self, _u: Float, _v: Float, _p: &Point3D) -> Srgb {
+        Srgb::new(self.glow, self.glow, self.glow)
     }
 }
 
+#[test]
+fn test_custom_material() {
+    let mut registry = Registry::new();
+    registry.register_material("absorber", |params| {
+        let glow = params["glow"].as_f64().ok_or("absorber needs a glow")?;
+        Ok(Arc::new(Absorber { glow: glow as f32 }))
+    });
+
+    let json = r#"{"Custom":{"name":"absorber","params":{"glow":2.0}}}"#;
+    let read = |json| registry.scope(|| serde_json::from_str::<Material>(json));
+    let material = read(json).unwrap();
+    let p = Point3D::new(0.0, 0.0, 0.0);
+    assert_eq!(material.emitted(0.0, 0.0, &p), Srgb::new(2.0, 2.0, 2.0));
+    let hit = HitRecord {
+        t: 1.0,
+        point: p,
+        normal: Point3D::new(0.0, 0.0, 1.0),
+        front_face: true,
+        material: &material,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 1.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(material.scatter(&ray, &hit).is_none());
+    assert_eq!(json, serde_json::to_string(&material).unwrap());
+
+    let unknown = r#"{"Custom":{"name":"no-such-material"}}"#;
+    let err = read(unknown).unwrap_err();
+    assert!(err.to_string().contains("unknown custom material"));
+    let bad = r#"{"Custom":{"name":"absorber","params":{}}}"#;
+    assert!(read(bad).is_err());
+    // Only scenes loaded with the registry can use it.
+    let err = serde_json::from_str::<Material>(json).unwrap_err();
+    assert!(err.to_string().contains("unknown custom material"));
+}
+
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Light {}
 
@@ -62,64 +370,282 @@ impl Light {
     }
 }
 
+impl Default for Light {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Scatterable for Light {
     fn scatter(&self, _ray: &Ray, _hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
         Some((None, Srgb::new(1.0, 1.0, 1.0)))
     }
 }
 
-#[serde_with::serde_as]
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+// An emitter that absorbs every ray hitting it. Unlike Light, its color can
+// be brighter than white, so it can actually illuminate the scene.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiffuseLight {
+    pub emit: Texture,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Srgb) -> DiffuseLight {
+        DiffuseLight {
+            emit: Texture::Solid(emit),
+        }
+    }
+
+    pub fn textured(emit: Texture) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Scatterable for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        None
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        self.emit.value(u, v, p)
+    }
+}
+
+// Scatters light equally in all directions, as particles in fog or smoke
+// do. Used inside volumes rather than on surfaces.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Isotropic {
+    pub albedo: Texture,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Texture) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Scatterable for Isotropic {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let direction = Point3D::random_in_unit_sphere().unit_vector();
+        let scattered = Ray::with_time(hit_record.point, direction, ray.time);
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        Some((Some(scattered), attenuation))
+    }
+
+    // Light is scattered equally in all directions, over the whole sphere.
+    fn evaluate(&self, _ray: &Ray, hit_record: &HitRecord, _direction: &Point3D) -> Option<Srgb> {
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        Some(scale(albedo, 1.0 / (4.0 * PI)))
+    }
+
+    fn pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Point3D) -> Float {
+        1.0 / (4.0 * PI)
+    }
+}
+
+// The Lambertian BRDF (albedo / pi) times the cosine term, for light from
+// unit `direction`.
+fn lambert(albedo: Srgb, normal: &Point3D, direction: &Point3D) -> Srgb {
+    let cosine = normal.dot(direction).max(0.0);
+    scale(albedo, cosine / PI)
+}
+
+// Density of cosine-weighted sampling about `normal`.
+fn cosine_pdf(normal: &Point3D, direction: &Point3D) -> Float {
+    normal.dot(direction).max(0.0) / PI
+}
+
+fn scale(color: Srgb, factor: Float) -> Srgb {
+    let factor = to_f32(factor);
+    Srgb::new(
+        color.red * factor,
+        color.green * factor,
+        color.blue * factor,
+    )
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Lambertian {
-    #[serde_as(as = "SrgbAsArray")]
-    pub albedo: Srgb,
+    pub albedo: Texture,
 }
 
 impl Lambertian {
     pub fn new(albedo: Srgb) -> Lambertian {
+        Lambertian {
+            albedo: Texture::Solid(albedo),
+        }
+    }
+
+    pub fn textured(albedo: Texture) -> Lambertian {
         Lambertian { albedo }
     }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
-        let mut scatter_direction = hit_record.normal + Point3D::random_in_unit_sphere();
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.normal;
-        }
-        let target = hit_record.point + scatter_direction;
-        let scattered = Ray::new(hit_record.point, target - hit_record.point);
-        let attenuation = self.albedo;
+    // Directions are sampled in proportion to the cosine, as the light they
+    // bring is, so each sample's weight is just the albedo. pdf gives the
+    // same density for mixing with light sampling.
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let direction = Onb::new(hit_record.normal).cosine_direction(sampler::next_2d());
+        let scattered = Ray::with_time(hit_record.point, direction, ray.time);
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
         Some((Some(scattered), attenuation))
     }
+
+    fn evaluate(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        Some(lambert(albedo, &hit_record.normal, direction))
+    }
+
+    fn pdf(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        cosine_pdf(&hit_record.normal, direction)
+    }
 }
 
-#[serde_with::serde_as]
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+// A rough diffuse surface (Oren-Nayar), made of tiny Lambertian facets
+// tilted with a standard deviation of `sigma` radians. Unlike Lambertian,
+// it gets brighter towards the light source and darker at silhouettes
+// facing away from it, like clay or concrete. Sigma 0 is Lambertian.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OrenNayar {
+    pub albedo: Texture,
+    pub sigma: Float,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Srgb, sigma: Float) -> OrenNayar {
+        OrenNayar {
+            albedo: Texture::Solid(albedo),
+            sigma,
+        }
+    }
+
+    pub fn textured(albedo: Texture, sigma: Float) -> OrenNayar {
+        OrenNayar { albedo, sigma }
+    }
+
+    // The BRDF relative to a Lambertian one, for unit directions towards the
+    // viewer (`wo`) and the light (`wi`).
+    fn factor(&self, n: &Point3D, wo: &Point3D, wi: &Point3D) -> Float {
+        let s2 = self.sigma * self.sigma;
+        let a = 1.0 - 0.5 * s2 / (s2 + 0.33);
+        let b = 0.45 * s2 / (s2 + 0.09);
+
+        let cos_o = n.dot(wo).clamp(0.0, 1.0);
+        let cos_i = n.dot(wi).clamp(0.0, 1.0);
+        let sin_o = (1.0 - cos_o * cos_o).sqrt();
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        // Cosine of the azimuth between the two directions.
+        let po = *wo - *n * cos_o;
+        let pi = *wi - *n * cos_i;
+        let cos_phi = if po.near_zero() || pi.near_zero() {
+            0.0
+        } else {
+            po.unit_vector().dot(&pi.unit_vector()).max(0.0)
+        };
+        // sin(alpha) * tan(beta), alpha and beta the larger and smaller
+        // of the two angles from the normal.
+        let sin_alpha_tan_beta = if cos_i < cos_o {
+            sin_i * sin_o / cos_o.max(1e-6)
+        } else {
+            sin_o * sin_i / cos_i.max(1e-6)
+        };
+        a + b * cos_phi * sin_alpha_tan_beta
+    }
+}
+
+impl Scatterable for OrenNayar {
+    // Cosine-weighted sampling as for Lambertian, so the sample weight is
+    // just the albedo scaled by the Oren-Nayar factor.
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let n = hit_record.normal;
+        let wi = Onb::new(n).cosine_direction(sampler::next_2d());
+        let wo = -ray.direction.unit_vector();
+        let factor = to_f32(self.factor(&n, &wo, &wi));
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        Some((
+            Some(Ray::with_time(hit_record.point, wi, ray.time)),
+            Srgb::new(
+                albedo.red * factor,
+                albedo.green * factor,
+                albedo.blue * factor,
+            ),
+        ))
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        let n = hit_record.normal;
+        let factor = self.factor(&n, &-ray.direction.unit_vector(), direction);
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        Some(scale(lambert(albedo, &n, direction), factor))
+    }
+
+    fn pdf(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        cosine_pdf(&hit_record.normal, direction)
+    }
+}
+
+#[test]
+fn test_oren_nayar() {
+    let n = Point3D::new(0.0, 0.0, 1.0);
+    let up = Point3D::new(0.0, 0.0, 1.0);
+    let grazing = Point3D::new(1.0, 0.0, 0.2).unit_vector();
+    let opposite = Point3D::new(-1.0, 0.0, 0.2).unit_vector();
+
+    // No roughness is exactly Lambertian.
+    let smooth = OrenNayar::new(Srgb::new(0.5, 0.5, 0.5), 0.0);
+    assert_eq!(smooth.factor(&n, &grazing, &grazing), 1.0);
+
+    // Rough surfaces are darker than Lambertian seen from straight above,
+    // and brighter when viewed from the side the light is on.
+    let rough = OrenNayar::new(Srgb::new(0.5, 0.5, 0.5), 0.5);
+    assert!(rough.factor(&n, &up, &grazing) < 1.0);
+    assert!(rough.factor(&n, &grazing, &grazing) > 1.0);
+    assert!(rough.factor(&n, &grazing, &opposite) < rough.factor(&n, &grazing, &grazing));
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Metal {
-    #[serde_as(as = "SrgbAsArray")]
-    pub albedo: Srgb,
-    pub fuzz: f64,
+    pub albedo: Texture,
+    pub fuzz: Float,
 }
 
 impl Metal {
-    pub fn new(albedo: Srgb, fuzz: f64) -> Metal {
-        Metal { albedo, fuzz }
+    pub fn new(albedo: Srgb, fuzz: Float) -> Metal {
+        Metal {
+            albedo: Texture::Solid(albedo),
+            fuzz,
+        }
     }
-}
 
-fn reflect(v: &Point3D, n: &Point3D) -> Point3D {
-    *v - *n * (2.0 * v.dot(n))
+    pub fn textured(albedo: Texture, fuzz: Float) -> Metal {
+        Metal { albedo, fuzz }
+    }
 }
 
 impl Scatterable for Metal {
     fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
-        let reflected = reflect(&ray.direction, &hit_record.normal);
-        let scattered = Ray::new(
+        let reflected = ray.direction.reflect(&hit_record.normal);
+        let scattered = Ray::with_time(
             hit_record.point,
             reflected + Point3D::random_in_unit_sphere() * self.fuzz,
+            ray.time,
         );
-        let attenuation = self.albedo;
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
         if scattered.direction.dot(&hit_record.normal) > 0.0 {
             Some((Some(scattered), attenuation))
         } else {
@@ -128,42 +654,350 @@ impl Scatterable for Metal {
     }
 }
 
+// A metal using the GGX (Trowbridge-Reitz) microfacet model: the surface
+// is made of tiny mirrors whose orientations spread out as `roughness` goes
+// from 0 (polished) to 1 (brushed/matte). The albedo is the reflectance at
+// normal incidence; grazing angles reflect more (Schlick's Fresnel).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoughMetal {
+    pub albedo: Texture,
+    pub roughness: Float,
+}
+
+impl RoughMetal {
+    pub fn new(albedo: Srgb, roughness: Float) -> RoughMetal {
+        RoughMetal {
+            albedo: Texture::Solid(albedo),
+            roughness,
+        }
+    }
+
+    pub fn textured(albedo: Texture, roughness: Float) -> RoughMetal {
+        RoughMetal { albedo, roughness }
+    }
+
+    fn alpha(&self) -> Float {
+        roughness_to_alpha(self.roughness)
+    }
+}
+
+// GGX width for a perceptually linear roughness in [0, 1]. Very small values
+// make the distribution numerically unstable without looking any different
+// from a mirror.
+pub(crate) fn roughness_to_alpha(roughness: Float) -> Float {
+    let r = roughness.clamp(0.001, 1.0);
+    r * r
+}
+
+// Smith masking term for one direction, `cosine` away from the normal.
+fn smith_g1(cosine: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    2.0 * cosine / (cosine + (a2 + (1.0 - a2) * cosine * cosine).sqrt())
+}
+
+// The GGX distribution of microfacet normals, `n_dot_h` being the cosine
+// between the facet normal and the surface normal.
+fn ggx_distribution(n_dot_h: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// The half vector between the directions from the hit to the viewer and to
+// the light, with the cosines `sample_ggx` works with; None if either
+// direction is below the surface.
+fn ggx_half_vector(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    wi: &Point3D,
+) -> Option<(Point3D, Float, Float, Float)> {
+    let n = hit_record.normal;
+    let wo = -ray.direction.unit_vector();
+    let n_dot_o = n.dot(&wo);
+    let n_dot_i = n.dot(wi);
+    if n_dot_o <= 0.0 || n_dot_i <= 0.0 {
+        return None;
+    }
+    let h = (wo + *wi).unit_vector();
+    Some((h, n_dot_o, n_dot_i, wo.dot(&h)))
+}
+
+// The GGX BRDF times the cosine term, for light from unit `wi`.
+pub(crate) fn evaluate_ggx(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    alpha: Float,
+    f0: Srgb,
+    wi: &Point3D,
+) -> Srgb {
+    let (h, n_dot_o, n_dot_i, o_dot_h) = match ggx_half_vector(ray, hit_record, wi) {
+        Some(v) => v,
+        None => return Srgb::new(0.0, 0.0, 0.0),
+    };
+    let d = ggx_distribution(hit_record.normal.dot(&h), alpha);
+    let masking = smith_g1(n_dot_o, alpha) * smith_g1(n_dot_i, alpha);
+    let fresnel = to_f32((1.0 - o_dot_h).powi(5));
+    let weight = (d * masking / (4.0 * n_dot_o)) as f32;
+    Srgb::new(
+        (f0.red + (1.0 - f0.red) * fresnel) * weight,
+        (f0.green + (1.0 - f0.green) * fresnel) * weight,
+        (f0.blue + (1.0 - f0.blue) * fresnel) * weight,
+    )
+}
+
+// The density with which `sample_ggx` picks unit `wi`: that of the facet
+// normal, D(h) cos(h), changed from half vectors to reflected directions.
+pub(crate) fn ggx_pdf(ray: &Ray, hit_record: &HitRecord, alpha: Float, wi: &Point3D) -> Float {
+    match ggx_half_vector(ray, hit_record, wi) {
+        Some((h, _, _, o_dot_h)) if o_dot_h > 0.0 => {
+            let n_dot_h = hit_record.normal.dot(&h);
+            ggx_distribution(n_dot_h, alpha) * n_dot_h / (4.0 * o_dot_h)
+        }
+        _ => 0.0,
+    }
+}
+
+// Samples the GGX lobe of a surface with reflectance `f0` at normal
+// incidence. A microfacet normal is importance sampled from the distribution
+// and the ray mirrored about it; with that choice the distribution term
+// cancels out of the sample weight, leaving Fresnel times masking.
+pub(crate) fn sample_ggx(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    alpha: Float,
+    f0: Srgb,
+) -> Option<(Option<Ray>, Srgb)> {
+    let n = hit_record.normal;
+    let wo = -ray.direction.unit_vector();
+    let n_dot_o = n.dot(&wo);
+    if n_dot_o <= 0.0 {
+        return None;
+    }
+
+    let (u1, u2) = sampler::next_2d();
+    let theta = (alpha * (u1 / (1.0 - u1)).sqrt()).atan();
+    let phi = 2.0 * PI * u2;
+    let h = Onb::new(n).local(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    );
+
+    let o_dot_h = wo.dot(&h);
+    let wi = h * (2.0 * o_dot_h) - wo;
+    let n_dot_i = n.dot(&wi);
+    if n_dot_i <= 0.0 || o_dot_h <= 0.0 {
+        return None;
+    }
+
+    let masking = smith_g1(n_dot_o, alpha) * smith_g1(n_dot_i, alpha);
+    let weight = (masking * o_dot_h / (n_dot_o * n.dot(&h))) as f32;
+    let fresnel = to_f32((1.0 - o_dot_h).powi(5));
+    let attenuation = Srgb::new(
+        (f0.red + (1.0 - f0.red) * fresnel) * weight,
+        (f0.green + (1.0 - f0.green) * fresnel) * weight,
+        (f0.blue + (1.0 - f0.blue) * fresnel) * weight,
+    );
+    Some((
+        Some(Ray::with_time(hit_record.point, wi, ray.time)),
+        attenuation,
+    ))
+}
+
+impl Scatterable for RoughMetal {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let f0 = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        sample_ggx(ray, hit_record, self.alpha(), f0)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        let f0 = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        Some(evaluate_ggx(ray, hit_record, self.alpha(), f0, direction))
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        ggx_pdf(ray, hit_record, self.alpha(), direction)
+    }
+}
+
+#[test]
+fn test_rough_metal() {
+    let n = Point3D::new(0.0, 0.0, 1.0);
+    let ray = Ray::new(Point3D::new(-1.0, 0.0, 1.0), Point3D::new(1.0, 0.0, -1.0));
+    let mirror_direction = ray.direction.unit_vector().reflect(&n);
+
+    let polished = Material::RoughMetal(RoughMetal::new(Srgb::new(0.9, 0.9, 0.9), 0.0));
+    let rough = Material::RoughMetal(RoughMetal::new(Srgb::new(0.9, 0.9, 0.9), 0.8));
+    // Largest deviation from the mirror direction, and mean attenuation.
+    let sample = |material: &Material| {
+        let hit = HitRecord {
+            t: 1.0,
+            point: Point3D::new(0.0, 0.0, 0.0),
+            normal: n,
+            front_face: true,
+            material,
+            u: 0.0,
+            v: 0.0,
+            tangent: None,
+        };
+        let mut spread: Float = 0.0;
+        let mut total = 0.0;
+        for _ in 0..1000 {
+            if let Some((Some(scattered), attenuation)) = material.scatter(&ray, &hit) {
+                let direction = scattered.direction.unit_vector();
+                assert!(direction.dot(&n) > 0.0);
+                spread = spread.max((direction - mirror_direction).length());
+                total += attenuation.red;
+            }
+        }
+        (spread, total / 1000.0)
+    };
+    let (spread, mean) = sample(&polished);
+    assert!(spread < 1e-3);
+    assert!(mean <= 1.0);
+    let (spread, mean) = sample(&rough);
+    assert!(spread > 0.1);
+    // Microfacets can't reflect more light than arrives.
+    assert!(mean <= 1.0);
+
+    // Evaluating the BRDF in a sampled direction agrees with the sample
+    // weight, which is what lets lights be sampled instead.
+    let hit = HitRecord {
+        t: 1.0,
+        point: Point3D::new(0.0, 0.0, 0.0),
+        normal: n,
+        front_face: true,
+        material: &rough,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    for _ in 0..100 {
+        if let Some((Some(scattered), attenuation)) = rough.scatter(&ray, &hit) {
+            let direction = scattered.direction.unit_vector();
+            let f = rough.evaluate(&ray, &hit, &direction).unwrap();
+            let pdf = rough.pdf(&ray, &hit, &direction);
+            assert!(
+                (f.red / to_f32(pdf) - attenuation.red).abs() < 1e-3 * attenuation.red.max(1.0)
+            );
+        }
+    }
+}
+
+// How a glass's index of refraction changes with the wavelength of the
+// light, so that blue bends more than red. Wavelengths are in micrometres,
+// as the coefficients are usually given.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Dispersion {
+    // n = a + b / λ² + c / λ⁴: simple, and close enough across the
+    // visible range for most glasses.
+    Cauchy {
+        a: Float,
+        b: Float,
+        #[serde(default)]
+        c: Float,
+    },
+    // n² = 1 + Σ bᵢ λ² / (λ² - cᵢ), as glass makers publish it.
+    Sellmeier {
+        b: [Float; 3],
+        c: [Float; 3],
+    },
+}
+
+impl Dispersion {
+    // Schott N-BK7, an ordinary crown glass.
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945].map(from_f64),
+            c: [0.00600069867, 0.0200179144, 103.560653].map(from_f64),
+        }
+    }
+
+    // Schott SF11, a dense flint glass that spreads colors several times
+    // more, as cut into prisms.
+    pub fn sf11() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.73759695, 0.313747346, 1.89878101].map(from_f64),
+            c: [0.013188707, 0.0623068142, 155.23629].map(from_f64),
+        }
+    }
+
+    // The index of refraction for light of `wavelength` nanometres.
+    pub fn index_at(&self, wavelength: Float) -> Float {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match *self {
+            Dispersion::Cauchy { a, b, c } => a + b / l2 + c / (l2 * l2),
+            Dispersion::Sellmeier { b, c } => {
+                let sum: Float = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Glass {
-    pub index_of_refraction: f64,
+    pub index_of_refraction: Float,
+    // Red, green and blue absorption coefficients per unit of distance
+    // travelled inside the glass (Beer–Lambert law). Clear glass absorbs
+    // nothing; thicker parts of tinted glass look darker.
+    #[serde(default, skip_serializing_if = "is_clear")]
+    pub absorption: [f32; 3],
+    // With the spectral integrator, the index at each path's wavelength
+    // instead of index_of_refraction, which RGB rendering still uses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispersion: Option<Dispersion>,
+}
+
+fn is_clear(absorption: &[f32; 3]) -> bool {
+    absorption.iter().all(|a| *a == 0.0)
 }
 
 impl Glass {
-    pub fn new(index_of_refraction: f64) -> Glass {
+    pub fn new(index_of_refraction: Float) -> Glass {
         Glass {
             index_of_refraction,
+            absorption: [0.0; 3],
+            dispersion: None,
+        }
+    }
+
+    pub fn with_absorption(mut self, absorption: [f32; 3]) -> Glass {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Glass {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    // The index of refraction for the path being traced: at its wavelength
+    // if that matters and there is one.
+    pub(crate) fn index_of_refraction(&self) -> Float {
+        match (self.dispersion, spectrum::hero_wavelength()) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index_at(wavelength),
+            _ => self.index_of_refraction,
         }
     }
-}
 
-fn refract(uv: &Point3D, n: &Point3D, etai_over_etat: f64) -> Point3D {
-    let cos_theta = ((-*uv).dot(n)).min(1.0);
-    let r_out_perp = (*uv + *n * cos_theta) * etai_over_etat;
-    let r_out_parallel = *n * (-1.0 * (1.0 - r_out_perp.length_squared()).abs().sqrt());
-    r_out_perp + r_out_parallel
+    // Light left after travelling `distance` through the glass.
+    fn transmittance(&self, distance: Float) -> Srgb {
+        let [r, g, b] = self.absorption.map(|a| (-a * to_f32(distance)).exp());
+        Srgb::new(r, g, b)
+    }
 }
 
-fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+pub(crate) fn reflectance(cosine: Float, ref_idx: Float) -> Float {
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
-#[test]
-fn test_refract() {
-    let uv = Point3D::new(1.0, 1.0, 0.0);
-    let n = Point3D::new(-1.0, 0.0, 0.0);
-    let etai_over_etat = 1.0;
-    let expected = Point3D::new(0.0, 1.0, 0.0);
-    let actual = refract(&uv, &n, etai_over_etat);
-    assert_eq!(actual, expected);
-}
-
 #[test]
 fn test_reflectance() {
     let cosine = 0.0;
@@ -173,111 +1007,286 @@ fn test_reflectance() {
     assert_eq!(actual, expected);
 }
 
-impl Scatterable for Glass {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
-        let mut rng = rand::thread_rng();
-        let attenuation = Srgb::new(1.0 as f32, 1.0 as f32, 1.0 as f32);
+impl Glass {
+    // Scatters as glass does, but reflecting as often as `reflectance` says
+    // given the cosine of the angle of incidence and the refraction ratio,
+    // e.g. with a coating on the glass.
+    pub(crate) fn scatter_by(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        reflectance: impl Fn(Float, Float) -> Float,
+    ) -> Option<(Option<Ray>, Srgb)> {
+        // A ray hitting the inside of the surface has just crossed the glass.
+        let attenuation = if hit_record.front_face {
+            Srgb::new(1.0, 1.0, 1.0)
+        } else {
+            self.transmittance(hit_record.t * ray.direction.length())
+        };
+        let index_of_refraction = self.index_of_refraction();
         let refraction_ratio = if hit_record.front_face {
-            1.0 / self.index_of_refraction
+            1.0 / index_of_refraction
         } else {
-            self.index_of_refraction
+            index_of_refraction
         };
         let unit_direction = ray.direction.unit_vector();
         let cos_theta = (-unit_direction).dot(&hit_record.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>() {
-            let reflected = reflect(&unit_direction, &hit_record.normal);
-            let scattered = Ray::new(hit_record.point, reflected);
+        if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler::next_1d() {
+            let reflected = unit_direction.reflect(&hit_record.normal);
+            let scattered = Ray::with_time(hit_record.point, reflected, ray.time);
             Some((Some(scattered), attenuation))
         } else {
-            let direction = refract(&unit_direction, &hit_record.normal, refraction_ratio);
-            let scattered = Ray::new(hit_record.point, direction);
+            let direction = unit_direction.refract(&hit_record.normal, refraction_ratio);
+            let scattered = Ray::with_time(hit_record.point, direction, ray.time);
             Some((Some(scattered), attenuation))
         }
     }
 }
 
-#[serde_with::serde_as]
+impl Scatterable for Glass {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        self.scatter_by(ray, hit_record, reflectance)
+    }
+}
+
+#[test]
+fn test_glass_absorption() {
+    let glass = Glass::new(1.5).with_absorption([0.5, 0.0, 0.0]);
+    let material = Material::Glass(glass);
+    // Leaving the glass after 2 units inside: straight out along the normal,
+    // so the ray refracts rather than reflecting.
+    let hit = HitRecord {
+        t: 1.0,
+        point: Point3D::new(0.0, 0.0, 2.0),
+        normal: Point3D::new(0.0, 0.0, -1.0),
+        front_face: false,
+        material: &material,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, 2.0));
+    let (_, attenuation) = glass.scatter(&ray, &hit).unwrap();
+    assert!((attenuation.red - (-1.0_f32).exp()).abs() < 1e-6);
+    assert_eq!(attenuation.green, 1.0);
+
+    let serialized = serde_json::to_string(&glass).unwrap();
+    assert_eq!(
+        r#"{"index_of_refraction":1.5,"absorption":[0.5,0.0,0.0]}"#,
+        serialized
+    );
+    let clear = serde_json::to_string(&Glass::new(1.5)).unwrap();
+    assert_eq!(r#"{"index_of_refraction":1.5}"#, clear);
+}
+
+#[test]
+fn test_glass_dispersion() {
+    // BK7 at the sodium line, and bending blue more than red.
+    let bk7 = Dispersion::bk7();
+    assert!((bk7.index_at(587.6) - 1.5168).abs() < 1e-4);
+    assert!(bk7.index_at(450.0) > bk7.index_at(650.0));
+    let cauchy = Dispersion::Cauchy {
+        a: 1.5,
+        b: 0.01,
+        c: 0.0,
+    };
+    assert!((cauchy.index_at(500.0) - 1.54).abs() < 1e-9);
+
+    // Into the glass at an angle, each wavelength its own way; RGB rendering
+    // uses the single index.
+    let glass = Glass::new(1.5).with_dispersion(Dispersion::sf11());
+    let material = Material::Glass(glass);
+    assert!(material.disperses());
+    let hit = HitRecord {
+        t: 1.0,
+        point: Point3D::new(0.0, 0.0, 0.0),
+        normal: Point3D::new(0.0, 1.0, 0.0),
+        front_face: true,
+        material: &material,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    let ray = Ray::new(Point3D::new(-1.0, 1.0, 0.0), Point3D::new(1.0, -1.0, 0.0));
+    let bent = |wavelength: Option<Float>| {
+        spectrum::set_wavelengths(wavelength.map(|w| [w; spectrum::WAVELENGTHS]));
+        // Refracted, unless it happened to reflect.
+        (0..100)
+            .filter_map(|_| glass.scatter(&ray, &hit).unwrap().0)
+            .map(|r| r.direction.unit_vector())
+            .find(|d| d.y() < 0.0)
+            .unwrap()
+    };
+    let (blue, red) = (bent(Some(450.0)), bent(Some(650.0)));
+    assert!(blue.x() < red.x());
+    let sin = |d: Point3D| d.x() / d.length();
+    assert!((sin(red) - Float::sqrt(0.5) / Dispersion::sf11().index_at(650.0)).abs() < 1e-6);
+    assert!((sin(bent(None)) - Float::sqrt(0.5) / 1.5).abs() < 1e-6);
+}
+
+// A diffuse surface coloured by an image, as older scenes give one: turned
+// `h_offset` of the way around in u, and otherwise looked up just as
+// Texture::Image is, with v = 0 at the bottom row. On a sphere that puts the
+// south pole at the bottom of the image.
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Texture {
-    #[serde_as(as = "SrgbAsArray")]
+#[serde(try_from = "TexturedParams", into = "TexturedParams")]
+pub struct Textured {
     pub albedo: Srgb,
-    #[serde_as(as = "TexturePixelsAsPath")]
-    pub pixels: Vec<u8>,
+    pub pixels: ImageTexture,
+    h_offset: Float,
+}
+
+// A Textured as scenes write it, with the image's size alongside its path.
+#[serde_with::serde_as]
+#[derive(Deserialize, Serialize)]
+struct TexturedParams {
+    #[serde_as(as = "SrgbAsArray")]
+    albedo: Srgb,
+    pixels: String,
+    #[serde(default)]
     width: u64,
+    #[serde(default)]
     height: u64,
-    h_offset: f64,
+    h_offset: Float,
 }
 
-fn load_texture_image(path: &str) -> (Vec<u8>, u64, u64) {
-    let file = File::open(path).expect(path);
-    let mut decoder = Decoder::new(BufReader::new(file));
-    let pixels = decoder.decode().expect("failed to decode image");
-    let metadata = decoder.info().unwrap();
-    (pixels, metadata.width as u64, metadata.height as u64)
+impl TryFrom<TexturedParams> for Textured {
+    type Error = String;
+
+    // The image's own size is used, whatever the scene says.
+    fn try_from(p: TexturedParams) -> Result<Textured, String> {
+        Ok(Textured {
+            albedo: p.albedo,
+            pixels: ImageTexture::load(&p.pixels)?,
+            h_offset: p.h_offset,
+        })
+    }
 }
 
-impl Texture {
-    pub fn new(albedo: Srgb, texture_path: &str, rot: f64) -> Texture {
-        let file = File::open(texture_path).expect("failed to open texture file");
-        let mut decoder = Decoder::new(BufReader::new(file));
-        let pixels = decoder.decode().expect("failed to decode image");
-        let metadata = decoder.info().unwrap();
-        Texture {
+// TODO: write the image's own path, as ImageTexture does.
+impl From<Textured> for TexturedParams {
+    fn from(t: Textured) -> TexturedParams {
+        let (width, height) = t.pixels.dimensions();
+        TexturedParams {
+            albedo: t.albedo,
+            pixels: "/tmp/texture.jpg".to_string(),
+            width: width as u64,
+            height: height as u64,
+            h_offset: t.h_offset,
+        }
+    }
+}
+
+impl Textured {
+    pub fn new(albedo: Srgb, texture_path: &str, rot: Float) -> Textured {
+        Textured {
             albedo,
-            pixels,
-            width: metadata.width as u64,
-            height: metadata.height as u64,
+            pixels: ImageTexture::load(texture_path).expect("failed to load texture"),
             h_offset: rot,
         }
     }
 
-    pub fn get_albedo(&self, u: f64, v: f64) -> Srgb {
-        let mut rot = u + self.h_offset;
-        if rot > 1.0 {
-            rot = rot - 1.0;
-        }
-        let uu = rot * (self.width) as f64;
-        let vv = (1.0 - v) * (self.height - 1) as f64;
-        let base_pixel =
-            (3 * ((vv.floor() as u64) * self.width as u64 + (uu.floor() as u64))) as usize;
-        let pixel_r = self.pixels[base_pixel];
-        let pixel_g = self.pixels[base_pixel + 1];
-        let pixel_b = self.pixels[base_pixel + 2];
-        Srgb::new(
-            pixel_r as f32 / 255.0,
-            pixel_g as f32 / 255.0,
-            pixel_b as f32 / 255.0,
-        )
+    pub fn get_albedo(&self, u: Float, v: Float) -> Srgb {
+        let u = (u + self.h_offset).rem_euclid(1.0);
+        self.pixels.value(u, v, &Point3D::new(0.0, 0.0, 0.0))
     }
 }
 
-impl Scatterable for Texture {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
-        let mut scatter_direction = hit_record.normal + Point3D::random_in_unit_sphere();
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.normal;
-        }
-        let target = hit_record.point + scatter_direction;
-        let scattered = Ray::new(hit_record.point, target - hit_record.point);
+impl Scatterable for Textured {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let direction = Onb::new(hit_record.normal).cosine_direction(sampler::next_2d());
+        let scattered = Ray::with_time(hit_record.point, direction, ray.time);
         let attenuation = self.get_albedo(hit_record.u, hit_record.v);
         Some((Some(scattered), attenuation))
     }
+
+    fn evaluate(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        let albedo = self.get_albedo(hit_record.u, hit_record.v);
+        Some(lambert(albedo, &hit_record.normal, direction))
+    }
+
+    fn pdf(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        cosine_pdf(&hit_record.normal, direction)
+    }
 }
 
+#[cfg(feature = "images")]
 #[test]
 fn test_texture() {
-    let _world = Material::Texture(Texture::new(
+    let _world = Material::Texture(Textured::new(
         Srgb::new(1.0, 1.0, 1.0),
         "data/earth.jpg",
         0.0,
     ));
 }
 
+#[cfg(feature = "images")]
+#[test]
+fn test_textured_uv() {
+    // 4x2: red, green, blue, white along the top; black underneath.
+    let mut image = image::RgbImage::new(4, 2);
+    for (x, rgb) in [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]]
+        .into_iter()
+        .enumerate()
+    {
+        image.put_pixel(x as u32, 0, image::Rgb(rgb));
+    }
+    image.save("/tmp/test_textured_uv.png").unwrap();
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    let textured = Textured::new(white, "/tmp/test_textured_uv.png", 0.25);
+    let image = Texture::Image(ImageTexture::load("/tmp/test_textured_uv.png").unwrap());
+
+    // The same lookup as an image texture, a quarter turn on: v = 1 is the
+    // top row, so the north pole of a sphere, and u wraps around.
+    let p = Point3D::new(0.0, 0.0, 0.0);
+    assert_eq!(textured.get_albedo(0.125, 0.75), Srgb::new(0.0, 1.0, 0.0));
+    assert_eq!(textured.get_albedo(0.875, 0.75), Srgb::new(1.0, 0.0, 0.0));
+    assert_eq!(textured.get_albedo(0.0, 0.25), Srgb::new(0.0, 0.0, 0.0));
+    for (u, v) in [(0.1, 0.9), (0.3, 0.2), (0.6, 0.6)] {
+        assert_eq!(textured.get_albedo(u, v), image.value(u + 0.25, v, &p));
+    }
+}
+
 #[test]
 fn test_to_json() {
     let m = Metal::new(Srgb::new(0.8, 0.8, 0.8), 2.0);
     let serialized = serde_json::to_string(&m).unwrap();
     assert_eq!(r#"{"albedo":[0.8,0.8,0.8],"fuzz":2.0}"#, serialized,);
 }
+
+#[test]
+fn test_textured_metal_and_light() {
+    let black = Srgb::new(0.0, 0.0, 0.0);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    let checker = Texture::Checker(Checker::new(black, white, 1.0));
+
+    // A polished metal reflects the colour of the square it's hit in.
+    let metal = Material::Metal(Metal::textured(checker.clone(), 0.0));
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 1.0), Point3D::new(1.0, 0.0, -1.0));
+    let reflect_at = |point: Point3D| {
+        let hit = HitRecord {
+            t: 1.0,
+            point,
+            normal: Point3D::new(0.0, 0.0, 1.0),
+            front_face: true,
+            material: &metal,
+            u: 0.0,
+            v: 0.0,
+            tangent: None,
+        };
+        metal.scatter(&ray, &hit).unwrap().1
+    };
+    assert_eq!(reflect_at(Point3D::new(0.5, 0.5, 0.5)), white);
+    assert_eq!(reflect_at(Point3D::new(1.5, 0.5, 0.5)), black);
+
+    // So does a light, and the texture survives the scene file.
+    let light = DiffuseLight::textured(checker);
+    let serialized = serde_json::to_string(&light).unwrap();
+    let loaded = serde_json::from_str::<DiffuseLight>(&serialized).unwrap();
+    let p = Point3D::new(0.5, 0.5, 0.5);
+    assert_eq!(loaded.emitted(0.0, 0.0, &p), white);
+    let p = Point3D::new(1.5, 0.5, 0.5);
+    assert_eq!(loaded.emitted(0.0, 0.0, &p), black);
+}
@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use tracing::warn;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::displacement::Displacement;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::ply::parse_ply;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::stl::parse_stl;
+use crate::triangle::Triangle;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// How a mesh's faces are lit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Shading {
+    // Normals blended across each face from its vertices, so a low-poly
+    // model looks rounded. Vertices the file gives no normal for get the
+    // average of the faces around them.
+    #[default]
+    Smooth,
+    // Each face lit by its own geometric normal.
+    Flat,
+}
+
+// A triangle mesh loaded from an OBJ, PLY or STL file, with its own
+// hierarchy so that large models don't slow down the rest of the scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "MeshParams")]
+pub struct Mesh {
+    path: String,
+    material: Material,
+    shading: Shading,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    displacement: Option<Displacement>,
+    #[serde(skip_serializing)]
+    triangles: Bvh<Triangle>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshParams {
+    pub path: String,
+    pub material: Material,
+    #[serde(default)]
+    pub shading: Shading,
+    #[serde(default)]
+    pub displacement: Option<Displacement>,
+}
+
+impl TryFrom<MeshParams> for Mesh {
+    type Error = Error;
+
+    fn try_from(p: MeshParams) -> Result<Self, Self::Error> {
+        let mut triangles = read_triangles(&p.path, &p.material)?;
+        if let Some(displacement) = &p.displacement {
+            triangles = displacement.apply(&triangles);
+        }
+        shade(&mut triangles, p.shading);
+        // Often left over from modelling, and harmless, but a mesh made of
+        // little else is probably broken.
+        let degenerate = triangles
+            .iter()
+            .filter(|t| t.area().is_nan() || t.area() == 0.0)
+            .count();
+        if degenerate > 0 {
+            warn!(
+                "{}: {} of {} triangles have no area and will never be hit",
+                p.path,
+                degenerate,
+                triangles.len()
+            );
+        }
+        Ok(Mesh {
+            path: p.path,
+            material: p.material,
+            shading: p.shading,
+            displacement: p.displacement,
+            triangles: Bvh::new(triangles),
+        })
+    }
+}
+
+impl Mesh {
+    // The triangles are shaded with whatever vertex normals they carry.
+    pub fn new(triangles: Vec<Triangle>, material: Material) -> Mesh {
+        Mesh {
+            path: String::new(),
+            material,
+            shading: Shading::Smooth,
+            displacement: None,
+            triangles: Bvh::new(triangles),
+        }
+    }
+
+    pub fn load(path: &str, material: Material, shading: Shading) -> Result<Mesh, Error> {
+        Mesh::try_from(MeshParams {
+            path: path.to_string(),
+            material,
+            shading,
+            displacement: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.triangles.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        self.triangles.bounding_box(t0, t1)
+    }
+}
+
+// Reads a .ply or .stl file, or otherwise an OBJ one.
+pub fn read_triangles(path: &str, material: &Material) -> Result<Vec<Triangle>, Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let path = path.to_lowercase();
+    if path.ends_with(".ply") {
+        parse_ply(reader, material)
+    } else if path.ends_with(".stl") {
+        parse_stl(reader, material)
+    } else {
+        parse_obj(reader, material)
+    }
+}
+
+// Sets up the triangles' vertex normals for the shading. Smooth normals
+// are missing only where a face didn't give them, and are then filled in
+// from the faces sharing each vertex, weighted by their area.
+pub fn shade(triangles: &mut [Triangle], shading: Shading) {
+    if shading == Shading::Flat {
+        for triangle in triangles.iter_mut() {
+            triangle.normals = None;
+        }
+        return;
+    }
+    let key = |p: &Point3D| [p.x().to_bits(), p.y().to_bits(), p.z().to_bits()];
+    let mut sums: HashMap<_, Point3D> = HashMap::new();
+    for triangle in triangles.iter() {
+        // The cross product's length is twice the area.
+        let weighted = (triangle.v1 - triangle.v0).cross(&(triangle.v2 - triangle.v0));
+        for v in [triangle.v0, triangle.v1, triangle.v2] {
+            let sum = sums.entry(key(&v)).or_insert(Point3D::new(0.0, 0.0, 0.0));
+            *sum += weighted;
+        }
+    }
+    for triangle in triangles.iter_mut().filter(|t| t.normals.is_none()) {
+        let normal = |v: &Point3D| sums[&key(v)].unit_vector();
+        triangle.normals = Some([
+            normal(&triangle.v0),
+            normal(&triangle.v1),
+            normal(&triangle.v2),
+        ]);
+    }
+}
+
+fn invalid(line: usize, message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+fn parse_floats(line: usize, parts: &[&str], count: usize) -> Result<Vec<Float>, Error> {
+    if parts.len() < count {
+        return Err(invalid(line, "not enough coordinates"));
+    }
+    parts[..count]
+        .iter()
+        .map(|p| p.parse::<Float>().map_err(|_| invalid(line, "bad number")))
+        .collect()
+}
+
+// OBJ indices are 1-based, and negative values count back from the most
+// recently defined element.
+fn resolve_index(line: usize, index: &str, len: usize) -> Result<usize, Error> {
+    let i = index
+        .parse::<i64>()
+        .map_err(|_| invalid(line, "bad face index"))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(invalid(line, "face index out of range"));
+    }
+    Ok(resolved as usize)
+}
+
+#[derive(Clone, Copy)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Parses the subset of Wavefront OBJ needed for geometry: `v`, `vt`, `vn`
+// and `f` statements. Polygons are triangulated as fans; everything else
+// (groups, smoothing, materials) is ignored.
+pub fn parse_obj<R: BufRead>(reader: R, material: &Material) -> Result<Vec<Triangle>, Error> {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut triangles = Vec::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let number = number + 1;
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let rest: Vec<&str> = parts.collect();
+        match keyword {
+            "v" => {
+                let c = parse_floats(number, &rest, 3)?;
+                positions.push(Point3D::new(c[0], c[1], c[2]));
+            }
+            "vt" => {
+                let c = parse_floats(number, &rest, 2)?;
+                uvs.push((c[0], c[1]));
+            }
+            "vn" => {
+                let c = parse_floats(number, &rest, 3)?;
+                normals.push(Point3D::new(c[0], c[1], c[2]).unit_vector());
+            }
+            "f" => {
+                if rest.len() < 3 {
+                    return Err(invalid(number, "face needs at least three vertices"));
+                }
+                let mut face = Vec::with_capacity(rest.len());
+                for vertex in rest {
+                    let mut indices = vertex.split('/');
+                    let position = resolve_index(number, indices.next().unwrap(), positions.len())?;
+                    let uv = match indices.next() {
+                        Some("") | None => None,
+                        Some(i) => Some(resolve_index(number, i, uvs.len())?),
+                    };
+                    let normal = match indices.next() {
+                        Some("") | None => None,
+                        Some(i) => Some(resolve_index(number, i, normals.len())?),
+                    };
+                    face.push(FaceVertex {
+                        position,
+                        uv,
+                        normal,
+                    });
+                }
+                for i in 1..face.len() - 1 {
+                    let [a, b, c] = [face[0], face[i], face[i + 1]];
+                    let mut triangle = Triangle::new(
+                        positions[a.position],
+                        positions[b.position],
+                        positions[c.position],
+                        material.clone(),
+                    );
+                    if let (Some(na), Some(nb), Some(nc)) = (a.normal, b.normal, c.normal) {
+                        triangle.normals = Some([normals[na], normals[nb], normals[nc]]);
+                    }
+                    if let (Some(ua), Some(ub), Some(uc)) = (a.uv, b.uv, c.uv) {
+                        triangle.uvs = Some([uvs[ua], uvs[ub], uvs[uc]]);
+                    }
+                    triangles.push(triangle);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+#[test]
+fn test_parse_obj() {
+    let obj = "\
+# a unit quad in the xy plane
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 1.0 0.0
+v 0.0 1.0 0.0
+vt 0.0 0.0
+vt 1.0 0.0
+vt 1.0 1.0
+vt 0.0 1.0
+vn 0.0 0.0 2.0
+f 1/1/1 2/2/1 3/3/1 4/4/1
+f -4 -3 -2
+";
+    let material = Material::Glass(Glass::new(1.5));
+    let triangles = parse_obj(obj.as_bytes(), &material).unwrap();
+    assert_eq!(triangles.len(), 3);
+    assert_eq!(triangles[1].v1, Point3D::new(1.0, 1.0, 0.0));
+    assert_eq!(triangles[1].uvs.unwrap()[2], (0.0, 1.0));
+    assert_eq!(
+        triangles[0].normals.unwrap()[0],
+        Point3D::new(0.0, 0.0, 1.0)
+    );
+    assert!(triangles[2].uvs.is_none());
+    assert!(triangles[2].normals.is_none());
+
+    let mesh = Mesh::new(triangles, material);
+    let ray = Ray::new(Point3D::new(0.75, 0.5, 1.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = mesh.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_eq!(hit.t, 1.0);
+}
+
+#[test]
+fn test_shade() {
+    // Two faces of a tent meeting along the ridge at x = 0.
+    let obj = "\
+v -1 0 0
+v 0 1 0
+v 0 1 1
+v 1 0 0
+v 1 0 1
+v -1 0 1
+f 1 6 3
+f 1 3 2
+f 4 3 5
+f 4 2 3
+";
+    let material = Material::Glass(Glass::new(1.5));
+    let mut triangles = parse_obj(obj.as_bytes(), &material).unwrap();
+    shade(&mut triangles, Shading::Smooth);
+    // Along the ridge the faces' normals average out to straight up.
+    let ridge = triangles[0].normals.unwrap()[2];
+    assert_eq!(ridge, Point3D::new(0.0, 1.0, 0.0));
+    let mesh = Mesh::new(triangles.clone(), material.clone());
+    let ray = Ray::new(Point3D::new(-0.5, 5.0, 0.5), Point3D::new(0.0, -1.0, 0.0));
+    let hit = mesh.hit(&ray, 0.001, Float::MAX).unwrap();
+    let slope = Point3D::new(-1.0, 1.0, 0.0).unit_vector();
+    assert!(hit.normal.y() > slope.y() && hit.normal.y() < 1.0);
+
+    shade(&mut triangles, Shading::Flat);
+    assert!(triangles.iter().all(|t| t.normals.is_none()));
+    let mesh = Mesh::new(triangles, material);
+    let hit = mesh.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.normal.y(), slope.y());
+}
+
+#[test]
+fn test_parse_obj_errors() {
+    let material = Material::Glass(Glass::new(1.5));
+    let out_of_range = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+    let bad_number = "v 0 zero 0\n";
+    let err = parse_obj(out_of_range.as_bytes(), &material).unwrap_err();
+    assert_eq!(err.to_string(), "line 3: face index out of range");
+    assert!(parse_obj(bad_number.as_bytes(), &material).is_err());
+}
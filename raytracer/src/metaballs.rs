@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::poly::solve_quartic;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::sphere::u_v_from_sphere_hit_point;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// One source of a blob: its field is `strength` at the `center`, falling
+// smoothly to 0 at `radius` away. A negative strength carves a dent.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Metaball {
+    pub center: Point3D,
+    pub radius: Float,
+    pub strength: Float,
+}
+
+impl Metaball {
+    pub fn new(center: Point3D, radius: Float, strength: Float) -> Metaball {
+        Metaball {
+            center,
+            radius,
+            strength,
+        }
+    }
+
+    // The falloff (1 - r^2 / R^2)^2 along a unit ray from `origin`, as the
+    // coefficients of a quartic in t, if the ray comes within the radius:
+    // then also where it comes in and goes out.
+    fn along(&self, origin: &Point3D, d: &Point3D) -> Option<([Float; 5], Float, Float)> {
+        let oc = *origin - self.center;
+        let half_b = oc.dot(d);
+        let r2 = self.radius * self.radius;
+        let c = oc.length_squared();
+        let discriminant = half_b * half_b - (c - r2);
+        if discriminant <= 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        // 1 - r^2 / R^2 = a2 t^2 + a1 t + a0.
+        let (a2, a1, a0) = (-1.0 / r2, -2.0 * half_b / r2, (r2 - c) / r2);
+        let s = self.strength;
+        Some((
+            [
+                s * a0 * a0,
+                s * 2.0 * a0 * a1,
+                s * (a1 * a1 + 2.0 * a0 * a2),
+                s * 2.0 * a1 * a2,
+                s * a2 * a2,
+            ],
+            -half_b - sqrtd,
+            -half_b + sqrtd,
+        ))
+    }
+}
+
+fn default_threshold() -> Float {
+    0.5
+}
+
+// The blobby surface where the sources' fields add up to `threshold`, so
+// nearby balls run together like drops of liquid. Each source's field is a
+// polynomial, so the surface is found exactly, a quartic at a time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Metaballs {
+    pub balls: Vec<Metaball>,
+    #[serde(default = "default_threshold")]
+    pub threshold: Float,
+    pub material: Material,
+}
+
+impl Metaballs {
+    pub fn new(balls: Vec<Metaball>, material: Material) -> Metaballs {
+        Metaballs {
+            balls,
+            threshold: default_threshold(),
+            material,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: Float) -> Metaballs {
+        self.threshold = threshold;
+        self
+    }
+
+    // The sum of the balls' fields at `p`: above the threshold is inside.
+    pub fn field(&self, p: &Point3D) -> Float {
+        self.balls
+            .iter()
+            .map(|ball| {
+                let g = 1.0 - (*p - ball.center).length_squared() / (ball.radius * ball.radius);
+                if g > 0.0 {
+                    ball.strength * g * g
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+
+    // The field falls going out of the surface, so outward is down its
+    // gradient.
+    fn outward(&self, p: &Point3D) -> Point3D {
+        let mut gradient = Point3D::new(0.0, 0.0, 0.0);
+        for ball in &self.balls {
+            let r2 = ball.radius * ball.radius;
+            let offset = *p - ball.center;
+            let g = 1.0 - offset.length_squared() / r2;
+            if g > 0.0 {
+                gradient += offset * (-4.0 * ball.strength * g / r2);
+            }
+        }
+        (-gradient).unit_vector()
+    }
+}
+
+impl Hittable for Metaballs {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let length = ray.direction.length();
+        let d = ray.direction / length;
+        // Where each ball the ray passes through starts and stops counting.
+        let mut spans: Vec<(usize, Float, Float)> = Vec::new();
+        for (i, ball) in self.balls.iter().enumerate() {
+            if let Some((_, t_in, t_out)) = ball.along(&ray.origin, &d) {
+                spans.push((i, t_in, t_out));
+            }
+        }
+        let start = spans.iter().map(|s| s.1).fold(Float::INFINITY, Float::min);
+        if start == Float::INFINITY {
+            return None;
+        }
+        // The quartics lose precision quickly far from where t is 0, so
+        // measure from where the first ball starts.
+        let origin = ray.origin + d * start;
+        let mut events: Vec<Float> = spans.iter().flat_map(|s| [s.1, s.2]).collect();
+        events.sort_by(Float::total_cmp);
+
+        // Between one event and the next the same balls count, so the field
+        // is one quartic there.
+        for pair in events.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if to / length <= t_min || to - from <= 0.0 {
+                continue;
+            }
+            if from / length >= t_max {
+                return None;
+            }
+            let mut coefficients = [0.0; 5];
+            for (i, t_in, t_out) in &spans {
+                if *t_in > from || *t_out < to {
+                    continue;
+                }
+                if let Some((c, _, _)) = self.balls[*i].along(&origin, &d) {
+                    for (sum, c) in coefficients.iter_mut().zip(c) {
+                        *sum += c;
+                    }
+                }
+            }
+            coefficients[0] -= self.threshold;
+            if coefficients[4].abs() < 1e-12 {
+                continue;
+            }
+            let (roots, n) = solve_quartic(coefficients);
+            let t = roots[..n]
+                .iter()
+                .map(|t| t + start)
+                .filter(|t| *t >= from && *t <= to)
+                .map(|t| t / length)
+                .filter(|t| *t > t_min && *t < t_max)
+                .fold(Float::INFINITY, Float::min);
+            if t < Float::INFINITY {
+                let point = ray.at(t);
+                let outward = self.outward(&point);
+                let front_face = ray.direction.dot(&outward) < 0.0;
+                let middle = self
+                    .bounding_box(ray.time, ray.time)
+                    .map_or(point, |b| b.centroid());
+                let (u, v) = u_v_from_sphere_hit_point(point - middle);
+                return Some(HitRecord {
+                    t,
+                    point,
+                    normal: if front_face { outward } else { -outward },
+                    front_face,
+                    material: &self.material,
+                    u,
+                    v,
+                    tangent: None,
+                });
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        self.balls
+            .iter()
+            .map(|ball| {
+                let reach = Point3D::new(ball.radius, ball.radius, ball.radius);
+                Aabb::new(ball.center - reach, ball.center + reach)
+            })
+            .reduce(|a, b| Aabb::surrounding_box(&a, &b))
+    }
+}
+
+#[cfg(test)]
+fn test_blobs(gap: Float) -> Metaballs {
+    Metaballs::new(
+        vec![
+            Metaball::new(Point3D::new(-gap / 2.0, 0.0, 0.0), 1.0, 1.0),
+            Metaball::new(Point3D::new(gap / 2.0, 0.0, 0.0), 1.0, 1.0),
+        ],
+        Material::grey(),
+    )
+}
+
+#[test]
+fn test_metaballs_hit() {
+    // Far apart, each ball on its own reaches out to where
+    // (1 - r^2)^2 = 0.5.
+    let apart = test_blobs(10.0);
+    let surface = (1.0 - Float::sqrt(0.5)).sqrt();
+    let side = Ray::new(Point3D::new(5.0, 0.0, -10.0), Point3D::new(0.0, 0.0, 2.0));
+    let hit = apart.hit(&side, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.z(), -surface);
+    assert_approx_eq!(hit.t, (10.0 - surface) / 2.0);
+    assert!(hit.front_face);
+    assert_approx_eq!(hit.normal.z(), -1.0);
+    let middle = Ray::new(Point3D::new(0.0, 0.0, -10.0), Point3D::new(0.0, 0.0, 1.0));
+    assert!(apart.hit(&middle, 0.001, Float::MAX).is_none());
+
+    // Closer than twice that, they run together across the middle, and the
+    // neck is narrower than the balls.
+    let close = test_blobs(1.2);
+    assert!(1.2 > 2.0 * surface);
+    let hit = close.hit(&middle, 0.001, Float::MAX).unwrap();
+    let neck = -hit.point.z();
+    assert!(neck > 0.0 && neck < surface);
+    assert_approx_eq!(close.field(&hit.point), 0.5);
+    assert_approx_eq!(hit.normal.x(), 0.0);
+    // From inside, the way out.
+    let out = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = close.hit(&out, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_approx_eq!(hit.point.y(), neck);
+
+    let bounds = close.bounding_box(0.0, 1.0).unwrap();
+    assert_approx_eq!(bounds.axis_max(0), 1.6);
+    assert_approx_eq!(bounds.axis_min(1), -1.0);
+}
+
+#[test]
+fn test_metaballs_uv() {
+    // A pair well away from the origin is mapped like a sphere around the
+    // middle of the pair, not around the origin.
+    let middle = Point3D::new(3.0, 0.0, 0.0);
+    let blobs = Metaballs::new(
+        vec![
+            Metaball::new(middle - Point3D::new(0.6, 0.0, 0.0), 1.0, 1.0),
+            Metaball::new(middle + Point3D::new(0.6, 0.0, 0.0), 1.0, 1.0),
+        ],
+        Material::grey(),
+    );
+    let uv_from = |origin: Point3D, direction: Point3D| {
+        let hit = blobs
+            .hit(&Ray::new(origin, direction), 0.001, Float::MAX)
+            .unwrap();
+        (hit.u, hit.v)
+    };
+    let (_, v) = uv_from(Point3D::new(3.0, 5.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    assert_approx_eq!(v, 1.0);
+    let (u, v) = uv_from(Point3D::new(3.0, 0.0, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    assert_approx_eq!(u, 0.5);
+    assert_approx_eq!(v, 0.5);
+    // A quarter of the way around, at the end of the pair.
+    let (u, v) = uv_from(Point3D::new(10.0, 0.0, 0.0), Point3D::new(-1.0, 0.0, 0.0));
+    assert_approx_eq!(u, 0.75);
+    assert_approx_eq!(v, 0.5);
+}
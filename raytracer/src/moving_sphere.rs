@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::sphere::hit_sphere;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// A sphere moving in a straight line from `center0` at `time0` to `center1`
+// at `time1`. Rays sent at different times during the exposure see it in
+// different places, which blurs it along its path.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MovingSphere {
+    pub center0: Point3D,
+    pub center1: Point3D,
+    pub time0: Float,
+    pub time1: Float,
+    pub radius: Float,
+    pub material: Material,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3D,
+        center1: Point3D,
+        time0: Float,
+        time1: Float,
+        radius: Float,
+        material: Material,
+    ) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    // Times outside [time0, time1] extrapolate along the same line.
+    pub fn center(&self, time: Float) -> Point3D {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let fraction = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + (self.center1 - self.center0) * fraction
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            self.center(ray.time),
+            self.radius,
+            &self.material,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    // Covers the whole sweep between time0 and time1.
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        // A negative radius, which turns the sphere inside out (e.g. the
+        // inside of a glass bubble), takes up as much room.
+        let r = self.radius.abs();
+        let r = Point3D::new(r, r, r);
+        // It moves in a straight line, so it's between where it is at the
+        // start and at the end.
+        let (start, end) = (self.center(t0), self.center(t1));
+        let start = Aabb::new(start - r, start + r);
+        let end = Aabb::new(end - r, end + r);
+        Some(Aabb::surrounding_box(&start, &end))
+    }
+}
+
+#[test]
+fn test_moving_sphere_hit() {
+    let sphere = MovingSphere::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(2.0, 0.0, 0.0),
+        0.0,
+        1.0,
+        0.5,
+        Material::Glass(Glass::new(1.5)),
+    );
+    assert_eq!(sphere.center(0.5), Point3D::new(1.0, 0.0, 0.0));
+
+    let origin = Point3D::new(2.0, 0.0, 5.0);
+    let direction = Point3D::new(0.0, 0.0, -1.0);
+    let early = Ray::with_time(origin, direction, 0.0);
+    let late = Ray::with_time(origin, direction, 1.0);
+    assert!(sphere.hit(&early, 0.001, Float::MAX).is_none());
+    let hit = sphere.hit(&late, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 4.5);
+    assert_eq!(hit.normal, Point3D::new(0.0, 0.0, 1.0));
+}
+
+#[test]
+fn test_moving_sphere_shutter() {
+    // An inside-out bubble, moving along x over the second.
+    let bubble = MovingSphere::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(2.0, 0.0, 0.0),
+        0.0,
+        1.0,
+        -0.5,
+        Material::Glass(Glass::new(1.5)),
+    );
+    // With the shutter open all the time its box covers the whole path,
+    // but open for only the first half, the first half of it.
+    let bounds = bubble.bounding_box(0.0, 1.0).unwrap();
+    assert_eq!(bounds.min, Point3D::new(-0.5, -0.5, -0.5));
+    assert_eq!(bounds.max, Point3D::new(2.5, 0.5, 0.5));
+    let bounds = bubble.bounding_box(0.0, 0.5).unwrap();
+    assert_eq!(bounds.max, Point3D::new(1.5, 0.5, 0.5));
+    // Looking down at x = 2.2 finds it by the end of the second, but not
+    // halfway through, when it has only got to x = 1.
+    let down = Point3D::new(0.0, 0.0, -1.0);
+    let halfway = Ray::with_time(Point3D::new(1.2, 0.0, 5.0), down, 0.5);
+    assert!(bubble.hit(&halfway, 0.001, Float::MAX).is_some());
+    let late = Ray::with_time(Point3D::new(2.2, 0.0, 5.0), down, 1.0);
+    assert!(bubble.hit(&late, 0.001, Float::MAX).is_some());
+    let early = Ray::with_time(Point3D::new(2.2, 0.0, 5.0), down, 0.5);
+    assert!(bubble.hit(&early, 0.001, Float::MAX).is_none());
+}
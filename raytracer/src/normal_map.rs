@@ -0,0 +1,249 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::float::Float;
+use crate::materials::Material;
+use crate::materials::Scatterable;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Ray;
+use crate::texture::Texture;
+
+#[cfg(test)]
+use crate::ray::Hittable;
+#[cfg(test)]
+use crate::rect::XzRect;
+#[cfg(test)]
+use crate::texture::Noise;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+fn default_strength() -> Float {
+    1.0
+}
+
+// A unit tangent and bitangent square to the hit's normal, following the
+// way u and v increase where the shape says so. Otherwise the tangent runs
+// around the y axis, which is how a sphere's u goes, and along x where the
+// normal is straight up or down, as on an XzRect.
+pub fn tangent_frame(hit: &HitRecord) -> (Point3D, Point3D) {
+    let n = hit.normal;
+    let around_y = Point3D::new(n.z(), 0.0, -n.x());
+    let guess = match hit.tangent {
+        Some(t) => t,
+        None if around_y.length_squared() > 1e-12 => around_y,
+        None => Point3D::new(1.0, 0.0, 0.0),
+    };
+    // Square it up with the normal, which may have been smoothed or turned
+    // since.
+    let mut tangent = guess - n * n.dot(&guess);
+    if tangent.length_squared() < 1e-12 {
+        tangent = n.orthonormal_basis().0;
+    }
+    let tangent = tangent.unit_vector();
+    (tangent, n.cross(&tangent))
+}
+
+// The hit with its shading normal replaced by `normal`, unless that
+// would light the surface from behind.
+fn leaned<'a>(ray: &Ray, hit: &HitRecord<'a>, normal: Point3D, tangent: Point3D) -> HitRecord<'a> {
+    if normal.dot(&ray.direction) >= 0.0 || !normal.x().is_finite() {
+        return *hit;
+    }
+    HitRecord {
+        normal,
+        tangent: Some(tangent),
+        ..*hit
+    }
+}
+
+// A material seen through a tangent-space normal map: each texel's red,
+// green and blue say how far the shading normal leans along the tangent,
+// the bitangent and the normal, from 0 for -1 to 1 for +1, as most normal
+// maps are baked. `strength` scales the lean; the surface itself stays
+// flat, so silhouettes don't change.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NormalMap {
+    pub map: Texture,
+    #[serde(default = "default_strength")]
+    pub strength: Float,
+    pub material: Box<Material>,
+}
+
+impl NormalMap {
+    pub fn new(map: Texture, material: Material) -> NormalMap {
+        NormalMap {
+            map,
+            strength: default_strength(),
+            material: Box::new(material),
+        }
+    }
+
+    pub fn with_strength(mut self, strength: Float) -> NormalMap {
+        self.strength = strength;
+        self
+    }
+
+    // The hit with its normal leaned as the map says.
+    pub fn shade<'a>(&self, ray: &Ray, hit: &HitRecord<'a>) -> HitRecord<'a> {
+        let texel = self.map.value(hit.u, hit.v, &hit.point);
+        let lean = |c: f32| (2.0 * Float::from(c) - 1.0) * self.strength;
+        let (tangent, bitangent) = tangent_frame(hit);
+        let normal = (tangent * lean(texel.red)
+            + bitangent * lean(texel.green)
+            + hit.normal * (2.0 * Float::from(texel.blue) - 1.0))
+            .unit_vector();
+        leaned(ray, hit, normal, tangent)
+    }
+}
+
+impl Scatterable for NormalMap {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        self.material.scatter(ray, &self.shade(ray, hit_record))
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        self.material.emitted(u, v, p)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        self.material
+            .evaluate(ray, &self.shade(ray, hit_record), direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        self.material
+            .pdf(ray, &self.shade(ray, hit_record), direction)
+    }
+}
+
+// How far along the surface the bump map's slope is measured.
+const BUMP_STEP: Float = 1e-3;
+
+// A material seen through a grayscale height map: the normal leans away
+// from where the height rises, by `strength` times the slope. Heights are
+// the average of the map's channels, with 1 the highest. The slope is found
+// by stepping u and v along with the point the same small distance, so
+// both image textures, which go by u and v, and solid ones, which go by the
+// point, can be bump maps.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BumpMap {
+    pub map: Texture,
+    #[serde(default = "default_strength")]
+    pub strength: Float,
+    pub material: Box<Material>,
+}
+
+impl BumpMap {
+    pub fn new(map: Texture, material: Material) -> BumpMap {
+        BumpMap {
+            map,
+            strength: default_strength(),
+            material: Box::new(material),
+        }
+    }
+
+    pub fn with_strength(mut self, strength: Float) -> BumpMap {
+        self.strength = strength;
+        self
+    }
+
+    pub fn shade<'a>(&self, ray: &Ray, hit: &HitRecord<'a>) -> HitRecord<'a> {
+        let (tangent, bitangent) = tangent_frame(hit);
+        let (u, v, p) = (hit.u, hit.v, hit.point);
+        let here = self.map.height(u, v, &p);
+        let along_u = self
+            .map
+            .height(u + BUMP_STEP, v, &(p + tangent * BUMP_STEP));
+        let along_v = self
+            .map
+            .height(u, v + BUMP_STEP, &(p + bitangent * BUMP_STEP));
+        let slope_u = (along_u - here) / BUMP_STEP;
+        let slope_v = (along_v - here) / BUMP_STEP;
+        let normal =
+            (hit.normal - (tangent * slope_u + bitangent * slope_v) * self.strength).unit_vector();
+        leaned(ray, hit, normal, tangent)
+    }
+}
+
+impl Scatterable for BumpMap {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        self.material.scatter(ray, &self.shade(ray, hit_record))
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        self.material.emitted(u, v, p)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        self.material
+            .evaluate(ray, &self.shade(ray, hit_record), direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        self.material
+            .pdf(ray, &self.shade(ray, hit_record), direction)
+    }
+}
+
+#[test]
+fn test_normal_map() {
+    let floor = XzRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, Material::grey());
+    let down = Ray::new(Point3D::new(0.2, 1.0, 0.3), Point3D::new(0.0, -1.0, 0.0));
+    let hit = floor.hit(&down, 0.001, Float::MAX).unwrap();
+
+    // The flat color of most of a normal map leaves the normal alone.
+    let flat = NormalMap::new(
+        Texture::Solid(Srgb::new(0.5, 0.5, 1.0)),
+        floor.material.clone(),
+    );
+    let shaded = flat.shade(&down, &hit);
+    assert_approx_eq!(shaded.normal.y(), 1.0);
+
+    // Leaning halfway along the tangent, which on the floor is +x.
+    let leaning = NormalMap::new(
+        Texture::Solid(Srgb::new(1.0, 0.5, 1.0)),
+        floor.material.clone(),
+    );
+    let shaded = leaning.shade(&down, &hit);
+    assert_approx_eq!(shaded.normal.x(), Float::sqrt(0.5));
+    assert_approx_eq!(shaded.normal.y(), Float::sqrt(0.5));
+    assert_eq!(shaded.point, hit.point);
+    let gentle = leaning.clone().with_strength(0.0).shade(&down, &hit);
+    assert_approx_eq!(gentle.normal.y(), 1.0);
+
+    // Around a sphere, the tangent follows u: at the front, towards +x.
+    let mut front = hit;
+    front.normal = Point3D::new(0.0, 0.0, 1.0);
+    let (tangent, bitangent) = tangent_frame(&front);
+    assert_eq!(tangent, Point3D::new(1.0, 0.0, 0.0));
+    assert_eq!(bitangent, Point3D::new(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn test_bump_map() {
+    let floor = XzRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, Material::grey());
+    let down = Ray::new(Point3D::new(0.2, 1.0, 0.3), Point3D::new(0.0, -1.0, 0.0));
+    let hit = floor.hit(&down, 0.001, Float::MAX).unwrap();
+
+    // Level ground stays level.
+    let level = BumpMap::new(
+        Texture::Solid(Srgb::new(0.3, 0.3, 0.3)),
+        floor.material.clone(),
+    );
+    assert_approx_eq!(level.shade(&down, &hit).normal.y(), 1.0);
+
+    // On rolling noise, the normal leans downhill along x.
+    let noise = Texture::Noise(Noise::new(4.0, 1));
+    let rolling = BumpMap::new(noise, floor.material.clone()).with_strength(0.2);
+    let shaded = rolling.shade(&down, &hit);
+    assert_approx_eq!(shaded.normal.length(), 1.0);
+    let uphill_x = rolling.map.height(
+        hit.u,
+        hit.v,
+        &(hit.point + Point3D::new(BUMP_STEP, 0.0, 0.0)),
+    ) - rolling.map.height(hit.u, hit.v, &hit.point);
+    assert!(uphill_x != 0.0);
+    assert!(shaded.normal.x() * uphill_x < 0.0);
+    assert!(shaded.normal.y() > 0.0 && shaded.normal.y() < 1.0);
+}
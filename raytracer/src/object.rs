@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::cone::Cone;
+use crate::constant_medium::ConstantMedium;
+use crate::csg::Csg;
+use crate::cuboid::Cuboid;
+use crate::cylinder::Cylinder;
+use crate::disc::Disc;
+use crate::displacement::DisplacedSphere;
+use crate::float::Float;
+use crate::heightfield::Heightfield;
+use crate::heterogeneous_medium::HeterogeneousMedium;
+use crate::instance::Instance;
+use crate::instance::Rotate;
+use crate::instance::Translate;
+use crate::materials::Material;
+use crate::mesh::Mesh;
+use crate::metaballs::Metaballs;
+use crate::moving_sphere::MovingSphere;
+use crate::plane::Plane;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::rect::XyRect;
+use crate::rect::XzRect;
+use crate::rect::YzRect;
+use crate::sdf::SdfShape;
+use crate::sphere::Sphere;
+use crate::torus::Torus;
+use crate::triangle::Triangle;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::point3d::Point3D;
+
+// Anything that can be placed in a scene file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum Object {
+    MovingSphere(MovingSphere),
+    Plane(Plane),
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Disc(Disc),
+    XyRect(XyRect),
+    XzRect(XzRect),
+    YzRect(YzRect),
+    Triangle(Triangle),
+    Mesh(Mesh),
+    ConstantMedium(ConstantMedium),
+    HeterogeneousMedium(HeterogeneousMedium),
+    Translate(Translate),
+    Rotate(Rotate),
+    Instance(Instance),
+    Csg(Csg),
+    SdfShape(SdfShape),
+    Metaballs(Metaballs),
+    Heightfield(Heightfield),
+    DisplacedSphere(DisplacedSphere),
+    // Spheres predate the other objects, so scene files may list them
+    // without a tag.
+    #[serde(untagged)]
+    Sphere(Sphere),
+}
+
+impl Hittable for Object {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        match self {
+            Object::Sphere(s) => s.hit(ray, t_min, t_max),
+            Object::MovingSphere(s) => s.hit(ray, t_min, t_max),
+            Object::Plane(p) => p.hit(ray, t_min, t_max),
+            Object::Cuboid(c) => c.hit(ray, t_min, t_max),
+            Object::Cylinder(c) => c.hit(ray, t_min, t_max),
+            Object::Cone(c) => c.hit(ray, t_min, t_max),
+            Object::Torus(t) => t.hit(ray, t_min, t_max),
+            Object::Disc(d) => d.hit(ray, t_min, t_max),
+            Object::XyRect(r) => r.hit(ray, t_min, t_max),
+            Object::XzRect(r) => r.hit(ray, t_min, t_max),
+            Object::YzRect(r) => r.hit(ray, t_min, t_max),
+            Object::Triangle(t) => t.hit(ray, t_min, t_max),
+            Object::Mesh(m) => m.hit(ray, t_min, t_max),
+            Object::ConstantMedium(c) => c.hit(ray, t_min, t_max),
+            Object::HeterogeneousMedium(h) => h.hit(ray, t_min, t_max),
+            Object::Translate(t) => t.hit(ray, t_min, t_max),
+            Object::Rotate(r) => r.hit(ray, t_min, t_max),
+            Object::Instance(i) => i.hit(ray, t_min, t_max),
+            Object::Csg(c) => c.hit(ray, t_min, t_max),
+            Object::SdfShape(s) => s.hit(ray, t_min, t_max),
+            Object::Metaballs(m) => m.hit(ray, t_min, t_max),
+            Object::Heightfield(h) => h.hit(ray, t_min, t_max),
+            Object::DisplacedSphere(d) => d.hit(ray, t_min, t_max),
+        }
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb> {
+        match self {
+            Object::Sphere(s) => s.bounding_box(t0, t1),
+            Object::MovingSphere(s) => s.bounding_box(t0, t1),
+            Object::Plane(p) => p.bounding_box(t0, t1),
+            Object::Cuboid(c) => c.bounding_box(t0, t1),
+            Object::Cylinder(c) => c.bounding_box(t0, t1),
+            Object::Cone(c) => c.bounding_box(t0, t1),
+            Object::Torus(t) => t.bounding_box(t0, t1),
+            Object::Disc(d) => d.bounding_box(t0, t1),
+            Object::XyRect(r) => r.bounding_box(t0, t1),
+            Object::XzRect(r) => r.bounding_box(t0, t1),
+            Object::YzRect(r) => r.bounding_box(t0, t1),
+            Object::Triangle(t) => t.bounding_box(t0, t1),
+            Object::Mesh(m) => m.bounding_box(t0, t1),
+            Object::ConstantMedium(c) => c.bounding_box(t0, t1),
+            Object::HeterogeneousMedium(h) => h.bounding_box(t0, t1),
+            Object::Translate(t) => t.bounding_box(t0, t1),
+            Object::Rotate(r) => r.bounding_box(t0, t1),
+            Object::Instance(i) => i.bounding_box(t0, t1),
+            Object::Csg(c) => c.bounding_box(t0, t1),
+            Object::SdfShape(s) => s.bounding_box(t0, t1),
+            Object::Metaballs(m) => m.bounding_box(t0, t1),
+            Object::Heightfield(h) => h.bounding_box(t0, t1),
+            Object::DisplacedSphere(d) => d.bounding_box(t0, t1),
+        }
+    }
+}
+
+impl Object {
+    pub fn material(&self) -> &Material {
+        match self {
+            Object::Sphere(s) => &s.material,
+            Object::MovingSphere(s) => &s.material,
+            Object::Plane(p) => &p.material,
+            Object::Cuboid(c) => &c.material,
+            Object::Cylinder(c) => &c.material,
+            Object::Cone(c) => &c.material,
+            Object::Torus(t) => &t.material,
+            Object::Disc(d) => &d.material,
+            Object::XyRect(r) => &r.material,
+            Object::XzRect(r) => &r.material,
+            Object::YzRect(r) => &r.material,
+            Object::Triangle(t) => &t.material,
+            Object::Mesh(m) => m.material(),
+            Object::ConstantMedium(c) => &c.material,
+            Object::HeterogeneousMedium(h) => &h.material,
+            Object::Translate(t) => t.object.material(),
+            Object::Rotate(r) => r.object.material(),
+            Object::Instance(i) => i.object.material(),
+            Object::Csg(c) => c.left.material(),
+            Object::SdfShape(s) => &s.material,
+            Object::Metaballs(m) => &m.material,
+            Object::Heightfield(h) => &h.material,
+            Object::DisplacedSphere(d) => &d.material,
+        }
+    }
+}
+
+impl From<Sphere> for Object {
+    fn from(s: Sphere) -> Self {
+        Object::Sphere(s)
+    }
+}
+
+impl From<MovingSphere> for Object {
+    fn from(s: MovingSphere) -> Self {
+        Object::MovingSphere(s)
+    }
+}
+
+impl From<Plane> for Object {
+    fn from(p: Plane) -> Self {
+        Object::Plane(p)
+    }
+}
+
+impl From<Cuboid> for Object {
+    fn from(c: Cuboid) -> Self {
+        Object::Cuboid(c)
+    }
+}
+
+impl From<Cylinder> for Object {
+    fn from(c: Cylinder) -> Self {
+        Object::Cylinder(c)
+    }
+}
+
+impl From<Cone> for Object {
+    fn from(c: Cone) -> Self {
+        Object::Cone(c)
+    }
+}
+
+impl From<Torus> for Object {
+    fn from(t: Torus) -> Self {
+        Object::Torus(t)
+    }
+}
+
+impl From<Disc> for Object {
+    fn from(d: Disc) -> Self {
+        Object::Disc(d)
+    }
+}
+
+impl From<XyRect> for Object {
+    fn from(r: XyRect) -> Self {
+        Object::XyRect(r)
+    }
+}
+
+impl From<XzRect> for Object {
+    fn from(r: XzRect) -> Self {
+        Object::XzRect(r)
+    }
+}
+
+impl From<YzRect> for Object {
+    fn from(r: YzRect) -> Self {
+        Object::YzRect(r)
+    }
+}
+
+impl From<Triangle> for Object {
+    fn from(t: Triangle) -> Self {
+        Object::Triangle(t)
+    }
+}
+
+impl From<Mesh> for Object {
+    fn from(m: Mesh) -> Self {
+        Object::Mesh(m)
+    }
+}
+
+impl From<ConstantMedium> for Object {
+    fn from(c: ConstantMedium) -> Self {
+        Object::ConstantMedium(c)
+    }
+}
+
+impl From<HeterogeneousMedium> for Object {
+    fn from(h: HeterogeneousMedium) -> Self {
+        Object::HeterogeneousMedium(h)
+    }
+}
+
+impl From<Translate> for Object {
+    fn from(t: Translate) -> Self {
+        Object::Translate(t)
+    }
+}
+
+impl From<Rotate> for Object {
+    fn from(r: Rotate) -> Self {
+        Object::Rotate(r)
+    }
+}
+
+impl From<Instance> for Object {
+    fn from(i: Instance) -> Self {
+        Object::Instance(i)
+    }
+}
+
+impl From<Csg> for Object {
+    fn from(c: Csg) -> Self {
+        Object::Csg(c)
+    }
+}
+
+impl From<SdfShape> for Object {
+    fn from(s: SdfShape) -> Self {
+        Object::SdfShape(s)
+    }
+}
+
+impl From<Metaballs> for Object {
+    fn from(m: Metaballs) -> Self {
+        Object::Metaballs(m)
+    }
+}
+
+impl From<Heightfield> for Object {
+    fn from(h: Heightfield) -> Self {
+        Object::Heightfield(h)
+    }
+}
+
+impl From<DisplacedSphere> for Object {
+    fn from(d: DisplacedSphere) -> Self {
+        Object::DisplacedSphere(d)
+    }
+}
+
+#[test]
+fn test_to_json() {
+    let sphere: Object = Sphere::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        1.0,
+        Material::Glass(Glass::new(1.5)),
+    )
+    .into();
+    let serialized = serde_json::to_string(&sphere).unwrap();
+    assert_eq!(
+        r#"{"center":{"x":0.0,"y":0.0,"z":0.0},"radius":1.0,"material":{"Glass":{"index_of_refraction":1.5}}}"#,
+        serialized
+    );
+    assert!(matches!(
+        serde_json::from_str::<Object>(&serialized).unwrap(),
+        Object::Sphere(_)
+    ));
+
+    let plane = r#"{"Plane":{"point":{"x":0.0,"y":0.0,"z":0.0},"normal":{"x":0.0,"y":1.0,"z":0.0},"material":{"Glass":{"index_of_refraction":1.5}}}}"#;
+    let loaded = serde_json::from_str::<Object>(plane).unwrap();
+    assert!(matches!(loaded, Object::Plane(_)));
+    assert!(loaded.bounding_box(0.0, 1.0).is_none());
+    assert_eq!(plane, serde_json::to_string(&loaded).unwrap());
+}
@@ -0,0 +1,84 @@
+use crate::float::Float;
+use crate::point3d::Point3D;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use rand::Rng;
+
+// Three unit vectors at right angles, `w` usually a surface normal, for
+// working out directions around the z axis and then turning them to be
+// around `w` instead.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Point3D,
+    pub v: Point3D,
+    pub w: Point3D,
+}
+
+impl Onb {
+    // Some basis around the (unit) vector `w`; which way u and v point
+    // around it is arbitrary.
+    pub fn new(w: Point3D) -> Onb {
+        let helper = if w.x().abs() > 0.9 {
+            Point3D::new(0.0, 1.0, 0.0)
+        } else {
+            Point3D::new(1.0, 0.0, 0.0)
+        };
+        let u = helper.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        Onb { u, v, w }
+    }
+
+    // The direction `a` along u, `b` along v and `c` along w.
+    pub fn local(&self, a: Float, b: Float, c: Float) -> Point3D {
+        self.u * a + self.v * b + self.w * c
+    }
+
+    // The reverse of local: how far `d` goes along u, v and w.
+    pub fn to_local(&self, d: &Point3D) -> Point3D {
+        Point3D::new(d.dot(&self.u), d.dot(&self.v), d.dot(&self.w))
+    }
+
+    // Maps a point of the unit square to a unit direction around w, with a
+    // density of cos(theta) / pi: a point on the disk lifted up onto the
+    // hemisphere.
+    pub fn cosine_direction(&self, sample: (Float, Float)) -> Point3D {
+        let d = Point3D::disk_from_square(sample);
+        let up = (1.0 - d.length_squared()).max(0.0).sqrt();
+        self.local(d.x(), d.y(), up)
+    }
+}
+
+#[test]
+fn test_onb() {
+    let n = Point3D::new(1.0, 2.0, -3.0).unit_vector();
+    let onb = Onb::new(n);
+    assert_approx_eq!(onb.u.dot(&onb.v), 0.0);
+    assert_approx_eq!(onb.u.dot(&n), 0.0);
+    assert_approx_eq!(onb.v.length(), 1.0);
+    let d = Point3D::new(0.3, -0.4, 0.5);
+    let back = onb.local(0.3, -0.4, 0.5);
+    assert_approx_eq!(back.length(), d.length());
+    assert_approx_eq!(back.dot(&n), 0.5);
+    let there_and_back = onb.to_local(&back);
+    assert_approx_eq!(there_and_back.x(), 0.3);
+    assert_approx_eq!(there_and_back.y(), -0.4);
+}
+
+#[test]
+fn test_cosine_direction() {
+    let onb = Onb::new(Point3D::new(0.0, 1.0, 0.0));
+    assert_approx_eq!(onb.cosine_direction((0.0, 0.0)).y(), 1.0);
+    let mut rng = rand::thread_rng();
+    let mut mean_cosine = 0.0;
+    for _ in 0..1000 {
+        let d = onb.cosine_direction((rng.gen(), rng.gen()));
+        assert_approx_eq!(d.length(), 1.0);
+        assert!(d.y() >= 0.0);
+        mean_cosine += d.y() / 1000.0;
+    }
+    // The mean of cos(theta) over a cosine-weighted hemisphere is 2/3.
+    assert!((mean_cosine - 2.0 / 3.0).abs() < 0.05);
+    assert!(Point3D::disk_from_square((0.99, 0.3)).length() < 1.0);
+}
@@ -0,0 +1,390 @@
+#[cfg(feature = "images")]
+use exr::prelude::WritableImage;
+#[cfg(feature = "images")]
+use exr::prelude::{AnyChannel, AnyChannels, FlatSamples, Image, Layer, LayerAttributes};
+#[cfg(feature = "images")]
+use image::jpeg::JPEGEncoder;
+#[cfg(feature = "images")]
+use image::png::PNGEncoder;
+#[cfg(feature = "images")]
+use image::ColorType;
+use std::fs;
+#[cfg(feature = "images")]
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::film::Film;
+use crate::tone_map::ToneMap;
+use palette::Srgb;
+
+#[cfg(all(test, feature = "images"))]
+use crate::environment::EnvironmentMap;
+
+// The image file formats that can be written, told apart by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Exr,
+    Ppm,
+}
+
+impl Format {
+    pub fn from_filename(filename: &str) -> Option<Format> {
+        let extension = Path::new(filename).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "exr" => Some(Format::Exr),
+            "ppm" => Some(Format::Ppm),
+            _ => None,
+        }
+    }
+}
+
+// How linear light values become the numbers stored in an 8 or 16-bit image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    // The sRGB curve, which image viewers expect by default.
+    Srgb,
+    // c^(1/gamma); gamma 2 is the square root "Ray Tracing in One Weekend"
+    // uses.
+    Gamma(f32),
+    // The values unchanged, for programs that do their own conversion.
+    Linear,
+}
+
+impl Transfer {
+    pub fn apply(&self, c: f32) -> f32 {
+        let c = c.clamp(0.0, 1.0);
+        match *self {
+            Transfer::Srgb if c <= 0.0031308 => 12.92 * c,
+            Transfer::Srgb => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+            Transfer::Gamma(gamma) => c.powf(1.0 / gamma),
+            Transfer::Linear => c,
+        }
+    }
+}
+
+impl FromStr for Transfer {
+    type Err = String;
+
+    // "srgb", "linear", or a gamma such as "2.2".
+    fn from_str(s: &str) -> Result<Transfer, String> {
+        match s.to_lowercase().as_str() {
+            "srgb" => Ok(Transfer::Srgb),
+            "linear" => Ok(Transfer::Linear),
+            gamma => match gamma.trim_start_matches("gamma").parse::<f32>() {
+                Ok(gamma) if gamma > 0.0 => Ok(Transfer::Gamma(gamma)),
+                _ => Err(format!("unknown transfer function: {}", s)),
+            },
+        }
+    }
+}
+
+// Choices about how images are encoded, on top of the format chosen by the
+// file's extension.
+#[derive(Debug, Clone, Copy)]
+pub struct Encoding {
+    // Write PPM as text (P3), one number per channel, rather than binary
+    // (P6). Other "Ray Tracing in One Weekend" programs usually write text.
+    pub ascii_ppm: bool,
+    // Write PNG with 16 bits per channel instead of 8, so smooth gradients
+    // like the sky don't show bands.
+    pub png_16_bit: bool,
+    // From 1 (smallest) to 100 (best).
+    pub jpeg_quality: u8,
+    // Applied to everything but OpenEXR, which stays linear.
+    pub tone_map: ToneMap,
+    // Also applied to everything but OpenEXR.
+    pub transfer: Transfer,
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding {
+            ascii_ppm: false,
+            png_16_bit: false,
+            jpeg_quality: 90,
+            tone_map: ToneMap::default(),
+            // The square root earlier versions wrote, so renders look as they
+            // did; sRGB has to be asked for.
+            transfer: Transfer::Gamma(2.0),
+        }
+    }
+}
+
+impl Encoding {
+    // A linear colour as it is stored in an 8 or 16-bit image: tone mapped,
+    // then through the transfer function. Everything shown or written goes
+    // through here.
+    pub fn display(&self, color: Srgb) -> Srgb {
+        let mapped = self.tone_map.apply(color);
+        Srgb::new(
+            self.transfer.apply(mapped.red),
+            self.transfer.apply(mapped.green),
+            self.transfer.apply(mapped.blue),
+        )
+    }
+}
+
+// Writes the film's current estimate to `filename`, in the format its
+// extension asks for. OpenEXR keeps the linear floating point values,
+// including everything brighter than white, for grading later.
+pub fn write_film(filename: &str, film: &Film, encoding: &Encoding) -> Result<(), Error> {
+    let format = Format::from_filename(filename).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{}: unknown image format, use .png, .jpg, .exr or .ppm",
+                filename
+            ),
+        )
+    })?;
+    let bounds = (film.width, film.height);
+    match format {
+        Format::Exr => write_exr(filename, film),
+        Format::Png if encoding.png_16_bit => {
+            // PNG stores 16-bit values big-endian.
+            let bytes: Vec<u8> = film
+                .to_rgb16(encoding)
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect();
+            write_png(filename, &bytes, bounds, 16)
+        }
+        _ => write_rgb8(filename, &film.to_rgb8(encoding), bounds, encoding),
+    }
+}
+
+// Writes 8-bit RGB pixels, row by row, to `filename` in the format its
+// extension asks for, which can't be OpenEXR.
+pub fn write_rgb8(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    encoding: &Encoding,
+) -> Result<(), Error> {
+    match Format::from_filename(filename) {
+        Some(Format::Png) => write_png(filename, pixels, bounds, 8),
+        Some(Format::Jpeg) => write_jpeg(filename, pixels, bounds, encoding.jpeg_quality),
+        Some(Format::Ppm) => fs::write(filename, encode_ppm(pixels, bounds, encoding.ascii_ppm)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: can't be written with 8 bits per channel", filename),
+        )),
+    }
+}
+
+// 8-bit RGB pixels as a PPM file, which needs no library to read or write.
+pub fn encode_ppm(pixels: &[u8], bounds: (usize, usize), ascii: bool) -> Vec<u8> {
+    let magic = if ascii { "P3" } else { "P6" };
+    let mut ppm = format!("{}\n{} {}\n255\n", magic, bounds.0, bounds.1).into_bytes();
+    if ascii {
+        for pixel in pixels.chunks(3) {
+            ppm.extend(format!("{} {} {}\n", pixel[0], pixel[1], pixel[2]).bytes());
+        }
+    } else {
+        ppm.extend_from_slice(pixels);
+    }
+    ppm
+}
+
+#[cfg(not(feature = "images"))]
+fn unsupported(filename: &str) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!("{}: built without image support", filename),
+    )
+}
+
+#[cfg(not(feature = "images"))]
+fn write_png(
+    filename: &str,
+    _pixels: &[u8],
+    _bounds: (usize, usize),
+    _bits: u8,
+) -> Result<(), Error> {
+    Err(unsupported(filename))
+}
+
+#[cfg(feature = "images")]
+fn write_png(filename: &str, pixels: &[u8], bounds: (usize, usize), bits: u8) -> Result<(), Error> {
+    let output = File::create(filename)?;
+    let encoder = PNGEncoder::new(output);
+    encoder.encode(
+        pixels,
+        bounds.0 as u32,
+        bounds.1 as u32,
+        ColorType::RGB(bits),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(feature = "images"))]
+fn write_jpeg(
+    filename: &str,
+    _pixels: &[u8],
+    _bounds: (usize, usize),
+    _quality: u8,
+) -> Result<(), Error> {
+    Err(unsupported(filename))
+}
+
+#[cfg(feature = "images")]
+fn write_jpeg(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    quality: u8,
+) -> Result<(), Error> {
+    let mut output = File::create(filename)?;
+    let mut encoder = JPEGEncoder::new_with_quality(&mut output, quality);
+    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::RGB(8))
+}
+
+#[cfg(not(feature = "images"))]
+fn write_exr(filename: &str, _film: &Film) -> Result<(), Error> {
+    Err(unsupported(filename))
+}
+
+#[cfg(feature = "images")]
+fn write_exr(filename: &str, film: &Film) -> Result<(), Error> {
+    let pixels = film.pixels();
+    exr::prelude::write_rgb_file(filename, film.width, film.height, |x, y| {
+        let mean = pixels[y * film.width + x].mean();
+        (mean.red, mean.green, mean.blue)
+    })
+    .map_err(|e| Error::other(format!("{}: {}", filename, e)))
+}
+
+#[cfg(not(feature = "images"))]
+pub fn write_ids_exr(
+    filename: &str,
+    _bounds: (usize, usize),
+    _channels: &[(&str, &[u32])],
+) -> Result<(), Error> {
+    Err(unsupported(filename))
+}
+
+// Writes OpenEXR with a whole number channel for each of `channels`, which
+// hold their values row by row.
+#[cfg(feature = "images")]
+pub fn write_ids_exr(
+    filename: &str,
+    bounds: (usize, usize),
+    channels: &[(&str, &[u32])],
+) -> Result<(), Error> {
+    let (width, height) = bounds;
+    let channels = channels
+        .iter()
+        .map(|(name, values)| AnyChannel::new(*name, FlatSamples::U32(values.to_vec())))
+        .collect();
+    let layer = Layer::new(
+        (width, height),
+        LayerAttributes::default(),
+        exr::prelude::Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(filename)
+        .map_err(|e| Error::other(format!("{}: {}", filename, e)))
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_write_exr() {
+    let mut film = Film::new(3, 2);
+    film.pixels_mut()[4].add(Srgb::new(4.0, 0.5, 0.0));
+    write_film("/tmp/test_write_exr.exr", &film, &Encoding::default()).unwrap();
+
+    // Read back with the environment map loader: values above 1 survive.
+    let map = EnvironmentMap::load("/tmp/test_write_exr.exr").unwrap();
+    assert_eq!(map.pixel(1, 1), Srgb::new(4.0, 0.5, 0.0));
+    assert_eq!(map.pixel(0, 0), Srgb::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn test_encode_ppm() {
+    let pixels = [255, 0, 0, 0, 128, 255];
+    assert_eq!(
+        encode_ppm(&pixels, (2, 1), true),
+        b"P3\n2 1\n255\n255 0 0\n0 128 255\n".to_vec()
+    );
+    let mut binary = b"P6\n2 1\n255\n".to_vec();
+    binary.extend_from_slice(&pixels);
+    assert_eq!(encode_ppm(&pixels, (2, 1), false), binary);
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_write_png_16_bit() {
+    let film = Film::new(3, 2);
+    let encoding = Encoding {
+        png_16_bit: true,
+        ..Default::default()
+    };
+    write_film("/tmp/test_write_png_16_bit.png", &film, &encoding).unwrap();
+    // The bit depth is the first byte after the width and height in the
+    // header chunk.
+    let png = fs::read("/tmp/test_write_png_16_bit.png").unwrap();
+    assert_eq!(png[24], 16);
+    write_film(
+        "/tmp/test_write_png_16_bit.png",
+        &film,
+        &Encoding::default(),
+    )
+    .unwrap();
+    let png = fs::read("/tmp/test_write_png_16_bit.png").unwrap();
+    assert_eq!(png[24], 8);
+}
+
+#[test]
+fn test_format_from_filename() {
+    assert_eq!(Format::from_filename("out.png"), Some(Format::Png));
+    assert_eq!(Format::from_filename("a/b.JPEG"), Some(Format::Jpeg));
+    assert_eq!(Format::from_filename("render.jpg"), Some(Format::Jpeg));
+    assert_eq!(Format::from_filename("x.exr"), Some(Format::Exr));
+    assert_eq!(Format::from_filename("x.ppm"), Some(Format::Ppm));
+    assert_eq!(Format::from_filename("x.gif"), None);
+    assert_eq!(Format::from_filename("png"), None);
+    assert!(write_film(
+        "/tmp/test_format.gif",
+        &Film::new(1, 1),
+        &Encoding::default()
+    )
+    .is_err());
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_write_jpeg() {
+    let mut film = Film::new(16, 16);
+    for pixel in film.pixels_mut() {
+        pixel.add(Srgb::new(0.25, 0.25, 0.25));
+    }
+    write_film("/tmp/test_write_jpeg.jpg", &film, &Encoding::default()).unwrap();
+    let image = image::open("/tmp/test_write_jpeg.jpg").unwrap().to_rgb();
+    assert_eq!(image.dimensions(), (16, 16));
+    // A quarter of white is half way with gamma 2.
+    let grey = image.get_pixel(8, 8).data[0];
+    assert!((124..=132).contains(&grey));
+}
+
+#[test]
+fn test_transfer() {
+    assert_eq!(Transfer::Linear.apply(0.25), 0.25);
+    assert_eq!(Transfer::Gamma(2.0).apply(0.25), 0.5);
+    assert!((Transfer::Srgb.apply(0.25) - 0.5371).abs() < 1e-4);
+    assert!((Transfer::Srgb.apply(0.002) - 0.02584).abs() < 1e-5);
+    assert!((Transfer::Srgb.apply(4.0) - 1.0).abs() < 1e-6);
+    assert_eq!("sRGB".parse::<Transfer>(), Ok(Transfer::Srgb));
+    assert_eq!("2.2".parse::<Transfer>(), Ok(Transfer::Gamma(2.2)));
+    assert_eq!("gamma1.8".parse::<Transfer>(), Ok(Transfer::Gamma(1.8)));
+    assert!("0".parse::<Transfer>().is_err());
+    assert!("log".parse::<Transfer>().is_err());
+}
@@ -0,0 +1,77 @@
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::point3d::Point3D;
+
+// How many rays go through a hierarchy together: four f64 lanes fill an AVX
+// register. The lanes are plain arrays looped over in step, which the
+// compiler turns into vector instructions without nightly std::simd or
+// per-platform intrinsics.
+pub const PACKET_SIZE: usize = 4;
+
+// A few rays laid out lane by lane, all the origins' x together and so on,
+// so that one node's slab test runs on all of them at once. Worth it for
+// coherent rays, like the camera rays through one pixel or a row of them,
+// which mostly visit the same nodes.
+#[derive(Debug, Clone)]
+pub struct RayPacket {
+    pub rays: [Ray; PACKET_SIZE],
+    origin: [[Float; PACKET_SIZE]; 3],
+    inv_direction: [[Float; PACKET_SIZE]; 3],
+}
+
+impl RayPacket {
+    pub fn new(rays: [Ray; PACKET_SIZE]) -> RayPacket {
+        let origin = [0, 1, 2].map(|a| rays.map(|ray| ray.origin[a]));
+        let inv_direction = [0, 1, 2].map(|a| rays.map(|ray| 1.0 / ray.direction[a]));
+        RayPacket {
+            rays,
+            origin,
+            inv_direction,
+        }
+    }
+
+    // Which rays pass through `bounds` between t_min and their own t_max:
+    // Aabb::hit for each lane, giving the same answers.
+    pub fn hit(
+        &self,
+        bounds: &Aabb,
+        t_min: Float,
+        t_max: &[Float; PACKET_SIZE],
+    ) -> [bool; PACKET_SIZE] {
+        let mut near = [t_min; PACKET_SIZE];
+        let mut far = *t_max;
+        for a in 0..3 {
+            let (low, high) = (bounds.axis_min(a), bounds.axis_max(a));
+            for i in 0..PACKET_SIZE {
+                let inv_d = self.inv_direction[a][i];
+                let t0 = (low - self.origin[a][i]) * inv_d;
+                let t1 = (high - self.origin[a][i]) * inv_d;
+                let (t0, t1) = if inv_d < 0.0 { (t1, t0) } else { (t0, t1) };
+                near[i] = if t0 > near[i] { t0 } else { near[i] };
+                far[i] = if t1 < far[i] { t1 } else { far[i] };
+            }
+        }
+        std::array::from_fn(|i| far[i] >= near[i])
+    }
+}
+
+#[test]
+fn test_packet_matches_single_rays() {
+    let bounds = Aabb::new(Point3D::new(-1.0, -1.0, -1.0), Point3D::new(1.0, 1.0, 1.0));
+    for _ in 0..100 {
+        let rays = [(); PACKET_SIZE]
+            .map(|_| Ray::new(Point3D::random(-3.0, 3.0), Point3D::random(-1.0, 1.0)));
+        let t_max = [0.5, 2.0, 10.0, Float::MAX];
+        let found = RayPacket::new(rays).hit(&bounds, 0.001, &t_max);
+        for i in 0..PACKET_SIZE {
+            assert_eq!(found[i], bounds.hit(&rays[i], 0.001, t_max[i]));
+        }
+    }
+    // Along a face, as the single test has it.
+    let along = Ray::new(Point3D::new(1.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    let found = RayPacket::new([along; PACKET_SIZE]).hit(&bounds, 0.001, &[Float::MAX; 4]);
+    assert_eq!(found[0], bounds.hit(&along, 0.001, Float::MAX));
+}
@@ -0,0 +1,134 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::float::Float;
+use crate::point3d::Point3D;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise after Ken Perlin: random unit vectors on a lattice,
+// picked through three hashed permutation tables and blended with
+// Hermite-smoothed trilinear interpolation.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    ranvec: Vec<Point3D>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+fn generate_perm(rng: &mut StdRng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    p.shuffle(rng);
+    p
+}
+
+fn perlin_interp(c: &[[[Point3D; 2]; 2]; 2], u: Float, v: Float, w: Float) -> Float {
+    let uu = u * u * (3.0 - 2.0 * u);
+    let vv = v * v * (3.0 - 2.0 * v);
+    let ww = w * w * (3.0 - 2.0 * w);
+    let mut accum = 0.0;
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, gradient) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as Float, j as Float, k as Float);
+                let weight = Point3D::new(u - fi, v - fj, w - fk);
+                accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                    * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                    * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                    * gradient.dot(&weight);
+            }
+        }
+    }
+    accum
+}
+
+impl Perlin {
+    // The same seed always produces the same noise.
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| {
+                Point3D::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .unit_vector()
+            })
+            .collect();
+        let perm_x = generate_perm(&mut rng);
+        let perm_y = generate_perm(&mut rng);
+        let perm_z = generate_perm(&mut rng);
+        Perlin {
+            ranvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    // Smooth noise in roughly [-1, 1], zero at every integer lattice point.
+    pub fn noise(&self, p: &Point3D) -> Float {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+        let i = p.x().floor() as i64;
+        let j = p.y().floor() as i64;
+        let k = p.z().floor() as i64;
+
+        let mask = POINT_COUNT as i64 - 1;
+        let mut c = [[[Point3D::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, gradient) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & mask) as usize]
+                        ^ self.perm_y[((j + dj as i64) & mask) as usize]
+                        ^ self.perm_z[((k + dk as i64) & mask) as usize];
+                    *gradient = self.ranvec[index];
+                }
+            }
+        }
+        perlin_interp(&c, u, v, w)
+    }
+
+    // Sum of `depth` octaves of noise, each at twice the frequency and half
+    // the amplitude of the last.
+    pub fn turb(&self, p: &Point3D, depth: usize) -> Float {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[test]
+fn test_noise() {
+    let perlin = Perlin::new(42);
+    assert_approx_eq!(perlin.noise(&Point3D::new(1.0, 2.0, 3.0)), 0.0);
+    let p = Point3D::new(0.3, 0.7, 0.2);
+    let n = perlin.noise(&p);
+    assert!((-1.0..=1.0).contains(&n));
+    // Continuous...
+    let nearby = perlin.noise(&(p + Point3D::new(1e-6, 0.0, 0.0)));
+    assert!((n - nearby).abs() < 1e-4);
+    // ...and deterministic for a given seed.
+    assert_eq!(n, Perlin::new(42).noise(&p));
+}
+
+#[test]
+fn test_turb() {
+    let perlin = Perlin::new(7);
+    let p = Point3D::new(1.5, 0.25, -3.75);
+    assert_eq!(perlin.turb(&p, 1), perlin.noise(&p).abs());
+    assert!(perlin.turb(&p, 7) >= 0.0);
+}
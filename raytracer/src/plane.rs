@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Rays closer to parallel with the plane than this are treated as misses.
+const PARALLEL_EPSILON: Float = 1e-9;
+
+// An infinite plane through `point`, facing `normal`. A flat ground that,
+// unlike a huge sphere, has no curvature and keeps its precision far from
+// the origin.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Plane {
+    pub point: Point3D,
+    pub normal: Point3D,
+    pub material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Point3D, normal: Point3D, material: Material) -> Plane {
+        Plane {
+            point,
+            normal: normal.unit_vector(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Scene files may not give a unit normal.
+        let normal = self.normal.unit_vector();
+        let denom = ray.direction.dot(&normal);
+        if denom.abs() < PARALLEL_EPSILON * ray.direction.length() {
+            return None;
+        }
+        let t = (self.point - ray.origin).dot(&normal) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let front_face = denom < 0.0;
+        // The texture repeats once per unit along each tangent.
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        let offset = point - self.point;
+        Some(HitRecord {
+            t,
+            point,
+            normal: if front_face { normal } else { -normal },
+            front_face,
+            material: &self.material,
+            u: offset.dot(&tangent).rem_euclid(1.0),
+            v: offset.dot(&bitangent).rem_euclid(1.0),
+            tangent: None,
+        })
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        None
+    }
+}
+
+#[test]
+fn test_plane_hit() {
+    let plane = Plane::new(
+        Point3D::new(0.0, -1.0, 0.0),
+        Point3D::new(0.0, 2.0, 0.0),
+        Material::grey(),
+    );
+    let ray = Ray::new(Point3D::new(3.0, 1.0, -7.0), Point3D::new(0.0, -1.0, 0.0));
+    let hit = plane.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 2.0);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 1.0, 0.0));
+    assert!((0.0..1.0).contains(&hit.u));
+    assert!((0.0..1.0).contains(&hit.v));
+
+    let below = Ray::new(Point3D::new(0.0, -3.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = plane.hit(&below, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, -1.0, 0.0));
+}
+
+#[test]
+fn test_plane_miss() {
+    let plane = Plane::new(
+        Point3D::new(0.0, -1.0, 0.0),
+        Point3D::new(0.0, 2.0, 0.0),
+        Material::grey(),
+    );
+    let parallel = Ray::new(Point3D::new(0.0, 1.0, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    assert!(plane.hit(&parallel, 0.001, Float::MAX).is_none());
+    let away = Ray::new(Point3D::new(0.0, 1.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    assert!(plane.hit(&away, 0.001, Float::MAX).is_none());
+    assert!(plane.bounding_box(0.0, 1.0).is_none());
+}
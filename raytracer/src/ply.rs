@@ -0,0 +1,410 @@
+use palette::Srgb;
+use std::io::{BufRead, Error, ErrorKind};
+
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::texture::Texture;
+use crate::texture::VertexColors;
+use crate::triangle::Triangle;
+
+#[cfg(test)]
+use crate::materials::Lambertian;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    // Both the old and the sized names are in use.
+    fn parse(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // What a color component of this type counts up to.
+    fn full(self) -> Float {
+        match self {
+            Scalar::U8 => 255.0,
+            Scalar::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Single(String, Scalar),
+    // A count of this type, then that many items.
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Single(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn parse_header<R: BufRead>(reader: &mut R) -> Result<(Format, Vec<Element>), Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid("not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("PLY header has no end_header"));
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let scalar = |name: &str| Scalar::parse(name).ok_or_else(|| invalid("unknown PLY type"));
+        match parts.as_slice() {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::LittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(Format::BigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid("bad PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid("PLY property before any element"))?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    scalar(count)?,
+                    scalar(item)?,
+                )),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid("PLY property before any element"))?
+                .properties
+                .push(Property::Single(name.to_string(), scalar(kind)?)),
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid("bad PLY header line")),
+        }
+    }
+    let format = format.ok_or_else(|| invalid("PLY header has no format"))?;
+    Ok((format, elements))
+}
+
+// Numbers from the body, as text or as binary of either byte order.
+struct Values<R> {
+    reader: R,
+    format: Format,
+    // The rest of the current line, backwards.
+    tokens: Vec<String>,
+}
+
+impl<R: BufRead> Values<R> {
+    fn next(&mut self, scalar: Scalar) -> Result<Float, Error> {
+        if self.format == Format::Ascii {
+            while self.tokens.is_empty() {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Err(invalid("PLY file ends early"));
+                }
+                self.tokens = line.split_whitespace().rev().map(String::from).collect();
+            }
+            let token = self.tokens.pop().unwrap();
+            return token.parse().map_err(|_| invalid("bad number in PLY file"));
+        }
+        let mut bytes = [0u8; 8];
+        let bytes = &mut bytes[..scalar.size()];
+        self.reader.read_exact(bytes)?;
+        if self.format == Format::BigEndian {
+            bytes.reverse();
+        }
+        let b = &*bytes;
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as Float,
+            Scalar::U8 => b[0] as Float,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as Float,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as Float,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as Float,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as Float,
+            Scalar::F32 => Float::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Scalar::F64 => {
+                f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as Float
+            }
+        })
+    }
+
+    // One element's properties; lists other than the one wanted are read
+    // past.
+    fn row(&mut self, element: &Element, list: &str) -> Result<(Vec<Float>, Vec<Float>), Error> {
+        let mut singles = Vec::with_capacity(element.properties.len());
+        let mut items = Vec::new();
+        for property in &element.properties {
+            match property {
+                Property::Single(_, scalar) => singles.push(self.next(*scalar)?),
+                Property::List(name, count, item) => {
+                    let n = self.next(*count)? as usize;
+                    for _ in 0..n {
+                        let value = self.next(*item)?;
+                        if name == list {
+                            items.push(value);
+                        }
+                    }
+                    singles.push(0.0);
+                }
+            }
+        }
+        Ok((singles, items))
+    }
+}
+
+// Parses a PLY file, text or binary, as written by the Stanford scans and
+// most scanning software: vertices with optional normals (`nx`, `ny`,
+// `nz`), colors (`red`, `green`, `blue`) and texture coordinates (`u`, `v`
+// or `s`, `t`), and faces listing their `vertex_indices`, triangulated as
+// fans. Other elements and properties are skipped. A colored face gets the
+// material with its vertices' colors blended across it.
+pub fn parse_ply<R: BufRead>(mut reader: R, material: &Material) -> Result<Vec<Triangle>, Error> {
+    let (format, elements) = parse_header(&mut reader)?;
+    let mut values = Values {
+        reader,
+        format,
+        tokens: Vec::new(),
+    };
+
+    let mut vertices: Vec<Vec<Float>> = Vec::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                for _ in 0..element.count {
+                    vertices.push(values.row(element, "")?.0);
+                }
+            }
+            "face" => {
+                let list = element
+                    .properties
+                    .iter()
+                    .map(Property::name)
+                    .find(|name| *name == "vertex_indices" || *name == "vertex_index")
+                    .ok_or_else(|| invalid("PLY faces have no vertex_indices"))?;
+                for _ in 0..element.count {
+                    let (_, indices) = values.row(element, list)?;
+                    // Read as floats, which would turn negative ones into 0.
+                    if indices.iter().any(|i| *i < 0.0) {
+                        return Err(invalid("PLY face index out of range"));
+                    }
+                    faces.push(indices.iter().map(|i| *i as usize).collect());
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    values.row(element, "")?;
+                }
+            }
+        }
+    }
+    let vertex = elements
+        .iter()
+        .find(|e| e.name == "vertex")
+        .ok_or_else(|| invalid("PLY file has no vertices"))?;
+
+    let column = |name: &str| {
+        vertex
+            .properties
+            .iter()
+            .position(|p| matches!(p, Property::Single(n, _) if n == name))
+    };
+    let columns = |names: [&str; 3]| -> Option<[usize; 3]> {
+        Some([column(names[0])?, column(names[1])?, column(names[2])?])
+    };
+    let [x, y, z] =
+        columns(["x", "y", "z"]).ok_or_else(|| invalid("PLY vertices have no x, y, z"))?;
+    let normal = columns(["nx", "ny", "nz"]);
+    let color = columns(["red", "green", "blue"]);
+    let uv = match (column("u"), column("v"), column("s"), column("t")) {
+        (Some(u), Some(v), _, _) | (_, _, Some(u), Some(v)) => Some((u, v)),
+        _ => None,
+    };
+    let full = match color.map(|[r, _, _]| &vertex.properties[r]) {
+        Some(Property::Single(_, scalar)) => scalar.full(),
+        _ => 1.0,
+    };
+
+    let point = |row: &[Float], [a, b, c]: [usize; 3]| Point3D::new(row[a], row[b], row[c]);
+    let mut triangles = Vec::new();
+    for face in faces {
+        if face.len() < 3 {
+            return Err(invalid("PLY face needs at least three vertices"));
+        }
+        if face.iter().any(|i| *i >= vertices.len()) {
+            return Err(invalid("PLY face index out of range"));
+        }
+        for i in 1..face.len() - 1 {
+            let rows = [face[0], face[i], face[i + 1]].map(|i| &vertices[i]);
+            let corners = rows.map(|row| point(row, [x, y, z]));
+            let material = match color {
+                Some(columns) => {
+                    let colors = rows.map(|row| {
+                        let c = point(row, columns) / full;
+                        Srgb::new(to_f32(c.x()), to_f32(c.y()), to_f32(c.z()))
+                    });
+                    material.with_texture(Texture::Vertex(VertexColors::new(corners, colors)))
+                }
+                None => material.clone(),
+            };
+            let [a, b, c] = corners;
+            let mut triangle = Triangle::new(a, b, c, material);
+            if let Some(columns) = normal {
+                triangle.normals = Some(rows.map(|row| point(row, columns).unit_vector()));
+            }
+            if let Some((u, v)) = uv {
+                triangle.uvs = Some(rows.map(|row| (row[u], row[v])));
+            }
+            triangles.push(triangle);
+        }
+    }
+    Ok(triangles)
+}
+
+#[test]
+fn test_parse_ply_ascii() {
+    let ply = "\
+ply
+format ascii 1.0
+comment a unit quad with a red and a blue corner
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 0 0 2 255 0 0
+1 0 0 0 0 1 255 0 0
+1 1 0 0 0 1 0 0 255
+0 1 0 0 0 1 0 0 255
+4 0 1 2 3
+0 1
+";
+    let material = Material::grey();
+    let triangles = parse_ply(ply.as_bytes(), &material).unwrap();
+    assert_eq!(triangles.len(), 2);
+    assert_eq!(triangles[1].v2, Point3D::new(0.0, 1.0, 0.0));
+    assert_eq!(
+        triangles[0].normals.unwrap()[0],
+        Point3D::new(0.0, 0.0, 1.0)
+    );
+    // Two red corners and one blue, blended in between.
+    match &triangles[0].material {
+        Material::Lambertian(Lambertian {
+            albedo: Texture::Vertex(colors),
+        }) => {
+            let at = |x, y| colors.value(0.0, 0.0, &Point3D::new(x, y, 0.0));
+            assert_eq!(at(1.0, 0.0), Srgb::new(1.0, 0.0, 0.0));
+            assert_eq!(at(1.0, 1.0), Srgb::new(0.0, 0.0, 1.0));
+            let middle = at(2.0 / 3.0, 1.0 / 3.0);
+            assert_approx_eq!(middle.red, 2.0 / 3.0);
+            assert_approx_eq!(middle.blue, 1.0 / 3.0);
+        }
+        m => panic!("expected a vertex colored Lambertian, got {:?}", m),
+    }
+
+    for face in ["3 0 1 4", "3 0 -1 2"] {
+        let out_of_range = ply.replace("4 0 1 2 3", face);
+        let err = parse_ply(out_of_range.as_bytes(), &material).unwrap_err();
+        assert_eq!(err.to_string(), "PLY face index out of range");
+    }
+    assert!(parse_ply("obj\n".as_bytes(), &material).is_err());
+}
+
+#[test]
+fn test_parse_ply_binary() {
+    let mut ply = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property double x
+property double y
+property double z
+element face 1
+property uchar flags
+property list uchar uint vertex_indices
+end_header
+"
+    .to_vec();
+    for c in [0.0f64, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+        ply.extend(c.to_be_bytes());
+    }
+    ply.extend([7, 3]);
+    for i in [0u32, 1, 2] {
+        ply.extend(i.to_be_bytes());
+    }
+    let material = Material::grey();
+    let triangles = parse_ply(ply.as_slice(), &material).unwrap();
+    assert_eq!(triangles.len(), 1);
+    assert_eq!(triangles[0].v1, Point3D::new(1.0, 0.0, 0.0));
+    assert!(triangles[0].normals.is_none());
+
+    // Cut short, the last index is missing.
+    assert!(parse_ply(&ply[..ply.len() - 1], &material).is_err());
+}
@@ -1,26 +1,102 @@
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
-use std::f64;
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::iter::Sum;
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
+
+use crate::float::consts::PI;
+use crate::float::Float;
+use crate::onb::Onb;
+use crate::sampler;
+#[cfg(feature = "simd")]
+use wide::CmpEq;
+#[cfg(feature = "simd")]
+use wide::CmpLt;
 
 #[cfg(test)]
 use assert_approx_eq::assert_approx_eq;
 
+// A position, direction or offset, written as maths: p + d * t, -v,
+// a.dot(&b), v[axis].
+#[cfg(not(feature = "simd"))]
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Point3D {
-    x: f64,
-    y: f64,
-    z: f64,
+    x: Float,
+    y: Float,
+    z: Float,
+}
+
+// With the simd feature a point is held in one vector register, wide's
+// f64x4 (f32x4 with the f32 feature), so that adding, scaling and the like
+// are one instruction instead of three. The fourth lane is along for the
+// ride: nothing reads it, so whatever it ends up holding doesn't matter.
+#[cfg(all(feature = "simd", not(feature = "f32")))]
+type Lanes = wide::f64x4;
+#[cfg(all(feature = "simd", feature = "f32"))]
+type Lanes = wide::f32x4;
+
+#[cfg(feature = "simd")]
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(from = "Xyz", into = "Xyz")]
+pub struct Point3D {
+    lanes: Lanes,
+}
+
+// How a point is written in a scene however it's held.
+#[cfg(feature = "simd")]
+#[derive(Deserialize, Serialize)]
+struct Xyz {
+    x: Float,
+    y: Float,
+    z: Float,
+}
+
+#[cfg(feature = "simd")]
+impl From<Xyz> for Point3D {
+    fn from(p: Xyz) -> Point3D {
+        Point3D::new(p.x, p.y, p.z)
+    }
+}
+
+#[cfg(feature = "simd")]
+impl From<Point3D> for Xyz {
+    fn from(p: Point3D) -> Xyz {
+        Xyz {
+            x: p.x(),
+            y: p.y(),
+            z: p.z(),
+        }
+    }
+}
+
+#[cfg(feature = "simd")]
+impl std::fmt::Debug for Point3D {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Point3D")
+            .field("x", &self.x())
+            .field("y", &self.y())
+            .field("z", &self.z())
+            .finish()
+    }
 }
 
 impl Point3D {
-    pub fn new(x: f64, y: f64, z: f64) -> Point3D {
+    #[cfg(not(feature = "simd"))]
+    pub fn new(x: Float, y: Float, z: Float) -> Point3D {
         Point3D { x, y, z }
     }
 
-    pub fn random(min: f64, max: f64) -> Point3D {
-        let mut rng = rand::thread_rng();
+    #[cfg(feature = "simd")]
+    pub fn new(x: Float, y: Float, z: Float) -> Point3D {
+        Point3D {
+            lanes: Lanes::new([x, y, z, 0.0]),
+        }
+    }
+
+    pub fn random(min: Float, max: Float) -> Point3D {
+        let mut rng = sampler::rng();
         Point3D::new(
             rng.gen_range(min..max),
             rng.gen_range(min..max),
@@ -37,58 +113,179 @@ impl Point3D {
         }
     }
 
-    pub fn x(&self) -> f64 {
+    pub fn random_in_unit_disk() -> Point3D {
+        let mut rng = sampler::rng();
+        loop {
+            let p = Point3D::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            if p.length_squared() < 1.0 {
+                return p;
+            }
+        }
+    }
+
+    // Maps a point of the unit square evenly onto the unit disk in the xy
+    // plane, so samplers that spread points over the square spread them
+    // over the disk too.
+    pub fn disk_from_square(u: (Float, Float)) -> Point3D {
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        Point3D::new(r * phi.cos(), r * phi.sin(), 0.0)
+    }
+
+    #[cfg(not(feature = "simd"))]
+    pub fn x(&self) -> Float {
         self.x
     }
 
-    pub fn y(&self) -> f64 {
+    #[cfg(not(feature = "simd"))]
+    pub fn y(&self) -> Float {
         self.y
     }
 
-    pub fn z(&self) -> f64 {
+    #[cfg(not(feature = "simd"))]
+    pub fn z(&self) -> Float {
         self.z
     }
 
-    pub fn distance(&self, other: &Point3D) -> f64 {
-        let dx = self.x - other.x();
-        let dy = self.y - other.y();
-        let dz = self.z - other.z();
-        (dx * dx + dy * dy + dz * dz).sqrt()
+    #[cfg(feature = "simd")]
+    pub fn x(&self) -> Float {
+        self.lanes.as_array_ref()[0]
+    }
+
+    #[cfg(feature = "simd")]
+    pub fn y(&self) -> Float {
+        self.lanes.as_array_ref()[1]
+    }
+
+    #[cfg(feature = "simd")]
+    pub fn z(&self) -> Float {
+        self.lanes.as_array_ref()[2]
+    }
+
+    pub fn distance(&self, other: &Point3D) -> Float {
+        (*self - *other).length()
     }
 
-    pub fn length_squared(&self) -> f64 {
-        self.x * self.x + self.y * self.y + self.z * self.z
+    pub fn length_squared(&self) -> Float {
+        self.dot(self)
     }
 
-    pub fn length(&self) -> f64 {
-        self.distance(&Point3D::new(0.0, 0.0, 0.0))
+    pub fn length(&self) -> Float {
+        self.length_squared().sqrt()
     }
 
     pub fn unit_vector(&self) -> Point3D {
-        let length = self.length();
-        Point3D::new(self.x / length, self.y / length, self.z / length)
+        *self / self.length()
     }
 
-    pub fn dot(&self, other: &Point3D) -> f64 {
+    #[cfg(not(feature = "simd"))]
+    pub fn dot(&self, other: &Point3D) -> Float {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 
+    #[cfg(feature = "simd")]
+    pub fn dot(&self, other: &Point3D) -> Float {
+        let [x, y, z, _] = (self.lanes * other.lanes).to_array();
+        x + y + z
+    }
+
+    #[cfg(not(feature = "simd"))]
     pub fn cross(&self, other: &Point3D) -> Point3D {
         Point3D::new(
-            self.y * other.z - self.z * other.y,
-            self.z * other.x - self.x * other.z,
-            self.x * other.y - self.y * other.x,
+            self.y() * other.z() - self.z() * other.y(),
+            self.z() * other.x() - self.x() * other.z(),
+            self.x() * other.y() - self.y() * other.x(),
         )
     }
 
+    // Rotating the lanes to do this as whole vectors goes through memory
+    // without AVX and is several times slower than picking them out.
+    #[cfg(feature = "simd")]
+    pub fn cross(&self, other: &Point3D) -> Point3D {
+        let [ax, ay, az, _] = self.lanes.to_array();
+        let [bx, by, bz, _] = other.lanes.to_array();
+        Point3D::new(ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx)
+    }
+
+    #[cfg(not(feature = "simd"))]
     pub fn near_zero(&self) -> bool {
-        self.x.abs() < f64::EPSILON && self.y.abs() < f64::EPSILON && self.z.abs() < f64::EPSILON
+        self.x().abs() < Float::EPSILON
+            && self.y().abs() < Float::EPSILON
+            && self.z().abs() < Float::EPSILON
+    }
+
+    #[cfg(feature = "simd")]
+    pub fn near_zero(&self) -> bool {
+        let small = self.lanes.abs().cmp_lt(Lanes::splat(Float::EPSILON));
+        small.move_mask() & 0b111 == 0b111
+    }
+
+    // Two unit vectors perpendicular to this (unit) vector and to each other.
+    pub fn orthonormal_basis(&self) -> (Point3D, Point3D) {
+        let onb = Onb::new(*self);
+        (onb.u, onb.v)
+    }
+
+    // This direction mirrored about the unit normal `n`.
+    pub fn reflect(&self, n: &Point3D) -> Point3D {
+        *self - *n * (2.0 * self.dot(n))
+    }
+
+    // This unit direction bent through a surface with unit normal `n` by
+    // Snell's law, `eta` being the ratio of the refractive indices either
+    // side (coming from over going into). Where there's total internal
+    // reflection this is meaningless, so check for it first.
+    pub fn refract(&self, n: &Point3D, eta: Float) -> Point3D {
+        let cos_theta = (-*self).dot(n).min(1.0);
+        let r_out_perp = (*self + *n * cos_theta) * eta;
+        let r_out_parallel = *n * (-(1.0 - r_out_perp.length_squared()).abs().sqrt());
+        r_out_perp + r_out_parallel
+    }
+}
+
+// The coordinates by axis, 0 being x, 1 y and 2 z.
+impl Index<usize> for Point3D {
+    type Output = Float;
+
+    #[cfg(not(feature = "simd"))]
+    fn index(&self, axis: usize) -> &Float {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("no axis {} in three dimensions", axis),
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn index(&self, axis: usize) -> &Float {
+        assert!(axis < 3, "no axis {} in three dimensions", axis);
+        &self.lanes.as_array_ref()[axis]
+    }
+}
+
+impl IndexMut<usize> for Point3D {
+    #[cfg(not(feature = "simd"))]
+    fn index_mut(&mut self, axis: usize) -> &mut Float {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("no axis {} in three dimensions", axis),
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn index_mut(&mut self, axis: usize) -> &mut Float {
+        assert!(axis < 3, "no axis {} in three dimensions", axis);
+        &mut self.lanes.as_array_mut()[axis]
     }
 }
 
 impl Add for Point3D {
     type Output = Point3D;
 
+    #[cfg(not(feature = "simd"))]
     fn add(self, other: Point3D) -> Point3D {
         Point3D {
             x: self.x + other.x(),
@@ -96,11 +293,19 @@ impl Add for Point3D {
             z: self.z + other.z(),
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn add(self, other: Point3D) -> Point3D {
+        Point3D {
+            lanes: self.lanes + other.lanes,
+        }
+    }
 }
 
 impl Sub for Point3D {
     type Output = Point3D;
 
+    #[cfg(not(feature = "simd"))]
     fn sub(self, other: Point3D) -> Point3D {
         Point3D {
             x: self.x - other.x(),
@@ -108,11 +313,19 @@ impl Sub for Point3D {
             z: self.z - other.z(),
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn sub(self, other: Point3D) -> Point3D {
+        Point3D {
+            lanes: self.lanes - other.lanes,
+        }
+    }
 }
 
 impl Neg for Point3D {
     type Output = Point3D;
 
+    #[cfg(not(feature = "simd"))]
     fn neg(self) -> Point3D {
         Point3D {
             x: -self.x,
@@ -120,11 +333,17 @@ impl Neg for Point3D {
             z: -self.z,
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn neg(self) -> Point3D {
+        Point3D { lanes: -self.lanes }
+    }
 }
 
 impl Mul<Point3D> for Point3D {
     type Output = Point3D;
 
+    #[cfg(not(feature = "simd"))]
     fn mul(self, other: Point3D) -> Point3D {
         Point3D {
             x: self.x * other.x(),
@@ -132,23 +351,39 @@ impl Mul<Point3D> for Point3D {
             z: self.z * other.z(),
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn mul(self, other: Point3D) -> Point3D {
+        Point3D {
+            lanes: self.lanes * other.lanes,
+        }
+    }
 }
 
-impl Mul<f64> for Point3D {
+impl Mul<Float> for Point3D {
     type Output = Point3D;
 
-    fn mul(self, other: f64) -> Point3D {
+    #[cfg(not(feature = "simd"))]
+    fn mul(self, other: Float) -> Point3D {
         Point3D {
             x: self.x * other,
             y: self.y * other,
             z: self.z * other,
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn mul(self, other: Float) -> Point3D {
+        Point3D {
+            lanes: self.lanes * Lanes::splat(other),
+        }
+    }
 }
 
 impl Div<Point3D> for Point3D {
     type Output = Point3D;
 
+    #[cfg(not(feature = "simd"))]
     fn div(self, other: Point3D) -> Point3D {
         Point3D {
             x: self.x / other.x(),
@@ -156,33 +391,88 @@ impl Div<Point3D> for Point3D {
             z: self.z / other.z(),
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn div(self, other: Point3D) -> Point3D {
+        Point3D {
+            lanes: self.lanes / other.lanes,
+        }
+    }
 }
 
-impl Div<f64> for Point3D {
+impl Div<Float> for Point3D {
     type Output = Point3D;
 
-    fn div(self, other: f64) -> Point3D {
+    #[cfg(not(feature = "simd"))]
+    fn div(self, other: Float) -> Point3D {
         Point3D {
             x: self.x / other,
             y: self.y / other,
             z: self.z / other,
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn div(self, other: Float) -> Point3D {
+        Point3D {
+            lanes: self.lanes / Lanes::splat(other),
+        }
+    }
+}
+
+impl Mul<Point3D> for Float {
+    type Output = Point3D;
+
+    fn mul(self, other: Point3D) -> Point3D {
+        other * self
+    }
+}
+
+impl AddAssign for Point3D {
+    fn add_assign(&mut self, other: Point3D) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Point3D {
+    fn sub_assign(&mut self, other: Point3D) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign<Float> for Point3D {
+    fn mul_assign(&mut self, other: Float) {
+        *self = *self * other;
+    }
+}
+
+impl DivAssign<Float> for Point3D {
+    fn div_assign(&mut self, other: Float) {
+        *self = *self / other;
+    }
+}
+
+impl Sum for Point3D {
+    fn sum<I: Iterator<Item = Point3D>>(iter: I) -> Point3D {
+        iter.fold(Point3D::new(0.0, 0.0, 0.0), |a, b| a + b)
+    }
 }
 
 impl PartialEq for Point3D {
+    #[cfg(not(feature = "simd"))]
+    fn eq(&self, other: &Point3D) -> bool {
+        self.x() == other.x() && self.y() == other.y() && self.z() == other.z()
+    }
+
+    #[cfg(feature = "simd")]
     fn eq(&self, other: &Point3D) -> bool {
-        self.x == other.x() && self.y == other.y() && self.z == other.z()
+        self.lanes.cmp_eq(other.lanes).move_mask() & 0b111 == 0b111
     }
 }
 
 #[test]
 fn test_gen() {
-    let p = Point3D {
-        x: 0.1,
-        y: 0.2,
-        z: 0.3,
-    };
+    let p = Point3D::new(0.1, 0.2, 0.3);
     assert_eq!(p.x(), 0.1);
     assert_eq!(p.y(), 0.2);
     assert_eq!(p.z(), 0.3);
@@ -238,7 +528,7 @@ fn test_div() {
     let q = Point3D::new(0.2, 0.3, 0.4);
     let r = p / q;
     assert_approx_eq!(r.x(), 0.5);
-    assert_approx_eq!(r.y(), 0.6666666666666666);
+    assert_approx_eq!(r.y(), 2.0 / 3.0);
     assert_approx_eq!(r.z(), 0.3 / 0.4);
 }
 
@@ -263,6 +553,13 @@ fn test_random() {
     assert!(p.z() >= -1.0 && p.z() <= 1.0);
 }
 
+#[test]
+fn test_random_in_unit_disk() {
+    let p = Point3D::random_in_unit_disk();
+    assert!(p.length_squared() < 1.0);
+    assert_eq!(p.z(), 0.0);
+}
+
 #[test]
 fn test_near_zero() {
     let p = Point3D::new(0.1, 0.2, 0.3);
@@ -270,3 +567,84 @@ fn test_near_zero() {
     let p = Point3D::new(0.0, 0.0, 0.0);
     assert!(p.near_zero());
 }
+
+#[test]
+fn test_orthonormal_basis() {
+    for n in [
+        Point3D::new(0.0, 0.0, 1.0),
+        Point3D::new(1.0, 0.0, 0.0),
+        Point3D::new(1.0, 2.0, -3.0).unit_vector(),
+    ] {
+        let (t, b) = n.orthonormal_basis();
+        assert_approx_eq!(t.length(), 1.0);
+        assert_approx_eq!(b.length(), 1.0);
+        assert_approx_eq!(t.dot(&n), 0.0);
+        assert_approx_eq!(b.dot(&n), 0.0);
+        assert_approx_eq!(t.dot(&b), 0.0);
+    }
+}
+
+#[test]
+fn test_refract() {
+    let uv = Point3D::new(1.0, 1.0, 0.0);
+    let n = Point3D::new(-1.0, 0.0, 0.0);
+    let etai_over_etat = 1.0;
+    let expected = Point3D::new(0.0, 1.0, 0.0);
+    let actual = uv.refract(&n, etai_over_etat);
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_vector_api() {
+    let mut p = Point3D::new(1.0, 2.0, 3.0);
+    assert_eq!((p[0], p[1], p[2]), (1.0, 2.0, 3.0));
+    p[1] = 5.0;
+    assert_eq!(p, Point3D::new(1.0, 5.0, 3.0));
+    p += Point3D::new(1.0, 1.0, 1.0);
+    p -= Point3D::new(0.0, 0.0, 4.0);
+    p *= 2.0;
+    p /= 4.0;
+    assert_eq!(p, Point3D::new(1.0, 3.0, 0.0));
+    assert_eq!(2.0 * p, p * 2.0);
+    let total: Point3D = [p, p, -p].into_iter().sum();
+    assert_eq!(total, p);
+
+    // Off a floor, and straight through a surface of the same index.
+    let n = Point3D::new(0.0, 1.0, 0.0);
+    let d = Point3D::new(1.0, -1.0, 0.0).unit_vector();
+    assert_eq!(d.reflect(&n), Point3D::new(d.x(), -d.y(), 0.0));
+    let through = d.refract(&n, 1.0);
+    assert_approx_eq!(through.x(), d.x());
+    assert_approx_eq!(through.y(), d.y());
+    // Into glass, bent towards the normal by Snell's law.
+    let bent = d.refract(&n, 1.0 / 1.5);
+    assert_approx_eq!(bent.x() * 1.5, d.x());
+    assert_approx_eq!(bent.length(), 1.0);
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn test_simd_fourth_lane() {
+    // Whatever the lane past z holds, the point is the same point.
+    let p = Point3D::new(1.0, 2.0, 3.0);
+    let stray = Point3D {
+        lanes: Lanes::new([1.0, 2.0, 3.0, 99.0]),
+    };
+    assert_eq!(stray, p);
+    assert_eq!(stray.dot(&stray), 14.0);
+    assert_eq!(stray.length_squared(), p.length_squared());
+    assert_eq!(
+        stray.cross(&Point3D::new(0.0, 0.0, 1.0)),
+        Point3D::new(2.0, -1.0, 0.0)
+    );
+    assert_eq!(
+        serde_json::to_string(&stray).unwrap(),
+        r#"{"x":1.0,"y":2.0,"z":3.0}"#
+    );
+    let origin = Point3D {
+        lanes: Lanes::new([0.0, 0.0, 0.0, 1.0]),
+    };
+    assert!(origin.near_zero());
+    assert_eq!(origin, Point3D::new(0.0, 0.0, 0.0));
+    assert_ne!(origin, Point3D::new(0.0, 0.0, 1e-3));
+}
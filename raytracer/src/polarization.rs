@@ -0,0 +1,285 @@
+use crate::float::Float;
+use crate::materials::reflectance;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::normal_map::NormalMap;
+#[cfg(test)]
+use crate::texture::Texture;
+#[cfg(test)]
+use crate::thin_film::ThinFilm;
+#[cfg(test)]
+use palette::Srgb;
+
+// How a surface changes the polarization of light (a Mueller matrix),
+// acting on Stokes vectors: the intensity, how much more is polarized along
+// the frame's x axis than across it, the same at 45 degrees, and how much is
+// circularly polarized.
+pub type Mueller = [[Float; 4]; 4];
+
+// Letting through `a` of the light polarized along x and `b` of that across
+// it, with `c` the product of their amplitudes.
+fn diattenuator(a: Float, b: Float, c: Float) -> Mueller {
+    [
+        [(a + b) / 2.0, (a - b) / 2.0, 0.0, 0.0],
+        [(a - b) / 2.0, (a + b) / 2.0, 0.0, 0.0],
+        [0.0, 0.0, c, 0.0],
+        [0.0, 0.0, 0.0, c],
+    ]
+}
+
+// What a smooth dielectric surface reflects and lets through of light
+// arriving `cosine` away from the normal, where `eta` is the index of
+// refraction on the far side over the near one; x is across the plane of
+// incidence. All of it is reflected if it can't get through, leaving aside
+// the shift in phase.
+pub fn fresnel(cosine: Float, eta: Float) -> (Mueller, Mueller) {
+    let sin2 = (1.0 - cosine * cosine) / (eta * eta);
+    if sin2 >= 1.0 {
+        return (diattenuator(1.0, 1.0, 1.0), diattenuator(0.0, 0.0, 0.0));
+    }
+    let cos_t = (1.0 - sin2).sqrt();
+    let rs = (cosine - eta * cos_t) / (cosine + eta * cos_t);
+    let rp = (eta * cosine - cos_t) / (eta * cosine + cos_t);
+    let (ts, tp) = (1.0 - rs * rs, 1.0 - rp * rp);
+    (
+        diattenuator(rs * rs, rp * rp, rs * rp),
+        diattenuator(ts, tp, (ts * tp).sqrt()),
+    )
+}
+
+// The material a normal or bump map wraps, and the hit as the map turns its
+// normal, as that is what reflects and refracts the light.
+fn unwrapped<'a>(
+    material: &'a Material,
+    ray: &Ray,
+    hit_record: &HitRecord<'a>,
+) -> (&'a Material, HitRecord<'a>) {
+    match material {
+        Material::NormalMap(n) => unwrapped(&n.material, ray, &n.shade(ray, hit_record)),
+        Material::BumpMap(b) => unwrapped(&b.material, ray, &b.shade(ray, hit_record)),
+        _ => (material, *hit_record),
+    }
+}
+
+// How light coming off `material` along `ray` reversed, having arrived along
+// `scattered`, is polarized, and how much of unpolarized light the path's
+// throughput already takes to go that way: the chance `scatter` had of
+// picking it, or for a film what it weighs the way by. None if the light
+// leaving is unpolarized whatever arrives.
+fn mueller(
+    material: &Material,
+    ray: &Ray,
+    hit_record: &HitRecord,
+    scattered: &Ray,
+) -> Option<(Mueller, Float)> {
+    let cosine = (-ray.direction.unit_vector())
+        .dot(&hit_record.normal)
+        .min(1.0);
+    let reflected = scattered.direction.dot(&hit_record.normal) > 0.0;
+    match material {
+        Material::Glass(glass) => {
+            let index = glass.index_of_refraction();
+            let ratio = if hit_record.front_face {
+                1.0 / index
+            } else {
+                index
+            };
+            let (reflection, transmission) = fresnel(cosine, 1.0 / ratio);
+            // As Glass decides between the two.
+            let chance = if ratio * (1.0 - cosine * cosine).sqrt() > 1.0 {
+                1.0
+            } else {
+                reflectance(cosine, ratio)
+            };
+            Some(if reflected {
+                (reflection, chance)
+            } else {
+                (transmission, 1.0 - chance)
+            })
+        }
+        // A film over glass reflects and lets through as its interference
+        // says, at one wavelength, leaving aside the shift in phase.
+        Material::ThinFilm(film) if matches!(*film.material, Material::Glass(_)) => {
+            let (rs, rp) = film.polarized_reflectance(cosine, hit_record);
+            let (ts, tp) = (1.0 - rs, 1.0 - rp);
+            let reflectance = (rs + rp) / 2.0;
+            Some(if reflected {
+                (diattenuator(rs, rp, (rs * rp).sqrt()), reflectance)
+            } else {
+                (diattenuator(ts, tp, (ts * tp).sqrt()), 1.0 - reflectance)
+            })
+        }
+        // A mirror reflects both ways alike, turning the handedness; a fuzzy
+        // one is taken to as well.
+        Material::Metal(_) => Some((diattenuator(1.0, 1.0, -1.0), 1.0)),
+        _ => None,
+    }
+}
+
+// How the light reaching the camera along a path depends on how it is
+// polarized, followed back from the camera: a Stokes vector that, dotted
+// with the light's, gives how much of it the camera sees, in a frame whose x
+// axis is `axis`, across the path. Kept relative to what the camera sees of
+// unpolarized light, which the path's throughput takes care of.
+#[derive(Debug, Clone, Copy)]
+pub struct Polarization {
+    stokes: [Float; 4],
+    axis: Point3D,
+}
+
+impl Polarization {
+    // What the camera sees along `direction` through a linear polarizer
+    // turned `filter` degrees from `right`: light polarized that way, and
+    // half of unpolarized light. Without one, all light alike. Also gives
+    // the share of unpolarized light seen.
+    pub fn camera(
+        filter: Option<Float>,
+        right: &Point3D,
+        direction: &Point3D,
+    ) -> (Polarization, f32) {
+        let d = direction.unit_vector();
+        let mut axis = *right - d * right.dot(&d);
+        if axis.length_squared() < 1e-12 {
+            // Looking along right, as an all-round camera can.
+            let other = if d.x().abs() < 0.9 {
+                Point3D::new(1.0, 0.0, 0.0)
+            } else {
+                Point3D::new(0.0, 1.0, 0.0)
+            };
+            axis = d.cross(&other);
+        }
+        let axis = axis.unit_vector();
+        match filter {
+            Some(angle) => {
+                let (sin, cos) = (2.0 * angle.to_radians()).sin_cos();
+                let stokes = [1.0, cos, sin, 0.0];
+                (Polarization { stokes, axis }, 0.5)
+            }
+            None => {
+                let stokes = [1.0, 0.0, 0.0, 0.0];
+                (Polarization { stokes, axis }, 1.0)
+            }
+        }
+    }
+
+    // The Stokes vector in a frame turned to `axis`, across `direction`.
+    fn turned(&self, axis: &Point3D, direction: &Point3D) -> [Float; 4] {
+        let angle = direction
+            .cross(&self.axis)
+            .dot(axis)
+            .atan2(self.axis.dot(axis));
+        let (sin, cos) = (2.0 * angle).sin_cos();
+        let [s0, s1, s2, s3] = self.stokes;
+        [s0, s1 * cos + s2 * sin, s2 * cos - s1 * sin, s3]
+    }
+
+    // Follows the path back through `material` scattering `ray` into
+    // `scattered`, into the plane of incidence. Gives how much more of the
+    // light found further along the camera sees than the throughput says,
+    // or None once the light there is unpolarized and it no longer matters.
+    pub fn scatter(
+        &mut self,
+        material: &Material,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        scattered: &Ray,
+    ) -> Option<Float> {
+        let (material, hit_record) = unwrapped(material, ray, hit_record);
+        let (mueller, chance) = mueller(material, ray, &hit_record, scattered)?;
+        let direction = ray.direction.unit_vector();
+        let across = direction.cross(&hit_record.normal);
+        // Head on, any frame will do.
+        let axis = if across.length_squared() < 1e-12 {
+            self.axis
+        } else {
+            across.unit_vector()
+        };
+        let stokes = self.turned(&axis, &direction);
+        let mut next = [0.0; 4];
+        for (j, n) in next.iter_mut().enumerate() {
+            *n = (0..4).map(|i| stokes[i] * mueller[i][j]).sum();
+        }
+        let seen = next[0];
+        if seen > 0.0 {
+            self.stokes = next.map(|s| s / seen);
+        }
+        self.axis = axis;
+        Some(seen / chance)
+    }
+}
+
+#[test]
+fn test_polarization() {
+    // At Brewster's angle glass reflects only light polarized across the
+    // plane of incidence.
+    let brewster = Float::atan(1.5);
+    let (reflection, transmission) = fresnel(brewster.cos(), 1.5);
+    assert!((reflection[0][0] - reflection[0][1]).abs() < 1e-6);
+    assert!(reflection[0][0] > 0.05);
+    assert!((transmission[0][0] + reflection[0][0] - 1.0).abs() < 1e-6);
+    let (total, _) = fresnel(0.1, 1.0 / 1.5);
+    assert_eq!(total[0][0], 1.0);
+
+    // Seen there off a window, the glare is all let through a filter
+    // turned along the glass, and none through one turned across it.
+    let material = Material::Glass(Glass::new(1.5));
+    let hit = HitRecord {
+        t: 1.0,
+        point: Point3D::new(0.0, 0.0, 0.0),
+        normal: Point3D::new(0.0, 1.0, 0.0),
+        front_face: true,
+        material: &material,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    let direction = Point3D::new(brewster.sin(), -brewster.cos(), 0.0);
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0) - direction, direction);
+    let mirrored = Ray::new(hit.point, direction.reflect(&hit.normal));
+    let right = Point3D::new(0.0, 0.0, 1.0);
+    let glare = |filter| {
+        let (mut polarization, share) = Polarization::camera(filter, &right, &direction);
+        let seen = polarization
+            .scatter(&material, &ray, &hit, &mirrored)
+            .unwrap();
+        // Picked as often as Schlick's approximation says.
+        Float::from(share) * seen * reflectance(brewster.cos(), 1.0 / 1.5)
+    };
+    let unfiltered = glare(None);
+    assert!((unfiltered - reflection[0][0]).abs() < 1e-6);
+    assert!((glare(Some(0.0)) - unfiltered).abs() < 1e-6);
+    assert!(glare(Some(90.0)).abs() < 1e-6);
+    assert!((glare(Some(45.0)) - unfiltered / 2.0).abs() < 1e-6);
+
+    // Glass under a flat normal map polarizes as it would bare. So does a
+    // film that adds nothing to it, though its weight already has the
+    // reflectance for unpolarized light in it.
+    let seen = |material: &Material, filter| {
+        let (mut polarization, share) = Polarization::camera(filter, &right, &direction);
+        let seen = polarization
+            .scatter(material, &ray, &hit, &mirrored)
+            .unwrap();
+        Float::from(share) * seen
+    };
+    let flat = Texture::Solid(Srgb::new(0.5, 0.5, 1.0));
+    let mapped = Material::NormalMap(NormalMap::new(flat, material.clone()));
+    for filter in [Some(0.0), Some(45.0), Some(90.0)] {
+        assert!((seen(&mapped, filter) - seen(&material, filter)).abs() < 1e-6);
+    }
+    let filmed = Material::ThinFilm(ThinFilm::new(0.0, 1.5, material.clone()));
+    assert!((seen(&filmed, Some(0.0)) - 1.0).abs() < 1e-6);
+    assert!(seen(&filmed, Some(90.0)).abs() < 1e-6);
+
+    // A matte surface leaves the light unpolarized.
+    let matte = Material::grey();
+    let (mut polarization, _) = Polarization::camera(Some(0.0), &right, &direction);
+    assert!(polarization
+        .scatter(&matte, &ray, &hit, &mirrored)
+        .is_none());
+}
@@ -0,0 +1,123 @@
+use crate::float::consts::PI;
+use crate::float::Float;
+use crate::float::ROUNDING;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// The real roots of c[0] + c[1] x + c[2] x^2 + c[3] x^3 + c[4] x^4 (with
+// c[4] not 0), by Ferrari's method, each polished by Newton's method. The
+// first of the returned values are the roots, however many there are.
+pub fn solve_quartic(c: [Float; 5]) -> ([Float; 4], usize) {
+    // x^4 + a x^3 + b x^2 + c x + d.
+    let (a, b, cc, d) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+    // Without the cubic term, in y = x + a / 4: y^4 + p y^2 + q y + r.
+    let a2 = a * a;
+    let p = b - 3.0 / 8.0 * a2;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 / 256.0 * a2 * a2;
+
+    let mut roots = [0.0; 4];
+    let mut n = 0;
+    if r.abs() < ROUNDING {
+        // y (y^3 + p y + q) = 0.
+        roots[0] = 0.0;
+        n = 1;
+        let (cubic, m) = solve_cubic(p, q);
+        roots[1..1 + m].copy_from_slice(&cubic[..m]);
+        n += m;
+    } else {
+        // One root z of the resolvent cubic splits the quartic into two
+        // quadratics.
+        let (cubic, _) = solve_cubic_monic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0);
+        let z = cubic[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -ROUNDING || v < -ROUNDING {
+            return ([0.0; 4], 0);
+        }
+        let u = u.max(0.0).sqrt();
+        let v = if q < 0.0 {
+            -v.max(0.0).sqrt()
+        } else {
+            v.max(0.0).sqrt()
+        };
+        for (linear, constant) in [(v, z - u), (-v, z + u)] {
+            let discriminant = linear * linear - 4.0 * constant;
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                roots[n] = (-linear - sqrtd) / 2.0;
+                roots[n + 1] = (-linear + sqrtd) / 2.0;
+                n += 2;
+            }
+        }
+    }
+
+    let f = |x: Float| (((x + a) * x + b) * x + cc) * x + d;
+    let df = |x: Float| ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + cc;
+    for root in &mut roots[..n] {
+        let mut x = *root - a / 4.0;
+        for _ in 0..2 {
+            let slope = df(x);
+            if slope == 0.0 {
+                break;
+            }
+            x -= f(x) / slope;
+        }
+        *root = x;
+    }
+    (roots, n)
+}
+
+// The real roots of y^3 + p y + q.
+fn solve_cubic(p: Float, q: Float) -> ([Float; 3], usize) {
+    let mut roots = [0.0; 3];
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    if discriminant > 0.0 {
+        // One real root (Cardano).
+        let s = discriminant.sqrt();
+        roots[0] = (-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt();
+        (roots, 1)
+    } else if p == 0.0 {
+        (roots, 1)
+    } else {
+        // Three real roots (the trigonometric method).
+        let m = 2.0 * (-p / 3.0).sqrt();
+        let theta = (3.0 * q / (p * m)).clamp(-1.0, 1.0).acos() / 3.0;
+        for (k, root) in roots.iter_mut().enumerate() {
+            *root = m * (theta - 2.0 * PI * k as Float / 3.0).cos();
+        }
+        (roots, 3)
+    }
+}
+
+// The real roots of x^3 + a x^2 + b x + c, the largest first.
+fn solve_cubic_monic(a: Float, b: Float, c: Float) -> ([Float; 3], usize) {
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let (mut roots, n) = solve_cubic(p, q);
+    for root in &mut roots[..n] {
+        *root -= a / 3.0;
+    }
+    roots[..n].sort_by(|x, y| y.total_cmp(x));
+    (roots, n)
+}
+
+#[test]
+fn test_solve_quartic() {
+    // (x - 1)(x + 2)(x - 3)(x + 0.5) and (x^2 + 1)(x - 2)(x - 4).
+    for (coefficients, expected) in [
+        ([3.0, 3.5, -6.0, -1.5, 1.0], vec![-2.0, -0.5, 1.0, 3.0]),
+        ([8.0, -6.0, 9.0, -6.0, 1.0], vec![2.0, 4.0]),
+    ] {
+        let (roots, n) = solve_quartic(coefficients);
+        let mut roots = roots[..n].to_vec();
+        roots.sort_by(Float::total_cmp);
+        assert_eq!(roots.len(), expected.len());
+        for (root, expected) in roots.iter().zip(expected) {
+            assert_approx_eq!(root, expected);
+        }
+    }
+    // x^4 + 1 has none.
+    assert_eq!(solve_quartic([1.0, 0.0, 0.0, 0.0, 1.0]).1, 0);
+}
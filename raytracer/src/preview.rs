@@ -0,0 +1,321 @@
+use crossterm::cursor::MoveTo;
+use crossterm::cursor::MoveToNextLine;
+use crossterm::event;
+use crossterm::event::DisableMouseCapture;
+use crossterm::event::EnableMouseCapture;
+use crossterm::event::Event;
+use crossterm::event::KeyCode;
+use crossterm::event::KeyEvent;
+use crossterm::event::KeyEventKind;
+use crossterm::event::KeyModifiers;
+use crossterm::event::MouseButton;
+use crossterm::event::MouseEvent;
+use crossterm::event::MouseEventKind;
+use crossterm::execute;
+use crossterm::queue;
+use crossterm::style::Color;
+use crossterm::style::Print;
+use crossterm::style::ResetColor;
+use crossterm::style::SetBackgroundColor;
+use crossterm::style::SetForegroundColor;
+use crossterm::terminal;
+use crossterm::terminal::Clear;
+use crossterm::terminal::ClearType;
+use crossterm::Command;
+use std::io::Write as _;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::camera::Navigation;
+use crate::film;
+use crate::film::Tile;
+use crate::float::Float;
+use crate::output::Encoding;
+use crate::sampler::Estimate;
+
+#[cfg(test)]
+use palette::Srgb;
+
+// Drawing the whole picture is slow enough that it must not happen for
+// every tile.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+// A live view of the image as it renders, drawn in the terminal: each
+// character cell is an upper half block coloured with one pixel in front and
+// the one below it behind, in 24-bit colour.
+pub struct Preview {
+    width: usize,
+    height: usize,
+    columns: usize,
+    encoding: Encoding,
+    pixels: Mutex<Vec<[u8; 3]>>,
+    drawn: Mutex<Option<Instant>>,
+}
+
+impl Preview {
+    // A preview of a `width` x `height` image, scaled down to fit in
+    // `columns` characters, with colours encoded as for the output image.
+    pub fn new(width: usize, height: usize, columns: usize, encoding: Encoding) -> Preview {
+        Preview {
+            width,
+            height,
+            columns: columns.clamp(1, width.max(1)),
+            encoding,
+            pixels: Mutex::new(vec![[0; 3]; width * height]),
+            drawn: Mutex::new(None),
+        }
+    }
+
+    // Takes in a freshly rendered tile, redrawing the terminal if it hasn't
+    // been for a while. Meant to be the renderer's tile callback.
+    pub fn update(&self, tile: &Tile, estimates: &[Estimate]) {
+        self.store(tile, estimates);
+        let due = self
+            .drawn
+            .lock()
+            .unwrap()
+            .is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL);
+        if due {
+            self.draw();
+        }
+    }
+
+    fn store(&self, tile: &Tile, estimates: &[Estimate]) {
+        let mut pixels = self.pixels.lock().unwrap();
+        for (i, estimate) in estimates.iter().enumerate() {
+            let x = tile.x + i % tile.width;
+            let y = tile.y + i / tile.width;
+            pixels[y * self.width + x] = film::to_rgb8(estimate, &self.encoding);
+        }
+    }
+
+    // Draws the image as it is now, over the previous drawing.
+    pub fn draw(&self) {
+        let mut drawn = self.drawn.lock().unwrap();
+        let mut stderr = std::io::stderr().lock();
+        if drawn.is_none() {
+            // Clear the screen the first time.
+            queue!(stderr, Clear(ClearType::All)).ok();
+        }
+        queue!(stderr, MoveTo(0, 0), Print(self.frame())).ok();
+        stderr.flush().ok();
+        *drawn = Some(Instant::now());
+    }
+
+    // The image as lines of coloured characters.
+    fn frame(&self) -> String {
+        let pixels = self.pixels.lock().unwrap();
+        // Pixels per character, across and (half of it) down.
+        let scale = self.width as Float / self.columns as Float;
+        let rows = (self.height as Float / scale / 2.0).ceil() as usize;
+        let mut frame = String::new();
+        for row in 0..rows {
+            for column in 0..self.columns {
+                let x = (column as Float * scale) as usize;
+                let top = ((2 * row) as Float * scale) as usize;
+                let bottom = ((2 * row + 1) as Float * scale) as usize;
+                let [r, g, b] = pixels[top.min(self.height - 1) * self.width + x];
+                SetForegroundColor(Color::Rgb { r, g, b })
+                    .write_ansi(&mut frame)
+                    .unwrap();
+                let [r, g, b] = if bottom < self.height {
+                    pixels[bottom * self.width + x]
+                } else {
+                    [0; 3]
+                };
+                SetBackgroundColor(Color::Rgb { r, g, b })
+                    .write_ansi(&mut frame)
+                    .unwrap();
+                frame.push('\u{2580}');
+            }
+            // The terminal may be in raw mode, where a newline doesn't go
+            // back to the start of the line.
+            ResetColor.write_ansi(&mut frame).unwrap();
+            MoveToNextLine(1).write_ansi(&mut frame).unwrap();
+        }
+        frame
+    }
+}
+
+// W and S travel forwards and back, A and D left and right, R and F up and
+// down; the arrow keys orbit; Q or Enter finishes. With the mouse, dragging
+// with the left button orbits, as if turning the scene round by hand, and
+// with another button pans; the wheel travels forwards and back. `dragged`
+// is where the mouse was at the last event of the drag going on, if any.
+pub fn navigation(event: &Event, dragged: &mut Option<(u16, u16)>) -> Option<Navigation> {
+    if let Event::Mouse(MouseEvent {
+        kind, column, row, ..
+    }) = *event
+    {
+        // Pressing a button starts a drag from there; moving without one,
+        // or letting go, ends it.
+        let (x, y) = match kind {
+            MouseEventKind::Down(_) => {
+                *dragged = Some((column, row));
+                return None;
+            }
+            MouseEventKind::Drag(_) => dragged.replace((column, row)).unwrap_or((column, row)),
+            MouseEventKind::ScrollUp => return Some(Navigation::Travel(1.0, 0.0, 0.0)),
+            MouseEventKind::ScrollDown => return Some(Navigation::Travel(-1.0, 0.0, 0.0)),
+            _ => {
+                *dragged = None;
+                return None;
+            }
+        };
+        let (dx, dy) = (column as Float - x as Float, row as Float - y as Float);
+        if dx == 0.0 && dy == 0.0 {
+            return None;
+        }
+        return Some(if kind == MouseEventKind::Drag(MouseButton::Left) {
+            Navigation::Orbit(-dx, dy)
+        } else {
+            Navigation::Travel(0.0, -dx, dy)
+        });
+    }
+    let Event::Key(KeyEvent {
+        code,
+        modifiers,
+        kind: KeyEventKind::Press | KeyEventKind::Repeat,
+        ..
+    }) = event
+    else {
+        return None;
+    };
+    let navigation = match code {
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Navigation::Cancel,
+        KeyCode::Up => Navigation::Orbit(0.0, 1.0),
+        KeyCode::Down => Navigation::Orbit(0.0, -1.0),
+        KeyCode::Right => Navigation::Orbit(1.0, 0.0),
+        KeyCode::Left => Navigation::Orbit(-1.0, 0.0),
+        KeyCode::Enter => Navigation::Finish,
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            'w' => Navigation::Travel(1.0, 0.0, 0.0),
+            's' => Navigation::Travel(-1.0, 0.0, 0.0),
+            'd' => Navigation::Travel(0.0, 1.0, 0.0),
+            'a' => Navigation::Travel(0.0, -1.0, 0.0),
+            'r' => Navigation::Travel(0.0, 0.0, 1.0),
+            'f' => Navigation::Travel(0.0, 0.0, -1.0),
+            'q' => Navigation::Finish,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(navigation)
+}
+
+// Key presses and mouse moves, as they happen. The terminal is put into raw
+// mode, passing keys on straight away without echoing them, and reports the
+// mouse, until the returned guard is dropped. When the terminal can't be read, the last thing sent is Finish.
+pub fn read_keys() -> (Receiver<Navigation>, RawTerminal) {
+    let terminal = RawTerminal::new();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut dragged = None;
+        while let Ok(event) = event::read() {
+            if let Some(navigation) = navigation(&event, &mut dragged) {
+                if sender.send(navigation).is_err() {
+                    return;
+                }
+            }
+        }
+        sender.send(Navigation::Finish).ok();
+    });
+    (receiver, terminal)
+}
+
+pub struct RawTerminal {
+    raw: bool,
+}
+
+impl RawTerminal {
+    fn new() -> RawTerminal {
+        let raw = terminal::enable_raw_mode().is_ok();
+        if raw {
+            execute!(std::io::stderr(), EnableMouseCapture).ok();
+        }
+        RawTerminal { raw }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if self.raw {
+            execute!(std::io::stderr(), DisableMouseCapture).ok();
+            terminal::disable_raw_mode().ok();
+        }
+    }
+}
+
+#[test]
+fn test_navigation() {
+    let key = |code, modifiers| navigation(&Event::Key(KeyEvent::new(code, modifiers)), &mut None);
+    let none = KeyModifiers::NONE;
+    assert_eq!(
+        key(KeyCode::Char('W'), KeyModifiers::SHIFT),
+        Some(Navigation::Travel(1.0, 0.0, 0.0))
+    );
+    assert_eq!(key(KeyCode::Right, none), Some(Navigation::Orbit(1.0, 0.0)));
+    assert_eq!(key(KeyCode::Char('x'), none), None);
+    assert_eq!(key(KeyCode::Char('q'), none), Some(Navigation::Finish));
+    assert_eq!(
+        key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+        Some(Navigation::Cancel)
+    );
+
+    // A drag two cells right and one down with the left button turns the
+    // scene the same way; with the right button it moves it.
+    let mouse = |kind, column, row| {
+        Event::Mouse(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        })
+    };
+    let mut dragged = None;
+    let left = MouseButton::Left;
+    assert_eq!(
+        navigation(&mouse(MouseEventKind::Down(left), 10, 5), &mut dragged),
+        None
+    );
+    assert_eq!(
+        navigation(&mouse(MouseEventKind::Drag(left), 12, 6), &mut dragged),
+        Some(Navigation::Orbit(-2.0, 1.0))
+    );
+    let right = MouseButton::Right;
+    assert_eq!(
+        navigation(&mouse(MouseEventKind::Drag(right), 13, 6), &mut dragged),
+        Some(Navigation::Travel(0.0, -1.0, 0.0))
+    );
+    assert_eq!(
+        navigation(&mouse(MouseEventKind::Up(right), 13, 6), &mut dragged),
+        None
+    );
+    assert_eq!(dragged, None);
+    assert_eq!(
+        navigation(&mouse(MouseEventKind::ScrollUp, 0, 0), &mut dragged),
+        Some(Navigation::Travel(1.0, 0.0, 0.0))
+    );
+}
+#[test]
+fn test_preview_frame() {
+    // Two characters across and one down for a 4x4 image.
+    let preview = Preview::new(4, 4, 2, Encoding::default());
+    let mut white = Estimate::default();
+    white.add(Srgb::new(1.0, 1.0, 1.0));
+    let tile = Tile {
+        x: 2,
+        y: 0,
+        width: 2,
+        height: 1,
+    };
+    preview.store(&tile, &[white, white]);
+    // The right character has white above black; the left one is all black.
+    let black = "\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m\u{2580}";
+    let right = "\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2580}";
+    assert_eq!(preview.frame(), format!("{}{}\x1b[0m\x1b[1E", black, right));
+}
@@ -0,0 +1,197 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::materials::roughness_to_alpha;
+use crate::materials::sample_ggx;
+use crate::materials::Glass;
+use crate::materials::Scatterable;
+use crate::onb::Onb;
+use crate::ray::HitRecord;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::texture::Texture;
+
+#[cfg(test)]
+use crate::materials::Material;
+#[cfg(test)]
+use crate::point3d::Point3D;
+
+// Roughness of the clearcoat layer, which is always fairly glossy.
+const CLEARCOAT_ROUGHNESS: Float = 0.1;
+
+fn default_specular() -> Float {
+    0.5
+}
+
+fn default_ior() -> Float {
+    1.5
+}
+
+// One material covering most real surfaces, driven by the familiar PBR
+// parameters (base color, metallic, roughness) plus optional layers. All
+// parameters are in [0, 1]; `base_color`, `metallic` and `roughness` map
+// directly from glTF's metallic-roughness model.
+//
+// Each scatter picks one layer at random in proportion to its weight, so
+// the material is a mixture of:
+// - a clearcoat: a colorless, glossy GGX layer on top, weighted by `clearcoat`;
+// - for `metallic`: GGX reflection tinted by the base color;
+// - for `transmission`: glass tinted by the base color;
+// - otherwise a dielectric whose specular GGX reflection (strength
+//   `specular`) sits over a diffuse base, with `sheen` adding a soft white
+//   rim at grazing angles like on cloth.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Principled {
+    pub base_color: Texture,
+    pub metallic: Float,
+    pub roughness: Float,
+    #[serde(default = "default_specular")]
+    pub specular: Float,
+    #[serde(default)]
+    pub sheen: Float,
+    #[serde(default)]
+    pub clearcoat: Float,
+    #[serde(default)]
+    pub transmission: Float,
+    #[serde(default = "default_ior")]
+    pub ior: Float,
+}
+
+impl Principled {
+    pub fn new(base_color: Srgb, metallic: Float, roughness: Float) -> Principled {
+        Principled {
+            base_color: Texture::Solid(base_color),
+            metallic,
+            roughness,
+            specular: default_specular(),
+            sheen: 0.0,
+            clearcoat: 0.0,
+            transmission: 0.0,
+            ior: default_ior(),
+        }
+    }
+
+    fn sample_diffuse(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        base: Srgb,
+    ) -> Option<(Option<Ray>, Srgb)> {
+        let direction = Onb::new(hit_record.normal).cosine_direction(sampler::next_2d());
+        let cos_o = hit_record
+            .normal
+            .dot(&-ray.direction.unit_vector())
+            .clamp(0.0, 1.0);
+        let sheen = to_f32(self.sheen * (1.0 - cos_o).powi(5));
+        Some((
+            Some(Ray::with_time(hit_record.point, direction, ray.time)),
+            Srgb::new(base.red + sheen, base.green + sheen, base.blue + sheen),
+        ))
+    }
+}
+
+impl Scatterable for Principled {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let base = self
+            .base_color
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        let alpha = roughness_to_alpha(self.roughness);
+
+        // Layers over the base are chosen by how much they reflect at this
+        // angle (Schlick's Fresnel), which accounts for Fresnel in the
+        // sample weight.
+        let cos_o = hit_record
+            .normal
+            .dot(&-ray.direction.unit_vector())
+            .clamp(0.0, 1.0);
+        let schlick = |f0: Float| f0 + (1.0 - f0) * (1.0 - cos_o).powi(5);
+        let white = Srgb::new(1.0, 1.0, 1.0);
+
+        if sampler::next_1d() < self.clearcoat * schlick(0.04) {
+            let clearcoat_alpha = roughness_to_alpha(CLEARCOAT_ROUGHNESS);
+            return sample_ggx(ray, hit_record, clearcoat_alpha, white);
+        }
+        if sampler::next_1d() < self.metallic {
+            return sample_ggx(ray, hit_record, alpha, base);
+        }
+        if sampler::next_1d() < self.transmission {
+            let (ray, weight) = Glass::new(self.ior).scatter(ray, hit_record)?;
+            return Some((
+                ray,
+                Srgb::new(
+                    weight.red * base.red,
+                    weight.green * base.green,
+                    weight.blue * base.blue,
+                ),
+            ));
+        }
+
+        if sampler::next_1d() < schlick(0.08 * self.specular) {
+            return sample_ggx(ray, hit_record, alpha, white);
+        }
+        self.sample_diffuse(ray, hit_record, base)
+    }
+}
+
+#[cfg(test)]
+fn scatter_many(material: &Material, ray: &Ray, normal: Point3D) -> Vec<(Ray, Srgb)> {
+    let hit = HitRecord {
+        t: 1.0,
+        point: Point3D::new(0.0, 0.0, 0.0),
+        normal,
+        front_face: true,
+        material,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    (0..500)
+        .filter_map(|_| match material.scatter(ray, &hit) {
+            Some((Some(r), a)) => Some((r, a)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_principled_lobes() {
+    let n = Point3D::new(0.0, 0.0, 1.0);
+    let ray = Ray::new(Point3D::new(-1.0, 0.0, 1.0), Point3D::new(1.0, 0.0, -1.0));
+    let red = Srgb::new(0.9, 0.1, 0.1);
+
+    // A polished metal is a red mirror.
+    let metal = Material::Principled(Principled::new(red, 1.0, 0.0));
+    for (r, a) in scatter_many(&metal, &ray, n) {
+        let d = r.direction.unit_vector();
+        assert!((d - Point3D::new(1.0, 0.0, 1.0).unit_vector()).length() < 1e-3);
+        assert!(a.red > a.green);
+    }
+
+    // Fully transmissive glass sends some rays through the surface.
+    let mut glass = Principled::new(red, 0.0, 0.0);
+    glass.transmission = 1.0;
+    let glass = Material::Principled(glass);
+    assert!(scatter_many(&glass, &ray, n)
+        .iter()
+        .any(|(r, _)| r.direction.dot(&n) < 0.0));
+
+    // Without a specular layer the rest is plain diffuse.
+    let mut matte = Principled::new(red, 0.0, 1.0);
+    matte.specular = 0.0;
+    let matte = Material::Principled(matte);
+    let samples = scatter_many(&matte, &ray, n);
+    assert!(samples.iter().all(|(r, _)| r.direction.dot(&n) >= 0.0));
+    assert!(samples.iter().any(|(_, a)| *a == red));
+}
+
+#[test]
+fn test_to_json() {
+    // Only the glTF parameters are required.
+    let json = r#"{"base_color":[0.8,0.8,0.8],"metallic":1.0,"roughness":0.3}"#;
+    let p = serde_json::from_str::<Principled>(json).unwrap();
+    assert_eq!(p.specular, 0.5);
+    assert_eq!(p.clearcoat, 0.0);
+    assert_eq!(p.ior, 1.5);
+}
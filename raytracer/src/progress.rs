@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::camera::StereoLayout;
+use crate::config::Config;
+use crate::film::Tile;
+use crate::sampler::Adaptive;
+use crate::sampler::Estimate;
+
+#[cfg(test)]
+use palette::Srgb;
+
+// Often enough to look alive, seldom enough not to cost anything.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+// Characters in the bar itself.
+const BAR_WIDTH: usize = 30;
+
+// How far a render has got, drawn as one line on the terminal: a bar, the
+// percentage done, the time so far and the time it should still take, and
+// how many samples a second are being taken. Tiles finish in any order and
+// any number of times (once per pass when rendering progressively), so what
+// is done is worked out from each tile's pixels, in samples.
+pub struct Progress {
+    sampling: Adaptive,
+    // Samples in the whole render, if every pixel took all it may.
+    total: u64,
+    start: Instant,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    // Work done and samples taken in each tile, by its corner, as of the
+    // last time it was seen.
+    tiles: HashMap<(usize, usize), (u64, u64)>,
+    work: u64,
+    samples: u64,
+    drawn: Option<Instant>,
+    finished: bool,
+}
+
+impl Progress {
+    // Progress through `views` images of `pixels` pixels, each sampled as
+    // `sampling` says.
+    pub fn new(pixels: usize, views: usize, sampling: Adaptive) -> Progress {
+        Progress {
+            sampling,
+            total: (pixels * views) as u64 * sampling.max_samples as u64,
+            start: Instant::now(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    // Progress through rendering `scene`, both eyes of it if they go to
+    // separate files.
+    pub fn for_scene(scene: &Config) -> Progress {
+        let views = match scene.camera.stereo() {
+            Some(stereo) if stereo.layout == StereoLayout::Files => 2,
+            _ => 1,
+        };
+        Progress::new(scene.width * scene.height, views, scene.sampling())
+    }
+
+    // Takes in a freshly rendered tile, redrawing the line if it hasn't been
+    // for a while. Meant to be the renderer's tile callback.
+    pub fn update(&self, tile: &Tile, estimates: &[Estimate]) {
+        // A pixel that needs no more samples is as good as finished.
+        let work = estimates
+            .iter()
+            .map(|e| {
+                if self.sampling.is_done(e) {
+                    self.sampling.max_samples as u64
+                } else {
+                    e.count() as u64
+                }
+            })
+            .sum::<u64>();
+        let samples = estimates.iter().map(|e| e.count() as u64).sum::<u64>();
+
+        let mut state = self.state.lock().unwrap();
+        let (last_work, last_samples) = state
+            .tiles
+            .insert((tile.x, tile.y), (work, samples))
+            .unwrap_or_default();
+        if samples < last_samples {
+            // Fewer samples than before: the tile is being rendered again
+            // from nothing, for the other eye.
+            state.work += work;
+            state.samples += samples;
+        } else {
+            state.work += work.saturating_sub(last_work);
+            state.samples += samples - last_samples;
+        }
+
+        let finished = state.work >= self.total;
+        let due = state
+            .drawn
+            .is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL);
+        if state.finished || !(due || finished) {
+            return;
+        }
+        let line = self.line(state.work, state.samples, self.start.elapsed());
+        // Clearing to the end of the line covers a longer line drawn before.
+        let mut stderr = std::io::stderr().lock();
+        write!(stderr, "\r{}\x1b[K", line).ok();
+        if finished {
+            writeln!(stderr).ok();
+        }
+        stderr.flush().ok();
+        state.drawn = Some(Instant::now());
+        state.finished = finished;
+    }
+
+    // The line to show once `work` of the total is done, `samples` taken,
+    // `elapsed` after starting.
+    fn line(&self, work: u64, samples: u64, elapsed: Duration) -> String {
+        let fraction = (work as f64 / self.total.max(1) as f64).min(1.0);
+        let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+        let seconds = elapsed.as_secs_f64();
+        // Assuming the rest goes as fast as what has been done so far.
+        let left = if fraction > 0.0 {
+            format_duration(seconds * (1.0 - fraction) / fraction)
+        } else {
+            "--:--".to_string()
+        };
+        let rate = if seconds > 0.0 {
+            samples as f64 / seconds
+        } else {
+            0.0
+        };
+        format!(
+            "[{}{}] {:5.1}%  {} elapsed  {} left  {:.2}M samples/s",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            fraction * 100.0,
+            format_duration(seconds),
+            left,
+            rate / 1e6
+        )
+    }
+}
+
+// As m:ss, or h:mm:ss from an hour on.
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[test]
+fn test_progress() {
+    let sampling = Adaptive {
+        min_samples: 4,
+        max_samples: 4,
+        threshold: 0.0,
+    };
+    let progress = Progress::new(4, 2, sampling);
+    let tile = Tile {
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 2,
+    };
+    let sampled = |n| {
+        let mut estimate = Estimate::default();
+        (0..n).for_each(|_| estimate.add(Srgb::new(0.5, 0.5, 0.5)));
+        vec![estimate; 4]
+    };
+    // The same tile again only counts what is new; fewer samples than
+    // before is the other eye.
+    progress.update(&tile, &sampled(2));
+    progress.update(&tile, &sampled(4));
+    progress.update(&tile, &sampled(1));
+    let state = progress.state.lock().unwrap();
+    assert_eq!((state.work, state.samples), (20, 20));
+    assert!(!state.finished);
+
+    let line = progress.line(state.work, state.samples, Duration::from_secs(50));
+    assert!(line.starts_with(&format!("[{}{}]  62.5%", "#".repeat(19), "-".repeat(11))));
+    assert!(line.ends_with("0:50 elapsed  0:30 left  0.00M samples/s"));
+    assert_eq!(format_duration(3725.0), "1:02:05");
+}
@@ -1,3 +1,5 @@
+use crate::aabb::Aabb;
+use crate::float::Float;
 use crate::materials::Material;
 use crate::point3d::Point3D;
 
@@ -8,30 +10,48 @@ use assert_approx_eq::assert_approx_eq;
 pub struct Ray {
     pub origin: Point3D,
     pub direction: Point3D,
+    // When during the exposure the ray was sent, for moving objects.
+    pub time: Float,
 }
 
 impl Ray {
     pub fn new(origin: Point3D, direction: Point3D) -> Ray {
-        Ray { origin, direction }
+        Ray::with_time(origin, direction, 0.0)
     }
 
-    pub fn at(&self, t: f64) -> Point3D {
+    pub fn with_time(origin: Point3D, direction: Point3D, time: Float) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
+    }
+
+    pub fn at(&self, t: Float) -> Point3D {
         self.origin + self.direction * t
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct HitRecord<'material> {
-    pub t: f64,
+    pub t: Float,
     pub point: Point3D,
     pub normal: Point3D,
     pub front_face: bool,
     pub material: &'material Material,
-    pub u: f64,
-    pub v: f64,
+    pub u: Float,
+    pub v: Float,
+    // The way u increases along the surface, for shapes that know it better
+    // than the normal alone tells; see normal_map::tangent_frame.
+    pub tangent: Option<Point3D>,
 }
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>>;
+    // A box the object stays inside for rays sent at times from t0 to t1
+    // (a moving object's covers its path in that time). None for objects
+    // that are unbounded, like infinite planes.
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<Aabb>;
 }
 
 #[test]
@@ -1,177 +1,580 @@
-use bvh::bounding_hierarchy::BoundingHierarchy;
-use bvh::bvh::Bvh;
-use image::png::PNGEncoder;
-use image::ColorType;
-use palette::Pixel;
 use palette::Srgb;
 use rand::Rng;
 use rayon::prelude::*;
-use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
+use crate::aov::pass_filename;
+use crate::aov::AovSample;
+use crate::aov::Aovs;
+use crate::aov::IdPasses;
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::camera::Navigation;
+use crate::camera::StereoLayout;
+use crate::color::Color;
+use crate::config::Accelerator;
+use crate::config::Built;
 use crate::config::Config;
+use crate::config::Integrator;
+use crate::config::Sky;
+use crate::denoise::denoise;
+use crate::film::Film;
+use crate::film::Tile;
+use crate::float::consts::PI;
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::grid::Grid;
+use crate::hittable_list::hit_closest;
+use crate::kdtree::KdTree;
+use crate::light::Emitter;
 use crate::materials::Material;
 use crate::materials::Scatterable;
+use crate::normal_map::tangent_frame;
+use crate::object::Object;
+use crate::output;
+use crate::output::Encoding;
+use crate::packet::RayPacket;
+use crate::packet::PACKET_SIZE;
+use crate::point3d::Point3D;
+use crate::polarization::Polarization;
 use crate::ray::HitRecord;
 use crate::ray::Hittable;
 use crate::ray::Ray;
+use crate::sampler;
+use crate::sampler::Adaptive;
+use crate::sampler::Estimate;
+use crate::sampler::SamplePosition;
+use crate::spectrum;
+use crate::spectrum::Radiance;
+use crate::spectrum::Spectrum;
 use crate::sphere::Sphere;
+use crate::stats;
+use crate::stats::Counts;
+use crate::stats::Report;
+use crate::texture;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "images"))]
 use std::fs;
+#[cfg(all(test, feature = "images"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(all(test, feature = "images"))]
+use crate::camera::Stereo;
 #[cfg(test)]
-use crate::point3d::Point3D;
-
-#[cfg(test)]
-use crate::camera::Camera;
+use crate::light::PointLight;
 #[cfg(test)]
-use crate::config::Sky;
+use crate::materials::DiffuseLight;
 #[cfg(test)]
 use crate::materials::Lambertian;
 #[cfg(test)]
 use crate::materials::Light;
-
-fn write_image(
-    filename: &str,
-    pixels: &[u8],
-    bounds: (usize, usize),
-) -> Result<(), std::io::Error> {
-    let output = File::create(filename)?;
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::RGB(8))?;
-    Ok(())
-}
+#[cfg(test)]
+use crate::materials::Metal;
+#[cfg(test)]
+use crate::materials::RoughMetal;
+#[cfg(test)]
+use crate::plane::Plane;
+#[cfg(test)]
+use crate::rect::XzRect;
+#[cfg(test)]
+use crate::sampler::FireflyFilter;
 
 fn hit_world<'material>(
     world: &'material Config,
     r: &Ray,
-    t_min: f64,
-    t_max: f64,
+    t_min: Float,
+    t_max: Float,
 ) -> Option<HitRecord<'material>> {
-    let mut closest_so_far = t_max;
-    let mut hit_record = None;
-    // for sphere in &world.objects {
-    //     if let Some(hit) = sphere.hit(r, t_min, closest_so_far) {
-    //         closest_so_far = hit.t;
-    //         hit_record = Some(hit);
-    //     }
-    // }
-    let ro = nalgebra::Point3::new(r.origin.x(), r.origin.y(), r.origin.z());
-    let rd = nalgebra::Vector3::new(r.direction.x(), r.direction.y(), r.direction.z());
-    let ray: bvh::ray::Ray<f64,3> = bvh::ray::Ray::new(ro, rd);
-    for sphere in world.bvh.as_ref().unwrap().nearest_traverse_iterator(&ray, &world.objects) {
-        if let Some(hit) = sphere.hit(r, t_min, closest_so_far) {
-            closest_so_far = hit.t;
-            hit_record = Some(hit);
-        }
-    }
-    hit_record
+    stats::count(|c| c.rays += 1);
+    match &world.built {
+        Some(Built::Bvh(bvh)) => bvh.hit(r, t_min, t_max),
+        Some(Built::KdTree(kdtree)) => kdtree.hit(r, t_min, t_max),
+        Some(Built::Grid(grid)) => grid.hit(r, t_min, t_max),
+        // No hierarchy has been built (e.g. in tests), so check every object.
+        None => hit_closest(&world.objects, r, t_min, t_max),
+    }
 }
 
-fn clamp(value: f32) -> f32 {
-    if value < 0.0 {
-        0.0
-    } else if value > 1.0 {
-        1.0
-    } else {
-        value
+// As hit_world, along with the object hit's place in the scene's list.
+fn hit_world_with_id<'material>(
+    world: &'material Config,
+    r: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(usize, HitRecord<'material>)> {
+    stats::count(|c| c.rays += 1);
+    match &world.built {
+        Some(Built::Bvh(bvh)) => bvh.hit_with_id(r, t_min, t_max),
+        Some(Built::KdTree(kdtree)) => kdtree.hit_with_id(r, t_min, t_max),
+        Some(Built::Grid(grid)) => grid.hit_with_id(r, t_min, t_max),
+        None => world
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(id, object)| object.hit(r, t_min, t_max).map(|hit| (id, hit)))
+            .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t)),
     }
 }
 
-fn ray_color(
+// As hit_world_with_id for each ray of a packet, up to its own t_max; a ray
+// given a t_max below t_min isn't traced. Only the BVH traces a packet's
+// rays together; otherwise they're taken one by one.
+fn hit_world_packet<'material>(
+    world: &'material Config,
+    packet: &RayPacket,
+    t_min: Float,
+    t_max: &[Float; PACKET_SIZE],
+) -> [Option<(usize, HitRecord<'material>)>; PACKET_SIZE] {
+    match &world.built {
+        Some(Built::Bvh(bvh)) => {
+            let traced = t_max.iter().filter(|t| **t >= t_min).count();
+            stats::count(|c| c.rays += traced as u64);
+            bvh.hit_packet(packet, t_min, t_max)
+        }
+        _ => std::array::from_fn(|i| {
+            if t_max[i] < t_min {
+                return None;
+            }
+            hit_world_with_id(world, &packet.rays[i], t_min, t_max[i])
+        }),
+    }
+}
+
+// What each of `rays` leaving one point hits before its own distance, for
+// the shadow rays to several lights. They spread out, but mostly share the
+// nodes around the point, so they're traced a packet at a time.
+fn hit_world_shadows<'material>(
+    world: &'material Config,
+    rays: &[(Ray, Float)],
+) -> Vec<Option<HitRecord<'material>>> {
+    stats::count(|c| c.shadow_rays += rays.len() as u64);
+    let mut hits = Vec::with_capacity(rays.len());
+    for chunk in rays.chunks(PACKET_SIZE) {
+        // The lanes past the end are left out by a t_max of zero.
+        let lane = |i: usize| chunk.get(i).copied().unwrap_or((chunk[0].0, 0.0));
+        let packet = RayPacket::new(std::array::from_fn(|i| lane(i).0));
+        let t_max = std::array::from_fn(|i| lane(i).1);
+        let found = hit_world_packet(world, &packet, 0.001, &t_max);
+        hits.extend(
+            found
+                .into_iter()
+                .take(chunk.len())
+                .map(|hit| hit.map(|(_, h)| h)),
+        );
+    }
+    hits
+}
+
+fn clamp(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
+}
+
+// Direct light from the scene's point lights arriving at a diffuse surface,
+// skipping lights that are hidden behind other objects at the time of `ray`.
+fn point_light_contribution(
+    scene: &Config,
     ray: &Ray,
+    hit_record: &HitRecord,
+    albedo: Srgb,
+) -> Srgb {
+    let mut light = Srgb::new(0.0, 0.0, 0.0);
+    let facing: Vec<_> = scene
+        .point_lights
+        .iter()
+        .filter_map(|point_light| {
+            let to_light = point_light.position - hit_record.point;
+            let distance = to_light.length();
+            let direction = to_light / distance;
+            let cosine = hit_record.normal.dot(&direction);
+            let shadow_ray = Ray::with_time(hit_record.point, direction, ray.time);
+            (cosine > 0.0).then_some((point_light, cosine, (shadow_ray, distance)))
+        })
+        .collect();
+    let shadow_rays: Vec<_> = facing.iter().map(|(_, _, shadow)| *shadow).collect();
+    let hits = hit_world_shadows(scene, &shadow_rays);
+    for ((point_light, cosine, (_, distance)), hit) in facing.iter().zip(hits) {
+        if hit.is_some() {
+            continue;
+        }
+        // Lambertian BRDF (albedo / pi) times the cosine-weighted irradiance.
+        let irradiance = point_light.irradiance_at(*distance);
+        let weight = to_f32(cosine / PI);
+        light.red += albedo.red * irradiance.red * weight;
+        light.green += albedo.green * irradiance.green * weight;
+        light.blue += albedo.blue * irradiance.blue * weight;
+    }
+    light
+}
+
+// Weight for a sample drawn with density `pdf`, given that another strategy
+// could have drawn it with density `other_pdf` (Veach's power heuristic).
+// The two weights always add up to one, so combining both strategies stays
+// unbiased while each gets the directions it is good at.
+fn power_heuristic(pdf: Float, other_pdf: Float) -> Float {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+}
+
+// Density with which `sample_emitters` picks unit `direction` from `from`.
+fn emitters_pdf(emitters: &[Emitter], from: &Point3D, direction: &Point3D) -> Float {
+    let total: Float = emitters
+        .iter()
+        .map(|e| e.direction_pdf(from, direction))
+        .sum();
+    total / emitters.len() as Float
+}
+
+// Next event estimation: light arriving at the hit directly from one of the
+// emitters, picked at random, as seen through a shadow ray. None if the
+// material can't be lit this way, in which case the emitters have to be
+// found by scattering instead.
+//
+// The light found is weighted against the chance that `scatter` would have
+// found it too, by multiple importance sampling: on a glossy surface a small
+// light is better found by scattering, on a matte one by light sampling.
+fn sample_emitters(
     scene: &Config,
-    lights: &Vec<Sphere>,
+    emitters: &[Emitter],
+    ray: &Ray,
+    hit_record: &HitRecord,
+) -> Option<Srgb> {
+    if emitters.is_empty() {
+        return None;
+    }
+    let pick = (sampler::next_1d() * emitters.len() as Float) as usize;
+    let emitter = &emitters[pick.min(emitters.len() - 1)];
+    // From inside the emitter, leave it to scattering.
+    let (direction, pdf) = emitter.sample_direction(&hit_record.point)?;
+    let f = hit_record.material.evaluate(ray, hit_record, &direction)?;
+    let shadow_ray = Ray::with_time(hit_record.point, direction, ray.time);
+    stats::count(|c| c.shadow_rays += 1);
+    let light = match hit_world(scene, &shadow_ray, 0.001, Float::MAX) {
+        // Another emitter may be in front of the one picked.
+        Some(h) if emitters.iter().any(|e| e.is_on_surface(&h.point)) => {
+            h.material.emitted(h.u, h.v, &h.point)
+        }
+        _ => return Some(Srgb::new(0.0, 0.0, 0.0)),
+    };
+    // Each emitter is picked with probability 1 / n, and emitters that
+    // overlap as seen from here could also have given this direction.
+    let others: Float = emitters
+        .iter()
+        .filter(|e| !std::ptr::eq(*e, emitter))
+        .map(|e| e.direction_pdf(&hit_record.point, &direction))
+        .sum();
+    let light_pdf = (pdf + others) / emitters.len() as Float;
+    let bsdf_pdf = hit_record.material.pdf(ray, hit_record, &direction);
+    let weight = (power_heuristic(light_pdf, bsdf_pdf) / light_pdf) as f32;
+    Some(Srgb::new(
+        f.red * light.red * weight,
+        f.green * light.green * weight,
+        f.blue * light.blue * weight,
+    ))
+}
+
+// Paths are never cut short before this many bounces.
+const ROULETTE_MIN_BOUNCES: usize = 3;
+
+// Chance of a path carrying `throughput` being continued by Russian
+// roulette. Paths that can only add a little more light are mostly stopped;
+// the survivors are weighted up by the inverse to make up for the rest.
+// Kept above a minimum so a surviving path isn't weighted up too much.
+fn survival_probability(throughput: impl Radiance) -> f32 {
+    throughput.largest().clamp(0.05, 1.0)
+}
+
+// The light arriving along `ray`, following it from surface to surface for
+// up to `depth` bounces (of `max_depth` in all).
+fn ray_color(ray: &Ray, scene: &Config, lights: &Lights, max_depth: usize, depth: usize) -> Srgb {
+    ray_color_from(ray, None, scene, lights, max_depth, depth)
+}
+
+// As ray_color, given what `ray` hits first if that's already been found.
+fn ray_color_from<'material>(
+    ray: &Ray,
+    first_hit: Option<Option<(usize, HitRecord<'material>)>>,
+    scene: &'material Config,
+    lights: &Lights,
     max_depth: usize,
     depth: usize,
 ) -> Srgb {
-    let mut rng = rand::thread_rng();
+    let (seen, mut direct, mut indirect) = match scene.integrator {
+        Integrator::Rgb => {
+            let (seen, direct, indirect) =
+                trace::<Color>(ray, first_hit, scene, lights, max_depth, depth, None);
+            (seen.into(), direct.into(), indirect.into())
+        }
+        Integrator::Spectral => {
+            let wavelengths = spectrum::sample_wavelengths(sampler::next_1d());
+            spectrum::set_wavelengths(Some(wavelengths));
+            let (seen, direct, indirect) =
+                trace::<Spectrum>(ray, first_hit, scene, lights, max_depth, depth, None);
+            let rgb = (seen.to_rgb(), direct.to_rgb(), indirect.to_rgb());
+            spectrum::set_wavelengths(None);
+            rgb
+        }
+        Integrator::Polarized => {
+            let camera = &scene.camera;
+            let (polarization, share) =
+                Polarization::camera(camera.polarizer(), &camera.right(), &ray.direction);
+            let (seen, direct, indirect) = trace::<Color>(
+                ray,
+                first_hit,
+                scene,
+                lights,
+                max_depth,
+                depth,
+                Some(polarization),
+            );
+            (
+                (seen * share).into(),
+                (direct * share).into(),
+                (indirect * share).into(),
+            )
+        }
+    };
 
-    if depth <= 0 {
-        return Srgb::new(0.0, 0.0, 0.0);
+    // Fireflies are judged by the whole sample, at the first surface the
+    // camera sees. Lights and the sky seen straight on are as bright as
+    // they are.
+    if let (Some(firefly), true) = (&scene.firefly, depth == max_depth) {
+        direct = firefly.direct(direct);
+        indirect = firefly.indirect(indirect);
     }
-    let hit = hit_world(&scene, ray, 0.001, std::f64::MAX);
-    match hit {
-        Some(hit_record) => {
-            let scattered = hit_record.material.scatter(ray, &hit_record);
-            match scattered {
-                Some((scattered_ray, albedo)) => {
-                    let mut light_red = 0.0;
-                    let mut light_green = 0.0;
-                    let mut light_blue = 0.0;
-                    let mut prob = 0.1;
-                    match hit_record.material {
-                        Material::Glass(_) => {
-                            prob = 0.05;
-                        }
-                        _ => {}
-                    }
-                    if lights.len() > 0
-                        && rng.gen::<f64>() > (1.0 - lights.len() as f64 * prob)
-                        && depth > (max_depth - 2)
-                    {
-                        for light in lights {
-                            let light_ray =
-                                Ray::new(hit_record.point, light.center - hit_record.point);
-                            let target_color = ray_color(&light_ray, scene, lights, 2, 1);
-                            light_red += albedo.red * target_color.red;
-                            light_green += albedo.green * target_color.green;
-                            light_blue += albedo.blue * target_color.blue;
-                        }
-                        light_red /= lights.len() as f32;
-                        light_green /= lights.len() as f32;
-                        light_blue /= lights.len() as f32;
-                    }
-                    match scattered_ray {
-                        Some(sr) => {
-                            let target_color = ray_color(&sr, scene, lights, max_depth, depth - 1);
-                            return Srgb::new(
-                                clamp(light_red + albedo.red * target_color.red),
-                                clamp(light_green + albedo.green * target_color.green),
-                                clamp(light_blue + albedo.blue * target_color.blue),
-                            );
-                        }
-                        None => albedo,
-                    }
-                }
-                None => {
-                    // don't bother bouncing absorbed rays towards lights
-                    // (they would be absorbed in the opposite direction).
-                    return Srgb::new(0.0, 0.0, 0.0);
-                }
+    (Color::from(seen) + direct.into() + indirect.into()).into()
+}
+
+// The light arriving along `ray`, as carried in `C`: given off by the first
+// surface (or the sky) itself, arriving there from the lights, and after
+// bouncing off it. Rather than recursing, the path's
+// throughput, the fraction of the light found at the current surface that
+// makes it back to the camera, is carried from one bounce to the next.
+// Colors are only turned into `C` where they meet the throughput. With a
+// `polarization`, that is followed along too, until the light is unpolarized.
+// The first hit is looked for unless given.
+fn trace<'material, C: Radiance>(
+    ray: &Ray,
+    mut first_hit: Option<Option<(usize, HitRecord<'material>)>>,
+    scene: &'material Config,
+    lights: &Lights,
+    max_depth: usize,
+    depth: usize,
+    mut polarization: Option<Polarization>,
+) -> (C, C, C) {
+    let mut rng = sampler::rng();
+    let emitters = &lights.emitters;
+    let first = depth;
+    let mut ray = *ray;
+    let mut depth = depth;
+    let mut throughput = C::one();
+    // Whether the path has been left with one wavelength.
+    let mut hero_only = false;
+    // The density with which the previous bounce scattered `ray`, if it also
+    // sampled the emitters directly. An emitter hit now is then weighted
+    // against light sampling, which could have found it as well.
+    let mut bsdf_pdf = None;
+    // The light given off at the first surface, found there, and after
+    // bouncing off it.
+    let mut seen = C::from_rgb(Srgb::new(0.0, 0.0, 0.0));
+    let mut direct = seen;
+    let mut indirect = seen;
+    let mut add = |depth: usize, throughput: C, light: Srgb, emitted: bool| {
+        let sum = match (depth == first, emitted) {
+            (true, true) => &mut seen,
+            (true, false) => &mut direct,
+            (false, _) => &mut indirect,
+        };
+        *sum = sum.plus(throughput.times(C::from_rgb(light)));
+    };
+
+    while depth > 0 {
+        let found = match first_hit.take() {
+            Some(found) => found,
+            None => hit_world_with_id(scene, &ray, 0.001, Float::MAX),
+        };
+        let (id, hit_record) = match found {
+            Some(found) => found,
+            None => {
+                add(depth, throughput, sky_color(&ray, scene), true);
+                break;
+            }
+        };
+        // Image textures seen from the camera are averaged over the pixel's
+        // footprint; after a bounce a ray stands for no pixel in particular,
+        // so they're sampled sharp. Finding the footprint takes two more
+        // rays, so it's only done where there's an image to filter.
+        texture::set_footprint(if depth == first && hit_record.material.has_image() {
+            footprint(scene, &ray, id, &hit_record)
+        } else {
+            0.0
+        });
+        let mut emitted =
+            hit_record
+                .material
+                .emitted(hit_record.u, hit_record.v, &hit_record.point);
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            if emitters.iter().any(|e| e.is_on_surface(&hit_record.point)) {
+                let direction = ray.direction.unit_vector();
+                let light_pdf = emitters_pdf(emitters, &ray.origin, &direction);
+                let weight = power_heuristic(bsdf_pdf, light_pdf) as f32;
+                emitted = Srgb::new(
+                    emitted.red * weight,
+                    emitted.green * weight,
+                    emitted.blue * weight,
+                );
             }
         }
-        None => {
-            let t: f32 = clamp(0.5 * (ray.direction.unit_vector().y() as f32 + 1.0));
-            let u: f32 = clamp(0.5 * (ray.direction.unit_vector().x() as f32 + 1.0));
-            match &scene.sky {
-                None => {
-                    return Srgb::new(0.0, 0.0, 0.0);
-                }
-                Some(sky) => match &sky.texture {
-                    None => {
-                        return Srgb::new(
-                            (1.0 - t) * 1.0 + t * 0.5,
-                            (1.0 - t) * 1.0 + t * 0.7,
-                            (1.0 - t) * 1.0 + t * 1.0,
-                        );
-                    }
-                    Some((pixels, width, height, _)) => {
-                        let x = (u * (*width - 1) as f32) as usize;
-                        let y = ((1.0 - t) * (*height - 1) as f32) as usize;
-                        let pixel_red = &pixels[(y * *width + x) * 3];
-                        let pixel_green = &pixels[(y * *width + x) * 3 + 1];
-                        let pixel_blue = &pixels[(y * *width + x) * 3 + 2];
-                        return Srgb::new(
-                            0.7 * *pixel_red as f32 / 255.0,
-                            0.7 * *pixel_green as f32 / 255.0,
-                            0.7 * *pixel_blue as f32 / 255.0,
-                        );
-                    }
-                },
+        let (scattered_ray, albedo) = match hit_record.material.scatter(&ray, &hit_record) {
+            Some(scattered) => scattered,
+            None => {
+                // don't bother bouncing absorbed rays towards lights
+                // (they would be absorbed in the opposite direction).
+                // Emitters still contribute their own light.
+                add(depth, throughput, emitted, true);
+                break;
+            }
+        };
+
+        let mut light = Srgb::new(0.0, 0.0, 0.0);
+        let mut prob = 0.1;
+        if let Material::Glass(_) = hit_record.material {
+            prob = 0.05;
+        }
+        if !lights.spheres.is_empty()
+            && rng.gen::<Float>() > (1.0 - lights.spheres.len() as Float * prob)
+            && depth > (max_depth - 2)
+        {
+            let share = 1.0 / lights.spheres.len() as f32;
+            let light_rays: Vec<_> = lights
+                .spheres
+                .iter()
+                .map(|sphere| {
+                    let to_center = sphere.center - hit_record.point;
+                    let light_ray = Ray::with_time(hit_record.point, to_center, ray.time);
+                    (light_ray, Float::MAX)
+                })
+                .collect();
+            let hits = hit_world_shadows(scene, &light_rays);
+            for ((sphere, (light_ray, _)), hit) in lights.spheres.iter().zip(&light_rays).zip(hits)
+            {
+                let target_color = light_sphere_color(light_ray, sphere, hit);
+                light.red += albedo.red * target_color.red * share;
+                light.green += albedo.green * target_color.green * share;
+                light.blue += albedo.blue * target_color.blue * share;
+            }
+        }
+        if matches!(
+            hit_record.material,
+            Material::Lambertian(_) | Material::Texture(_)
+        ) {
+            let lit = point_light_contribution(scene, &ray, &hit_record, albedo);
+            light.red += lit.red;
+            light.green += lit.green;
+            light.blue += lit.blue;
+        }
+        let sampled = sample_emitters(scene, emitters, &ray, &hit_record);
+        if let Some(lit) = sampled {
+            light.red += lit.red;
+            light.green += lit.green;
+            light.blue += lit.blue;
+        }
+
+        let next = match scattered_ray {
+            Some(next) => next,
+            None => {
+                add(depth, throughput, albedo, true);
+                break;
+            }
+        };
+        add(depth, throughput, emitted, true);
+        add(depth, throughput, light, false);
+        throughput = throughput.times(C::from_rgb(albedo));
+        if hit_record.material.disperses() && !hero_only {
+            throughput = throughput.hero();
+            hero_only = true;
+        }
+        polarization = polarization.and_then(|mut state| {
+            let seen = to_f32(state.scatter(hit_record.material, &ray, &hit_record, &next)?);
+            throughput = throughput.times(C::from_rgb(Srgb::new(seen, seen, seen)));
+            Some(state)
+        });
+        if max_depth - depth >= ROULETTE_MIN_BOUNCES {
+            let survival = survival_probability(throughput);
+            if sampler::next_1d() as f32 >= survival {
+                break;
+            }
+            throughput = throughput.over(survival);
+        }
+        bsdf_pdf = sampled.map(|_| {
+            let direction = next.direction.unit_vector();
+            hit_record.material.pdf(&ray, &hit_record, &direction)
+        });
+        ray = next;
+        depth -= 1;
+        stats::count(|c| c.bounces += 1);
+    }
+    texture::set_footprint(0.0);
+    (seen, direct, indirect)
+}
+
+// What a ray aimed at the centre of the Light sphere `light`, hitting `hit`
+// first, sees of it: its colour, or black if something else is in the way.
+fn light_sphere_color(ray: &Ray, light: &Sphere, hit: Option<HitRecord>) -> Srgb {
+    match hit {
+        Some(hit_record) if light.is_on_surface(&hit_record.point) => {
+            match hit_record.material.scatter(ray, &hit_record) {
+                Some((None, albedo)) => albedo,
+                _ => Srgb::new(0.0, 0.0, 0.0),
             }
         }
+        _ => Srgb::new(0.0, 0.0, 0.0),
+    }
+}
+
+// The colour seen along a ray that hits nothing.
+fn sky_color(ray: &Ray, scene: &Config) -> Srgb {
+    let t: f32 = clamp(0.5 * (to_f32(ray.direction.unit_vector().y()) + 1.0));
+    let u: f32 = clamp(0.5 * (to_f32(ray.direction.unit_vector().x()) + 1.0));
+    match &scene.sky {
+        None => Srgb::new(0.0, 0.0, 0.0),
+        Some(Sky {
+            environment: Some(environment),
+            ..
+        }) => environment.lookup(&ray.direction),
+        Some(sky) => match &sky.texture {
+            None => Srgb::new(
+                (1.0 - t) * 1.0 + t * 0.5,
+                (1.0 - t) * 1.0 + t * 0.7,
+                (1.0 - t) * 1.0 + t * 1.0,
+            ),
+            Some((pixels, width, height, _)) => {
+                let x = (u * (*width - 1) as f32) as usize;
+                let y = ((1.0 - t) * (*height - 1) as f32) as usize;
+                let pixel_red = &pixels[(y * *width + x) * 3];
+                let pixel_green = &pixels[(y * *width + x) * 3 + 1];
+                let pixel_blue = &pixels[(y * *width + x) * 3 + 2];
+                Srgb::new(
+                    0.7 * *pixel_red as f32 / 255.0,
+                    0.7 * *pixel_green as f32 / 255.0,
+                    0.7 * *pixel_blue as f32 / 255.0,
+                )
+            }
+        },
     }
 }
 
@@ -192,51 +595,662 @@ fn test_ray_color() {
             Point3D::new(0.0, 1.0, 0.0),
             20.0,
             1.333,
+            0.0,
+            3.0,
         ),
-        objects: Vec::new(),
-        bvh: None,
+        ..Default::default()
     };
-    let l = Vec::new();
+    let l = Lights::default();
     assert_eq!(ray_color(&r, &scene, &l, 2, 2), Srgb::new(0.75, 0.85, 1.0));
 }
 
-fn render_line(pixels: &mut [u8], scene: &Config, lights: &Vec<Sphere>, y: usize) {
-    let mut rng = rand::thread_rng();
+#[test]
+fn test_spectral_ray_color() {
+    let scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 2,
+        sky: Some(Sky::new_default_sky()),
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        integrator: Integrator::Spectral,
+        ..Default::default()
+    };
+    // Each sample sees the sky at a few wavelengths, so is off color, but on
+    // average it is the sky's RGB.
+    let r = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(1.0, 0.0, 0.0));
+    let samples = 4000;
+    let mut sum = Color::BLACK;
+    for _ in 0..samples {
+        sum += ray_color(&r, &scene, &Lights::default(), 2, 2).into();
+    }
+    let mean = sum / samples as f32;
+    assert!((mean.red - 0.75).abs() < 0.05);
+    assert!((mean.green - 0.85).abs() < 0.05);
+    assert!((mean.blue - 1.0).abs() < 0.05);
+}
+
+#[test]
+fn test_ray_color_emission() {
+    let scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 2,
+        sky: None,
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects: vec![Sphere::new(
+            Point3D::new(0.0, 0.0, -2.0),
+            0.5,
+            Material::DiffuseLight(DiffuseLight::new(Srgb::new(4.0, 2.0, 1.0))),
+        )
+        .into()],
+        ..Default::default()
+    };
+    let l = Lights::default();
+    let towards = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, -1.0));
+    let away = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, 1.0));
+    // Emitters aren't limited to [0, 1].
+    assert_eq!(
+        ray_color(&towards, &scene, &l, 2, 2),
+        Srgb::new(4.0, 2.0, 1.0)
+    );
+    assert_eq!(ray_color(&away, &scene, &l, 2, 2), Srgb::new(0.0, 0.0, 0.0));
+}
 
-    let bounds = (scene.width, scene.height);
+#[test]
+fn test_point_light_shadows() {
+    let mut scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 2,
+        sky: None,
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects: vec![Sphere::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            1.0,
+            Material::Lambertian(Lambertian::new(Srgb::new(1.0, 1.0, 1.0))),
+        )
+        .into()],
+        point_lights: vec![PointLight::new(
+            Point3D::new(0.0, 3.0, 0.0),
+            Srgb::new(4.0, 4.0, 4.0),
+        )],
+        ..Default::default()
+    };
+    let albedo = Srgb::new(1.0, 1.0, 1.0);
+    let top = Ray::new(Point3D::new(0.0, 5.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let bottom = Ray::new(Point3D::new(0.0, -5.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+
+    // Straight below the light, 2 units away: 4 / 2^2 / pi.
+    let hit = hit_world(&scene, &top, 0.001, Float::MAX).unwrap();
+    let lit = point_light_contribution(&scene, &top, &hit, albedo);
+    assert!((lit.red - 1.0 / std::f32::consts::PI).abs() < 1e-6);
+
+    // The underside faces away from the light.
+    let hit = hit_world(&scene, &bottom, 0.001, Float::MAX).unwrap();
+    assert_eq!(
+        point_light_contribution(&scene, &bottom, &hit, albedo),
+        Srgb::new(0.0, 0.0, 0.0)
+    );
+
+    // A second sphere between the light and the first casts a shadow.
+    scene.objects.push(
+        Sphere::new(
+            Point3D::new(0.0, 2.0, 0.0),
+            0.5,
+            Material::Lambertian(Lambertian::new(albedo)),
+        )
+        .into(),
+    );
+    // (Intersect the first sphere directly, as the world ray would now stop
+    // at the occluder.)
+    let hit = scene.objects[0].hit(&top, 0.001, Float::MAX).unwrap();
+    assert_eq!(
+        point_light_contribution(&scene, &top, &hit, albedo),
+        Srgb::new(0.0, 0.0, 0.0)
+    );
+    // Only the lit side sent rays towards the light.
+    assert_eq!(stats::take().shadow_rays, 2);
+}
+
+#[test]
+fn test_next_event_estimation() {
+    let objects: Vec<Object> = vec![
+        Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            Material::Lambertian(Lambertian::new(Srgb::new(1.0, 1.0, 1.0))),
+        )
+        .into(),
+        Sphere::new(
+            Point3D::new(0.0, 2.0, 0.0),
+            0.5,
+            Material::DiffuseLight(DiffuseLight::new(Srgb::new(4.0, 4.0, 4.0))),
+        )
+        .into(),
+    ];
+    let lights = Lights::new(&objects);
+    assert_eq!(lights.emitters.len(), 1);
+    let scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 2,
+        sky: None,
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects,
+        ..Default::default()
+    };
+    let ray = Ray::new(Point3D::new(0.0, 1.0, 1.0), Point3D::new(0.0, -1.0, -1.0));
+    let samples = |lights: &Lights| -> Vec<f32> {
+        (0..2000)
+            .map(|_| ray_color(&ray, &scene, lights, 2, 2).red)
+            .collect()
+    };
+    let variance = |s: &[f32]| {
+        let mean = s.iter().sum::<f32>() / s.len() as f32;
+        s.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / s.len() as f32
+    };
+
+    // Straight below a sphere of radius r at distance d, a white diffuse
+    // surface reflects E r^2 / d^2 = 4 / 16.
+    let sampled = samples(&lights);
+    let mean = sampled.iter().sum::<f32>() / sampled.len() as f32;
+    assert!((mean - 0.25).abs() < 0.01);
+    // Waiting for bounced rays to find the emitter is far noisier.
+    assert!(variance(&sampled) * 10.0 < variance(&samples(&Lights::default())));
+
+    // The same for a square light, a unit across and 2 above. It is lit by
+    // 4 times the form factor from the point below it, 0.0735.
+    let light = XzRect::new(
+        -0.5,
+        0.5,
+        -0.5,
+        0.5,
+        2.0,
+        Material::DiffuseLight(DiffuseLight::new(Srgb::new(4.0, 4.0, 4.0))),
+    );
+    let objects = vec![scene.objects[0].clone(), light.into()];
+    let lights = Lights::new(&objects);
+    assert_eq!(lights.emitters.len(), 1);
+    let scene = Config { objects, ..scene };
+    let sampled: Vec<f32> = (0..2000)
+        .map(|_| ray_color(&ray, &scene, &lights, 2, 2).red)
+        .collect();
+    let mean = sampled.iter().sum::<f32>() / sampled.len() as f32;
+    assert!((mean - 0.294).abs() < 0.01);
+}
+
+#[test]
+fn test_multiple_importance_sampling() {
+    // A glossy floor reflecting a small light.
+    let objects: Vec<Object> = vec![
+        Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            Material::RoughMetal(RoughMetal::new(Srgb::new(0.9, 0.9, 0.9), 0.3)),
+        )
+        .into(),
+        Sphere::new(
+            Point3D::new(0.0, 2.0, -2.0),
+            0.5,
+            Material::DiffuseLight(DiffuseLight::new(Srgb::new(0.5, 0.5, 0.5))),
+        )
+        .into(),
+    ];
+    let lights = Lights::new(&objects);
+    let scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 2,
+        sky: None,
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects,
+        ..Default::default()
+    };
+    let ray = Ray::new(Point3D::new(0.0, 2.0, 2.0), Point3D::new(0.0, -1.0, -1.0));
+    let stats = |lights: &Lights| {
+        let samples: Vec<f32> = (0..4000)
+            .map(|_| ray_color(&ray, &scene, lights, 2, 2).red)
+            .collect();
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let variance =
+            samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / samples.len() as f32;
+        (mean, variance)
+    };
+    let (mis_mean, mis_variance) = stats(&lights);
+    let (bsdf_mean, bsdf_variance) = stats(&Lights::default());
+    // Both estimate the same reflection...
+    assert!((mis_mean - bsdf_mean).abs() < 0.02);
+    // ...but weighing in light samples gives much less noise.
+    assert!(mis_variance * 2.0 < bsdf_variance);
+}
 
-    for x in 0..bounds.0 {
-        let mut pixel_colors: Vec<f32> = vec![0.0; 3];
-        for _s in 0..scene.samples_per_pixel {
-            let u = (x as f64 + rng.gen::<f64>()) / (bounds.0 as f64 - 1.0);
-            let v = (bounds.1 as f64 - (y as f64 + rng.gen::<f64>())) / (bounds.1 as f64 - 1.0);
-            let r = scene.camera.get_ray(u, v);
-            let c = ray_color(&r, scene, lights, scene.max_depth, scene.max_depth);
-            pixel_colors[0] += c.red;
-            pixel_colors[1] += c.green;
-            pixel_colors[2] += c.blue;
-        }
-        let scale = 1.0 / scene.samples_per_pixel as f32;
-        let color = Srgb::new(
-            (scale * pixel_colors[0]).sqrt(),
-            (scale * pixel_colors[1]).sqrt(),
-            (scale * pixel_colors[2]).sqrt(),
+#[test]
+fn test_russian_roulette() {
+    assert_eq!(survival_probability(Color::WHITE), 1.0);
+    assert_eq!(survival_probability(Color::new(0.5, 0.2, 0.1)), 0.5);
+    assert_eq!(survival_probability(Color::BLACK), 0.05);
+
+    let scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 10,
+        sky: Some(Sky::new_default_sky()),
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects: vec![Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            Material::Lambertian(Lambertian::new(Srgb::new(0.2, 0.2, 0.2))),
+        )
+        .into()],
+        ..Default::default()
+    };
+    let lights = Lights::default();
+    let ray = Ray::new(Point3D::new(0.0, 1.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let samples = |depth: usize| -> Vec<f32> {
+        (0..4000)
+            .map(|_| ray_color(&ray, &scene, &lights, 10, depth).red)
+            .collect()
+    };
+    let mean = |s: &[f32]| s.iter().sum::<f32>() / s.len() as f32;
+
+    // On the first bounces every path goes on; deeper in, four in five are
+    // stopped at this surface, and the rest make up for them.
+    let first = samples(10);
+    let deep = samples(5);
+    assert!(first.iter().all(|c| *c > 0.0));
+    let stopped = deep.iter().filter(|c| **c == 0.0).count();
+    assert!((2800..3600).contains(&stopped));
+    assert!((mean(&first) - mean(&deep)).abs() < 0.025);
+}
+
+#[test]
+fn test_firefly_clamp() {
+    let mut scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 10,
+        sky: Some(Sky::new_default_sky()),
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects: vec![Plane::new(
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            Material::grey(),
+        )
+        .into()],
+        ..Default::default()
+    };
+    let lights = Lights::default();
+    let ray = Ray::new(Point3D::new(0.0, 1.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let brightest = |scene: &Config| {
+        (0..200)
+            .map(|_| ray_color(&ray, scene, &lights, 10, 10).blue)
+            .fold(0.0, f32::max)
+    };
+
+    // All the light on the plane comes from the sky, after a bounce.
+    assert!(brightest(&scene) > 0.3);
+    scene.firefly = Some(FireflyFilter {
+        indirect: Some(0.1),
+        ..Default::default()
+    });
+    assert!(brightest(&scene) <= 0.1);
+
+    // A light seen straight from the camera isn't dimmed by the limit on
+    // what reaches the first surface from the lights.
+    scene.objects = vec![Plane::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        Material::DiffuseLight(DiffuseLight::new(Srgb::new(4.0, 4.0, 4.0))),
+    )
+    .into()];
+    scene.firefly = Some(FireflyFilter {
+        direct: Some(0.1),
+        indirect: Some(0.1),
+        ..Default::default()
+    });
+    assert_eq!(brightest(&scene), 4.0);
+}
+
+#[test]
+fn test_sample_packet() {
+    let material = Material::grey();
+    let mut scene = Config {
+        objects: vec![
+            Sphere::new(Point3D::new(0.0, 0.0, -1.0), 0.5, material.clone()).into(),
+            Sphere::new(Point3D::new(0.0, -100.5, -1.0), 100.0, material).into(),
+        ],
+        ..Default::default()
+    };
+    let lights = Lights::new(&scene.objects);
+    scene.built = Some(Built::Bvh(Bvh::new(std::mem::take(&mut scene.objects))));
+    // Finding the hits draws nothing at random here, so each path goes just
+    // as it would have on its own.
+    let depth = scene.max_depth;
+    for (x, y) in [(200, 150), (10, 290)] {
+        let colors = sample_packet(&scene, &lights, 7, (x, y), 4, 16);
+        for (i, c) in colors.into_iter().enumerate() {
+            let ray = camera_ray(&scene, 7, x, y, 4 + i as u32, 16);
+            assert_eq!(c, ray_color(&ray, &scene, &lights, depth, depth));
+        }
+    }
+}
+
+#[test]
+fn test_light_sphere_occluded() {
+    let light = Sphere::new(
+        Point3D::new(0.0, 5.0, 0.0),
+        1.0,
+        Material::Light(Light::new()),
+    );
+    let mut scene = Config {
+        objects: vec![light.clone().into()],
+        ..Default::default()
+    };
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), light.center);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    let hit = hit_world(&scene, &ray, 0.001, Float::MAX);
+    assert_eq!(light_sphere_color(&ray, &light, hit), white);
+
+    // Something in between casts a shadow, even if it's also a light.
+    for material in [Material::grey(), Material::Light(Light::new())] {
+        scene.objects = vec![
+            light.clone().into(),
+            Sphere::new(Point3D::new(0.0, 2.5, 0.0), 0.5, material).into(),
+        ];
+        let black = Srgb::new(0.0, 0.0, 0.0);
+        let hit = hit_world(&scene, &ray, 0.001, Float::MAX);
+        assert_eq!(light_sphere_color(&ray, &light, hit), black);
+    }
+}
+
+#[test]
+fn test_ray_color_deep_paths() {
+    let mirror = |y: Float, normal: Float| -> Object {
+        Plane::new(
+            Point3D::new(0.0, y, 0.0),
+            Point3D::new(0.0, normal, 0.0),
+            Material::Metal(Metal::new(Srgb::new(1.0, 1.0, 1.0), 0.0)),
+        )
+        .into()
+    };
+    let scene = Config {
+        width: 80,
+        height: 60,
+        samples_per_pixel: 1,
+        max_depth: 100_000,
+        sky: Some(Sky::new_default_sky()),
+        camera: Camera::new(
+            Point3D::new(0.0, 0.0, -3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            1.333,
+            0.0,
+            3.0,
+        ),
+        objects: vec![mirror(0.0, 1.0), mirror(1.0, -1.0)],
+        ..Default::default()
+    };
+    // Between two perfect mirrors the path never gets away, and would have
+    // overflowed the stack long before running out of bounces.
+    let ray = Ray::new(Point3D::new(0.0, 0.5, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let c = ray_color(&ray, &scene, &Lights::default(), 100_000, 100_000);
+    assert_eq!(c, Srgb::new(0.0, 0.0, 0.0));
+}
+
+// Width and height in pixels of the tiles the image is rendered in.
+const TILE_SIZE: usize = 32;
+
+// About how wide the pixel `ray` stands for is where it hits the object
+// `id`, in (u, v) units: how far the hit's (u, v) moves for rays a pixel's
+// width to either side of it, across the surface both ways. Rays to the side
+// that miss the object don't count.
+fn footprint(scene: &Config, ray: &Ray, id: usize, hit: &HitRecord) -> Float {
+    let (start, spread) = scene.camera.pixel_cone(scene.height);
+    let width = start + spread * hit.t * ray.direction.length();
+    let d = ray.direction.unit_vector();
+    let (tangent, bitangent) = tangent_frame(hit);
+    let mut footprint: Float = 0.0;
+    for axis in [tangent, bitangent] {
+        // Square to the ray, along the surface as seen from it: at a glancing
+        // angle the ray alongside lands much further away.
+        let side = axis - d * d.dot(&axis);
+        if side.length_squared() < 1e-12 {
+            continue;
+        }
+        let beside = Ray::with_time(
+            ray.origin + side.unit_vector() * width,
+            ray.direction,
+            ray.time,
         );
-        let pixel: [u8; 3] = color.into_format().into_raw();
-        pixels[x * 3] = pixel[0];
-        pixels[x * 3 + 1] = pixel[1];
-        pixels[x * 3 + 2] = pixel[2];
+        if let Some((other, near)) = hit_world_with_id(scene, &beside, 0.001, Float::MAX) {
+            if other == id {
+                // (u, v) wraps round across seams, as on a sphere.
+                let du = (near.u - hit.u).abs();
+                let dv = (near.v - hit.v).abs();
+                footprint = footprint.max(du.min(1.0 - du)).max(dv.min(1.0 - dv));
+            }
+        }
+    }
+    footprint
+}
+
+// Takes up to `samples` more samples for each pixel of `tile` that still
+// needs them, and returns whether the whole tile is done.
+fn render_tile(
+    pixels: &mut [Estimate],
+    tile: &Tile,
+    scene: &Config,
+    lights: &Lights,
+    adaptive: &Adaptive,
+    samples: u32,
+    seed: u64,
+) -> bool {
+    let mut done = true;
+    let add = |estimate: &mut Estimate, mut c: Srgb| {
+        if let Some(firefly) = &scene.firefly {
+            c = firefly.reject_outlier(estimate, c);
+        }
+        estimate.add(c);
+    };
+    for (i, estimate) in pixels.iter_mut().enumerate() {
+        let x = tile.x + i % tile.width;
+        let y = tile.y + i / tile.width;
+        let mut left = samples;
+        while left > 0 && !adaptive.is_done(estimate) {
+            let s = estimate.count();
+            // The samples every pixel gets are taken a packet at a time;
+            // past them, each sample decides whether there's another.
+            let packet = PACKET_SIZE as u32;
+            if left >= packet && s + packet <= adaptive.min_samples {
+                let colors = sample_packet(scene, lights, seed, (x, y), s, adaptive.min_samples);
+                for c in colors {
+                    add(estimate, c);
+                }
+                left -= packet;
+            } else {
+                let r = camera_ray(scene, seed, x, y, s, adaptive.min_samples);
+                stats::count(|c| c.paths += 1);
+                add(
+                    estimate,
+                    ray_color(&r, scene, lights, scene.max_depth, scene.max_depth),
+                );
+                left -= 1;
+            }
+        }
+        done &= adaptive.is_done(estimate);
+    }
+    done
+}
+
+// Samples `s` onwards (of `count`) of `pixel`, a packet's worth. Their
+// camera rays start close together going much the same way, so they go
+// down the hierarchy together; then each path carries on with its own
+// sample's random numbers.
+fn sample_packet(
+    scene: &Config,
+    lights: &Lights,
+    seed: u64,
+    (x, y): (usize, usize),
+    s: u32,
+    count: u32,
+) -> [Srgb; PACKET_SIZE] {
+    let started: [(Ray, SamplePosition); PACKET_SIZE] = std::array::from_fn(|i| {
+        let ray = camera_ray(scene, seed, x, y, s + i as u32, count);
+        (ray, sampler::position())
+    });
+    let mut positions = started.map(|(_, position)| position);
+    let packet = RayPacket::new(started.map(|(ray, _)| ray));
+    let hits = hit_world_packet(scene, &packet, 0.001, &[Float::MAX; PACKET_SIZE]);
+    // Whatever was drawn at random finding the hits came from the last
+    // sample, which goes on from after it.
+    positions[PACKET_SIZE - 1] = sampler::position();
+    let mut colors = [Srgb::new(0.0, 0.0, 0.0); PACKET_SIZE];
+    for (i, hit) in hits.into_iter().enumerate() {
+        sampler::resume(positions[i]);
+        stats::count(|c| c.paths += 1);
+        let depth = scene.max_depth;
+        colors[i] = ray_color_from(&packet.rays[i], Some(hit), scene, lights, depth, depth);
+    }
+    colors
+}
+
+// The camera ray for sample `s` (of `count`) of pixel (`x`, `y`), which
+// also starts the sample's random numbers.
+fn camera_ray(scene: &Config, seed: u64, x: usize, y: usize, s: u32, count: u32) -> Ray {
+    let mut rng = sampler::rng();
+    let bounds = (scene.width, scene.height);
+    scene.sampler.begin_sample(seed, x, y, s);
+    let (dx, dy) = scene.sampler.pixel_offset(s, count, &mut rng);
+    let u = (x as Float + dx) / (bounds.0 as Float - 1.0);
+    let v = (bounds.1 as Float - (y as Float + dy)) / (bounds.1 as Float - 1.0);
+    scene.camera.get_ray(u, v)
+}
+
+// The albedo, normal and depth where `ray` first hits the scene, given what
+// it hits. Surfaces that don't scatter, like lights, give their own colour as
+// albedo.
+fn first_hit(ray: &Ray, hit: Option<HitRecord>, scene: &Config) -> AovSample {
+    match hit {
+        Some(hit_record) => {
+            let albedo = match hit_record.material.scatter(ray, &hit_record) {
+                Some((_, albedo)) => albedo,
+                None => hit_record
+                    .material
+                    .emitted(hit_record.u, hit_record.v, &hit_record.point),
+            };
+            AovSample {
+                albedo: Srgb::new(clamp(albedo.red), clamp(albedo.green), clamp(albedo.blue)),
+                normal: hit_record.normal,
+                depth: hit_record.t * ray.direction.length(),
+            }
+        }
+        None => {
+            let sky = sky_color(ray, scene);
+            AovSample {
+                albedo: Srgb::new(clamp(sky.red), clamp(sky.green), clamp(sky.blue)),
+                normal: Point3D::new(0.0, 0.0, 0.0),
+                depth: 0.0,
+            }
+        }
     }
 }
 
-fn find_lights(world: &Vec<Sphere>) -> Vec<Sphere> {
+// Numbers the objects' materials from 1, in the order they first appear;
+// objects whose materials are written the same way in the scene share an id.
+fn material_ids(world: &[Object]) -> Vec<u32> {
+    let mut seen: Vec<String> = Vec::new();
     world
         .iter()
-        .filter(|s| match s.material {
-            Material::Light(_) => true,
-            _ => false,
+        .map(|object| {
+            let json = serde_json::to_string(object.material()).ok();
+            let found = json.as_ref().and_then(|j| seen.iter().position(|s| s == j));
+            match found {
+                Some(i) => i as u32 + 1,
+                None => {
+                    seen.push(json.unwrap_or_default());
+                    seen.len() as u32
+                }
+            }
+        })
+        .collect()
+}
+
+// Samples per pixel for the albedo, normal and depth passes, which only need
+// enough to smooth their edges, a packet at a time.
+const AOV_SAMPLES: u32 = 16;
+const _: () = assert!(AOV_SAMPLES.is_multiple_of(PACKET_SIZE as u32));
+
+fn find_lights(world: &[Object]) -> Vec<Sphere> {
+    world
+        .iter()
+        .filter_map(|o| match o {
+            Object::Sphere(s) if matches!(s.material, Material::Light(_)) => Some(s.clone()),
+            _ => None,
         })
-        .cloned()
         .collect()
 }
 
@@ -247,53 +1261,811 @@ fn test_find_lights() {
             Point3D::new(0.0, 0.0, -1.0),
             0.5,
             Material::Light(Light::new()),
-        ),
-        Sphere::new(
-            Point3D::new(0.0, 0.0, -1.0),
-            0.5,
-            Material::Lambertian(Lambertian::new(Srgb::new(
-                0.5 as f32, 0.5 as f32, 0.5 as f32,
-            ))),
-        ),
+        )
+        .into(),
+        Sphere::new(Point3D::new(0.0, 0.0, -1.0), 0.5, Material::grey()).into(),
     ];
     assert_eq!(find_lights(&world).len(), 1);
 }
 
-pub fn render(filename: &str, mut scene: Config) {
-    let image_width = scene.width;
-    let image_height = scene.height;
+// Spheres and rectangles that glow, which are sampled directly at every
+// bounce.
+fn find_emitters(world: &[Object]) -> Vec<Emitter> {
+    world.iter().filter_map(Emitter::from_object).collect()
+}
 
-    let bvh = Bvh::build(&mut scene.objects);
-    scene.bvh = Some(bvh);
+// The scene's light sources, collected before rendering.
+#[derive(Default)]
+struct Lights {
+    // Spheres with the Light material, which rays are aimed at now and then.
+    spheres: Vec<Sphere>,
+    emitters: Vec<Emitter>,
+}
 
-    let mut pixels = vec![0; image_width * image_height * 3];
-    let bands: Vec<(usize, &mut [u8])> = pixels.chunks_mut(image_width * 3).enumerate().collect();
+impl Lights {
+    fn new(world: &[Object]) -> Lights {
+        Lights {
+            spheres: find_lights(world),
+            emitters: find_emitters(world),
+        }
+    }
+}
+
+// Called with each tile as soon as it has been rendered, and the estimates of
+// its pixels; e.g. to report progress.
+pub type TileCallback = Arc<dyn Fn(&Tile, &[Estimate]) + Send + Sync>;
+
+// How a scene is rendered, as opposed to what is in it.
+#[derive(Clone, Default)]
+pub struct RenderSettings {
+    // Render in passes of one sample per pixel and write the image so far
+    // whenever this much time has gone by, so a long render can be looked at
+    // (or stopped) before it finishes.
+    pub flush_interval: Option<Duration>,
+    // Save the samples taken so far to this file along with the image, to
+    // carry on from later with `resume`.
+    pub checkpoint: Option<String>,
+    // Also save the checkpoint as tiles finish, whenever this much time has
+    // gone by since it was last saved, so a crash loses at most this much.
+    pub checkpoint_interval: Option<Duration>,
+    // Start from a checkpoint rather than from nothing. The scene has to be
+    // the one the checkpoint was made with.
+    pub resume: Option<String>,
+    pub on_tile: Option<TileCallback>,
+    pub encoding: Encoding,
+    // Also write albedo, normal and depth passes next to the image.
+    pub aovs: bool,
+    // Also write object and material id passes next to the image.
+    pub ids: bool,
+    // Makes the render repeatable: the same seed gives the same image. Left
+    // unset, every render is different.
+    pub seed: Option<u64>,
+    // Run the finished image through Open Image Denoise (with the oidn
+    // feature) before writing it. Checkpoints keep the samples as they were.
+    pub denoise: bool,
+    // Also write what the render cost (rays, memory and so on) as JSON next
+    // to the image.
+    pub stats: bool,
+}
+
+// A scene made ready to render, with its lights found and its bounding
+// volume hierarchy built. This is how other programs embed the tracer: the
+// results come back as a Film, to be written out or displayed as they like.
+pub struct Renderer {
+    scene: Config,
+    lights: Lights,
+    settings: RenderSettings,
+    // The material id of each object, in the scene's order.
+    material_ids: Vec<u32>,
+    seed: u64,
+    // What the tiles rendered so far have counted.
+    counts: Mutex<Counts>,
+}
+
+impl Renderer {
+    pub fn new(mut scene: Config, settings: RenderSettings) -> Renderer {
+        let lights = Lights::new(&scene.objects);
+        let material_ids = material_ids(&scene.objects);
+
+        // The accelerator takes ownership of the objects; from here on all
+        // ray queries go through it. Moving objects only need bounding while
+        // the shutter is open.
+        let (open, close) = scene.camera.shutter();
+        let objects = std::mem::take(&mut scene.objects);
+        let (count, start) = (objects.len(), Instant::now());
+        scene.built = Some(match scene.accelerator {
+            Accelerator::Bvh => {
+                Built::Bvh(Bvh::with_quality(objects, open, close, scene.bvh_quality))
+            }
+            Accelerator::KdTree => Built::KdTree(KdTree::for_shutter(objects, open, close)),
+            Accelerator::Grid => Built::Grid(Grid::for_shutter(objects, open, close)),
+        });
+        info!(
+            "Built the {:?} over {} objects in {}ms",
+            scene.accelerator,
+            count,
+            start.elapsed().as_millis()
+        );
+
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        Renderer {
+            scene,
+            lights,
+            settings,
+            material_ids,
+            seed,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    // What has been counted rendering so far: rays, paths and bounces.
+    pub fn counts(&self) -> Counts {
+        *self.counts.lock().unwrap()
+    }
 
-    let lights = find_lights(&scene.objects);
+    // What has been counted since the last time, counting again from
+    // nothing.
+    pub fn take_counts(&self) -> Counts {
+        std::mem::take(&mut *self.counts.lock().unwrap())
+    }
+
+    // Does some work on this thread and adds what it counted to the
+    // renderer's counts.
+    fn counted<R>(&self, work: impl FnOnce() -> R) -> R {
+        stats::take();
+        let result = work();
+        self.counts.lock().unwrap().add(&stats::take());
+        result
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.scene.camera
+    }
+
+    // Films rendered before the camera moved no longer match the scene.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.scene.camera = camera;
+    }
 
+    // The film to render into: the checkpoint named by settings.resume, else
+    // a blank one.
+    pub fn film(&self) -> Result<Film, std::io::Error> {
+        let path = match &self.settings.resume {
+            Some(path) => path,
+            None => return Ok(Film::new(self.scene.width, self.scene.height)),
+        };
+        let film = Film::load(path)?;
+        if (film.width, film.height) != (self.scene.width, self.scene.height) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Checkpoint {} is for a {}x{} image",
+                    path, film.width, film.height
+                ),
+            ));
+        }
+        Ok(film)
+    }
+
+    // Renders the whole image.
+    pub fn render(&self) -> Result<Film, std::io::Error> {
+        self.render_progressively(|_, _| {})
+    }
+
+    // Renders the whole image, calling `flush` with the film and the number
+    // of passes so far whenever settings.flush_interval has gone by.
+    pub fn render_progressively(
+        &self,
+        mut flush: impl FnMut(&Film, usize),
+    ) -> Result<Film, std::io::Error> {
+        let mut film = self.film()?;
+        match self.settings.flush_interval {
+            None => {
+                self.render_pass(&mut film, u32::MAX);
+            }
+            Some(interval) => {
+                let mut flushed = Instant::now();
+                let mut passes = 0;
+                while !self.render_pass(&mut film, 1) {
+                    passes += 1;
+                    if flushed.elapsed() >= interval {
+                        flush(&film, passes);
+                        flushed = Instant::now();
+                    }
+                }
+            }
+        }
+        Ok(film)
+    }
+
+    // One pass over the whole image, taking up to `samples` more samples for
+    // each pixel that needs them. Returns whether every pixel is done.
+    pub fn render_pass(&self, film: &mut Film, samples: u32) -> bool {
+        let adaptive = self.adaptive();
+
+        // Every tile is a job of its own, so threads that finish cheap tiles
+        // (e.g. open sky) steal the remaining ones from those still busy.
+        // Finished tiles go straight back into the film, so that a
+        // checkpoint saved part way through has them.
+        let tiles: Vec<(Tile, Vec<Estimate>)> = film
+            .tiles(TILE_SIZE)
+            .into_iter()
+            .map(|tile| {
+                let pixels = film.tile(&tile);
+                (tile, pixels)
+            })
+            .collect();
+        let film = Mutex::new(film);
+        let saved = Mutex::new(Instant::now());
+        tiles
+            .into_par_iter()
+            .with_max_len(1)
+            .map(|(tile, mut pixels)| {
+                let done = self.render_tile(&mut pixels, &tile, &adaptive, samples);
+                let mut film = film.lock().unwrap();
+                film.set_tile(&tile, &pixels);
+                self.checkpoint_if_due(&film, &saved);
+                done
+            })
+            .reduce(|| true, |a, b| a && b)
+    }
+
+    // Saves the checkpoint if settings.checkpoint_interval has gone by since
+    // `saved`. A checkpoint that can't be written is only warned about, as
+    // the render itself is fine.
+    fn checkpoint_if_due(&self, film: &Film, saved: &Mutex<Instant>) {
+        let (Some(path), Some(interval)) =
+            (&self.settings.checkpoint, self.settings.checkpoint_interval)
+        else {
+            return;
+        };
+        let mut saved = saved.lock().unwrap();
+        if saved.elapsed() < interval {
+            return;
+        }
+        match film.save(path) {
+            Ok(()) => debug!("Saved checkpoint {}", path),
+            Err(e) => warn!("Unable to write checkpoint {}: {}", path, e),
+        }
+        *saved = Instant::now();
+    }
+
+    // The image one finished tile at a time, rendered on the calling thread
+    // as the iterator is advanced.
+    pub fn tiles(&self) -> impl Iterator<Item = (Tile, Vec<Estimate>)> + '_ {
+        let adaptive = self.adaptive();
+        let tiles = Film::new(self.scene.width, self.scene.height).tiles(TILE_SIZE);
+        tiles.into_iter().map(move |tile| {
+            let mut pixels = vec![Estimate::default(); tile.width * tile.height];
+            self.render_tile(&mut pixels, &tile, &adaptive, u32::MAX);
+            (tile, pixels)
+        })
+    }
+
+    fn render_tile(
+        &self,
+        pixels: &mut [Estimate],
+        tile: &Tile,
+        adaptive: &Adaptive,
+        samples: u32,
+    ) -> bool {
+        let start = Instant::now();
+        let done = self.counted(|| {
+            render_tile(
+                pixels,
+                tile,
+                &self.scene,
+                &self.lights,
+                adaptive,
+                samples,
+                self.seed,
+            )
+        });
+        debug!(
+            "Tile at {},{} ({}x{}) took {:.1}ms",
+            tile.x,
+            tile.y,
+            tile.width,
+            tile.height,
+            start.elapsed().as_secs_f64() * 1e3
+        );
+        if let Some(on_tile) = &self.settings.on_tile {
+            on_tile(tile, pixels);
+        }
+        done
+    }
+
+    // The albedo, normal and depth passes, which take a fraction of the time
+    // the image does.
+    pub fn render_aovs(&self) -> Aovs {
+        let scene = &self.scene;
+        let rows: Vec<Aovs> = (0..scene.height)
+            .into_par_iter()
+            .map(|y| {
+                self.counted(|| {
+                    let mut row = Aovs::new(scene.width, 1);
+                    for x in 0..scene.width {
+                        // A pixel's rays all start close together, going much
+                        // the same way, so they're traced as packets.
+                        for s in (0..AOV_SAMPLES).step_by(PACKET_SIZE) {
+                            let rays = std::array::from_fn(|i| {
+                                camera_ray(scene, self.seed, x, y, s + i as u32, AOV_SAMPLES)
+                            });
+                            let packet = RayPacket::new(rays);
+                            let hits =
+                                hit_world_packet(scene, &packet, 0.001, &[Float::MAX; PACKET_SIZE]);
+                            for (ray, hit) in packet.rays.iter().zip(hits) {
+                                row.add(x, &first_hit(ray, hit.map(|(_, h)| h), scene));
+                            }
+                        }
+                    }
+                    row
+                })
+            })
+            .collect();
+
+        let mut aovs = Aovs::new(scene.width, scene.height);
+        for (y, row) in rows.iter().enumerate() {
+            let tile = Tile {
+                x: 0,
+                y,
+                width: scene.width,
+                height: 1,
+            };
+            aovs.albedo.set_tile(&tile, row.albedo.pixels());
+            aovs.normal.set_tile(&tile, row.normal.pixels());
+            aovs.depth.set_tile(&tile, row.depth.pixels());
+        }
+        aovs
+    }
+
+    // The object and material ids seen through the centre of each pixel.
+    pub fn render_ids(&self) -> IdPasses {
+        let scene = &self.scene;
+        let (width, height) = (scene.width, scene.height);
+        // Traced as packets of neighbouring pixels, the last one padded out
+        // by repeating the final pixel.
+        let pixels = width * height;
+        let mut ids: Vec<(u32, u32)> = (0..pixels.div_ceil(PACKET_SIZE))
+            .into_par_iter()
+            .flat_map_iter(|packet| {
+                let rays = std::array::from_fn(|lane| {
+                    let i = (packet * PACKET_SIZE + lane).min(pixels - 1);
+                    let u = (i % width) as Float + 0.5;
+                    let v = (height - i / width) as Float - 0.5;
+                    scene
+                        .camera
+                        .get_ray(u / (width as Float - 1.0), v / (height as Float - 1.0))
+                });
+                self.counted(|| {
+                    let hits = hit_world_packet(
+                        scene,
+                        &RayPacket::new(rays),
+                        0.001,
+                        &[Float::MAX; PACKET_SIZE],
+                    );
+                    hits.map(|hit| match hit {
+                        Some((id, _)) => (id as u32 + 1, self.material_ids[id]),
+                        None => (0, 0),
+                    })
+                })
+            })
+            .collect();
+        ids.truncate(pixels);
+        let (objects, materials) = ids.into_iter().unzip();
+        IdPasses {
+            width,
+            height,
+            objects,
+            materials,
+        }
+    }
+
+    fn adaptive(&self) -> Adaptive {
+        self.scene.sampling()
+    }
+}
+
+#[cfg(all(test, feature = "images"))]
+fn small_test_scene(width: usize, height: usize, samples: u32) -> Config {
+    let json = fs::read("data/test_scene.json").expect("Unable to read file");
+    let mut scene = serde_json::from_slice::<Config>(&json).expect("Unable to parse json");
+    scene.width = width;
+    scene.height = height;
+    scene.samples_per_pixel = samples;
+    scene
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_pass() {
+    let tiles = Arc::new(AtomicUsize::new(0));
+    let counter = tiles.clone();
+    let settings = RenderSettings {
+        on_tile: Some(Arc::new(move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })),
+        ..Default::default()
+    };
+    let renderer = Renderer::new(small_test_scene(40, 3, 3), settings);
+    let mut film = renderer.film().unwrap();
+    assert!(!renderer.render_pass(&mut film, 2));
+    assert!(film.pixels().iter().all(|e| e.count() == 2));
+    assert!(renderer.render_pass(&mut film, 2));
+    assert!(film.pixels().iter().all(|e| e.count() == 3));
+    assert_eq!(tiles.load(Ordering::Relaxed), 4);
+    // Every sample's path traced at least its camera ray.
+    let counts = renderer.counts();
+    assert_eq!(counts.paths, 40 * 3 * 3);
+    assert!(counts.rays >= counts.paths + counts.bounces);
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_seed() {
+    let render = |seed| {
+        let settings = RenderSettings {
+            seed: Some(seed),
+            ..Default::default()
+        };
+        let renderer = Renderer::new(small_test_scene(40, 30, 3), settings);
+        let film = renderer.render().unwrap();
+        let pixels: Vec<[f32; 3]> = film
+            .pixels()
+            .iter()
+            .map(|e| {
+                let mean = e.mean();
+                [mean.red, mean.green, mean.blue]
+            })
+            .collect();
+        pixels
+    };
+    let first = render(1);
+    assert_eq!(render(1), first);
+    assert_ne!(render(2), first);
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_any_threads() {
+    // Each sample draws from a stream of its own, so how the tiles are shared
+    // out between threads doesn't change the image.
+    let render = |threads| {
+        let settings = RenderSettings {
+            seed: Some(1),
+            ..Default::default()
+        };
+        let renderer = Renderer::new(small_test_scene(40, 30, 3), settings);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let film = pool.install(|| renderer.render()).unwrap();
+        let pixels: Vec<Srgb> = film.pixels().iter().map(|e| e.mean()).collect();
+        pixels
+    };
+    assert_eq!(render(4), render(1));
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_renderer_tiles() {
+    let renderer = Renderer::new(small_test_scene(40, 3, 2), RenderSettings::default());
+    let tiles: Vec<(Tile, Vec<Estimate>)> = renderer.tiles().collect();
+    assert_eq!(tiles.len(), 2);
+    assert_eq!(tiles[1].0.width, 8);
+    assert!(tiles
+        .iter()
+        .all(|(_, pixels)| pixels.iter().all(|e| e.count() == 2)));
+    let film = renderer.render().unwrap();
+    assert!(film.pixels().iter().all(|e| e.count() == 2));
+}
+
+pub fn render(filename: &str, scene: Config) -> Result<Vec<Report>, std::io::Error> {
+    render_with_settings(filename, scene, RenderSettings::default())
+}
+
+// Renders the scene to `filename`, or to a file for each eye, and gives what
+// each of them cost. Fails if a checkpoint can't be resumed or an image,
+// pass or checkpoint can't be written.
+pub fn render_with_settings(
+    filename: &str,
+    scene: Config,
+    settings: RenderSettings,
+) -> Result<Vec<Report>, std::io::Error> {
+    let mut renderer = Renderer::new(scene, settings);
+    let eyes = match renderer.camera().stereo() {
+        Some(stereo) if stereo.layout == StereoLayout::Files => renderer.camera().eyes(),
+        _ => None,
+    };
+    let Some(eyes) = eyes else {
+        return Ok(vec![render_view(filename, &renderer)?]);
+    };
+    // Each eye is its own image, with its own checkpoint.
+    let settings = renderer.settings.clone();
+    let mut reports = Vec::new();
+    for (eye, camera) in ["left", "right"].into_iter().zip(eyes) {
+        let suffixed = |path: &Option<String>| path.as_ref().map(|p| pass_filename(p, eye));
+        renderer.settings.checkpoint = suffixed(&settings.checkpoint);
+        renderer.settings.resume = suffixed(&settings.resume);
+        renderer.set_camera(camera);
+        reports.push(render_view(&pass_filename(filename, eye), &renderer)?);
+    }
+    Ok(reports)
+}
+
+// Renders the image from the renderer's camera to `filename`, and the
+// passes the settings ask for next to it, giving what it all cost.
+fn render_view(filename: &str, renderer: &Renderer) -> Result<Report, std::io::Error> {
     let start = Instant::now();
-    bands.into_par_iter().for_each(|(i, band)| {
-        render_line(band, &scene, &lights, i);
-    });
-    println!("Frame time: {}ms", start.elapsed().as_millis());
+    // The first image that couldn't be written along the way, if any.
+    let mut flushed = Ok(());
+    let film = renderer.render_progressively(|film, passes| {
+        if flushed.is_ok() {
+            flushed = write_film(filename, film, &renderer.settings);
+            info!("Wrote {} after {} passes", filename, passes);
+        }
+    })?;
+    flushed?;
+    info!("Frame time: {}ms", start.elapsed().as_millis());
 
-    write_image(filename, &pixels, (image_width, image_height)).expect("error writing image");
+    write_film(filename, &film, &renderer.settings)?;
+    let settings = &renderer.settings;
+    if settings.aovs || settings.denoise {
+        let aovs = renderer.render_aovs();
+        if settings.denoise {
+            // Over the noisy image, which stays if denoising fails.
+            match denoise(&film, &aovs) {
+                Ok(denoised) => output::write_film(filename, &denoised, &settings.encoding)?,
+                Err(e) => warn!("Unable to denoise, keeping the noisy image: {}", e),
+            }
+        }
+        if settings.aovs {
+            aovs.write(filename, &settings.encoding)?;
+        }
+    }
+    if renderer.settings.ids {
+        renderer
+            .render_ids()
+            .write(filename, &renderer.settings.encoding)?;
+    }
+
+    let report = Report::new(renderer.take_counts(), start.elapsed());
+    if renderer.settings.stats {
+        let path = Path::new(filename).with_extension("stats.json");
+        report.save(&path.to_string_lossy())?;
+    }
+    Ok(report)
+}
+
+// Renders a scene given as JSON to 8-bit RGBA pixels, row by row, without
+// touching any files; the entry point for running in a browser.
+pub fn render_to_rgba(
+    scene_json: &str,
+    width: usize,
+    height: usize,
+    samples: u32,
+) -> Result<Vec<u8>, String> {
+    let mut scene = serde_json::from_str::<Config>(scene_json).map_err(|e| e.to_string())?;
+    scene.width = width;
+    scene.height = height;
+    scene.samples_per_pixel = samples;
+    let film = Renderer::new(scene, RenderSettings::default())
+        .render()
+        .map_err(|e| e.to_string())?;
+    Ok(film.to_rgba8(&Encoding::default()))
+}
+
+// Renders `scene` over and over, a sample per pixel at a time and with every
+// tile going to `settings.on_tile`, starting afresh whenever `navigate` moves
+// the camera, until it says to finish. `navigate` is asked between passes,
+// and returns None when there is nothing to do. Returns the camera as it was
+// at the end.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn explore(
+    scene: Config,
+    settings: RenderSettings,
+    mut navigate: impl FnMut() -> Option<Navigation>,
+) -> Camera {
+    let mut renderer = Renderer::new(scene, settings);
+    let mut film = Film::new(renderer.scene.width, renderer.scene.height);
+    let mut done = false;
+    loop {
+        while let Some(navigation) = navigate() {
+            if matches!(navigation, Navigation::Finish | Navigation::Cancel) {
+                return *renderer.camera();
+            }
+            renderer.set_camera(navigation.apply(renderer.camera()));
+            film = Film::new(film.width, film.height);
+            done = false;
+        }
+        if done {
+            // Converged; wait for the next key.
+            thread::sleep(Duration::from_millis(20));
+        } else {
+            done = renderer.render_pass(&mut film, 1);
+        }
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_explore() {
+    let scene = small_test_scene(8, 6, 2);
+    let start = scene.camera;
+    let mut script = vec![
+        None,
+        Some(Navigation::Finish),
+        Some(Navigation::Orbit(1.0, 0.0)),
+        None,
+        None,
+        Some(Navigation::Travel(1.0, 0.0, 0.0)),
+    ];
+    let camera = explore(scene, RenderSettings::default(), || script.pop().unwrap());
+    let expected =
+        Navigation::Orbit(1.0, 0.0).apply(&Navigation::Travel(1.0, 0.0, 0.0).apply(&start));
+    assert!(camera.origin != start.origin);
+    assert_eq!(camera.origin, expected.origin);
 }
 
+fn write_film(
+    filename: &str,
+    film: &Film,
+    settings: &RenderSettings,
+) -> Result<(), std::io::Error> {
+    output::write_film(filename, film, &settings.encoding)?;
+    if let Some(path) = &settings.checkpoint {
+        film.save(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "images")]
 #[test]
 fn test_render_full_test_scene() {
     let json = fs::read("data/test_scene.json").expect("Unable to read file");
     let mut scene = serde_json::from_slice::<Config>(&json).expect("Unable to parse json");
     scene.width = 80;
     scene.height = 60;
-    render("/tmp/test_scene.png", scene);
+    render("/tmp/test_scene.png", scene).unwrap();
 }
 
+#[cfg(feature = "images")]
 #[test]
 fn test_render_full_cover_scene() {
     let json = fs::read("data/cover_scene.json").expect("Unable to read file");
     let mut scene = serde_json::from_slice::<Config>(&json).expect("Unable to parse json");
     scene.width = 40;
     scene.height = 30;
-    render("/tmp/cover_scene.png", scene);
+    render("/tmp/cover_scene.png", scene).unwrap();
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_progressive() {
+    let settings = RenderSettings {
+        flush_interval: Some(Duration::ZERO),
+        ..Default::default()
+    };
+    let scene = small_test_scene(40, 30, 4);
+    render_with_settings("/tmp/test_scene_progressive.png", scene, settings).unwrap();
+    assert!(fs::metadata("/tmp/test_scene_progressive.png").is_ok());
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_resume() {
+    let checkpoint = "/tmp/test_render_resume.json".to_string();
+    let settings = RenderSettings {
+        checkpoint: Some(checkpoint.clone()),
+        ..Default::default()
+    };
+    let scene = small_test_scene(20, 15, 2);
+    render_with_settings("/tmp/test_render_resume.png", scene, settings).unwrap();
+
+    // Resuming with a higher sample count only takes the extra samples.
+    let settings = RenderSettings {
+        checkpoint: Some(checkpoint.clone()),
+        resume: Some(checkpoint.clone()),
+        ..Default::default()
+    };
+    let scene = small_test_scene(20, 15, 5);
+    render_with_settings("/tmp/test_render_resume.png", scene, settings).unwrap();
+    let film = Film::load(&checkpoint).unwrap();
+    assert!(film.pixels().iter().all(|e| e.count() == 5));
+
+    // A checkpoint that isn't there, or an image that can't be written, is
+    // an error for the caller rather than a panic.
+    let settings = RenderSettings {
+        resume: Some("/tmp/test_render_resume_missing.json".to_string()),
+        ..Default::default()
+    };
+    let scene = small_test_scene(20, 15, 2);
+    assert!(render_with_settings("/tmp/test_render_resume.png", scene, settings).is_err());
+    let scene = small_test_scene(20, 15, 2);
+    assert!(render("/tmp/no/such/directory/out.png", scene).is_err());
+
+    // With an interval, the checkpoint is saved as tiles finish, before
+    // any image is written.
+    fs::remove_file(&checkpoint).unwrap();
+    let settings = RenderSettings {
+        checkpoint: Some(checkpoint.clone()),
+        checkpoint_interval: Some(Duration::ZERO),
+        ..Default::default()
+    };
+    let renderer = Renderer::new(small_test_scene(20, 15, 2), settings);
+    renderer.render_pass(&mut renderer.film().unwrap(), 2);
+    let saved = Film::load(&checkpoint).unwrap();
+    assert!(saved.pixels().iter().all(|e| e.count() == 2));
+
+    // A checkpoint of a different size is refused.
+    let settings = RenderSettings {
+        resume: Some(checkpoint),
+        ..Default::default()
+    };
+    assert!(Renderer::new(small_test_scene(10, 15, 5), settings)
+        .film()
+        .is_err());
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_aovs() {
+    let settings = RenderSettings {
+        aovs: true,
+        ..Default::default()
+    };
+    let scene = small_test_scene(20, 15, 1);
+    render_with_settings("/tmp/test_render_aovs.exr", scene, settings).unwrap();
+    for pass in ["albedo", "normal", "depth"] {
+        assert!(fs::metadata(format!("/tmp/test_render_aovs.{}.exr", pass)).is_ok());
+    }
+
+    let renderer = Renderer::new(small_test_scene(20, 15, 1), RenderSettings::default());
+    let aovs = renderer.render_aovs();
+    assert!(aovs.depth.pixels().iter().all(|e| e.count() == AOV_SAMPLES));
+    // The middle of the picture is a surface facing the camera, more or
+    // less.
+    let middle = 7 * 20 + 10;
+    assert!(aovs.depth.pixels()[middle].mean().red > 0.0);
+    let normal = aovs.normal.pixels()[middle].mean();
+    let towards = -renderer.camera().get_ray(0.5, 0.5).direction.unit_vector();
+    let facing = Float::from(normal.red) * towards.x()
+        + Float::from(normal.green) * towards.y()
+        + Float::from(normal.blue) * towards.z();
+    assert!(facing > 0.0);
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_stereo_files() {
+    let mut scene = small_test_scene(8, 6, 1);
+    scene.camera = scene.camera.with_stereo(Some(Stereo {
+        interocular: 0.1,
+        convergence: None,
+        layout: StereoLayout::Files,
+    }));
+    let settings = RenderSettings {
+        checkpoint: Some("/tmp/test_render_stereo.json".to_string()),
+        ..Default::default()
+    };
+    render_with_settings("/tmp/test_render_stereo.png", scene, settings).unwrap();
+    for eye in ["left", "right"] {
+        assert!(fs::metadata(format!("/tmp/test_render_stereo.{}.png", eye)).is_ok());
+        assert!(fs::metadata(format!("/tmp/test_render_stereo.{}.json", eye)).is_ok());
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_ids() {
+    let mut scene = small_test_scene(20, 15, 1);
+    let count = scene.objects.len() as u32;
+    // A copy of the first object's material is recognised as the same one.
+    let copy = scene.objects[0].clone();
+    scene.objects.push(copy);
+    let renderer = Renderer::new(scene, RenderSettings::default());
+    assert_eq!(renderer.material_ids.last(), Some(&1));
+
+    let ids = renderer.render_ids();
+    assert_eq!(ids.objects.len(), 20 * 15);
+    assert!(ids.objects.iter().all(|id| *id <= count + 1));
+    assert!(ids.objects.iter().any(|id| *id > 0));
+    for (object, material) in ids.objects.iter().zip(&ids.materials) {
+        if *object > 0 {
+            assert_eq!(*material, renderer.material_ids[*object as usize - 1]);
+        } else {
+            assert_eq!(*material, 0);
+        }
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_render_to_rgba() {
+    let json = fs::read_to_string("data/test_scene.json").expect("Unable to read file");
+    let pixels = render_to_rgba(&json, 8, 6, 2).unwrap();
+    assert_eq!(pixels.len(), 8 * 6 * 4);
+    assert!(pixels.chunks(4).all(|p| p[3] == 255));
+    assert!(render_to_rgba("{}", 8, 6, 2).is_err());
 }
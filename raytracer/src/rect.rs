@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::sampler;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Half the thickness given to the rectangles' bounding boxes, which would
+// otherwise be flat.
+const PAD: Float = 1e-4;
+
+// Axis-aligned rectangles, the walls and lights of a Cornell box: XyRect
+// spans x0 to x1 and y0 to y1 at z = k, and so on. Each faces the positive
+// direction of the axis it is fixed along.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct XyRect {
+    pub x0: Float,
+    pub x1: Float,
+    pub y0: Float,
+    pub y1: Float,
+    pub k: Float,
+    pub material: Material,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct XzRect {
+    pub x0: Float,
+    pub x1: Float,
+    pub z0: Float,
+    pub z1: Float,
+    pub k: Float,
+    pub material: Material,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct YzRect {
+    pub y0: Float,
+    pub y1: Float,
+    pub z0: Float,
+    pub z1: Float,
+    pub k: Float,
+    pub material: Material,
+}
+
+impl XyRect {
+    pub fn new(x0: Float, x1: Float, y0: Float, y1: Float, k: Float, material: Material) -> XyRect {
+        XyRect {
+            x0,
+            x1,
+            y0,
+            y1,
+            k,
+            material,
+        }
+    }
+}
+
+impl XzRect {
+    pub fn new(x0: Float, x1: Float, z0: Float, z1: Float, k: Float, material: Material) -> XzRect {
+        XzRect {
+            x0,
+            x1,
+            z0,
+            z1,
+            k,
+            material,
+        }
+    }
+}
+
+impl YzRect {
+    pub fn new(y0: Float, y1: Float, z0: Float, z1: Float, k: Float, material: Material) -> YzRect {
+        YzRect {
+            y0,
+            y1,
+            z0,
+            z1,
+            k,
+            material,
+        }
+    }
+}
+
+// A rectangle lying across axes `a` and `b`, from `from` to `to` along them,
+// at `k` along axis `c`.
+struct Rect<'material> {
+    axes: [usize; 3],
+    from: [Float; 2],
+    to: [Float; 2],
+    k: Float,
+    material: &'material Material,
+}
+
+fn components(p: &Point3D) -> [Float; 3] {
+    [p.x(), p.y(), p.z()]
+}
+
+fn point(axes: [usize; 3], values: [Float; 3]) -> Point3D {
+    let mut p = [0.0; 3];
+    for (axis, value) in axes.into_iter().zip(values) {
+        p[axis] = value;
+    }
+    Point3D::new(p[0], p[1], p[2])
+}
+
+impl<'material> Rect<'material> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'material>> {
+        let [a, b, c] = self.axes;
+        let origin = components(&ray.origin);
+        let direction = components(&ray.direction);
+        let t = (self.k - origin[c]) / direction[c];
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+        let along_a = origin[a] + t * direction[a];
+        let along_b = origin[b] + t * direction[b];
+        if along_a < self.from[0] || along_a > self.to[0] {
+            return None;
+        }
+        if along_b < self.from[1] || along_b > self.to[1] {
+            return None;
+        }
+        let outward = point(self.axes, [0.0, 0.0, 1.0]);
+        let front_face = direction[c] < 0.0;
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal: if front_face { outward } else { -outward },
+            front_face,
+            material: self.material,
+            u: (along_a - self.from[0]) / (self.to[0] - self.from[0]),
+            v: (along_b - self.from[1]) / (self.to[1] - self.from[1]),
+            tangent: None,
+        })
+    }
+
+    // The density, by solid angle, with which picking a point uniformly on
+    // the rectangle gives `direction` (a unit vector), if the point is
+    // `distance` away. Lights glow on both sides.
+    fn solid_angle_pdf(&self, direction: &Point3D, distance: Float) -> Float {
+        let cosine = components(direction)[self.axes[2]].abs();
+        let area = (self.to[0] - self.from[0]) * (self.to[1] - self.from[1]);
+        if cosine <= 0.0 || area <= 0.0 {
+            return 0.0;
+        }
+        distance * distance / (cosine * area)
+    }
+
+    // A direction from `from` to a point picked uniformly on the rectangle,
+    // and the density of picking it. None from the rectangle's own plane.
+    fn sample_direction(&self, from: &Point3D) -> Option<(Point3D, Float)> {
+        let (u1, u2) = sampler::next_2d();
+        let target = point(
+            self.axes,
+            [
+                self.from[0] + u1 * (self.to[0] - self.from[0]),
+                self.from[1] + u2 * (self.to[1] - self.from[1]),
+                self.k,
+            ],
+        );
+        let to_target = target - *from;
+        let distance = to_target.length();
+        let direction = to_target / distance;
+        match self.solid_angle_pdf(&direction, distance) {
+            pdf if pdf > 0.0 && pdf.is_finite() => Some((direction, pdf)),
+            _ => None,
+        }
+    }
+
+    // The density `sample_direction` gives to unit `direction`: zero if it
+    // misses the rectangle.
+    fn direction_pdf(&self, from: &Point3D, direction: &Point3D) -> Float {
+        match self.hit(&Ray::new(*from, *direction), 0.0, Float::MAX) {
+            Some(hit) => self.solid_angle_pdf(direction, hit.t),
+            None => 0.0,
+        }
+    }
+
+    fn is_on_surface(&self, p: &Point3D) -> bool {
+        let [a, b, c] = self.axes;
+        let p = components(p);
+        (p[c] - self.k).abs() < 1e-6 * self.k.abs().max(1.0)
+            && (self.from[0]..=self.to[0]).contains(&p[a])
+            && (self.from[1]..=self.to[1]).contains(&p[b])
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            point(self.axes, [self.from[0], self.from[1], self.k - PAD]),
+            point(self.axes, [self.to[0], self.to[1], self.k + PAD]),
+        )
+    }
+}
+
+impl XyRect {
+    fn rect(&self) -> Rect<'_> {
+        Rect {
+            axes: [0, 1, 2],
+            from: [self.x0, self.y0],
+            to: [self.x1, self.y1],
+            k: self.k,
+            material: &self.material,
+        }
+    }
+}
+
+impl XzRect {
+    fn rect(&self) -> Rect<'_> {
+        Rect {
+            axes: [0, 2, 1],
+            from: [self.x0, self.z0],
+            to: [self.x1, self.z1],
+            k: self.k,
+            material: &self.material,
+        }
+    }
+}
+
+impl YzRect {
+    fn rect(&self) -> Rect<'_> {
+        Rect {
+            axes: [1, 2, 0],
+            from: [self.y0, self.z0],
+            to: [self.y1, self.z1],
+            k: self.k,
+            material: &self.material,
+        }
+    }
+}
+
+// So a glowing rectangle can be sampled as a light.
+impl XyRect {
+    pub fn sample_direction(&self, from: &Point3D) -> Option<(Point3D, Float)> {
+        self.rect().sample_direction(from)
+    }
+
+    pub fn direction_pdf(&self, from: &Point3D, direction: &Point3D) -> Float {
+        self.rect().direction_pdf(from, direction)
+    }
+
+    pub fn is_on_surface(&self, point: &Point3D) -> bool {
+        self.rect().is_on_surface(point)
+    }
+}
+
+impl Hittable for XyRect {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.rect().hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        Some(self.rect().bounding_box())
+    }
+}
+
+impl XzRect {
+    pub fn sample_direction(&self, from: &Point3D) -> Option<(Point3D, Float)> {
+        self.rect().sample_direction(from)
+    }
+
+    pub fn direction_pdf(&self, from: &Point3D, direction: &Point3D) -> Float {
+        self.rect().direction_pdf(from, direction)
+    }
+
+    pub fn is_on_surface(&self, point: &Point3D) -> bool {
+        self.rect().is_on_surface(point)
+    }
+}
+
+impl Hittable for XzRect {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.rect().hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        Some(self.rect().bounding_box())
+    }
+}
+
+impl YzRect {
+    pub fn sample_direction(&self, from: &Point3D) -> Option<(Point3D, Float)> {
+        self.rect().sample_direction(from)
+    }
+
+    pub fn direction_pdf(&self, from: &Point3D, direction: &Point3D) -> Float {
+        self.rect().direction_pdf(from, direction)
+    }
+
+    pub fn is_on_surface(&self, point: &Point3D) -> bool {
+        self.rect().is_on_surface(point)
+    }
+}
+
+impl Hittable for YzRect {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.rect().hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        Some(self.rect().bounding_box())
+    }
+}
+
+#[test]
+fn test_rect_hit() {
+    let grey = || Material::grey();
+    let xy = XyRect::new(0.0, 2.0, 0.0, 1.0, -1.0, grey());
+    let xz = XzRect::new(0.0, 2.0, 0.0, 1.0, -1.0, grey());
+    let yz = YzRect::new(0.0, 2.0, 0.0, 1.0, -1.0, grey());
+    // Each from 4 units in front, 3/4 of the way along the first axis and
+    // 1/4 along the second.
+    let cases: [(&dyn Hittable, Point3D, Point3D); 3] = [
+        (
+            &xy,
+            Point3D::new(1.5, 0.25, 3.0),
+            Point3D::new(0.0, 0.0, 1.0),
+        ),
+        (
+            &xz,
+            Point3D::new(1.5, 3.0, 0.25),
+            Point3D::new(0.0, 1.0, 0.0),
+        ),
+        (
+            &yz,
+            Point3D::new(3.0, 1.5, 0.25),
+            Point3D::new(1.0, 0.0, 0.0),
+        ),
+    ];
+    for (rect, origin, normal) in cases {
+        let hit = rect
+            .hit(&Ray::new(origin, -normal), 0.001, Float::MAX)
+            .unwrap();
+        assert_approx_eq!(hit.t, 4.0);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, normal);
+        assert_approx_eq!(hit.u, 0.75);
+        assert_approx_eq!(hit.v, 0.25);
+        // From behind it faces the other way, and beside it there's nothing.
+        let behind = origin - normal * 8.0;
+        let hit = rect
+            .hit(&Ray::new(behind, normal), 0.001, Float::MAX)
+            .unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, -normal);
+        let beside = origin + Point3D::new(5.0, 5.0, 5.0) - normal * 5.0;
+        assert!(rect
+            .hit(&Ray::new(beside, -normal), 0.001, Float::MAX)
+            .is_none());
+    }
+
+    let bounds = xz.bounding_box(0.0, 1.0).unwrap();
+    assert_eq!(bounds.axis_max(0), 2.0);
+    assert_eq!(bounds.axis_min(2), 0.0);
+    assert!(bounds.axis_min(1) < -1.0 && bounds.axis_max(1) > -1.0);
+}
+
+#[test]
+fn test_rect_sampling() {
+    let grey = Material::grey();
+    let light = XzRect::new(-1.0, 1.0, -1.0, 1.0, 2.0, grey);
+    let below = Point3D::new(0.0, 0.0, 0.0);
+    for _ in 0..100 {
+        let (direction, pdf) = light.sample_direction(&below).unwrap();
+        assert_approx_eq!(light.direction_pdf(&below, &direction), pdf, 1e-6 * pdf);
+        let hit = light
+            .hit(&Ray::new(below, direction), 0.001, Float::MAX)
+            .unwrap();
+        assert!(light.is_on_surface(&hit.point));
+    }
+    // Straight up, the 4 square units 2 away look as big as 1 would at 1.
+    let up = Point3D::new(0.0, 1.0, 0.0);
+    assert_approx_eq!(light.direction_pdf(&below, &up), 1.0);
+    assert_eq!(light.direction_pdf(&below, &-up), 0.0);
+    assert!(light
+        .sample_direction(&Point3D::new(5.0, 2.0, 0.0))
+        .is_none());
+    assert!(!light.is_on_surface(&Point3D::new(5.0, 2.0, 0.0)));
+}
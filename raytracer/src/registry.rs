@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::materials::SharedScatterable;
+use crate::sdf;
+use crate::sdf::SharedDistanceField;
+
+type MaterialFactory =
+    Arc<dyn Fn(&serde_json::Value) -> Result<SharedScatterable, String> + Send + Sync>;
+type FieldFactory =
+    Arc<dyn Fn(&serde_json::Value) -> Result<SharedDistanceField, String> + Send + Sync>;
+
+// What scene files can name that code has to provide: Custom materials and
+// the distance fields of SdfShapes, each made by a factory from its params.
+// A registry is handed to Config::load_with, so different scenes can use
+// different registries.
+#[derive(Clone)]
+pub struct Registry {
+    materials: HashMap<String, MaterialFactory>,
+    fields: HashMap<String, FieldFactory>,
+}
+
+thread_local! {
+    // The registry of the scene being read on this thread, if any.
+    static LOADING: RefCell<Option<Registry>> = const { RefCell::new(None) };
+}
+
+// Puts back the registry that was in use, even if reading panics.
+struct Restore(Option<Registry>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        LOADING.with(|loading| *loading.borrow_mut() = self.0.take());
+    }
+}
+
+impl Registry {
+    // Just the built-in distance fields.
+    pub fn new() -> Registry {
+        let mut registry = Registry {
+            materials: HashMap::new(),
+            fields: HashMap::new(),
+        };
+        sdf::register_built_in_fields(&mut registry);
+        registry
+    }
+
+    // Makes `{"Custom": {"name": <name>, "params": ...}}` usable in scene
+    // files. The factory is given `params` and builds the material;
+    // registering a name again replaces the old factory.
+    pub fn register_material<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&serde_json::Value) -> Result<SharedScatterable, String> + Send + Sync + 'static,
+    {
+        self.materials.insert(name.to_string(), Arc::new(factory));
+    }
+
+    // Makes `{"SdfShape": {"name": <name>, "params": ..., "material": ...}}`
+    // usable in scene files, like register_material does for materials.
+    pub fn register_distance_field<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&serde_json::Value) -> Result<SharedDistanceField, String> + Send + Sync + 'static,
+    {
+        self.fields.insert(name.to_string(), Arc::new(factory));
+    }
+
+    // Runs `f`, e.g. reading a scene, with the names in this registry
+    // usable. Outside of it only what Registry::new has are.
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        let previous = LOADING.with(|loading| loading.replace(Some(self.clone())));
+        let _restore = Restore(previous);
+        f()
+    }
+
+    // The registry names are looked up in right now.
+    fn with_current<T>(f: impl FnOnce(&Registry) -> T) -> T {
+        LOADING.with(|loading| match &*loading.borrow() {
+            Some(registry) => f(registry),
+            None => f(&Registry::new()),
+        })
+    }
+
+    pub(crate) fn material(
+        name: &str,
+        params: &serde_json::Value,
+    ) -> Result<SharedScatterable, String> {
+        let factory = Registry::with_current(|registry| registry.materials.get(name).cloned())
+            .ok_or_else(|| format!("unknown custom material: {}", name))?;
+        factory(params)
+    }
+
+    pub(crate) fn distance_field(
+        name: &str,
+        params: &serde_json::Value,
+    ) -> Result<SharedDistanceField, String> {
+        let factory = Registry::with_current(|registry| registry.fields.get(name).cloned())
+            .ok_or_else(|| format!("unknown distance field: {}", name))?;
+        factory(params)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
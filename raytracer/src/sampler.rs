@@ -0,0 +1,726 @@
+use palette::Srgb;
+use rand::Rng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::str::FromStr;
+
+use crate::blue_noise;
+use crate::float::to_f32;
+use crate::float::Float;
+
+#[cfg(test)]
+use crate::float::consts::FRAC_PI_4;
+
+// Bases of the Halton sequence's dimensions. Paths needing more random
+// numbers than this get plain random ones for the rest.
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// How the positions of a pixel's samples are chosen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Sampler {
+    // Independently random, which can leave clumps and gaps when there are
+    // only a few samples.
+    #[default]
+    Random,
+    // One sample at a random spot in each cell of an N x N grid over the
+    // pixel, so samples are spread evenly. With a samples_per_pixel that
+    // isn't a square, the remaining samples are random.
+    Stratified,
+    // The Halton low-discrepancy sequence, used for every random decision
+    // along a path: the position in the pixel, on the lens, the time, and
+    // the bounces. Its points fill the space of possible paths far more
+    // evenly than random ones, so images converge faster. Each pixel's
+    // sequence is scrambled differently (Owen scrambling, shuffling the
+    // digits of every value) so neighbouring pixels don't repeat the same
+    // pattern, and don't line up the way the later dimensions otherwise do.
+    Halton,
+    // Halton with each pixel's sequence shifted by amounts taken from a
+    // blue-noise mask (Cranley-Patterson rotation). The remaining noise is
+    // then spread evenly over the image rather than in blotches, which looks
+    // better at low sample counts and denoises more cleanly.
+    BlueNoise,
+    // The Sobol sequence, Owen scrambled, in pairs of dimensions each with
+    // its samples shuffled differently. Any power of two of a pixel's
+    // samples is stratified in every pair, so it converges at least as fast
+    // as Halton, and with the same cost for every dimension.
+    Sobol,
+}
+
+impl Sampler {
+    pub fn is_random(&self) -> bool {
+        *self == Sampler::Random
+    }
+
+    // Starts sample `index` of pixel (x, y) of a render seeded with `seed`
+    // on this thread: until the next call, `next_1d` and `next_2d` walk
+    // through the dimensions of that sample, and `rng` gives the sample's own
+    // stream of random numbers. Everything about a sample then follows from
+    // the seed, the pixel and the index, and not from what the thread did
+    // before it.
+    pub fn begin_sample(&self, seed: u64, x: usize, y: usize, index: u32) {
+        let pixel = ((y as u64) << 32) | x as u64;
+        RNG.with(|rng| rng.set(Pcg32::new(hash(seed, index as u64), pixel)));
+        let state = match self {
+            Sampler::Halton | Sampler::BlueNoise | Sampler::Sobol => Some(SampleState {
+                x,
+                y,
+                index: index as u64,
+                dimension: 0,
+                sampler: *self,
+                seed,
+            }),
+            Sampler::Random | Sampler::Stratified => None,
+        };
+        SAMPLE.with(|sample| sample.set(state));
+    }
+
+    // Where sample `index` of `count` falls within the pixel, as offsets in
+    // [0, 1) from its corner.
+    pub fn pixel_offset<R: Rng>(&self, index: u32, count: u32, rng: &mut R) -> (Float, Float) {
+        match self {
+            Sampler::Stratified => {
+                let n = (count as Float).sqrt() as u32;
+                if index >= n * n {
+                    return (rng.gen(), rng.gen());
+                }
+                let (column, row) = (index % n, index / n);
+                (
+                    (column as Float + rng.gen::<Float>()) / n as Float,
+                    (row as Float + rng.gen::<Float>()) / n as Float,
+                )
+            }
+            Sampler::Halton | Sampler::BlueNoise | Sampler::Sobol => next_2d(),
+            Sampler::Random => (rng.gen(), rng.gen()),
+        }
+    }
+}
+
+fn default_min_samples() -> u32 {
+    16
+}
+
+fn default_max_samples() -> u32 {
+    1024
+}
+
+fn default_threshold() -> Float {
+    0.01
+}
+
+// Adaptive sampling: instead of samples_per_pixel for every pixel, each
+// pixel gets at least `min_samples` and then more until the estimated error
+// of its brightness is below `threshold` (as a fraction of the brightness),
+// or it has had `max_samples`. Smooth areas finish early and the effort goes
+// to the noisy ones.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Adaptive {
+    #[serde(default = "default_min_samples")]
+    pub min_samples: u32,
+    #[serde(default = "default_max_samples")]
+    pub max_samples: u32,
+    #[serde(default = "default_threshold")]
+    pub threshold: Float,
+}
+
+impl Adaptive {
+    pub fn new(threshold: Float) -> Adaptive {
+        Adaptive {
+            min_samples: default_min_samples(),
+            max_samples: default_max_samples(),
+            threshold,
+        }
+    }
+
+    // Whether `estimate` needs no more samples.
+    pub fn is_done(&self, estimate: &Estimate) -> bool {
+        if estimate.count < self.min_samples {
+            return false;
+        }
+        if estimate.count >= self.max_samples {
+            return true;
+        }
+        // Very dark pixels are judged against a small floor, as any error is
+        // large relative to nearly nothing.
+        estimate.standard_error() <= self.threshold * estimate.luminance.max(1e-3)
+    }
+}
+
+// Limits that keep rare, very bright samples (fireflies, e.g. from a light
+// found through glass) from leaving white specks in an image that has
+// otherwise converged. Either limit makes the image a little darker than it
+// should be, so they are off unless set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct FireflyFilter {
+    // The brightest (in its largest channel) that the light reaching the
+    // first surface straight from the lights can be in one sample. Lights
+    // and the sky seen by the camera themselves are left alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direct: Option<f32>,
+    // The same for the light arriving after bouncing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indirect: Option<f32>,
+    // Once a pixel has a few samples, one that is brighter than its mean by
+    // more than this many standard deviations is scaled back to that.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier_sigmas: Option<Float>,
+}
+
+// Samples a pixel needs before outliers can be told apart.
+const OUTLIER_MIN_SAMPLES: u32 = 8;
+
+impl FireflyFilter {
+    pub fn direct(&self, c: Srgb) -> Srgb {
+        limit(c, self.direct)
+    }
+
+    pub fn indirect(&self, c: Srgb) -> Srgb {
+        limit(c, self.indirect)
+    }
+
+    // `c` as it should be added to `estimate`.
+    pub fn reject_outlier(&self, estimate: &Estimate, c: Srgb) -> Srgb {
+        let sigmas = match self.outlier_sigmas {
+            Some(sigmas) if estimate.count >= OUTLIER_MIN_SAMPLES => sigmas,
+            _ => return c,
+        };
+        let n = estimate.count as Float;
+        let deviation = (estimate.squared_deviation / (n - 1.0)).sqrt();
+        let bound = estimate.luminance + sigmas * deviation;
+        let luminance = luminance(c);
+        if luminance <= bound {
+            return c;
+        }
+        let scale = (bound / luminance) as f32;
+        Srgb::new(c.red * scale, c.green * scale, c.blue * scale)
+    }
+}
+
+// `c` scaled down, keeping its hue, so no channel is above `max`.
+fn limit(c: Srgb, max: Option<f32>) -> Srgb {
+    let brightest = c.red.max(c.green).max(c.blue);
+    match max {
+        Some(max) if brightest > max => {
+            let scale = max / brightest;
+            Srgb::new(c.red * scale, c.green * scale, c.blue * scale)
+        }
+        _ => c,
+    }
+}
+
+fn luminance(c: Srgb) -> Float {
+    0.2126 * Float::from(c.red) + 0.7152 * Float::from(c.green) + 0.0722 * Float::from(c.blue)
+}
+
+// The running mean of a pixel's samples, and the variance of their
+// brightness (Welford's method).
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+pub struct Estimate {
+    count: u32,
+    mean: [Float; 3],
+    luminance: Float,
+    squared_deviation: Float,
+}
+
+impl Estimate {
+    pub fn add(&mut self, c: Srgb) {
+        self.count += 1;
+        let n = self.count as Float;
+        for (mean, value) in self.mean.iter_mut().zip([c.red, c.green, c.blue]) {
+            *mean += (Float::from(value) - *mean) / n;
+        }
+        let luminance = luminance(c);
+        let delta = luminance - self.luminance;
+        self.luminance += delta / n;
+        self.squared_deviation += delta * (luminance - self.luminance);
+    }
+
+    // `count` samples known only by their mean, as a GPU renders them. Their
+    // brightness is taken not to vary.
+    pub fn from_mean(c: Srgb, count: u32) -> Estimate {
+        Estimate {
+            count,
+            mean: [
+                Float::from(c.red),
+                Float::from(c.green),
+                Float::from(c.blue),
+            ],
+            luminance: luminance(c),
+            squared_deviation: 0.0,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Srgb {
+        Srgb::new(
+            to_f32(self.mean[0]),
+            to_f32(self.mean[1]),
+            to_f32(self.mean[2]),
+        )
+    }
+
+    // Estimated error of the mean brightness.
+    fn standard_error(&self) -> Float {
+        if self.count < 2 {
+            return Float::INFINITY;
+        }
+        let n = self.count as Float;
+        (self.squared_deviation / (n - 1.0) / n).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SampleState {
+    x: usize,
+    y: usize,
+    index: u64,
+    dimension: usize,
+    sampler: Sampler,
+    seed: u64,
+}
+
+impl SampleState {
+    // The current dimension of this sample, or None past the last one the
+    // sequence has.
+    fn value(&self) -> Option<Float> {
+        let dimension = self.dimension as u64;
+        let pixel = ((self.y as u64) << 32) | self.x as u64;
+        let key = hash(pixel ^ self.seed, dimension);
+        match self.sampler {
+            Sampler::Sobol => {
+                // Each pair of dimensions takes the samples in its own
+                // order, so the pairs aren't correlated with each other.
+                let order = hash(pixel ^ !self.seed, dimension / 2) as u32;
+                let index = owen_scramble(self.index as u32, order);
+                let bits = sobol(index, self.dimension % 2 == 1);
+                Some(to_unit(owen_scramble(bits, key as u32)))
+            }
+            Sampler::BlueNoise => {
+                let base = *PRIMES.get(self.dimension)?;
+                // Each dimension uses the mask moved by a different amount,
+                // so the dimensions don't all follow the same pattern.
+                let offset = hash(dimension, self.seed) as usize;
+                let shift = blue_noise::mask_value(
+                    self.x + offset % blue_noise::SIZE,
+                    self.y + (offset / blue_noise::SIZE) % blue_noise::SIZE,
+                );
+                Some((radical_inverse(base, self.index) + shift) % 1.0)
+            }
+            _ => {
+                let base = *PRIMES.get(self.dimension)?;
+                Some(scrambled_radical_inverse(base, self.index, key))
+            }
+        }
+    }
+}
+
+thread_local! {
+    static SAMPLE: Cell<Option<SampleState>> = const { Cell::new(None) };
+    static RNG: Cell<Pcg32> = Cell::new(Pcg32::new(rand::random(), rand::random()));
+}
+
+// The random number generator for everything that happens while rendering,
+// in place of rand::thread_rng(): the current sample's stream.
+pub fn rng() -> SampleRng {
+    SampleRng
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampleRng;
+
+// How far the current sample has got, in its sequence and its stream of
+// random numbers, to carry on from with `resume`: several samples can be
+// started before any of them goes on.
+#[derive(Debug, Clone, Copy)]
+pub struct SamplePosition {
+    sample: Option<SampleState>,
+    rng: Pcg32,
+}
+
+pub fn position() -> SamplePosition {
+    SamplePosition {
+        sample: SAMPLE.with(|sample| sample.get()),
+        rng: RNG.with(|rng| rng.get()),
+    }
+}
+
+pub fn resume(position: SamplePosition) {
+    SAMPLE.with(|sample| sample.set(position.sample));
+    RNG.with(|rng| rng.set(position.rng));
+}
+
+impl RngCore for SampleRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|cell| {
+            let mut rng = cell.get();
+            let value = rng.next_u32();
+            cell.set(rng);
+            value
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// O'Neill's PCG32 (XSH RR): 16 bytes of state and a few instructions per
+// number, so starting a fresh one for every sample costs next to nothing.
+// Each `stream` gives a different sequence for the same seed.
+#[derive(Debug, Clone, Copy)]
+struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(self.increment);
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+}
+
+// The next random number in [0, 1) for the current sample: from the
+// sequence if the sampler uses one, otherwise from `rng`.
+// Code that makes random decisions while tracing a path should draw from
+// here so that low-discrepancy samplers apply to it.
+pub fn next_1d() -> Float {
+    let from_sequence = SAMPLE.with(|sample| {
+        let mut state = sample.get()?;
+        let value = state.value()?;
+        state.dimension += 1;
+        sample.set(Some(state));
+        Some(value)
+    });
+    from_sequence.unwrap_or_else(|| rng().gen())
+}
+
+pub fn next_2d() -> (Float, Float) {
+    let u = next_1d();
+    (u, next_1d())
+}
+
+// The digits of `index` in `base`, mirrored about the decimal point:
+// 1, 2, 3, 4 in base 2 give 0.5, 0.25, 0.75, 0.125.
+fn radical_inverse(base: u64, mut index: u64) -> Float {
+    let inverse_base = 1.0 / base as Float;
+    let mut value = 0.0;
+    let mut scale = inverse_base;
+    while index > 0 {
+        value += (index % base) as Float * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+    value
+}
+
+// As radical_inverse, with every digit permuted by one of `base` rotations
+// picked by `key` and the digits before it, so that values which shared
+// leading digits still do. Carries on past the last digit of `index`, as its
+// zeros are permuted too, until the digits are too small to matter.
+fn scrambled_radical_inverse(base: u64, mut index: u64, key: u64) -> Float {
+    let inverse_base = 1.0 / base as Float;
+    let mut value = 0.0;
+    let mut scale = inverse_base;
+    let mut prefix = 0u64;
+    let mut level = 0;
+    while scale > Float::EPSILON / 2.0 {
+        let digit = index % base;
+        let rotation = hash(key.wrapping_add(level), prefix) % base;
+        value += ((digit + rotation) % base) as Float * scale;
+        prefix = prefix.wrapping_mul(base).wrapping_add(digit);
+        index /= base;
+        scale *= inverse_base;
+        level += 1;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+// The first or second dimension of the Sobol sequence, as the bits of a
+// fraction: the first is the radical inverse in base 2, and the second's
+// generator matrix is Pascal's triangle, mod 2.
+fn sobol(index: u32, second: bool) -> u32 {
+    if !second {
+        return index.reverse_bits();
+    }
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    let mut index = index;
+    while index > 0 {
+        if index & 1 == 1 {
+            result ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    result
+}
+
+// Laine and Karras' hash, with the constants Vegdahl found to mix better:
+// each bit is flipped or not depending on `seed` and the bits below it only.
+fn laine_karras(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// Owen scrambling of the bits of a fraction: each bit is flipped or not
+// depending on `seed` and the bits above it, which keeps a set of values
+// that was stratified stratified.
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras(x.reverse_bits(), seed).reverse_bits()
+}
+
+// SplitMix64, to turn a pixel and dimension into an unrelated random value.
+fn hash(a: u64, b: u64) -> u64 {
+    let mut z = a
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        .wrapping_add(b)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// The largest value below 1, which rounding a fraction up must not pass.
+const ONE_MINUS_EPSILON: Float = 1.0 - Float::EPSILON / 2.0;
+
+// The bits of a fraction as one in [0, 1).
+fn to_unit(bits: u32) -> Float {
+    (bits as Float / 4_294_967_296.0).min(ONE_MINUS_EPSILON)
+}
+
+// For the command line, e.g. `--sampler stratified`.
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sampler, String> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(Sampler::Random),
+            "stratified" => Ok(Sampler::Stratified),
+            "halton" => Ok(Sampler::Halton),
+            "blue-noise" | "bluenoise" => Ok(Sampler::BlueNoise),
+            "sobol" => Ok(Sampler::Sobol),
+            _ => Err(format!("unknown sampler: {}", s)),
+        }
+    }
+}
+
+#[test]
+fn test_stratified() {
+    let mut rng = rand::thread_rng();
+    // Each of the 16 cells of a 4x4 grid gets exactly one sample.
+    let mut cells = [0; 16];
+    for i in 0..16 {
+        let (x, y) = Sampler::Stratified.pixel_offset(i, 16, &mut rng);
+        assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+        cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+    }
+    assert!(cells.iter().all(|c| *c == 1));
+
+    // 10 samples: a 3x3 grid plus one random sample.
+    let (x, y) = Sampler::Stratified.pixel_offset(4, 10, &mut rng);
+    assert!((1.0 / 3.0..2.0 / 3.0).contains(&x) && (1.0 / 3.0..2.0 / 3.0).contains(&y));
+    let (x, y) = Sampler::Stratified.pixel_offset(9, 10, &mut rng);
+    assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+
+    assert_eq!("stratified".parse::<Sampler>(), Ok(Sampler::Stratified));
+    assert!("poisson".parse::<Sampler>().is_err());
+}
+
+#[test]
+fn test_radical_inverse() {
+    assert_eq!(radical_inverse(2, 1), 0.5);
+    assert_eq!(radical_inverse(2, 3), 0.75);
+    assert_eq!(radical_inverse(2, 4), 0.125);
+    assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_halton() {
+    // 16 samples of a pixel put exactly one value in each sixteenth of the
+    // first dimension, whatever the pixel's shift.
+    let mut cells = [0; 16];
+    for i in 0..16 {
+        Sampler::Halton.begin_sample(0, 7, 2, i);
+        let (x, _) = Sampler::Halton.pixel_offset(i, 16, &mut rand::thread_rng());
+        cells[(x * 16.0) as usize] += 1;
+    }
+    assert!(cells.iter().all(|c| *c == 1));
+
+    // Estimating the area of a quarter circle from 1024 points is much more
+    // accurate than random sampling's typical error of 1.3%.
+    let mut inside = 0;
+    for i in 0..1024 {
+        Sampler::Halton.begin_sample(0, 3, 0, i);
+        let (x, y) = next_2d();
+        if x * x + y * y < 1.0 {
+            inside += 1;
+        }
+    }
+    assert!((inside as Float / 1024.0 - FRAC_PI_4).abs() < 0.005);
+
+    // Other samplers leave the numbers random, but repeatable.
+    Sampler::Random.begin_sample(0, 3, 0, 0);
+    let a = next_1d();
+    Sampler::Random.begin_sample(0, 3, 0, 1);
+    assert_ne!(a, next_1d());
+    Sampler::Random.begin_sample(0, 3, 0, 0);
+    assert_eq!(a, next_1d());
+}
+
+#[test]
+fn test_sobol() {
+    // 16 samples of a pixel put exactly one point in each cell of a 4x4
+    // grid, in the first pair of dimensions and in the next.
+    let mut cells = [[0; 16]; 2];
+    for i in 0..16 {
+        Sampler::Sobol.begin_sample(0, 7, 2, i);
+        for pair in cells.iter_mut() {
+            let (x, y) = next_2d();
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            pair[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+        }
+    }
+    assert!(cells.iter().flatten().all(|c| *c == 1));
+
+    // Other pixels are scrambled differently.
+    Sampler::Sobol.begin_sample(0, 7, 2, 0);
+    let a = next_1d();
+    Sampler::Sobol.begin_sample(0, 8, 2, 0);
+    assert_ne!(a, next_1d());
+
+    // 0, 1/2, 3/4, 1/4 in the second dimension.
+    assert_eq!(sobol(2, true), 3 << 30);
+    assert_eq!(sobol(3, true), 1 << 30);
+    assert_eq!("sobol".parse::<Sampler>(), Ok(Sampler::Sobol));
+}
+
+#[test]
+fn test_blue_noise_sampler() {
+    // With one sample per pixel, neighbouring pixels never get similar
+    // values, as they come from a blue-noise mask.
+    let first = |x, y| {
+        Sampler::BlueNoise.begin_sample(0, x, y, 0);
+        next_1d()
+    };
+    assert_eq!(blue_noise::touching_pairs(first, 32, 0.1), 0);
+    assert_eq!("blue-noise".parse::<Sampler>(), Ok(Sampler::BlueNoise));
+}
+
+#[test]
+fn test_adaptive() {
+    let adaptive = Adaptive::new(0.01);
+    let mut flat = Estimate::default();
+    let grey = Srgb::new(0.5, 0.5, 0.5);
+    while !adaptive.is_done(&flat) {
+        flat.add(grey);
+    }
+    // A pixel that never changes is done as soon as it is allowed to be.
+    assert_eq!(flat.count(), adaptive.min_samples);
+    assert_eq!(flat.mean(), grey);
+
+    // One that flips between black and white needs all it can get.
+    let mut noisy = Estimate::default();
+    while !adaptive.is_done(&noisy) {
+        let v = (noisy.count() % 2) as f32;
+        noisy.add(Srgb::new(v, v, v));
+    }
+    assert_eq!(noisy.count(), adaptive.max_samples);
+    assert!((noisy.mean().red - 0.5).abs() < 1e-3);
+
+    let json = r#"{"threshold":0.05}"#;
+    let a = serde_json::from_str::<Adaptive>(json).unwrap();
+    assert_eq!((a.min_samples, a.max_samples), (16, 1024));
+}
+
+#[test]
+fn test_firefly_filter() {
+    let filter = FireflyFilter {
+        direct: Some(2.0),
+        indirect: None,
+        outlier_sigmas: Some(3.0),
+    };
+    assert_eq!(
+        filter.direct(Srgb::new(4.0, 1.0, 0.0)),
+        Srgb::new(2.0, 0.5, 0.0)
+    );
+    assert_eq!(
+        filter.indirect(Srgb::new(4.0, 1.0, 0.0)),
+        Srgb::new(4.0, 1.0, 0.0)
+    );
+
+    let mut estimate = Estimate::default();
+    let firefly = Srgb::new(50.0, 50.0, 50.0);
+    // Too few samples to judge by.
+    assert_eq!(filter.reject_outlier(&estimate, firefly), firefly);
+    for i in 0..16 {
+        let c = if i % 2 == 0 { 0.4 } else { 0.6 };
+        estimate.add(Srgb::new(c, c, c));
+    }
+    let rejected = filter.reject_outlier(&estimate, firefly);
+    assert!(rejected.red < 1.0 && rejected.red > 0.6);
+    let ordinary = Srgb::new(0.55, 0.55, 0.55);
+    assert_eq!(filter.reject_outlier(&estimate, ordinary), ordinary);
+}
+
+#[test]
+fn test_sample_streams() {
+    let draw = |seed, x, y, index| -> Vec<u32> {
+        Sampler::Random.begin_sample(seed, x, y, index);
+        (0..8).map(|_| rng().next_u32()).collect()
+    };
+    let first = draw(7, 1, 2, 3);
+    assert_eq!(draw(7, 1, 2, 3), first);
+    // Neighbouring pixels, the next sample and another seed all differ.
+    assert_ne!(draw(7, 2, 2, 3), first);
+    assert_ne!(draw(7, 1, 3, 3), first);
+    assert_ne!(draw(7, 1, 2, 4), first);
+    assert_ne!(draw(8, 1, 2, 3), first);
+
+    // The reference output of PCG32 for seed 42 on stream 54.
+    let mut pcg = Pcg32::new(42, 54);
+    let values: Vec<u32> = (0..3).map(|_| pcg.next_u32()).collect();
+    assert_eq!(values, vec![0xa15c02b7, 0x7b47f409, 0xba1d3330]);
+}
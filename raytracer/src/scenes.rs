@@ -0,0 +1,226 @@
+use palette::Srgb;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+use crate::camera::Camera;
+use crate::config::Config;
+use crate::config::Sky;
+use crate::cuboid::Cuboid;
+use crate::float::Float;
+use crate::instance::Rotate;
+use crate::instance::Translate;
+use crate::materials::DiffuseLight;
+use crate::materials::Glass;
+use crate::materials::Lambertian;
+use crate::materials::Material;
+use crate::materials::Metal;
+use crate::object::Object;
+use crate::point3d::Point3D;
+use crate::rect::XyRect;
+use crate::rect::XzRect;
+use crate::rect::YzRect;
+use crate::sphere::Sphere;
+
+#[cfg(test)]
+use crate::hittable_list::hit_closest;
+#[cfg(test)]
+use crate::ray::Ray;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// The names of the scenes built in rather than loaded from a file, as given
+// to --scene.
+pub const NAMES: [&str; 2] = ["cornell", "cover"];
+
+// A built-in scene by name. Those generated at random come out the same for
+// the same `seed`.
+pub fn named(name: &str, seed: u64) -> Option<Config> {
+    match name {
+        "cornell" => Some(cornell_box()),
+        "cover" => Some(cover(seed)),
+        _ => None,
+    }
+}
+
+// The Cornell box: a 555 unit cube, open at the front, with a red wall on
+// the left, a green one on the right and a light in the ceiling, holding a
+// short and a tall box. Everything is diffuse and there is no sky, so all
+// the light comes from the ceiling; a good check that lighting and
+// materials behave.
+pub fn cornell_box() -> Config {
+    let lambertian = |r, g, b| Material::Lambertian(Lambertian::new(Srgb::new(r, g, b)));
+    let red = lambertian(0.65, 0.05, 0.05);
+    let white = lambertian(0.73, 0.73, 0.73);
+    let green = lambertian(0.12, 0.45, 0.15);
+    let light = Material::DiffuseLight(DiffuseLight::new(Srgb::new(15.0, 15.0, 15.0)));
+
+    let objects = vec![
+        Object::from(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, red)),
+        Object::from(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, green)),
+        Object::from(XzRect::new(213.0, 343.0, 227.0, 332.0, 554.0, light)),
+        Object::from(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone())),
+        Object::from(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
+        Object::from(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
+        // Each box turned a little on the spot, then moved into place.
+        Object::from(Translate::new(
+            Object::from(Rotate::y(
+                Object::from(Cuboid::new(
+                    Point3D::new(0.0, 0.0, 0.0),
+                    Point3D::new(165.0, 330.0, 165.0),
+                    white.clone(),
+                )),
+                15.0,
+            )),
+            Point3D::new(265.0, 0.0, 295.0),
+        )),
+        Object::from(Translate::new(
+            Object::from(Rotate::y(
+                Object::from(Cuboid::new(
+                    Point3D::new(0.0, 0.0, 0.0),
+                    Point3D::new(165.0, 165.0, 165.0),
+                    white,
+                )),
+                -18.0,
+            )),
+            Point3D::new(130.0, 0.0, 65.0),
+        )),
+    ];
+    Config {
+        width: 600,
+        height: 600,
+        samples_per_pixel: 200,
+        max_depth: 50,
+        sky: None,
+        camera: Camera::new(
+            Point3D::new(278.0, 278.0, -800.0),
+            Point3D::new(278.0, 278.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+        ),
+        objects,
+        ..Default::default()
+    }
+}
+
+// The cover of Ray Tracing in One Weekend: a grid of small spheres, each
+// randomly diffuse, metal or glass, scattered around a glass, a diffuse and
+// a metal sphere on a huge grey ground sphere.
+pub fn cover(seed: u64) -> Config {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world = vec![Sphere::new(
+        Point3D::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::Lambertian(Lambertian::new(Srgb::new(0.5, 0.5, 0.5))),
+    )];
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen::<Float>();
+            let center = Point3D::new(
+                a as Float + 0.9 * rng.gen::<Float>(),
+                0.2,
+                b as Float + 0.9 * rng.gen::<Float>(),
+            );
+
+            // Clear of the big metal sphere.
+            if ((center - Point3D::new(4.0, 0.2, 0.0)).length()) < 0.9 {
+                continue;
+            }
+
+            let material = if choose_mat < 0.8 {
+                // diffuse
+                Material::Lambertian(Lambertian::new(Srgb::new(
+                    rng.gen::<f32>() * rng.gen::<f32>(),
+                    rng.gen::<f32>() * rng.gen::<f32>(),
+                    rng.gen::<f32>() * rng.gen::<f32>(),
+                )))
+            } else if choose_mat < 0.95 {
+                // metal
+                Material::Metal(Metal::new(
+                    Srgb::new(
+                        0.5 * (1.0 + rng.gen::<f32>()),
+                        0.5 * (1.0 + rng.gen::<f32>()),
+                        0.5 * (1.0 + rng.gen::<f32>()),
+                    ),
+                    0.5 * rng.gen::<Float>(),
+                ))
+            } else {
+                // glass
+                Material::Glass(Glass::new(1.5))
+            };
+            world.push(Sphere::new(center, 0.2, material));
+        }
+    }
+
+    world.push(Sphere::new(
+        Point3D::new(0.0, 1.0, 0.0),
+        1.0,
+        Material::Glass(Glass::new(1.5)),
+    ));
+    world.push(Sphere::new(
+        Point3D::new(-4.0, 1.0, 0.0),
+        1.0,
+        Material::Lambertian(Lambertian::new(Srgb::new(0.4, 0.2, 0.1))),
+    ));
+    world.push(Sphere::new(
+        Point3D::new(4.0, 1.0, 0.0),
+        1.0,
+        Material::Metal(Metal::new(Srgb::new(0.7, 0.6, 0.5), 0.0)),
+    ));
+
+    Config {
+        width: 800,
+        height: 600,
+        samples_per_pixel: 64,
+        max_depth: 50,
+        sky: Some(Sky::new_default_sky()),
+        camera: Camera::new(
+            Point3D::new(13.0, 2.0, 3.0),
+            Point3D::new(0.0, 0.0, 0.0),
+            Point3D::new(0.0, 1.0, 0.0),
+            20.0,
+            800.0 / 600.0,
+            0.1,
+            10.0,
+        ),
+        objects: world.into_iter().map(Object::from).collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_cornell_box() {
+    let scene = named("cornell", 0).unwrap();
+    assert_eq!(scene.objects.len(), 8);
+    assert!(named("missing.json", 0).is_none());
+
+    // Straight ahead is the front of the tall box, 13 along from its turned
+    // corner, and straight up from the middle of the floor the light.
+    let ahead = scene.camera.get_ray(0.5, 0.5);
+    let hit = hit_closest(&scene.objects, &ahead, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.z(), 295.0 - 13.0 * Float::to_radians(15.0).tan());
+    let up = Ray::new(Point3D::new(278.0, 1.0, 280.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = hit_closest(&scene.objects, &up, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.y(), 554.0);
+    assert!(matches!(hit.material, Material::DiffuseLight(_)));
+
+    // It can be saved as a scene file like any other.
+    let json = serde_json::to_string(&scene).unwrap();
+    let loaded = serde_json::from_str::<Config>(&json).unwrap();
+    assert_eq!(loaded.objects.len(), 8);
+}
+
+#[test]
+fn test_cover() {
+    // The same seed gives the same spheres, another seed others.
+    let json = |seed| serde_json::to_string(&named("cover", seed).unwrap().objects).unwrap();
+    assert_eq!(json(7), json(7));
+    assert_ne!(json(7), json(8));
+    let scene = cover(7);
+    // Up to 22 x 22 small spheres, the ground and the three big ones.
+    assert!(scene.objects.len() > 400 && scene.objects.len() <= 22 * 22 + 4);
+}
@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+use crate::registry::Registry;
+use crate::sphere::u_v_from_sphere_hit_point;
+
+#[cfg(test)]
+use crate::object::Object;
+#[cfg(test)]
+use crate::sphere::Sphere;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Marching stops this close to the surface.
+const HIT_DISTANCE: Float = 1e-6;
+// Give up on rays that creep along the surface without reaching it.
+const MAX_STEPS: usize = 512;
+// Step used to estimate the gradient of the distance, i.e. the normal.
+const GRADIENT_STEP: Float = 1e-5;
+
+// A surface given by its signed distance: how far `p` is from the surface,
+// negative inside. The distance may be an underestimate, but never an
+// overestimate, or marching will step through the surface.
+pub trait DistanceField {
+    fn distance(&self, p: &Point3D) -> Float;
+
+    // A box the whole surface is in; rays are only marched through it.
+    fn bounds(&self) -> Aabb;
+}
+
+pub type SharedDistanceField = Arc<dyn DistanceField + Send + Sync>;
+
+// A distance field from a closure, e.g. blending other fields together.
+pub struct FnField<F> {
+    pub distance: F,
+    pub bounds: Aabb,
+}
+
+impl<F: Fn(&Point3D) -> Float> DistanceField for FnField<F> {
+    fn distance(&self, p: &Point3D) -> Float {
+        (self.distance)(p)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+// A sphere `radius` around the origin.
+pub fn sphere(p: &Point3D, radius: Float) -> Float {
+    p.length() - radius
+}
+
+// A box `half_size` out from the origin along each axis, its edges rounded
+// off by `radius`.
+pub fn round_box(p: &Point3D, half_size: &Point3D, radius: Float) -> Float {
+    let q = [
+        p.x().abs() - half_size.x() + radius,
+        p.y().abs() - half_size.y() + radius,
+        p.z().abs() - half_size.z() + radius,
+    ];
+    let outside = Point3D::new(q[0].max(0.0), q[1].max(0.0), q[2].max(0.0)).length();
+    let inside = q[0].max(q[1]).max(q[2]).min(0.0);
+    outside + inside - radius
+}
+
+// The union of two distances, blended smoothly where they are within `k`
+// of each other, so shapes melt together instead of meeting at a crease.
+pub fn smooth_union(a: Float, b: Float, k: Float) -> Float {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+fn number(params: &serde_json::Value, key: &str) -> Result<Float, String> {
+    params[key]
+        .as_f64()
+        .map(|n| n as Float)
+        .ok_or_else(|| format!("distance field needs a {}", key))
+}
+
+// The fields scene files can use without any code, which every Registry
+// starts with: "sphere" with a `radius`, and "round_box" with a `half_size`
+// and a `radius`, both around the origin (place them with Translate or
+// Instance).
+pub(crate) fn register_built_in_fields(registry: &mut Registry) {
+    registry.register_distance_field("sphere", |params| {
+        let radius = number(params, "radius")?;
+        let reach = Point3D::new(radius, radius, radius);
+        Ok(Arc::new(FnField {
+            distance: move |p: &Point3D| sphere(p, radius),
+            bounds: Aabb::new(-reach, reach),
+        }))
+    });
+    registry.register_distance_field("round_box", |params| {
+        let half_size = serde_json::from_value::<Point3D>(params["half_size"].clone())
+            .map_err(|e| format!("distance field needs a half_size: {}", e))?;
+        let radius = number(params, "radius")?;
+        Ok(Arc::new(FnField {
+            distance: move |p: &Point3D| round_box(p, &half_size, radius),
+            bounds: Aabb::new(-half_size, half_size),
+        }))
+    });
+}
+
+// The surface where a distance field is 0, found by sphere tracing: the
+// ray can safely step as far as the distance from where it is. For shapes
+// with no formula for where a ray meets them, like rounded boxes and
+// smooth blends.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(try_from = "SdfShapeParams", into = "SdfShapeParams")]
+pub struct SdfShape {
+    name: String,
+    params: serde_json::Value,
+    field: SharedDistanceField,
+    pub material: Material,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SdfShapeParams {
+    pub name: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+    pub material: Material,
+}
+
+impl TryFrom<SdfShapeParams> for SdfShape {
+    type Error = String;
+
+    fn try_from(p: SdfShapeParams) -> Result<Self, Self::Error> {
+        let field = Registry::distance_field(&p.name, &p.params)?;
+        Ok(SdfShape {
+            name: p.name,
+            params: p.params,
+            field,
+            material: p.material,
+        })
+    }
+}
+
+impl From<SdfShape> for SdfShapeParams {
+    fn from(s: SdfShape) -> Self {
+        SdfShapeParams {
+            name: s.name,
+            params: s.params,
+            material: s.material,
+        }
+    }
+}
+
+impl SdfShape {
+    // For fields built in code; `name` and `params` are only used when the
+    // scene is saved.
+    pub fn new(
+        name: &str,
+        params: serde_json::Value,
+        field: SharedDistanceField,
+        material: Material,
+    ) -> SdfShape {
+        SdfShape {
+            name: name.to_string(),
+            params,
+            field,
+            material,
+        }
+    }
+
+    fn normal(&self, p: &Point3D) -> Point3D {
+        let h = GRADIENT_STEP;
+        let d = |dx, dy, dz| self.field.distance(&(*p + Point3D::new(dx, dy, dz)));
+        Point3D::new(
+            d(h, 0.0, 0.0) - d(-h, 0.0, 0.0),
+            d(0.0, h, 0.0) - d(0.0, -h, 0.0),
+            d(0.0, 0.0, h) - d(0.0, 0.0, -h),
+        )
+        .unit_vector()
+    }
+}
+
+impl fmt::Debug for SdfShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SdfShape")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("material", &self.material)
+            .finish()
+    }
+}
+
+impl Hittable for SdfShape {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (mut t, t_end) = self.field.bounds().span(ray, t_min, t_max)?;
+        let length = ray.direction.length();
+        for _ in 0..MAX_STEPS {
+            let point = ray.at(t);
+            // From inside the distance is negative, but stepping by its size
+            // is just as safe.
+            let distance = self.field.distance(&point).abs();
+            if distance < HIT_DISTANCE && t > t_min {
+                let outward = self.normal(&point);
+                let front_face = ray.direction.dot(&outward) < 0.0;
+                let (u, v) = u_v_from_sphere_hit_point(point - self.field.bounds().centroid());
+                return Some(HitRecord {
+                    t,
+                    point,
+                    normal: if front_face { outward } else { -outward },
+                    front_face,
+                    material: &self.material,
+                    u,
+                    v,
+                    tangent: None,
+                });
+            }
+            // Right at t_min, e.g. leaving the surface, nudge past it.
+            t += distance.max(HIT_DISTANCE) / length;
+            if t > t_end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        Some(self.field.bounds())
+    }
+}
+
+#[cfg(test)]
+fn test_material() -> Material {
+    Material::grey()
+}
+
+#[test]
+fn test_sdf_round_box() {
+    let json = r#"{"name":"round_box","params":{"half_size":{"x":1.0,"y":1.0,"z":1.0},"radius":0.25},"material":{"Lambertian":{"albedo":[0.5,0.5,0.5]}}}"#;
+    let rounded = serde_json::from_str::<SdfShape>(json).unwrap();
+    assert_eq!(json, serde_json::to_string(&rounded).unwrap());
+    // Flat in the middle of a face...
+    let face = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 2.0));
+    let hit = rounded.hit(&face, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 2.0, 1e-5);
+    assert!(hit.front_face);
+    assert_approx_eq!(hit.normal.z(), -1.0, 1e-5);
+    assert_approx_eq!(hit.v, 0.5, 1e-5);
+    // ...and round across a corner, which is cut off by more than the
+    // radius.
+    let corner = Ray::new(Point3D::new(-5.0, -5.0, -5.0), Point3D::new(1.0, 1.0, 1.0));
+    let hit = rounded.hit(&corner, 0.001, Float::MAX).unwrap();
+    let inner = 0.75 * Float::sqrt(3.0);
+    assert_approx_eq!(hit.point.x() * Float::sqrt(3.0), -(inner + 0.25), 1e-5);
+    assert_approx_eq!(hit.normal.x(), -(1.0 / Float::sqrt(3.0)), 1e-4);
+    let beside = Ray::new(Point3D::new(1.0, 1.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    assert!(rounded.hit(&beside, 0.001, Float::MAX).is_none());
+
+    let unknown = r#"{"name":"no-such-field","material":{"Lambertian":{"albedo":[0.5,0.5,0.5]}}}"#;
+    let err = serde_json::from_str::<SdfShape>(unknown).unwrap_err();
+    assert!(err.to_string().contains("unknown distance field"));
+}
+
+#[test]
+fn test_sdf_uv() {
+    // A distance field ball takes a texture just as a sphere does.
+    let json = r#"{"name":"sphere","params":{"radius":1.0},"material":{"Lambertian":{"albedo":[0.5,0.5,0.5]}}}"#;
+    let ball = serde_json::from_str::<SdfShape>(json).unwrap();
+    let sphere = Sphere::new(Point3D::new(0.0, 0.0, 0.0), 1.0, test_material());
+    for direction in [
+        Point3D::new(0.0, 0.0, -1.0),
+        Point3D::new(-1.0, 0.2, 0.0),
+        Point3D::new(0.3, -1.0, 0.5),
+        Point3D::new(0.5, 0.5, 1.0),
+    ] {
+        let ray = Ray::new(-direction.unit_vector() * 5.0, direction);
+        let marched = ball.hit(&ray, 0.001, Float::MAX).unwrap();
+        let traced = sphere.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert_approx_eq!(marched.u, traced.u, 1e-4);
+        assert_approx_eq!(marched.v, traced.v, 1e-4);
+    }
+}
+
+#[test]
+fn test_sdf_smooth_union() {
+    // Two balls just apart, melted together in the gap between them by as
+    // much as `k`.
+    let mut registry = Registry::new();
+    registry.register_distance_field("two_balls", |params| {
+        let k = number(params, "k")?;
+        let reach = Point3D::new(2.1, 1.1, 1.1);
+        Ok(Arc::new(FnField {
+            distance: move |p: &Point3D| {
+                let a = sphere(&(*p - Point3D::new(-1.1, 0.0, 0.0)), 1.0);
+                let b = sphere(&(*p - Point3D::new(1.1, 0.0, 0.0)), 1.0);
+                smooth_union(a, b, k)
+            },
+            bounds: Aabb::new(-reach, reach),
+        }))
+    });
+    let json = r#"{"SdfShape":{"name":"two_balls","params":{"k":0.5},"material":{"Lambertian":{"albedo":[0.5,0.5,0.5]}}}}"#;
+    let shape = registry
+        .scope(|| serde_json::from_str::<Object>(json))
+        .unwrap();
+    assert!(serde_json::from_str::<Object>(json).is_err());
+    let gap = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    let hit = shape.hit(&gap, 0.001, Float::MAX).unwrap();
+    assert!(hit.point.z() < 0.0);
+    assert_approx_eq!(hit.normal.x(), 0.0, 1e-4);
+    // Leaving from inside, away from the gap, where it is just a ball.
+    let out = Ray::new(Point3D::new(-1.1, 0.0, 0.0), Point3D::new(0.0, 1.0, 0.0));
+    let hit = shape.hit(&out, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_approx_eq!(hit.point.y(), 1.0, 1e-5);
+    assert_approx_eq!(hit.normal.y(), -1.0, 1e-4);
+
+    // Unblended, the gap is still there.
+    let apart = SdfShape::new(
+        "two_balls",
+        serde_json::json!({ "k": 0.0 }),
+        Arc::new(FnField {
+            distance: |p: &Point3D| {
+                let a = sphere(&(*p - Point3D::new(-1.1, 0.0, 0.0)), 1.0);
+                let b = sphere(&(*p - Point3D::new(1.1, 0.0, 0.0)), 1.0);
+                a.min(b)
+            },
+            bounds: Aabb::new(Point3D::new(-2.1, -1.1, -1.1), Point3D::new(2.1, 1.1, 1.1)),
+        }),
+        test_material(),
+    );
+    assert!(apart.hit(&gap, 0.001, Float::MAX).is_none());
+}
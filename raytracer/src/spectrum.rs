@@ -0,0 +1,311 @@
+use palette::Srgb;
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::float::from_f64;
+use crate::float::to_f32;
+use crate::float::Float;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Wavelengths traced together along each path: a hero wavelength picked at
+// random and the others spaced evenly from it across the visible range, so
+// one path stands for the whole spectrum at once.
+pub const WAVELENGTHS: usize = 4;
+
+// The visible range, in nanometres, that wavelengths are picked from.
+pub const MIN_WAVELENGTH: Float = 380.0;
+pub const MAX_WAVELENGTH: Float = 780.0;
+
+// The wavelength range Smits' spectra are tabulated over, and their bins.
+const SMITS_MIN: Float = 380.0;
+const SMITS_MAX: Float = 720.0;
+const SMITS_BINS: usize = 10;
+
+// Smits' spectra ("An RGB-to-spectrum conversion for reflectances", 1999):
+// a colour is made of white and the pair of these that lie between its
+// channels, giving a smooth, plausible reflectance for any RGB.
+const WHITE: [f32; SMITS_BINS] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const CYAN: [f32; SMITS_BINS] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const MAGENTA: [f32; SMITS_BINS] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const YELLOW: [f32; SMITS_BINS] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const RED: [f32; SMITS_BINS] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [f32; SMITS_BINS] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const BLUE: [f32; SMITS_BINS] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// CIE XYZ to linear sRGB (D65).
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+thread_local! {
+    // The wavelengths of the path being traced on this thread, if it is
+    // traced spectrally.
+    static CURRENT: Cell<Option<[Float; WAVELENGTHS]>> = const { Cell::new(None) };
+}
+
+// The wavelengths for a path, from a random number in [0, 1): the hero
+// wavelength there, and the others after it, wrapping around the range.
+pub fn sample_wavelengths(u: Float) -> [Float; WAVELENGTHS] {
+    std::array::from_fn(|i| {
+        let offset = (u + i as Float / WAVELENGTHS as Float).fract();
+        MIN_WAVELENGTH + offset * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+    })
+}
+
+// None once a spectral path is done, so RGB paths don't see its
+// wavelengths.
+pub fn set_wavelengths(wavelengths: Option<[Float; WAVELENGTHS]>) {
+    CURRENT.with(|current| current.set(wavelengths));
+}
+
+fn wavelengths() -> [Float; WAVELENGTHS] {
+    CURRENT
+        .with(|current| current.get())
+        .unwrap_or([550.0; WAVELENGTHS])
+}
+
+// The wavelength a path goes on at where it can only follow one.
+pub fn hero_wavelength() -> Option<Float> {
+    CURRENT
+        .with(|current| current.get())
+        .map(|wavelengths| wavelengths[0])
+}
+
+fn smits(table: &[f32; SMITS_BINS], wavelength: Float) -> f32 {
+    let bin = (wavelength - SMITS_MIN) / (SMITS_MAX - SMITS_MIN) * SMITS_BINS as Float;
+    table[(bin.max(0.0) as usize).min(SMITS_BINS - 1)]
+}
+
+// The value at `wavelength` of a spectrum that looks like `c`, by Smits'
+// method. Written for reflectances, but used for lights too, scaled up.
+pub fn upsample(c: Srgb, wavelength: Float) -> f32 {
+    let at = |table| smits(table, wavelength);
+    let (r, g, b) = (c.red, c.green, c.blue);
+    if r <= g && r <= b {
+        r * at(&WHITE)
+            + if g <= b {
+                (g - r) * at(&CYAN) + (b - g) * at(&BLUE)
+            } else {
+                (b - r) * at(&CYAN) + (g - b) * at(&GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * at(&WHITE)
+            + if r <= b {
+                (r - g) * at(&MAGENTA) + (b - r) * at(&BLUE)
+            } else {
+                (b - g) * at(&MAGENTA) + (r - b) * at(&RED)
+            }
+    } else {
+        b * at(&WHITE)
+            + if r <= g {
+                (r - b) * at(&YELLOW) + (g - r) * at(&GREEN)
+            } else {
+                (g - b) * at(&YELLOW) + (r - g) * at(&RED)
+            }
+    }
+}
+
+// One lobe of the CIE matching functions' fit: a Gaussian with a different
+// width either side of its peak.
+fn lobe(wavelength: Float, peak: Float, below: Float, above: Float) -> Float {
+    let width = if wavelength < peak { below } else { above };
+    let x = (wavelength - peak) / width;
+    (-0.5 * x * x).exp()
+}
+
+// The CIE 1931 colour matching functions at `wavelength`, by Wyman, Sloan
+// and Shirley's multi-lobe fit ("Simple Analytic Approximations to the CIE
+// XYZ Color Matching Functions", 2013).
+pub fn xyz(wavelength: Float) -> [Float; 3] {
+    let l = wavelength;
+    [
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    ]
+}
+
+fn xyz_to_rgb(xyz: [Float; 3]) -> [Float; 3] {
+    XYZ_TO_RGB.map(|row| {
+        from_f64(row[0]) * xyz[0] + from_f64(row[1]) * xyz[1] + from_f64(row[2]) * xyz[2]
+    })
+}
+
+// How much light at `wavelength` adds to each linear sRGB channel, scaled
+// so that the same light at every wavelength comes out white: illuminant E
+// rather than D65, so that white surfaces under white lights stay white.
+fn rgb_weights(wavelength: Float) -> [Float; 3] {
+    static WHITE_RGB: OnceLock<[Float; 3]> = OnceLock::new();
+    let white = WHITE_RGB.get_or_init(|| {
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as usize;
+        let mut sum = [0.0; 3];
+        for step in 0..steps {
+            let rgb = xyz_to_rgb(xyz(MIN_WAVELENGTH + step as Float + 0.5));
+            for (sum, c) in sum.iter_mut().zip(rgb) {
+                *sum += c / steps as Float;
+            }
+        }
+        sum
+    });
+    let rgb = xyz_to_rgb(xyz(wavelength));
+    [rgb[0] / white[0], rgb[1] / white[1], rgb[2] / white[2]]
+}
+
+// Light carried along a path: as RGB, or as the spectrum at the path's
+// wavelengths. The integrator is written once for either.
+pub trait Radiance: Copy {
+    fn one() -> Self;
+    // A colour given in RGB, as a material or light gives it.
+    fn from_rgb(c: Srgb) -> Self;
+    fn to_rgb(self) -> Srgb;
+    fn plus(self, other: Self) -> Self;
+    fn times(self, other: Self) -> Self;
+    fn over(self, d: f32) -> Self;
+    fn largest(self) -> f32;
+    // The light at the hero wavelength alone, standing in for the others
+    // from here on.
+    fn hero(self) -> Self;
+}
+
+impl Radiance for Color {
+    fn one() -> Color {
+        Color::WHITE
+    }
+
+    fn from_rgb(c: Srgb) -> Color {
+        c.into()
+    }
+
+    fn to_rgb(self) -> Srgb {
+        self.into()
+    }
+
+    fn plus(self, other: Color) -> Color {
+        self + other
+    }
+
+    fn times(self, other: Color) -> Color {
+        self * other
+    }
+
+    fn over(self, d: f32) -> Color {
+        self / d
+    }
+
+    fn largest(self) -> f32 {
+        Color::largest(&self)
+    }
+
+    // Without wavelengths nothing can be dropped.
+    fn hero(self) -> Color {
+        self
+    }
+}
+
+// Light at each of the current wavelengths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spectrum(pub [f32; WAVELENGTHS]);
+
+impl Radiance for Spectrum {
+    fn one() -> Spectrum {
+        Spectrum([1.0; WAVELENGTHS])
+    }
+
+    fn from_rgb(c: Srgb) -> Spectrum {
+        Spectrum(wavelengths().map(|wavelength| upsample(c, wavelength)))
+    }
+
+    // Each wavelength stands for an equal share of the visible range.
+    fn to_rgb(self) -> Srgb {
+        let mut rgb = [0.0; 3];
+        for (value, wavelength) in self.0.iter().zip(wavelengths()) {
+            for (c, weight) in rgb.iter_mut().zip(rgb_weights(wavelength)) {
+                *c += Float::from(*value) * weight / WAVELENGTHS as Float;
+            }
+        }
+        Srgb::new(to_f32(rgb[0]), to_f32(rgb[1]), to_f32(rgb[2]))
+    }
+
+    fn plus(self, other: Spectrum) -> Spectrum {
+        Spectrum(std::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+
+    fn times(self, other: Spectrum) -> Spectrum {
+        Spectrum(std::array::from_fn(|i| self.0[i] * other.0[i]))
+    }
+
+    fn over(self, d: f32) -> Spectrum {
+        Spectrum(self.0.map(|v| v / d))
+    }
+
+    fn largest(self) -> f32 {
+        self.0.into_iter().fold(0.0, f32::max)
+    }
+
+    // Each wavelength was a 1 / WAVELENGTHS share of the estimate; the hero
+    // now takes all of it.
+    fn hero(self) -> Spectrum {
+        let mut hero = [0.0; WAVELENGTHS];
+        hero[0] = self.0[0] * WAVELENGTHS as f32;
+        Spectrum(hero)
+    }
+}
+
+#[test]
+fn test_spectrum_round_trip() {
+    // Averaged over evenly spread paths, a colour turned into a spectrum
+    // and back comes out much as it went in.
+    let round_trip = |c: Srgb| {
+        let paths = 256;
+        let mut sum = Color::BLACK;
+        for path in 0..paths {
+            set_wavelengths(Some(sample_wavelengths(
+                path as Float / paths as Float / 4.0,
+            )));
+            sum += Spectrum::from_rgb(c).to_rgb().into();
+        }
+        sum / paths as f32
+    };
+    let white = round_trip(Srgb::new(1.0, 1.0, 1.0));
+    assert_approx_eq!(white.red, 1.0, 0.01);
+    assert_approx_eq!(white.green, 1.0, 0.01);
+    assert_approx_eq!(white.blue, 1.0, 0.01);
+    for c in [
+        Srgb::new(0.8, 0.2, 0.1),
+        Srgb::new(0.1, 0.6, 0.3),
+        Srgb::new(0.75, 0.85, 1.0),
+    ] {
+        let back = round_trip(c);
+        assert_approx_eq!(back.red, c.red, 0.1);
+        assert_approx_eq!(back.green, c.green, 0.1);
+        assert_approx_eq!(back.blue, c.blue, 0.1);
+    }
+
+    // Spread across the visible range, the hero first.
+    let wavelengths = sample_wavelengths(0.5);
+    assert_approx_eq!(wavelengths[0], 580.0);
+    assert_approx_eq!(wavelengths[1], 680.0);
+    assert_approx_eq!(wavelengths[2], 380.0);
+    // The eye is most sensitive to green, around 555nm.
+    assert!(xyz(555.0)[1] > 0.99 && xyz(450.0)[1] < 0.1);
+}
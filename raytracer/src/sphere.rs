@@ -1,106 +1,204 @@
-use bvh::aabb::Aabb;
-use bvh::aabb::Bounded;
-use bvh::bounding_hierarchy::BHShape;
 use serde::{Deserialize, Serialize};
 
+use crate::aabb::Aabb;
+use crate::float::consts::PI;
+use crate::float::Float;
 use crate::materials::Material;
 use crate::point3d::Point3D;
 use crate::ray::HitRecord;
 use crate::ray::Hittable;
 use crate::ray::Ray;
+use crate::sampler;
+use crate::triangle::Triangle;
 
 #[cfg(test)]
 use crate::materials::Glass;
-#[cfg(test)]
+#[cfg(all(test, feature = "images"))]
 use crate::materials::Lambertian;
+#[cfg(all(test, feature = "images"))]
+use crate::materials::Textured;
 #[cfg(test)]
-use crate::materials::Texture;
-#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(all(test, feature = "images"))]
 use palette::Srgb;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Sphere {
     pub center: Point3D,
-    pub radius: f64,
+    pub radius: Float,
     pub material: Material,
-    #[serde(skip)]
-    node_index: usize,
 }
 
-impl Bounded<f64, 3> for Sphere {
-    fn aabb(&self) -> Aabb<f64,3> {
-        let radius = self.radius;
-        let center = self.center;
-        let min = nalgebra::Point3::new(center.x() - radius, center.y() - radius, center.z() - radius);
-        let max = nalgebra::Point3::new(center.x() + radius, center.y() + radius, center.z() + radius);
-        Aabb::with_bounds(min, max)
+impl Sphere {
+    pub fn new(center: Point3D, radius: Float, material: Material) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            material,
+        }
     }
-}
 
-impl BHShape<f64,3> for Sphere {
-    fn set_bh_node_index(&mut self, index: usize) {
-        self.node_index = index;
+    // Cosine of the half-angle of the cone the sphere fills as seen from
+    // `from`, or None if `from` is inside it.
+    fn cone_cos_max(&self, from: &Point3D) -> Option<Float> {
+        let distance_squared = (self.center - *from).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
     }
 
-    fn bh_node_index(&self) -> usize {
-        self.node_index
+    // A random unit direction from `from` towards the sphere, uniform over
+    // the cone it fills, and the probability density of having picked it
+    // (per unit of solid angle). This is how light sources are sampled.
+    pub fn sample_direction(&self, from: &Point3D) -> Option<(Point3D, Float)> {
+        let cos_max = self.cone_cos_max(from)?;
+        let w = (self.center - *from).unit_vector();
+        let (u, v) = w.orthonormal_basis();
+        let (u1, u2) = sampler::next_2d();
+        let cos_theta = 1.0 - u1 * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let direction = u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + w * cos_theta;
+        Some((direction, self.cone_pdf(cos_max)))
     }
-}
 
-impl Sphere {
-    pub fn new(center: Point3D, radius: f64, material: Material) -> Sphere {
-        Sphere {
-            center,
-            radius,
-            material,
-            node_index: 0,
+    // The density `sample_direction` gives to unit `direction`: constant
+    // within the cone and zero outside it.
+    pub fn direction_pdf(&self, from: &Point3D, direction: &Point3D) -> Float {
+        match self.cone_cos_max(from) {
+            Some(cos_max) if direction.dot(&(self.center - *from).unit_vector()) >= cos_max => {
+                self.cone_pdf(cos_max)
+            }
+            _ => 0.0,
         }
     }
+
+    fn cone_pdf(&self, cos_max: Float) -> Float {
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+
+    // Triangles approximating the sphere, `segments` around and half as
+    // many from pole to pole, with the sphere's own normals and texture
+    // coordinates at their corners. Corners shared along the seam and at
+    // the poles are exactly the same points.
+    pub fn tessellate(&self, segments: usize) -> Vec<Triangle> {
+        let segments = segments.max(3);
+        let rings = (segments / 2).max(2);
+        let corner = |i: usize, j: usize| {
+            let (u, v) = (i as Float / segments as Float, j as Float / rings as Float);
+            let normal = if j == 0 || j == rings {
+                Point3D::new(0.0, if j == 0 { -1.0 } else { 1.0 }, 0.0)
+            } else {
+                let phi = 2.0 * PI * ((i % segments) as Float / segments as Float - 0.5);
+                let latitude = PI * (v - 0.5);
+                Point3D::new(
+                    phi.sin() * latitude.cos(),
+                    latitude.sin(),
+                    phi.cos() * latitude.cos(),
+                )
+            };
+            (self.center + normal * self.radius, normal, (u, v))
+        };
+        let mut triangles = Vec::new();
+        let mut push = |corners: [(Point3D, Point3D, (Float, Float)); 3]| {
+            let [a, b, c] = corners;
+            let mut triangle = Triangle::new(a.0, b.0, c.0, self.material.clone());
+            triangle.normals = Some([a.1, b.1, c.1]);
+            triangle.uvs = Some([a.2, b.2, c.2]);
+            triangles.push(triangle);
+        };
+        for j in 0..rings {
+            for i in 0..segments {
+                let (a, b) = (corner(i, j), corner(i + 1, j));
+                let (c, d) = (corner(i, j + 1), corner(i + 1, j + 1));
+                // The rings next to the poles are fans of one triangle each.
+                if j > 0 {
+                    push([a, b, d]);
+                }
+                if j + 1 < rings {
+                    push([a, d, c]);
+                }
+            }
+        }
+        triangles
+    }
+
+    // Whether `point` lies on the surface, as a hit point would.
+    pub fn is_on_surface(&self, point: &Point3D) -> bool {
+        ((*point - self.center).length() - self.radius).abs() < 1e-6 * self.radius.max(1.0)
+    }
 }
 
-fn u_v_from_sphere_hit_point(hit_point_on_sphere: Point3D) -> (f64, f64) {
+// Equirectangular mapping: u follows longitude and v follows latitude (not
+// the height, as it once did), both linearly, so a standard world map wraps
+// onto the sphere undistorted. Other roundish shapes are mapped the same way,
+// by the direction from their middle.
+pub(crate) fn u_v_from_sphere_hit_point(hit_point_on_sphere: Point3D) -> (Float, Float) {
     let n = hit_point_on_sphere.unit_vector();
     let x = n.x();
-    let y = n.y();
+    let y = n.y().clamp(-1.0, 1.0);
     let z = n.z();
-    let u = (x.atan2(z) / (2.0 * std::f64::consts::PI)) + 0.5;
-    let v = y * 0.5 + 0.5;
+    let u = (x.atan2(z) / (2.0 * PI)) + 0.5;
+    let v = y.asin() / PI + 0.5;
     (u, v)
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = (half_b * half_b) - (a * c);
-
-        if discriminant >= 0.0 {
-            let sqrtd = discriminant.sqrt();
-            let root_a = ((-half_b) - sqrtd) / a;
-            let root_b = ((-half_b) + sqrtd) / a;
-            for root in [root_a, root_b].iter() {
-                if *root < t_max && *root > t_min {
-                    let p = ray.at(*root);
-                    let normal = (p - self.center) / self.radius;
-                    let front_face = ray.direction.dot(&normal) < 0.0;
-
-                    let (u, v) = u_v_from_sphere_hit_point(p - self.center);
-
-                    return Some(HitRecord {
-                        t: *root,
-                        point: p,
-                        normal: if front_face { normal } else { -normal },
-                        front_face,
-                        material: &self.material,
-                        u,
-                        v,
-                    });
-                }
+// Shared by Sphere and MovingSphere, which only differ in where the center is.
+pub(crate) fn hit_sphere<'material>(
+    center: Point3D,
+    radius: Float,
+    material: &'material Material,
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<HitRecord<'material>> {
+    let oc = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = (half_b * half_b) - (a * c);
+
+    if discriminant >= 0.0 {
+        let sqrtd = discriminant.sqrt();
+        let root_a = ((-half_b) - sqrtd) / a;
+        let root_b = ((-half_b) + sqrtd) / a;
+        for root in [root_a, root_b].iter() {
+            if *root < t_max && *root > t_min {
+                let p = ray.at(*root);
+                let normal = (p - center) / radius;
+                let front_face = ray.direction.dot(&normal) < 0.0;
+
+                let (u, v) = u_v_from_sphere_hit_point(p - center);
+
+                return Some(HitRecord {
+                    t: *root,
+                    point: p,
+                    normal: if front_face { normal } else { -normal },
+                    front_face,
+                    material,
+                    u,
+                    v,
+                    tangent: None,
+                });
             }
         }
-        None
+    }
+    None
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // A negative radius, which turns the sphere inside out (e.g. the
+        // inside of a glass bubble), takes up as much room.
+        let r = self.radius.abs();
+        let r = Point3D::new(r, r, r);
+        Some(Aabb::new(self.center - r, self.center + r))
     }
 }
 
@@ -109,18 +207,68 @@ fn test_sphere_hit() {
     let center = Point3D::new(0.0, 0.0, 0.0);
     let sphere = Sphere::new(center, 1.0, Material::Glass(Glass::new(1.5)));
     let ray = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
-    let hit = sphere.hit(&ray, 0.0, f64::INFINITY);
+    let hit = sphere.hit(&ray, 0.0, Float::INFINITY);
     assert_eq!(hit.unwrap().t, 4.0);
 }
 
+#[test]
+fn test_inside_out_sphere_bounding_box() {
+    let center = Point3D::new(1.0, 2.0, 3.0);
+    let inside_out = Sphere::new(center, -0.5, Material::Glass(Glass::new(1.5)));
+    let bounds = inside_out.bounding_box(0.0, 1.0).unwrap();
+    assert_eq!(bounds.min, Point3D::new(0.5, 1.5, 2.5));
+    assert_eq!(bounds.max, Point3D::new(1.5, 2.5, 3.5));
+    let ray = Ray::new(center, Point3D::new(0.0, 0.0, 1.0));
+    assert!(bounds.hit(&ray, 0.001, Float::INFINITY));
+    assert_eq!(inside_out.hit(&ray, 0.001, Float::INFINITY).unwrap().t, 0.5);
+}
+
+#[test]
+fn test_sphere_sample_direction() {
+    let sphere = Sphere::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        1.0,
+        Material::Glass(Glass::new(1.5)),
+    );
+    let from = Point3D::new(0.0, 0.0, -4.0);
+    for _ in 0..100 {
+        let (direction, pdf) = sphere.sample_direction(&from).unwrap();
+        assert_approx_eq!(direction.length(), 1.0);
+        let hit = sphere
+            .hit(&Ray::new(from, direction), 0.001, Float::MAX)
+            .unwrap();
+        assert!(sphere.is_on_surface(&hit.point));
+        assert_approx_eq!(sphere.direction_pdf(&from, &direction), pdf);
+    }
+    // The cone's solid angle is 2 pi (1 - cos), with cos = sqrt(15) / 4.
+    let (_, pdf) = sphere.sample_direction(&from).unwrap();
+    let solid_angle = 2.0 * PI * (1.0 - Float::sqrt(15.0) / 4.0);
+    assert_approx_eq!(pdf, 1.0 / solid_angle);
+    let away = Point3D::new(0.0, 0.0, -1.0);
+    assert_eq!(sphere.direction_pdf(&from, &away), 0.0);
+    assert!(sphere
+        .sample_direction(&Point3D::new(0.0, 0.5, 0.0))
+        .is_none());
+}
+
+#[test]
+fn test_sphere_uv() {
+    let (_, v) = u_v_from_sphere_hit_point(Point3D::new(0.0, 1.0, 0.0));
+    assert_eq!(v, 1.0);
+    let (u, v) = u_v_from_sphere_hit_point(Point3D::new(0.0, 0.0, 1.0));
+    assert_eq!((u, v), (0.5, 0.5));
+    // 45 degrees of latitude is a quarter of the way from the equator to the pole.
+    let (_, v) = u_v_from_sphere_hit_point(Point3D::new(1.0, 1.0, 0.0));
+    assert_approx_eq!(v, 0.75);
+}
+
+#[cfg(feature = "images")]
 #[test]
 fn test_to_json() {
     let sphere = Sphere::new(
         Point3D::new(0.0, 0.0, 0.0),
         1.0,
-        Material::Lambertian(Lambertian::new(Srgb::new(
-            0.5 as f32, 0.5 as f32, 0.5 as f32,
-        ))),
+        Material::Lambertian(Lambertian::new(Srgb::new(0.5, 0.5, 0.5))),
     );
     let serialized = serde_json::to_string(&sphere).unwrap();
     assert_eq!(
@@ -134,8 +282,8 @@ fn test_to_json() {
     let textured_sphere = Sphere::new(
         Point3D::new(0.0, 0.0, 0.0),
         1.0,
-        Material::Texture(Texture::new(
-            Srgb::new(0.5 as f32, 0.5 as f32, 0.5 as f32),
+        Material::Texture(Textured::new(
+            Srgb::new(0.5, 0.5, 0.5),
             "data/earth.jpg",
             0.0,
         )),
@@ -147,13 +295,9 @@ fn test_to_json() {
         tserialized,
     );
 
-    let tex = Texture::new(
-        Srgb::new(0.5 as f32, 0.5 as f32, 0.5 as f32),
-        "data/earth.jpg",
-        0.0,
-    );
+    let tex = Textured::new(Srgb::new(0.5, 0.5, 0.5), "data/earth.jpg", 0.0);
     let tloadable = "{\"center\":{\"x\":0.0,\"y\":0.0,\"z\":0.0},\"radius\":1.0,\"material\":{\"Texture\":{\"albedo\":[0.5,0.5,0.5],\"pixels\":\"data/earth.jpg\",\"width\":2048,\"height\":1024,\"h_offset\":0.0}}}";
-    let loaded = serde_json::from_str::<Sphere>(&tloadable).unwrap();
+    let loaded = serde_json::from_str::<Sphere>(tloadable).unwrap();
     match loaded.material {
         Material::Texture(ref t) => {
             assert_eq!(t.pixels, tex.pixels);
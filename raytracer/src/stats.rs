@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+// What a render did, counted as it goes, for judging what a scene costs and
+// whether a change made tracing faster.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Counts {
+    // Rays traced into the scene, whatever for: from the camera, onwards
+    // from a bounce or towards a light.
+    pub rays: u64,
+    // Paths started from the camera, one per sample.
+    pub paths: u64,
+    // Times a path scattered off a surface and went on.
+    pub bounces: u64,
+    // Of the rays, those sent towards a light to see whether it is blocked.
+    pub shadow_rays: u64,
+    // Nodes of the accelerators stepped through: BVH and kd-tree nodes and
+    // grid cells, the scene's and those inside meshes and instances.
+    pub nodes_visited: u64,
+    // Colors looked up in textures, solid colors included.
+    pub texture_lookups: u64,
+}
+
+const ZERO: Counts = Counts {
+    rays: 0,
+    paths: 0,
+    bounces: 0,
+    shadow_rays: 0,
+    nodes_visited: 0,
+    texture_lookups: 0,
+};
+
+impl Counts {
+    pub fn add(&mut self, other: &Counts) {
+        self.rays += other.rays;
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+        self.shadow_rays += other.shadow_rays;
+        self.nodes_visited += other.nodes_visited;
+        self.texture_lookups += other.texture_lookups;
+    }
+
+    // How many times a path bounced, on average.
+    pub fn average_bounces(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.0;
+        }
+        self.bounces as f64 / self.paths as f64
+    }
+}
+
+// Each thread counts on its own, so counting costs no more than an add, and
+// the renderer collects them after each piece of work.
+thread_local! {
+    static COUNTS: Cell<Counts> = const { Cell::new(ZERO) };
+}
+
+pub(crate) fn count(update: impl FnOnce(&mut Counts)) {
+    COUNTS.with(|counts| {
+        let mut c = counts.get();
+        update(&mut c);
+        counts.set(c);
+    });
+}
+
+// What this thread has counted since it last took them, starting again from
+// nothing.
+pub(crate) fn take() -> Counts {
+    COUNTS.with(|counts| counts.replace(ZERO))
+}
+
+// The most memory the process has held at once, in bytes, where the system
+// says (Linux).
+pub fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+// What a whole render cost, to print at the end or save as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub counts: Counts,
+    pub average_bounces: f64,
+    pub seconds: f64,
+    pub peak_memory: Option<u64>,
+}
+
+impl Report {
+    pub fn new(counts: Counts, elapsed: Duration) -> Report {
+        Report {
+            counts,
+            average_bounces: counts.average_bounces(),
+            seconds: elapsed.as_secs_f64(),
+            peak_memory: peak_memory(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), std::io::Error> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = &self.counts;
+        writeln!(
+            f,
+            "Rays:              {} ({} shadow)",
+            c.rays, c.shadow_rays
+        )?;
+        writeln!(f, "Paths:             {}", c.paths)?;
+        writeln!(f, "Average bounces:   {:.2}", self.average_bounces)?;
+        writeln!(f, "Nodes visited:     {}", c.nodes_visited)?;
+        writeln!(f, "Texture lookups:   {}", c.texture_lookups)?;
+        if let Some(bytes) = self.peak_memory {
+            writeln!(f, "Peak memory:       {:.1} MB", bytes as f64 / 1e6)?;
+        }
+        write!(
+            f,
+            "Rays per second:   {:.0}",
+            c.rays as f64 / self.seconds.max(1e-9)
+        )
+    }
+}
+
+#[test]
+fn test_counts() {
+    take();
+    count(|c| c.paths += 2);
+    count(|c| c.rays += 5);
+    count(|c| c.bounces += 3);
+    let mut counts = take();
+    assert_eq!(counts.rays, 5);
+    assert_eq!(counts.average_bounces(), 1.5);
+    assert_eq!(take(), Counts::default());
+    counts.add(&counts.clone());
+    assert_eq!(counts.paths, 4);
+}
+
+#[test]
+fn test_report() {
+    let counts = Counts {
+        rays: 30,
+        paths: 10,
+        bounces: 15,
+        shadow_rays: 5,
+        ..Default::default()
+    };
+    let report = Report::new(counts, Duration::from_secs(2));
+    let text = report.to_string();
+    assert!(text.starts_with("Rays:              30 (5 shadow)\n"));
+    assert!(text.contains("Average bounces:   1.50\n"));
+    assert!(text.ends_with("Rays per second:   15"));
+    let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["shadow_rays"], 5);
+    assert_eq!(json["average_bounces"], 1.5);
+}
@@ -0,0 +1,137 @@
+use std::io::{Error, ErrorKind, Read};
+
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::triangle::Triangle;
+
+#[cfg(test)]
+use crate::float::to_f32;
+#[cfg(test)]
+use crate::materials::Glass;
+
+// A binary STL is an 80 byte header and a count, then 50 bytes a triangle.
+const HEADER: usize = 84;
+const FACET: usize = 50;
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// Parses an STL file, binary or text. Some binary files start with "solid"
+// as text ones do, so a file is taken as binary when its length matches its
+// triangle count. The facet normals are ignored in favour of the winding,
+// which is counterclockwise seen from outside.
+pub fn parse_stl<R: Read>(mut reader: R, material: &Material) -> Result<Vec<Triangle>, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() >= HEADER {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() == HEADER + count * FACET {
+            return Ok(parse_binary(&bytes[HEADER..], material));
+        }
+    }
+    let text = std::str::from_utf8(&bytes).map_err(|_| invalid("not an STL file"))?;
+    parse_ascii(text, material)
+}
+
+fn parse_binary(facets: &[u8], material: &Material) -> Vec<Triangle> {
+    let float = |b: &[u8], at: usize| f32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]);
+    let point = |b: &[u8], at: usize| {
+        Point3D::new(
+            Float::from(float(b, at)),
+            Float::from(float(b, at + 4)),
+            Float::from(float(b, at + 8)),
+        )
+    };
+    facets
+        .chunks_exact(FACET)
+        // The normal comes first, then the corners.
+        .map(|f| Triangle::new(point(f, 12), point(f, 24), point(f, 36), material.clone()))
+        .collect()
+}
+
+fn parse_ascii(text: &str, material: &Material) -> Result<Vec<Triangle>, Error> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    match lines.next() {
+        Some(line) if line.starts_with("solid") => {}
+        _ => return Err(invalid("not an STL file")),
+    }
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+    for line in lines {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["vertex", x, y, z] => {
+                let c = [x, y, z].map(|c| c.parse::<Float>().ok());
+                match c {
+                    [Some(x), Some(y), Some(z)] => corners.push(Point3D::new(x, y, z)),
+                    _ => return Err(invalid("bad number in STL file")),
+                }
+            }
+            ["endloop"] => {
+                if corners.len() != 3 {
+                    return Err(invalid("an STL facet needs three vertices"));
+                }
+                triangles.push(Triangle::new(
+                    corners[0],
+                    corners[1],
+                    corners[2],
+                    material.clone(),
+                ));
+                corners.clear();
+            }
+            // Several solids can follow one another.
+            [keyword, ..]
+                if ["facet", "outer", "endfacet", "solid", "endsolid"].contains(keyword) => {}
+            _ => return Err(invalid("bad line in STL file")),
+        }
+    }
+    Ok(triangles)
+}
+
+#[test]
+fn test_parse_stl() {
+    let ascii = "\
+solid tetra
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+  facet normal 0 -1 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid tetra
+";
+    let material = Material::Glass(Glass::new(1.5));
+    let triangles = parse_stl(ascii.as_bytes(), &material).unwrap();
+    assert_eq!(triangles.len(), 2);
+    assert_eq!(triangles[0].v1, Point3D::new(0.0, 1.0, 0.0));
+    assert_eq!(triangles[1].normal(), Point3D::new(0.0, -1.0, 0.0));
+    let short = ascii.replace("      vertex 0 0 1\n", "");
+    assert!(parse_stl(short.as_bytes(), &material).is_err());
+
+    // The same as binary, with a header that looks like text.
+    let mut binary = b"solid tetra".to_vec();
+    binary.resize(80, 0);
+    binary.extend(2u32.to_le_bytes());
+    for triangle in &triangles {
+        let normal = triangle.normal();
+        for p in [normal, triangle.v0, triangle.v1, triangle.v2] {
+            for c in [p.x(), p.y(), p.z()] {
+                binary.extend(to_f32(c).to_le_bytes());
+            }
+        }
+        binary.extend([0, 0]);
+    }
+    let from_binary = parse_stl(binary.as_slice(), &material).unwrap();
+    assert_eq!(from_binary.len(), 2);
+    assert_eq!(from_binary[1].v2, Point3D::new(0.0, 0.0, 1.0));
+}
@@ -0,0 +1,151 @@
+use palette::Srgb;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::materials::Glass;
+use crate::materials::Scatterable;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Ray;
+use crate::sampler;
+
+#[cfg(test)]
+use crate::float::TOLERANCE;
+#[cfg(test)]
+use crate::materials::Material;
+
+fn default_ior() -> Float {
+    1.3
+}
+
+// A translucent material such as wax, skin or marble: light refracts into
+// the object and does a random walk through it, scattering off particles
+// (`scattering`) and being absorbed (`absorption`) along the way before it
+// leaves somewhere else. Both coefficients are per unit of distance, for red,
+// green and blue; small values make the object more see-through.
+//
+// The walk needs no knowledge of the object's shape: a ray inside travels
+// until it reaches the surface again, and if a scattering event would have
+// happened before that, the walk continues from there in a new direction.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Subsurface {
+    pub scattering: [f32; 3],
+    pub absorption: [f32; 3],
+    #[serde(default = "default_ior")]
+    pub ior: Float,
+}
+
+impl Subsurface {
+    pub fn new(scattering: [f32; 3], absorption: [f32; 3]) -> Subsurface {
+        Subsurface {
+            scattering,
+            absorption,
+            ior: default_ior(),
+        }
+    }
+
+    fn extinction(&self) -> [f32; 3] {
+        [
+            self.scattering[0] + self.absorption[0],
+            self.scattering[1] + self.absorption[1],
+            self.scattering[2] + self.absorption[2],
+        ]
+    }
+}
+
+impl Scatterable for Subsurface {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let boundary = Glass::new(self.ior);
+        if hit_record.front_face {
+            return boundary.scatter(ray, hit_record);
+        }
+
+        // Inside: the ray crossed `distance` of the medium to get here.
+        // Free-flight distances are sampled with the mean extinction over the
+        // three channels, and each channel is reweighted for its own.
+        let mut rng = sampler::rng();
+        let length = ray.direction.length();
+        let distance = hit_record.t * length;
+        let sigma_t = self.extinction();
+        let mean = Float::from(sigma_t[0] + sigma_t[1] + sigma_t[2]) / 3.0;
+        if mean <= 0.0 {
+            return boundary.scatter(ray, hit_record);
+        }
+        let flight = -(1.0 - rng.gen::<Float>()).ln() / mean;
+
+        if flight < distance {
+            let pdf = mean * (-mean * flight).exp();
+            let weight = |c: usize| {
+                let scattering = Float::from(self.scattering[c]);
+                to_f32(scattering * (-Float::from(sigma_t[c]) * flight).exp() / pdf)
+            };
+            let origin = ray.at(flight / length);
+            let direction = Point3D::random_in_unit_sphere().unit_vector();
+            Some((
+                Some(Ray::with_time(origin, direction, ray.time)),
+                Srgb::new(weight(0), weight(1), weight(2)),
+            ))
+        } else {
+            let probability = (-mean * distance).exp();
+            let weight =
+                |c: usize| to_f32((-Float::from(sigma_t[c]) * distance).exp() / probability);
+            let (scattered, through) = boundary.scatter(ray, hit_record)?;
+            Some((
+                scattered,
+                Srgb::new(
+                    through.red * weight(0),
+                    through.green * weight(1),
+                    through.blue * weight(2),
+                ),
+            ))
+        }
+    }
+}
+
+#[test]
+fn test_subsurface_walk() {
+    let wax = Subsurface::new([1.0, 1.0, 1.0], [0.0, 0.0, 0.0]);
+    let material = Material::Subsurface(wax);
+    // From the inside, 10 units from the surface: with a mean free path of
+    // 1, nearly every ray scatters before it gets out, and without
+    // absorption no energy is lost when it does.
+    let hit = HitRecord {
+        t: 10.0,
+        point: Point3D::new(0.0, 0.0, 10.0),
+        normal: Point3D::new(0.0, 0.0, -1.0),
+        front_face: false,
+        material: &material,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(0.0, 0.0, 1.0));
+    let mut scattered_inside = 0;
+    for _ in 0..100 {
+        let (scattered, weight) = wax.scatter(&ray, &hit).unwrap();
+        let scattered = scattered.unwrap();
+        if scattered.origin.z() < 10.0 {
+            scattered_inside += 1;
+            assert!((weight.red - 1.0).abs() < 1e-5);
+            assert!((scattered.direction.length() - 1.0).abs() < TOLERANCE);
+        }
+    }
+    assert!(scattered_inside > 95);
+
+    // Absorption alone darkens the red channel only.
+    let tinted = Subsurface::new([0.0, 0.0, 0.0], [0.5, 0.0, 0.0]);
+    let material = Material::Subsurface(tinted);
+    let hit = HitRecord {
+        t: 2.0,
+        material: &material,
+        ..hit
+    };
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 8.0), Point3D::new(0.0, 0.0, 1.0));
+    let total: f32 = (0..1000)
+        .map(|_| tinted.scatter(&ray, &hit).map_or(0.0, |(_, w)| w.red))
+        .sum();
+    // On average, the red transmittance over 2 units is exp(-1).
+    assert!((total / 1000.0 - (-1.0_f32).exp()).abs() < 0.05);
+}
@@ -0,0 +1,714 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::sync::Arc;
+
+use crate::float::to_f32;
+use crate::float::Float;
+use crate::materials::SrgbAsArray;
+use crate::perlin::Perlin;
+use crate::point3d::Point3D;
+use crate::stats;
+
+#[cfg(test)]
+use crate::float::consts::FRAC_PI_2;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Where a material's color comes from at a given hit.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum Texture {
+    Checker(Checker),
+    Noise(Noise),
+    Marble(Marble),
+    Wood(Wood),
+    Image(ImageTexture),
+    Vertex(VertexColors),
+    // A plain color, written as an [r, g, b] array like any other color.
+    #[serde(untagged)]
+    Solid(#[serde_as(as = "SrgbAsArray")] Srgb),
+}
+
+impl Texture {
+    pub fn value(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        stats::count(|c| c.texture_lookups += 1);
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker(c) => c.value(u, v, p),
+            Texture::Noise(n) => n.value(u, v, p),
+            Texture::Marble(m) => m.value(u, v, p),
+            Texture::Wood(w) => w.value(u, v, p),
+            Texture::Image(i) => i.value(u, v, p),
+            Texture::Vertex(c) => c.value(u, v, p),
+        }
+    }
+
+    // The texture read as a height map: the average of its channels, with
+    // black at 0 and white at 1.
+    pub fn height(&self, u: Float, v: Float, p: &Point3D) -> Float {
+        let c = self.value(u, v, p);
+        Float::from(c.red + c.green + c.blue) / 3.0
+    }
+}
+
+impl From<Srgb> for Texture {
+    fn from(color: Srgb) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+// A 3D checkerboard of cubes `scale` units wide. Being solid rather than
+// UV-mapped, it has no seams or pinching at the poles of a sphere.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Checker {
+    #[serde_as(as = "SrgbAsArray")]
+    pub odd: Srgb,
+    #[serde_as(as = "SrgbAsArray")]
+    pub even: Srgb,
+    pub scale: Float,
+}
+
+impl Checker {
+    pub fn new(odd: Srgb, even: Srgb, scale: Float) -> Checker {
+        Checker { odd, even, scale }
+    }
+
+    pub fn value(&self, _u: Float, _v: Float, p: &Point3D) -> Srgb {
+        let x = (p.x() / self.scale).floor() as i64;
+        let y = (p.y() / self.scale).floor() as i64;
+        let z = (p.z() / self.scale).floor() as i64;
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+// Colors given at the corners of a triangle, blended across it by how near
+// the point is to each, as scanned meshes color their vertices.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct VertexColors {
+    pub corners: [Point3D; 3],
+    #[serde_as(as = "[SrgbAsArray; 3]")]
+    pub colors: [Srgb; 3],
+}
+
+impl VertexColors {
+    pub fn new(corners: [Point3D; 3], colors: [Srgb; 3]) -> VertexColors {
+        VertexColors { corners, colors }
+    }
+
+    pub fn value(&self, _u: Float, _v: Float, p: &Point3D) -> Srgb {
+        // The barycentric coordinates of p, as projected onto the triangle.
+        let [v0, v1, v2] = self.corners;
+        let (e1, e2, d) = (v1 - v0, v2 - v0, *p - v0);
+        let (d11, d12, d22) = (e1.dot(&e1), e1.dot(&e2), e2.dot(&e2));
+        let denominator = d11 * d22 - d12 * d12;
+        let (b1, b2) = if denominator.abs() < 1e-12 {
+            (1.0 / 3.0, 1.0 / 3.0)
+        } else {
+            let (d1, d2) = (d.dot(&e1), d.dot(&e2));
+            (
+                ((d22 * d1 - d12 * d2) / denominator).clamp(0.0, 1.0),
+                ((d11 * d2 - d12 * d1) / denominator).clamp(0.0, 1.0),
+            )
+        };
+        let weights = [(1.0 - b1 - b2).max(0.0), b1, b2].map(to_f32);
+        let [c0, c1, c2] = self.colors;
+        Srgb::new(
+            weights[0] * c0.red + weights[1] * c1.red + weights[2] * c2.red,
+            weights[0] * c0.green + weights[1] * c1.green + weights[2] * c2.green,
+            weights[0] * c0.blue + weights[1] * c1.blue + weights[2] * c2.blue,
+        )
+    }
+}
+
+// Grey Perlin noise; `scale` sets how many noise features fit in one unit.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "NoiseParams")]
+pub struct Noise {
+    pub scale: Float,
+    pub seed: u64,
+    #[serde(skip_serializing)]
+    perlin: Perlin,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct NoiseParams {
+    pub scale: Float,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl From<NoiseParams> for Noise {
+    fn from(p: NoiseParams) -> Self {
+        Noise::new(p.scale, p.seed)
+    }
+}
+
+impl Noise {
+    pub fn new(scale: Float, seed: u64) -> Noise {
+        Noise {
+            scale,
+            seed,
+            perlin: Perlin::new(seed),
+        }
+    }
+
+    pub fn value(&self, _u: Float, _v: Float, p: &Point3D) -> Srgb {
+        let n = to_f32(0.5 * (1.0 + self.perlin.noise(&(*p * self.scale))));
+        Srgb::new(n, n, n)
+    }
+}
+
+// One color of a ramp, and where along it (from 0 to 1) it comes.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Stop {
+    pub at: Float,
+    #[serde_as(as = "SrgbAsArray")]
+    pub color: Srgb,
+}
+
+// Colors blending into one another along 0..1, for turning a pattern's
+// value into a color. Before the first stop and after the last, their
+// colors carry on. Written as a list of stops.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct ColorRamp(pub Vec<Stop>);
+
+impl ColorRamp {
+    // A ramp from `from` at 0 to `to` at 1.
+    pub fn between(from: Srgb, to: Srgb) -> ColorRamp {
+        ColorRamp(vec![
+            Stop {
+                at: 0.0,
+                color: from,
+            },
+            Stop { at: 1.0, color: to },
+        ])
+    }
+
+    pub fn at(&self, t: Float) -> Srgb {
+        let stops = &self.0;
+        let next = stops.iter().position(|stop| stop.at > t);
+        let (a, b) = match next {
+            None => match stops.last() {
+                Some(last) => return last.color,
+                None => return Srgb::new(to_f32(t), to_f32(t), to_f32(t)),
+            },
+            Some(0) => return stops[0].color,
+            Some(i) => (stops[i - 1], stops[i]),
+        };
+        let f = to_f32((t - a.at) / (b.at - a.at));
+        Srgb::new(
+            a.color.red + (b.color.red - a.color.red) * f,
+            a.color.green + (b.color.green - a.color.green) * f,
+            a.color.blue + (b.color.blue - a.color.blue) * f,
+        )
+    }
+}
+
+fn default_octaves() -> usize {
+    7
+}
+
+fn default_marble_turbulence() -> Float {
+    10.0
+}
+
+fn default_marble_ramp() -> ColorRamp {
+    ColorRamp::between(Srgb::new(0.1, 0.1, 0.12), Srgb::new(0.95, 0.95, 0.92))
+}
+
+// Veined stone: stripes across z whose phase is pushed around by
+// turbulence, `turbulence` stripes' worth at most, so they swirl. `scale`
+// sets how many stripes (each a full turn of the ramp and back) fit in
+// 2 pi units, and `octaves` how fine the swirling gets.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "MarbleParams")]
+pub struct Marble {
+    pub scale: Float,
+    pub turbulence: Float,
+    pub octaves: usize,
+    pub seed: u64,
+    pub ramp: ColorRamp,
+    #[serde(skip_serializing)]
+    perlin: Perlin,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MarbleParams {
+    pub scale: Float,
+    #[serde(default = "default_marble_turbulence")]
+    pub turbulence: Float,
+    #[serde(default = "default_octaves")]
+    pub octaves: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_marble_ramp")]
+    pub ramp: ColorRamp,
+}
+
+impl From<MarbleParams> for Marble {
+    fn from(p: MarbleParams) -> Self {
+        Marble::new(p.scale, p.seed)
+            .with_turbulence(p.turbulence, p.octaves)
+            .with_ramp(p.ramp)
+    }
+}
+
+impl Marble {
+    pub fn new(scale: Float, seed: u64) -> Marble {
+        Marble {
+            scale,
+            turbulence: default_marble_turbulence(),
+            octaves: default_octaves(),
+            seed,
+            ramp: default_marble_ramp(),
+            perlin: Perlin::new(seed),
+        }
+    }
+
+    pub fn with_turbulence(mut self, turbulence: Float, octaves: usize) -> Marble {
+        self.turbulence = turbulence;
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_ramp(mut self, ramp: ColorRamp) -> Marble {
+        self.ramp = ramp;
+        self
+    }
+
+    pub fn value(&self, _u: Float, _v: Float, p: &Point3D) -> Srgb {
+        let p = *p * self.scale;
+        let phase = p.z() + self.turbulence * self.perlin.turb(&p, self.octaves);
+        self.ramp.at(0.5 * (1.0 + phase.sin()))
+    }
+}
+
+fn default_wood_turbulence() -> Float {
+    0.5
+}
+
+fn default_wood_ramp() -> ColorRamp {
+    ColorRamp::between(Srgb::new(0.55, 0.35, 0.17), Srgb::new(0.3, 0.16, 0.07))
+}
+
+// Tree rings around the y axis, `scale` of them to a unit, each running
+// through the ramp from the inside out. Turbulence nudges the rings by up to
+// `turbulence` of a ring, so they wobble as real grain does.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "WoodParams")]
+pub struct Wood {
+    pub scale: Float,
+    pub turbulence: Float,
+    pub octaves: usize,
+    pub seed: u64,
+    pub ramp: ColorRamp,
+    #[serde(skip_serializing)]
+    perlin: Perlin,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WoodParams {
+    pub scale: Float,
+    #[serde(default = "default_wood_turbulence")]
+    pub turbulence: Float,
+    #[serde(default = "default_octaves")]
+    pub octaves: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_wood_ramp")]
+    pub ramp: ColorRamp,
+}
+
+impl From<WoodParams> for Wood {
+    fn from(p: WoodParams) -> Self {
+        Wood::new(p.scale, p.seed)
+            .with_turbulence(p.turbulence, p.octaves)
+            .with_ramp(p.ramp)
+    }
+}
+
+impl Wood {
+    pub fn new(scale: Float, seed: u64) -> Wood {
+        Wood {
+            scale,
+            turbulence: default_wood_turbulence(),
+            octaves: default_octaves(),
+            seed,
+            ramp: default_wood_ramp(),
+            perlin: Perlin::new(seed),
+        }
+    }
+
+    pub fn with_turbulence(mut self, turbulence: Float, octaves: usize) -> Wood {
+        self.turbulence = turbulence;
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_ramp(mut self, ramp: ColorRamp) -> Wood {
+        self.ramp = ramp;
+        self
+    }
+
+    pub fn value(&self, _u: Float, _v: Float, p: &Point3D) -> Srgb {
+        let p = *p * self.scale;
+        let radius = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let ring = radius + self.turbulence * self.perlin.turb(&p, self.octaves);
+        self.ramp.at(ring - ring.floor())
+    }
+}
+
+thread_local! {
+    static FOOTPRINT: Cell<Float> = const { Cell::new(0.0) };
+}
+
+// Sets how wide, in (u, v) units, this thread's image lookups average over
+// until it's set again: about one pixel's width on the surface being shaded.
+// 0 takes the sharpest level.
+pub fn set_footprint(footprint: Float) {
+    FOOTPRINT.with(|f| f.set(footprint));
+}
+
+// The pixels of a PNG or JPEG image as RGB bytes, top row first, and its
+// width and height. Every image a scene's surfaces or sky are coloured by is
+// read through here.
+#[cfg(not(feature = "images"))]
+pub fn decode(path: &str) -> Result<(Vec<u8>, usize, usize), String> {
+    Err(format!("{}: built without image support", path))
+}
+
+#[cfg(feature = "images")]
+pub fn decode(path: &str) -> Result<(Vec<u8>, usize, usize), String> {
+    let image = image::open(path)
+        .map_err(|e| format!("{}: failed to load image: {}", path, e))?
+        .to_rgb();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Err(format!("{}: image is empty", path));
+    }
+    Ok((image.into_raw(), width as usize, height as usize))
+}
+
+// A PNG or JPEG image sampled at the hit's (u, v), with u = 0 at the left
+// edge and v = 0 at the bottom row, blending the nearest four texels. Far away or at a glancing
+// angle, where a pixel covers many texels, lookups come from smaller copies
+// averaged down from it instead, which don't alias. Written in scenes as
+// just the path to the image.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ImageTexture {
+    path: String,
+    // The image and then each half the size of the one before, down to a
+    // single texel. Shared so that cloning the material doesn't copy them.
+    levels: Arc<Vec<MipLevel>>,
+}
+
+#[derive(Debug, PartialEq)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: usize, y: usize) -> [f32; 3] {
+        let i = 3 * (y * self.width + x);
+        [
+            self.pixels[i] as f32,
+            self.pixels[i + 1] as f32,
+            self.pixels[i + 2] as f32,
+        ]
+    }
+
+    // Blends the four texels whose centres surround (x, y), measured in 0..1
+    // down from the top, holding the edge texels beyond the edges.
+    fn bilinear(&self, x: Float, y: Float) -> [f32; 3] {
+        let x = x * self.width as Float - 0.5;
+        let y = y * self.height as Float - 0.5;
+        let (fx, fy) = (to_f32(x - x.floor()), to_f32(y - y.floor()));
+        let column = |i: Float| (i.max(0.0) as usize).min(self.width - 1);
+        let row = |i: Float| (i.max(0.0) as usize).min(self.height - 1);
+        let (x0, x1) = (column(x.floor()), column(x.floor() + 1.0));
+        let (y0, y1) = (row(y.floor()), row(y.floor() + 1.0));
+        let (a, b) = (self.texel(x0, y0), self.texel(x1, y0));
+        let (c, d) = (self.texel(x0, y1), self.texel(x1, y1));
+        [0, 1, 2].map(|k| {
+            let top = a[k] + (b[k] - a[k]) * fx;
+            let bottom = c[k] + (d[k] - c[k]) * fx;
+            top + (bottom - top) * fy
+        })
+    }
+
+    // Half the size (rounding up), each texel the average of the four
+    // under it; an odd last row or column is counted twice.
+    fn halve(&self) -> MipLevel {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut pixels = Vec::with_capacity(3 * width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = (2 * x, 2 * y);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                let texels = [
+                    self.texel(x0, y0),
+                    self.texel(x1, y0),
+                    self.texel(x0, y1),
+                    self.texel(x1, y1),
+                ];
+                for k in 0..3 {
+                    let sum: f32 = texels.iter().map(|t| t[k]).sum();
+                    pixels.push((sum / 4.0).round() as u8);
+                }
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+impl TryFrom<String> for ImageTexture {
+    type Error = String;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        ImageTexture::load(&path)
+    }
+}
+
+impl From<ImageTexture> for String {
+    fn from(texture: ImageTexture) -> Self {
+        texture.path
+    }
+}
+
+impl ImageTexture {
+    pub fn load(path: &str) -> Result<ImageTexture, String> {
+        let (pixels, width, height) = decode(path)?;
+        let mut levels = vec![MipLevel {
+            width,
+            height,
+            pixels,
+        }];
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            let next = last.halve();
+            levels.push(next);
+        }
+        Ok(ImageTexture {
+            path: path.to_string(),
+            levels: Arc::new(levels),
+        })
+    }
+
+    // The size of the image, in texels across and down.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    pub fn value(&self, u: Float, v: Float, _p: &Point3D) -> Srgb {
+        let x = u.clamp(0.0, 1.0);
+        let y = 1.0 - v.clamp(0.0, 1.0);
+        // Each level down doubles the area one texel covers, so the level
+        // whose texels are as wide as the footprint is log2 of how many of
+        // the image's texels that is; between levels, blend the two.
+        let top = &self.levels[0];
+        let texels = FOOTPRINT.with(Cell::get) * top.width.max(top.height) as Float;
+        let level = texels.max(1.0).log2().min((self.levels.len() - 1) as Float);
+        let i = level.floor() as usize;
+        let f = to_f32(level - i as Float);
+        let mut c = self.levels[i].bilinear(x, y);
+        if f > 0.0 {
+            let next = self.levels[i + 1].bilinear(x, y);
+            c = [0, 1, 2].map(|k| c[k] + (next[k] - c[k]) * f);
+        }
+        Srgb::new(c[0] / 255.0, c[1] / 255.0, c[2] / 255.0)
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_image_texture() {
+    // 2x2: red, green on top; blue, white underneath.
+    let mut image = image::RgbImage::new(2, 2);
+    image.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+    image.put_pixel(1, 0, image::Rgb([0, 255, 0]));
+    image.put_pixel(0, 1, image::Rgb([0, 0, 255]));
+    image.put_pixel(1, 1, image::Rgb([255, 255, 255]));
+    image.save("/tmp/test_image_texture.png").unwrap();
+
+    let p = Point3D::new(0.0, 0.0, 0.0);
+    let texture = ImageTexture::load("/tmp/test_image_texture.png").unwrap();
+    assert_eq!(texture.value(0.25, 0.75, &p), Srgb::new(1.0, 0.0, 0.0));
+    assert_eq!(texture.value(0.75, 0.75, &p), Srgb::new(0.0, 1.0, 0.0));
+    assert_eq!(texture.value(0.25, 0.25, &p), Srgb::new(0.0, 0.0, 1.0));
+    assert_eq!(texture.value(1.0, 0.0, &p), Srgb::new(1.0, 1.0, 1.0));
+
+    let loaded = serde_json::from_str::<Texture>(r#"{"Image":"data/earth.jpg"}"#).unwrap();
+    assert_eq!(
+        r#"{"Image":"data/earth.jpg"}"#,
+        serde_json::to_string(&loaded).unwrap()
+    );
+    assert!(ImageTexture::load("data/missing.png").is_err());
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn test_image_filtering() {
+    // 2x2: red, green on top; blue, black underneath.
+    let mut image = image::RgbImage::new(2, 2);
+    image.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+    image.put_pixel(1, 0, image::Rgb([0, 255, 0]));
+    image.put_pixel(0, 1, image::Rgb([0, 0, 255]));
+    image.save("/tmp/test_image_filtering.png").unwrap();
+    let texture = ImageTexture::load("/tmp/test_image_filtering.png").unwrap();
+    assert_eq!(texture.levels.len(), 2);
+
+    // Halfway between texel centres, half of each.
+    let p = Point3D::new(0.0, 0.0, 0.0);
+    let top = texture.value(0.5, 0.75, &p);
+    assert_approx_eq!(top.red, 0.5);
+    assert_approx_eq!(top.green, 0.5);
+    assert_approx_eq!(top.blue, 0.0);
+    let middle = texture.value(0.5, 0.5, &p);
+    assert_approx_eq!(middle.red, 0.25);
+    assert_approx_eq!(middle.blue, 0.25);
+
+    // A footprint as wide as the image averages all of it wherever it's
+    // looked up, and narrower ones take some of the sharper level.
+    set_footprint(1.0);
+    let whole = texture.value(0.0, 1.0, &p);
+    set_footprint(0.75);
+    let between = texture.value(0.0, 1.0, &p);
+    set_footprint(0.0);
+    assert_approx_eq!(whole.red, 64.0 / 255.0);
+    assert_approx_eq!(whole.green, 64.0 / 255.0);
+    assert!(between.red > whole.red && between.red < 1.0);
+    assert_eq!(texture.value(0.0, 1.0, &p), Srgb::new(1.0, 0.0, 0.0));
+}
+
+#[test]
+fn test_noise() {
+    let noise = Noise::new(4.0, 1);
+    let c = noise.value(0.0, 0.0, &Point3D::new(0.1, 0.2, 0.3));
+    assert!(c.red >= 0.0 && c.red <= 1.0);
+    assert_eq!(c.red, c.green);
+
+    let serialized = serde_json::to_string(&Texture::Noise(noise)).unwrap();
+    assert_eq!(r#"{"Noise":{"scale":4.0,"seed":1}}"#, serialized);
+    let loaded = serde_json::from_str::<Texture>(r#"{"Noise":{"scale":4.0}}"#).unwrap();
+    assert_eq!(
+        loaded.value(0.0, 0.0, &Point3D::new(0.1, 0.2, 0.3)),
+        Noise::new(4.0, 0).value(0.0, 0.0, &Point3D::new(0.1, 0.2, 0.3))
+    );
+}
+
+#[test]
+fn test_color_ramp() {
+    let black = Srgb::new(0.0, 0.0, 0.0);
+    let red = Srgb::new(1.0, 0.0, 0.0);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    let ramp = ColorRamp(vec![
+        Stop {
+            at: 0.25,
+            color: black,
+        },
+        Stop {
+            at: 0.5,
+            color: red,
+        },
+        Stop {
+            at: 1.0,
+            color: white,
+        },
+    ]);
+    assert_eq!(ramp.at(0.0), black);
+    assert_eq!(ramp.at(0.5), red);
+    assert_eq!(ramp.at(0.75), Srgb::new(1.0, 0.5, 0.5));
+    assert_eq!(ramp.at(2.0), white);
+    assert_eq!(
+        r#"[{"at":0.0,"color":[0.0,0.0,0.0]},{"at":1.0,"color":[1.0,1.0,1.0]}]"#,
+        serde_json::to_string(&ColorRamp::between(black, white)).unwrap()
+    );
+}
+
+#[test]
+fn test_marble_and_wood() {
+    // Both stay within their ramps, and follow them: marble's stripes run
+    // across z, and wood's rings around y.
+    let marble = serde_json::from_str::<Texture>(
+        r#"{"Marble":{"scale":4.0,"ramp":[{"at":0.0,"color":[0.0,0.0,0.0]},{"at":1.0,"color":[1.0,0.0,0.0]}]}}"#,
+    )
+    .unwrap();
+    let wood = Texture::Wood(Wood::new(2.0, 3));
+    for i in 0..50 {
+        let p = Point3D::new(0.37 * i as Float, -0.21 * i as Float, 0.13 * i as Float);
+        let c = marble.value(0.0, 0.0, &p);
+        assert!((0.0..=1.0).contains(&c.red));
+        assert_eq!(c.green, 0.0);
+        let c = wood.value(0.0, 0.0, &p);
+        assert!(c.red <= 0.55 && c.red >= 0.3);
+    }
+    let flat = Wood::new(2.0, 3).with_turbulence(0.0, 1);
+    let inside = flat.value(0.0, 0.0, &Point3D::new(0.05, 0.0, 0.0));
+    let above = flat.value(0.0, 0.0, &Point3D::new(0.0, 5.0, 0.05));
+    assert_approx_eq!(inside.red, above.red);
+    let smooth = Marble::new(1.0, 0).with_turbulence(0.0, 1);
+    let quarter = smooth.value(0.0, 0.0, &Point3D::new(0.0, 0.0, FRAC_PI_2));
+    assert_eq!(quarter, default_marble_ramp().at(1.0));
+
+    let serialized = serde_json::to_string(&wood).unwrap();
+    let loaded = serde_json::from_str::<Texture>(&serialized).unwrap();
+    let p = Point3D::new(0.3, 0.4, 0.5);
+    assert_eq!(loaded.value(0.0, 0.0, &p), wood.value(0.0, 0.0, &p));
+}
+
+#[test]
+fn test_checker() {
+    let black = Srgb::new(0.0, 0.0, 0.0);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    let checker = Checker::new(black, white, 0.5);
+    assert_eq!(checker.value(0.0, 0.0, &Point3D::new(0.1, 0.1, 0.1)), white);
+    assert_eq!(checker.value(0.0, 0.0, &Point3D::new(0.6, 0.1, 0.1)), black);
+    assert_eq!(
+        checker.value(0.0, 0.0, &Point3D::new(-0.1, 0.1, 0.1)),
+        black
+    );
+    assert_eq!(
+        checker.value(0.0, 0.0, &Point3D::new(-0.1, -0.1, 0.1)),
+        white
+    );
+}
+
+#[test]
+fn test_to_json() {
+    let solid = Texture::Solid(Srgb::new(0.5, 0.5, 0.5));
+    assert_eq!("[0.5,0.5,0.5]", serde_json::to_string(&solid).unwrap());
+
+    let checker = Texture::Checker(Checker::new(
+        Srgb::new(0.0, 0.0, 0.0),
+        Srgb::new(1.0, 1.0, 1.0),
+        2.0,
+    ));
+    let serialized = serde_json::to_string(&checker).unwrap();
+    assert_eq!(
+        r#"{"Checker":{"odd":[0.0,0.0,0.0],"even":[1.0,1.0,1.0],"scale":2.0}}"#,
+        serialized
+    );
+    match serde_json::from_str::<Texture>(&serialized).unwrap() {
+        Texture::Checker(c) => assert_eq!(c.scale, 2.0),
+        _ => panic!("Wrong texture type"),
+    }
+    match serde_json::from_str::<Texture>("[0.1,0.2,0.3]").unwrap() {
+        Texture::Solid(color) => assert_eq!(color, Srgb::new(0.1, 0.2, 0.3)),
+        _ => panic!("Wrong texture type"),
+    }
+}
@@ -0,0 +1,352 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::float::consts::PI;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::materials::Metal;
+use crate::materials::RoughMetal;
+use crate::materials::Scatterable;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::spectrum;
+
+#[cfg(test)]
+use crate::materials::Glass;
+
+// Where the red, green and blue of an RGB render are taken to be, in
+// nanometres, for the film's reflectance at each.
+const RGB_WAVELENGTHS: [Float; 3] = [630.0, 532.0, 465.0];
+
+// What a film is taken to lie on over a metal, denser than any glass, and
+// over anything else but glass: most paints and plastics.
+const METAL_INDEX: Float = 3.0;
+const SURFACE_INDEX: Float = 1.5;
+
+// A material under a transparent film `thickness` nanometres thick, like a
+// soap bubble or the oxide on heated metal. Light reflected off the top of
+// the film meets light reflected off the bottom, and depending on the
+// wavelength they add up or cancel out, giving the colors that shift with
+// the angle and the thickness. What isn't reflected goes on to the material
+// beneath, which for glass is refracted into it. A metal is the bottom of
+// the film itself, so it reflects as it would bare, but more or less of
+// each color.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThinFilm {
+    pub thickness: Float,
+    pub index_of_refraction: Float,
+    pub material: Box<Material>,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Float, index_of_refraction: Float, material: Material) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            index_of_refraction,
+            material: Box::new(material),
+        }
+    }
+
+    // The indices of refraction on the side the light comes from and on
+    // the far side of the film.
+    fn sides(&self, hit_record: &HitRecord) -> (Float, Float) {
+        match &*self.material {
+            Material::Glass(glass) => {
+                let glass = glass.index_of_refraction();
+                if hit_record.front_face {
+                    (1.0, glass)
+                } else {
+                    (glass, 1.0)
+                }
+            }
+            Material::Metal(_) | Material::RoughMetal(_) => (1.0, METAL_INDEX),
+            _ => (1.0, SURFACE_INDEX),
+        }
+    }
+
+    fn over_metal(&self) -> bool {
+        matches!(*self.material, Material::Metal(_) | Material::RoughMetal(_))
+    }
+
+    // How much of the light arriving `cosine` away from the normal the film
+    // reflects.
+    pub fn reflectance(&self, cosine: Float, hit_record: &HitRecord) -> Srgb {
+        let (outside, beneath) = self.sides(hit_record);
+        let indices = [outside, self.index_of_refraction, beneath];
+        per_wavelength(|wavelength| {
+            interference(cosine, wavelength, indices, self.thickness) as f32
+        })
+    }
+
+    // The film's reflectance for light polarized across the plane of
+    // incidence and along it, at the path's wavelength or, rendering in RGB,
+    // at green's.
+    pub fn polarized_reflectance(&self, cosine: Float, hit_record: &HitRecord) -> (Float, Float) {
+        let (outside, beneath) = self.sides(hit_record);
+        let indices = [outside, self.index_of_refraction, beneath];
+        let wavelength = spectrum::hero_wavelength().unwrap_or(RGB_WAVELENGTHS[1]);
+        polarized_interference(cosine, wavelength, indices, self.thickness)
+    }
+
+    // How much more or less of each color a metal beneath the film reflects
+    // than it would bare, though never more than all the light arriving.
+    fn tint(&self, cosine: Float, hit_record: &HitRecord) -> Srgb {
+        let (outside, beneath) = self.sides(hit_record);
+        let indices = [outside, self.index_of_refraction, beneath];
+        // With no film, the wavelength doesn't matter.
+        let bare = interference(cosine, 550.0, [outside, beneath, beneath], 0.0);
+        let tint = per_wavelength(|wavelength| {
+            (interference(cosine, wavelength, indices, self.thickness) / bare) as f32
+        });
+        let albedo = match &*self.material {
+            Material::Metal(Metal { albedo, .. })
+            | Material::RoughMetal(RoughMetal { albedo, .. }) => {
+                albedo.value(hit_record.u, hit_record.v, &hit_record.point)
+            }
+            _ => Srgb::new(1.0, 1.0, 1.0),
+        };
+        Srgb::new(
+            tint.red.min(1.0 / albedo.red),
+            tint.green.min(1.0 / albedo.green),
+            tint.blue.min(1.0 / albedo.blue),
+        )
+    }
+
+    fn cosine(ray: &Ray, hit_record: &HitRecord) -> Float {
+        (-ray.direction.unit_vector())
+            .dot(&hit_record.normal)
+            .clamp(0.0, 1.0)
+    }
+}
+
+// `at` the path's wavelength with the spectral integrator, and otherwise at
+// one for each of red, green and blue.
+fn per_wavelength(at: impl Fn(Float) -> f32) -> Srgb {
+    match spectrum::hero_wavelength() {
+        Some(wavelength) => {
+            let r = at(wavelength);
+            Srgb::new(r, r, r)
+        }
+        None => {
+            let [r, g, b] = RGB_WAVELENGTHS.map(at);
+            Srgb::new(r, g, b)
+        }
+    }
+}
+
+// The reflectance of a film `thickness` nanometres thick for unpolarized
+// light of `wavelength`, arriving `cosine` away from the normal, where
+// `indices` are those of the medium the light comes from, the film and the
+// medium beneath (Airy's formula). Light that can't get through is all
+// reflected.
+fn interference(cosine: Float, wavelength: Float, indices: [Float; 3], thickness: Float) -> Float {
+    let (s, p) = polarized_interference(cosine, wavelength, indices, thickness);
+    (s + p) / 2.0
+}
+
+// The same for light polarized across the plane of incidence and along it.
+fn polarized_interference(
+    cosine: Float,
+    wavelength: Float,
+    indices: [Float; 3],
+    thickness: Float,
+) -> (Float, Float) {
+    let [n1, n2, n3] = indices;
+    let sin2 = 1.0 - cosine * cosine;
+    let (film_sin2, beneath_sin2) = ((n1 / n2).powi(2) * sin2, (n1 / n3).powi(2) * sin2);
+    if film_sin2 >= 1.0 || beneath_sin2 >= 1.0 {
+        return (1.0, 1.0);
+    }
+    let (c1, c2, c3) = (
+        cosine,
+        (1.0 - film_sin2).sqrt(),
+        (1.0 - beneath_sin2).sqrt(),
+    );
+    // The difference in phase, going down through the film and back up.
+    let phase = (4.0 * PI * n2 * thickness * c2 / wavelength).cos();
+    let airy = |r12: Float, r23: Float| {
+        let cross = 2.0 * r12 * r23 * phase;
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+    let s = airy(
+        (n1 * c1 - n2 * c2) / (n1 * c1 + n2 * c2),
+        (n2 * c2 - n3 * c3) / (n2 * c2 + n3 * c3),
+    );
+    let p = airy(
+        (n2 * c1 - n1 * c2) / (n2 * c1 + n1 * c2),
+        (n3 * c2 - n2 * c3) / (n3 * c2 + n2 * c3),
+    );
+    (s, p)
+}
+
+fn times(a: Srgb, b: Srgb) -> Srgb {
+    Srgb::new(a.red * b.red, a.green * b.green, a.blue * b.blue)
+}
+
+fn transmitted(reflectance: Srgb) -> Srgb {
+    Srgb::new(
+        1.0 - reflectance.red,
+        1.0 - reflectance.green,
+        1.0 - reflectance.blue,
+    )
+}
+
+impl Scatterable for ThinFilm {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Srgb)> {
+        let reflectance = self.reflectance(ThinFilm::cosine(ray, hit_record), hit_record);
+        // Reflected as often as on average over the channels, then weighted
+        // for each.
+        let chance = (reflectance.red + reflectance.green + reflectance.blue) / 3.0;
+        let weigh = |attenuation: Srgb, reflected: bool| {
+            let (weight, over) = if reflected {
+                (reflectance, chance)
+            } else {
+                (transmitted(reflectance), 1.0 - chance)
+            };
+            let w = times(attenuation, weight);
+            Srgb::new(w.red / over, w.green / over, w.blue / over)
+        };
+        match &*self.material {
+            material if self.over_metal() => {
+                let (scattered, attenuation) = material.scatter(ray, hit_record)?;
+                let tint = self.tint(ThinFilm::cosine(ray, hit_record), hit_record);
+                Some((scattered, times(attenuation, tint)))
+            }
+            Material::Glass(glass) => {
+                let (scattered, attenuation) =
+                    glass.scatter_by(ray, hit_record, |_, _| Float::from(chance))?;
+                let reflected = scattered
+                    .as_ref()
+                    .is_some_and(|s| s.direction.dot(&hit_record.normal) > 0.0);
+                Some((scattered, weigh(attenuation, reflected)))
+            }
+            material => {
+                if Float::from(chance) > sampler::next_1d() {
+                    let reflected = ray.direction.unit_vector().reflect(&hit_record.normal);
+                    let scattered = Ray::with_time(hit_record.point, reflected, ray.time);
+                    Some((Some(scattered), weigh(Srgb::new(1.0, 1.0, 1.0), true)))
+                } else {
+                    let (scattered, attenuation) = material.scatter(ray, hit_record)?;
+                    Some((scattered, weigh(attenuation, false)))
+                }
+            }
+        }
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Point3D) -> Srgb {
+        self.material.emitted(u, v, p)
+    }
+
+    // Only what gets through the film reaches the material beneath.
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Option<Srgb> {
+        let f = self.material.evaluate(ray, hit_record, direction)?;
+        let cosine = ThinFilm::cosine(ray, hit_record);
+        if self.over_metal() {
+            return Some(times(f, self.tint(cosine, hit_record)));
+        }
+        Some(times(f, transmitted(self.reflectance(cosine, hit_record))))
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Point3D) -> Float {
+        if self.over_metal() {
+            return self.material.pdf(ray, hit_record, direction);
+        }
+        let reflectance = self.reflectance(ThinFilm::cosine(ray, hit_record), hit_record);
+        let chance = (reflectance.red + reflectance.green + reflectance.blue) / 3.0;
+        self.material.pdf(ray, hit_record, direction) * Float::from(1.0 - chance)
+    }
+}
+
+#[test]
+fn test_interference() {
+    // A soap film in air reflects most at normal incidence when it is a
+    // quarter of a wavelength thick inside, and nothing at half.
+    let n = 1.33;
+    let r = (n - 1.0) / (n + 1.0);
+    let quarter = interference(1.0, 550.0, [1.0, n, 1.0], 550.0 / (4.0 * n));
+    assert!((quarter - 4.0 * r * r / (1.0 + r * r).powi(2)).abs() < 1e-6);
+    assert!(interference(1.0, 550.0, [1.0, n, 1.0], 550.0 / (2.0 * n)).abs() < 1e-6);
+    // With no film, just the glass beneath.
+    assert!((interference(1.0, 550.0, [1.0, n, 1.5], 0.0) - 0.04).abs() < 1e-6);
+    // Tilting the film shortens the path through it, moving the colors
+    // towards blue.
+    let shorter = 550.0 * (1.0 - 0.75 / (n * n)).sqrt();
+    let tilted = |wavelength| interference(0.5, wavelength, [1.0, n, 1.0], 550.0 / (4.0 * n));
+    assert!(tilted(shorter) > tilted(550.0));
+
+    // Each channel its own reflectance in RGB; one at the path's wavelength
+    // in spectral rendering, where the film disperses.
+    let coated = Material::ThinFilm(ThinFilm::new(
+        300.0,
+        2.2,
+        Material::Metal(Metal::new(Srgb::new(0.6, 0.6, 0.6), 0.0)),
+    ));
+    assert!(coated.disperses());
+    let hit = HitRecord {
+        t: 1.0,
+        point: Point3D::new(0.0, 0.0, 0.0),
+        normal: Point3D::new(0.0, 1.0, 0.0),
+        front_face: true,
+        material: &coated,
+        u: 0.0,
+        v: 0.0,
+        tangent: None,
+    };
+    let film = match &coated {
+        Material::ThinFilm(film) => film,
+        _ => unreachable!(),
+    };
+    let rgb = film.reflectance(1.0, &hit);
+    assert!((rgb.red - rgb.green).abs() > 0.05 || (rgb.green - rgb.blue).abs() > 0.05);
+    spectrum::set_wavelengths(Some([532.0; spectrum::WAVELENGTHS]));
+    let green = film.reflectance(1.0, &hit);
+    spectrum::set_wavelengths(None);
+    assert_eq!((green.red, green.blue), (rgb.green, rgb.green));
+    // The metal reflects no more than the film lets it, and as it would
+    // bare with no film at all.
+    let (_, attenuation) = coated
+        .scatter(
+            &Ray::new(
+                hit.point + Point3D::new(0.0, 1.0, 0.0),
+                Point3D::new(0.0, -1.0, 0.0),
+            ),
+            &hit,
+        )
+        .unwrap();
+    assert!((attenuation.red - 0.6 * film.tint(1.0, &hit).red).abs() < 1e-6);
+    let bare = ThinFilm::new(0.0, 2.2, (*film.material).clone());
+    assert!((bare.tint(1.0, &hit).blue - 1.0).abs() < 1e-6);
+    // A film denser than the metal reflects more than it would bare, but a
+    // bright metal can't be made to reflect more light than arrives.
+    let chrome = ThinFilm::new(
+        550.0 / 16.0,
+        4.0,
+        Material::Metal(Metal::new(Srgb::new(0.95, 0.95, 0.95), 0.0)),
+    );
+    let tint = chrome.tint(1.0, &hit);
+    let brightest = tint.red.max(tint.green).max(tint.blue);
+    assert!((brightest * 0.95 - 1.0).abs() < 1e-6);
+
+    // Over glass the light either reflects off the film or goes into the
+    // glass, and on average comes out as the film's reflectance says.
+    let bubble = ThinFilm::new(550.0 / (4.0 * n), n, Material::Glass(Glass::new(1.0)));
+    let ray = Ray::new(Point3D::new(0.0, 1.0, 0.0), Point3D::new(0.0, -1.0, 0.0));
+    let expected = bubble.reflectance(1.0, &hit);
+    let mut reflected = Srgb::new(0.0, 0.0, 0.0);
+    let n_rays = 20000;
+    for _ in 0..n_rays {
+        let (scattered, attenuation) = bubble.scatter(&ray, &hit).unwrap();
+        let scattered = scattered.unwrap();
+        if scattered.direction.y() > 0.0 {
+            reflected.red += attenuation.red / n_rays as f32;
+            reflected.green += attenuation.green / n_rays as f32;
+        } else {
+            // Straight on through a film with air on both sides.
+            assert!(scattered.direction.x().abs() < 1e-6);
+        }
+    }
+    assert!((reflected.red - expected.red).abs() < 0.02);
+    assert!((reflected.green - expected.green).abs() < 0.02);
+}
@@ -0,0 +1,82 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// How linear radiance, which has no upper limit, is squeezed into the 0 to 1
+// range an image can show. Without one, everything brighter than white clips
+// to white.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ToneMap {
+    // Clip each channel at 1.
+    #[default]
+    Clamp,
+    // c / (1 + c): gentle, but washes out the brightest colours.
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, with a film-like toe and
+    // shoulder.
+    Aces,
+    // John Hable's curve from Uncharted 2.
+    Uncharted2,
+}
+
+impl ToneMap {
+    pub fn apply(&self, color: Srgb) -> Srgb {
+        let map = |c: f32| -> f32 {
+            let c = c.max(0.0);
+            let mapped = match self {
+                ToneMap::Clamp => c,
+                ToneMap::Reinhard => c / (1.0 + c),
+                ToneMap::Aces => c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14),
+                ToneMap::Uncharted2 => {
+                    // The curve is scaled so that WHITE maps to 1, after an
+                    // exposure bias of 2.
+                    const WHITE: f32 = 11.2;
+                    hable(2.0 * c) / hable(WHITE)
+                }
+            };
+            mapped.min(1.0)
+        };
+        Srgb::new(map(color.red), map(color.green), map(color.blue))
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        match s.to_lowercase().as_str() {
+            "clamp" | "none" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            "uncharted2" | "hable" => Ok(ToneMap::Uncharted2),
+            _ => Err(format!("unknown tone map: {}", s)),
+        }
+    }
+}
+
+#[test]
+fn test_tone_map() {
+    let bright = Srgb::new(0.0, 1.0, 100.0);
+    assert_eq!(ToneMap::Clamp.apply(bright), Srgb::new(0.0, 1.0, 1.0));
+    assert_eq!(ToneMap::Reinhard.apply(bright).green, 0.5);
+    for tone_map in [ToneMap::Reinhard, ToneMap::Aces, ToneMap::Uncharted2] {
+        let mapped = tone_map.apply(bright);
+        assert!(mapped.red.abs() < 1e-6);
+        // Brighter stays brighter, short of white.
+        assert!(mapped.green < mapped.blue);
+        assert!(mapped.blue <= 1.0);
+        assert!(mapped.blue > 0.9);
+    }
+    assert_eq!("ACES".parse::<ToneMap>(), Ok(ToneMap::Aces));
+    assert!("filmic".parse::<ToneMap>().is_err());
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::consts::PI;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::poly::solve_quartic;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use crate::float::TOLERANCE;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// A ring: a tube `minor_radius` thick around the circle `major_radius`
+// from `center`, in the plane square to `axis`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Torus {
+    pub center: Point3D,
+    pub axis: Point3D,
+    pub major_radius: Float,
+    pub minor_radius: Float,
+    pub material: Material,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3D,
+        axis: Point3D,
+        major_radius: Float,
+        minor_radius: Float,
+        material: Material,
+    ) -> Torus {
+        Torus {
+            center,
+            axis: axis.unit_vector(),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // Scene files may not give a unit axis.
+        let axis = self.axis.unit_vector();
+        let (big, small) = (self.major_radius, self.minor_radius);
+        let length = ray.direction.length();
+        let d = ray.direction / length;
+
+        // Skip rays that miss the sphere around the torus, and start the
+        // rest where they reach it: the quartic loses precision quickly as
+        // the ray's origin gets further away.
+        let oc = ray.origin - self.center;
+        let bound = big + small;
+        let half_b = oc.dot(&d);
+        let discriminant = half_b * half_b - (oc.length_squared() - bound * bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let skip = (-half_b - discriminant.sqrt()).max(0.0);
+        let o = oc + d * skip;
+
+        // |p|^2 + R^2 - r^2 squared is 4 R^2 times the squared distance of p
+        // from the axis, for the points p on the surface; along the ray that
+        // is a quartic in t.
+        let m = o.dot(&d);
+        let k = o.length_squared() + big * big - small * small;
+        let o_along = o.dot(&axis);
+        let d_along = d.dot(&axis);
+        let four_r2 = 4.0 * big * big;
+        let coefficients = [
+            k * k - four_r2 * (o.length_squared() - o_along * o_along),
+            4.0 * m * k - 2.0 * four_r2 * (m - o_along * d_along),
+            4.0 * m * m + 2.0 * k - four_r2 * (1.0 - d_along * d_along),
+            4.0 * m,
+            1.0,
+        ];
+        let (roots, n) = solve_quartic(coefficients);
+        let t = roots[..n]
+            .iter()
+            .map(|t| (t + skip) / length)
+            .filter(|t| *t > t_min && *t < t_max)
+            .fold(Float::INFINITY, Float::min);
+        if t == Float::INFINITY {
+            return None;
+        }
+
+        // The normal points away from the nearest point of the circle at the
+        // middle of the tube. u goes around the axis, v around the tube.
+        let point = ray.at(t);
+        let p = point - self.center;
+        let p_along = p.dot(&axis);
+        let across = p - axis * p_along;
+        let ring = across.unit_vector() * big;
+        let outward = (p - ring).unit_vector();
+        let front_face = ray.direction.dot(&outward) < 0.0;
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let around = across.dot(&bitangent).atan2(across.dot(&tangent));
+        let tube = p_along.atan2(across.length() - big);
+        Some(HitRecord {
+            t,
+            point,
+            normal: if front_face { outward } else { -outward },
+            front_face,
+            material: &self.material,
+            u: 0.5 + around / (2.0 * PI),
+            v: 0.5 + tube / (2.0 * PI),
+            tangent: None,
+        })
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // The middle circle reaches out along each world axis by the major
+        // radius times the sine of that axis's angle with the torus's, and
+        // the tube a minor radius further.
+        let axis = self.axis.unit_vector();
+        let reach =
+            |a: Float| self.major_radius * (1.0 - a * a).max(0.0).sqrt() + self.minor_radius;
+        let reach = Point3D::new(reach(axis.x()), reach(axis.y()), reach(axis.z()));
+        Some(Aabb::new(self.center - reach, self.center + reach))
+    }
+}
+
+#[test]
+fn test_torus_hit() {
+    let torus = Torus::new(
+        Point3D::new(0.0, 0.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        2.0,
+        0.5,
+        Material::grey(),
+    );
+    // Across the ring through the middle: in at 2.5 from the centre, out of
+    // the tube at 1.5, through the hole and into the tube on the other side.
+    let across = Ray::new(Point3D::new(-10.0, 0.0, 0.0), Point3D::new(2.0, 0.0, 0.0));
+    let hit = torus.hit(&across, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 3.75, TOLERANCE);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(-1.0, 0.0, 0.0));
+    let hit = torus.hit(&across, 3.8, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), -1.5, TOLERANCE);
+    assert!(!hit.front_face);
+    let hit = torus.hit(&across, 4.3, Float::MAX).unwrap();
+    assert_approx_eq!(hit.point.x(), 1.5, TOLERANCE);
+    assert!(hit.front_face);
+
+    // Onto the top of the tube.
+    let top = Ray::new(Point3D::new(0.0, 10.0, 2.0), Point3D::new(0.0, -1.0, 0.0));
+    let hit = torus.hit(&top, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 9.5, TOLERANCE);
+    assert_approx_eq!(hit.normal.y(), 1.0, TOLERANCE);
+    assert!((0.0..=1.0).contains(&hit.u) && (0.0..=1.0).contains(&hit.v));
+}
+
+#[test]
+fn test_torus_hole() {
+    // A ring lying on its side, around x.
+    let torus = Torus::new(
+        Point3D::new(1.0, 0.0, 0.0),
+        Point3D::new(3.0, 0.0, 0.0),
+        2.0,
+        0.5,
+        Material::grey(),
+    );
+    // Rays along the axis go through the hole anywhere within 1.5 of it,
+    // and over the tube out to 2.5, as high on it as that far out allows.
+    for i in 0..30 {
+        let r = i as Float * 0.1 + 0.05;
+        let ray = Ray::new(Point3D::new(10.0, r, 0.0), Point3D::new(-1.0, 0.0, 0.0));
+        let hit = torus.hit(&ray, 0.001, Float::MAX);
+        if (1.5..2.5).contains(&r) {
+            let height = (0.25 - (r - 2.0) * (r - 2.0)).sqrt();
+            assert_approx_eq!(hit.unwrap().point.x(), 1.0 + height, TOLERANCE);
+        } else {
+            assert!(hit.is_none(), "hit {} from the axis", r);
+        }
+    }
+    // Nor does a ray slanting through the hole touch the ring.
+    let slanted = Ray::new(Point3D::new(6.0, 1.0, 0.5), Point3D::new(-1.0, -0.2, -0.1));
+    assert!(torus.hit(&slanted, 0.001, Float::MAX).is_none());
+}
@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Mul;
+
+use crate::float::Float;
+use crate::point3d::Point3D;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// A 4x4 matrix acting on points and vectors as columns, (x, y, z, 1) and
+// (x, y, z, 0): `a * b` does b first, then a.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[Float; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        Matrix4::scaling(Point3D::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Point3D) -> Matrix4 {
+        let mut m = Matrix4::identity().m;
+        m[0][3] = offset.x();
+        m[1][3] = offset.y();
+        m[2][3] = offset.z();
+        Matrix4 { m }
+    }
+
+    pub fn scaling(factors: Point3D) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        m[0][0] = factors.x();
+        m[1][1] = factors.y();
+        m[2][2] = factors.z();
+        m[3][3] = 1.0;
+        Matrix4 { m }
+    }
+
+    // Turning `angle` degrees around `axis` through the origin, anticlockwise
+    // looking down the axis.
+    pub fn rotation(axis: Point3D, angle: Float) -> Matrix4 {
+        let a = axis.unit_vector();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let c = 1.0 - cos;
+        Matrix4 {
+            m: [
+                [
+                    cos + x * x * c,
+                    x * y * c - z * sin,
+                    x * z * c + y * sin,
+                    0.0,
+                ],
+                [
+                    y * x * c + z * sin,
+                    cos + y * y * c,
+                    y * z * c - x * sin,
+                    0.0,
+                ],
+                [
+                    z * x * c - y * sin,
+                    z * y * c + x * sin,
+                    cos + z * z * c,
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    pub fn point(&self, p: &Point3D) -> Point3D {
+        self.vector(p) + Point3D::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    // Directions aren't moved by the translation part.
+    pub fn vector(&self, v: &Point3D) -> Point3D {
+        let row = |r: [Float; 4]| r[0] * v.x() + r[1] * v.y() + r[2] * v.z();
+        Point3D::new(row(self.m[0]), row(self.m[1]), row(self.m[2]))
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+// One step of a Transform, as written in scene files.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Step {
+    Translate(Point3D),
+    Scale(Point3D),
+    Rotate { axis: Point3D, angle: Float },
+}
+
+impl Step {
+    fn matrix(&self) -> Matrix4 {
+        match self {
+            Step::Translate(offset) => Matrix4::translation(*offset),
+            Step::Scale(factors) => Matrix4::scaling(*factors),
+            Step::Rotate { axis, angle } => Matrix4::rotation(*axis, *angle),
+        }
+    }
+
+    fn undo(&self) -> Step {
+        match self {
+            Step::Translate(offset) => Step::Translate(-*offset),
+            Step::Scale(f) => Step::Scale(Point3D::new(1.0 / f.x(), 1.0 / f.y(), 1.0 / f.z())),
+            Step::Rotate { axis, angle } => Step::Rotate {
+                axis: *axis,
+                angle: -angle,
+            },
+        }
+    }
+}
+
+// Scaling, turning and moving, in the order the steps are given, e.g.
+// `Transform::new().scale(..).rotate(..).translate(..)`. Scene files list
+// the steps. Each step can be undone, so the inverse is kept alongside
+// without having to invert a matrix.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "Vec<Step>", into = "Vec<Step>")]
+pub struct Transform {
+    steps: Vec<Step>,
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Transform {
+    pub fn new() -> Transform {
+        Transform {
+            steps: Vec::new(),
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    pub fn translate(self, offset: Point3D) -> Transform {
+        self.then(Step::Translate(offset))
+    }
+
+    // Must not flatten: none of the factors can be 0.
+    pub fn scale(self, factors: Point3D) -> Transform {
+        self.then(Step::Scale(factors))
+    }
+
+    pub fn rotate(self, axis: Point3D, angle: Float) -> Transform {
+        self.then(Step::Rotate { axis, angle })
+    }
+
+    pub fn then(mut self, step: Step) -> Transform {
+        self.matrix = step.matrix() * self.matrix;
+        self.inverse = self.inverse * step.undo().matrix();
+        self.steps.push(step);
+        self
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Matrix4 {
+        &self.inverse
+    }
+
+    // Normals stay square to the surface under the inverse transpose.
+    pub fn normal(&self, n: &Point3D) -> Point3D {
+        self.inverse.transpose().vector(n).unit_vector()
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TryFrom<Vec<Step>> for Transform {
+    type Error = String;
+
+    fn try_from(steps: Vec<Step>) -> Result<Self, Self::Error> {
+        let mut transform = Transform::new();
+        for step in steps {
+            if let Step::Scale(f) = step {
+                if f.x() == 0.0 || f.y() == 0.0 || f.z() == 0.0 {
+                    return Err("a transform can't scale by 0".to_string());
+                }
+            }
+            transform = transform.then(step);
+        }
+        Ok(transform)
+    }
+}
+
+impl From<Transform> for Vec<Step> {
+    fn from(transform: Transform) -> Self {
+        transform.steps
+    }
+}
+
+#[test]
+fn test_transform() {
+    // Doubled, a quarter turn around z, then moved up 1: (1, 0, 0) goes to
+    // (2, 0, 0), then (0, 2, 0), then (0, 3, 0).
+    let transform = Transform::new()
+        .scale(Point3D::new(2.0, 2.0, 2.0))
+        .rotate(Point3D::new(0.0, 0.0, 1.0), 90.0)
+        .translate(Point3D::new(0.0, 1.0, 0.0));
+    let p = transform.matrix().point(&Point3D::new(1.0, 0.0, 0.0));
+    assert_approx_eq!(p.x(), 0.0);
+    assert_approx_eq!(p.y(), 3.0);
+    let v = transform.matrix().vector(&Point3D::new(1.0, 0.0, 0.0));
+    assert_approx_eq!(v.y(), 2.0);
+    // The inverse takes it back.
+    let back = transform.inverse().point(&p);
+    assert_approx_eq!(back.x(), 1.0);
+    assert_approx_eq!(back.y(), 0.0);
+    let product = *transform.matrix() * *transform.inverse();
+    for (i, row) in product.m.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            assert_approx_eq!(value, if i == j { 1.0 } else { 0.0 });
+        }
+    }
+
+    // Squashed flat along y, a slope's normal tips towards y.
+    let squash = Transform::new().scale(Point3D::new(1.0, 0.5, 1.0));
+    let n = squash.normal(&Point3D::new(1.0, 1.0, 0.0).unit_vector());
+    assert_approx_eq!(n.y(), 2.0 / Float::sqrt(5.0));
+
+    // Scene files list the steps.
+    let json = serde_json::to_string(&transform).unwrap();
+    assert!(json.starts_with(r#"[{"Scale":{"x":2.0"#));
+    assert_eq!(serde_json::from_str::<Transform>(&json).unwrap(), transform);
+    let flat = r#"[{"Scale":{"x":1.0,"y":0.0,"z":1.0}}]"#;
+    assert!(serde_json::from_str::<Transform>(flat).is_err());
+}
@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::float::Float;
+use crate::materials::Material;
+use crate::point3d::Point3D;
+use crate::ray::HitRecord;
+use crate::ray::Hittable;
+use crate::ray::Ray;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+// Rays closer to parallel with the triangle's plane than this are treated as
+// misses, which avoids dividing by a vanishing determinant.
+const PARALLEL_EPSILON: Float = 1e-9;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Triangle {
+    pub v0: Point3D,
+    pub v1: Point3D,
+    pub v2: Point3D,
+    pub material: Material,
+    // Optional per-vertex attributes, e.g. as loaded from a mesh file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals: Option<[Point3D; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uvs: Option<[(Float, Float); 3]>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3D, v1: Point3D, v2: Point3D, material: Material) -> Triangle {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            material,
+            normals: None,
+            uvs: None,
+        }
+    }
+
+    pub fn area(&self) -> Float {
+        (self.v1 - self.v0).cross(&(self.v2 - self.v0)).length() / 2.0
+    }
+
+    // Geometric normal; follows the right-hand rule for v0 -> v1 -> v2.
+    pub fn normal(&self) -> Point3D {
+        (self.v1 - self.v0)
+            .cross(&(self.v2 - self.v0))
+            .unit_vector()
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller–Trumbore: solve origin + t * direction = v0 + u * e1 + v * e2
+    // for (t, u, v) using Cramer's rule and scalar triple products.
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let e1 = self.v1 - self.v0;
+        let e2 = self.v2 - self.v0;
+        let pvec = ray.direction.cross(&e2);
+        let det = e1.dot(&pvec);
+        if det.abs() < PARALLEL_EPSILON * ray.direction.length() {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin - self.v0;
+        let u = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let qvec = tvec.cross(&e1);
+        let v = ray.direction.dot(&qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&qvec) * inv_det;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        // Which side was hit is told by the geometric normal; with vertex
+        // normals, the one shaded with is blended from them across the face.
+        let geometric = self.normal();
+        let front_face = ray.direction.dot(&geometric) < 0.0;
+        let normal = match self.normals {
+            Some([n0, n1, n2]) => {
+                let shading = (n0 * (1.0 - u - v) + n1 * u + n2 * v).unit_vector();
+                // Keep it on the same side as the face, however the file
+                // wound its vertices.
+                if shading.dot(&geometric) < 0.0 {
+                    -shading
+                } else {
+                    shading
+                }
+            }
+            None => geometric,
+        };
+        // Without texture coordinates, fall back to the barycentric ones.
+        let (tex_u, tex_v, tangent) = match self.uvs {
+            Some([uv0, uv1, uv2]) => {
+                let w = 1.0 - u - v;
+                // Solve e1 = du1 * T + dv1 * B, e2 = du2 * T + dv2 * B for T.
+                let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+                let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+                let det = du1 * dv2 - du2 * dv1;
+                (
+                    w * uv0.0 + u * uv1.0 + v * uv2.0,
+                    w * uv0.1 + u * uv1.1 + v * uv2.1,
+                    (det.abs() > 1e-12).then(|| ((e1 * dv2 - e2 * dv1) / det).unit_vector()),
+                )
+            }
+            None => (u, v, None),
+        };
+        Some(HitRecord {
+            t,
+            point: ray.at(t),
+            normal: if front_face { normal } else { -normal },
+            front_face,
+            material: &self.material,
+            u: tex_u,
+            v: tex_v,
+            tangent,
+        })
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<Aabb> {
+        // Pad the box slightly so axis-aligned triangles don't produce a
+        // zero-thickness box.
+        let pad = Point3D::new(1e-4, 1e-4, 1e-4);
+        let a = Aabb::new(self.v0, self.v0);
+        let b = Aabb::new(self.v1, self.v1);
+        let c = Aabb::new(self.v2, self.v2);
+        let bounds = Aabb::surrounding_box(&Aabb::surrounding_box(&a, &b), &c);
+        Some(Aabb::new(bounds.min - pad, bounds.max + pad))
+    }
+}
+
+#[test]
+fn test_triangle_hit() {
+    let triangle = Triangle::new(
+        Point3D::new(-1.0, -1.0, 0.0),
+        Point3D::new(1.0, -1.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        Material::grey(),
+    );
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = triangle.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.t, 5.0);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 0.0, 1.0));
+    assert_approx_eq!(hit.u, 0.25);
+    assert_approx_eq!(hit.v, 0.5);
+
+    // From behind, the normal is flipped to face the ray.
+    let ray = Ray::new(Point3D::new(0.0, 0.0, -5.0), Point3D::new(0.0, 0.0, 1.0));
+    let hit = triangle.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Point3D::new(0.0, 0.0, -1.0));
+    assert_approx_eq!(triangle.area(), 2.0);
+}
+
+#[test]
+fn test_triangle_uvs() {
+    let mut triangle = Triangle::new(
+        Point3D::new(-1.0, -1.0, 0.0),
+        Point3D::new(1.0, -1.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        Material::grey(),
+    );
+    triangle.uvs = Some([(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)]);
+    let ray = Ray::new(Point3D::new(0.0, 0.0, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = triangle.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert_approx_eq!(hit.u, 0.5);
+    assert_approx_eq!(hit.v, 0.5);
+}
+
+#[test]
+fn test_triangle_vertex_normals() {
+    let mut triangle = Triangle::new(
+        Point3D::new(-1.0, -1.0, 0.0),
+        Point3D::new(1.0, -1.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        Material::grey(),
+    );
+    let tilted = Point3D::new(1.0, 0.0, 1.0).unit_vector();
+    let up = Point3D::new(0.0, 0.0, 1.0);
+    triangle.normals = Some([up, tilted, up]);
+    // Halfway along the v0 -> v1 edge, halfway between their normals.
+    let ray = Ray::new(Point3D::new(0.0, -1.0, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    let hit = triangle.hit(&ray, 0.001, Float::MAX).unwrap();
+    let halfway = (up + tilted).unit_vector();
+    assert_approx_eq!(hit.normal.x(), halfway.x());
+    assert_approx_eq!(hit.normal.z(), halfway.z());
+
+    // Normals pointing away from the winding still face the way it does,
+    // and which side was hit doesn't depend on them.
+    triangle.normals = Some([-up, -up, -up]);
+    let hit = triangle.hit(&ray, 0.001, Float::MAX).unwrap();
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, up);
+}
+
+#[test]
+fn test_triangle_miss() {
+    let triangle = Triangle::new(
+        Point3D::new(-1.0, -1.0, 0.0),
+        Point3D::new(1.0, -1.0, 0.0),
+        Point3D::new(0.0, 1.0, 0.0),
+        Material::grey(),
+    );
+    let outside = Ray::new(Point3D::new(1.0, 1.0, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    let parallel = Ray::new(Point3D::new(0.0, 0.0, 5.0), Point3D::new(1.0, 0.0, 0.0));
+    let too_far = Ray::new(Point3D::new(0.0, 0.0, 5.0), Point3D::new(0.0, 0.0, -1.0));
+    assert!(triangle.hit(&outside, 0.001, Float::MAX).is_none());
+    assert!(triangle.hit(&parallel, 0.001, Float::MAX).is_none());
+    assert!(triangle.hit(&too_far, 0.001, 4.0).is_none());
+}